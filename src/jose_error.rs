@@ -1,5 +1,10 @@
 use thiserror::Error;
 
+/// Represents a error of this crate.
+///
+/// Every variant wraps the error that caused it and returns it from
+/// [`std::error::Error::source`], so the original OpenSSL, I/O or JSON error
+/// is kept in the error chain.
 #[derive(Error, Debug)]
 pub enum JoseError {
     #[error("Unsupported signature algorithm: {0}")]
@@ -0,0 +1,40 @@
+use std::fmt::Debug;
+
+use openssl::rand;
+
+/// Represent a source of random bytes.
+///
+/// The contexts draw the random values they generate, such as content encryption keys
+/// and initialization vectors, from a JoseRandom. A fixed generator can be set to
+/// reproduce test vectors, but it must never be used for real messages.
+pub trait JoseRandom: Debug + Send + Sync {
+    /// Fill the buffer with random bytes.
+    ///
+    /// # Arguments
+    ///
+    /// * `buf` - a buffer to fill
+    fn fill_bytes(&self, buf: &mut [u8]);
+
+    /// Return a new vector of random bytes.
+    ///
+    /// # Arguments
+    ///
+    /// * `len` - a length of the vector
+    fn random_bytes(&self, len: usize) -> Vec<u8> {
+        let mut vec = vec![0; len];
+        self.fill_bytes(&mut vec);
+        vec
+    }
+}
+
+/// Represent the cryptographically secure random generator of OpenSSL.
+///
+/// This is the default random generator of the contexts.
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub struct OsRandom;
+
+impl JoseRandom for OsRandom {
+    fn fill_bytes(&self, buf: &mut [u8]) {
+        rand::rand_bytes(buf).unwrap();
+    }
+}
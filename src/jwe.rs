@@ -1,4 +1,11 @@
 //! JSON Web Encryption (JWE) support.
+//!
+//! The symmetric keys held by the encrypters and decrypters of dir, AES key wrap,
+//! AES GCM key wrap and PBES2, and the content encryption keys used in
+//! [`JweContext`], are zeroed when they are dropped. This is a best-effort
+//! defense: the keys that are held by OpenSSL (including the HMAC keys of JWS),
+//! the keys returned to the caller (e.g. by recover_cek) and the copies made by
+//! the caller are not covered.
 
 pub mod alg;
 pub mod enc;
@@ -8,20 +15,26 @@ mod jwe_content_encryption;
 mod jwe_context;
 mod jwe_header;
 mod jwe_header_set;
+mod multi_encrypter;
 pub mod zip;
 
+use std::io::{Read, Write};
+
 use once_cell::sync::Lazy;
 
+use crate::jwk::JwkSet;
 use crate::JoseError;
 
 pub use crate::jwe::jwe_algorithm::JweAlgorithm;
 pub use crate::jwe::jwe_algorithm::JweDecrypter;
 pub use crate::jwe::jwe_algorithm::JweEncrypter;
+pub use crate::jwe::jwe_algorithm::JweTagBoundEncrypter;
 pub use crate::jwe::jwe_compression::JweCompression;
 pub use crate::jwe::jwe_content_encryption::JweContentEncryption;
 pub use crate::jwe::jwe_context::JweContext;
 pub use crate::jwe::jwe_header::JweHeader;
 pub use crate::jwe::jwe_header_set::JweHeaderSet;
+pub use crate::jwe::multi_encrypter::JweMultiEncrypter;
 
 pub use crate::jwe::alg::direct::DirectJweAlgorithm::Dir;
 
@@ -31,6 +44,12 @@ pub use EcdhEsJweAlgorithm::EcdhEsA128kw as ECDH_ES_A128KW;
 pub use EcdhEsJweAlgorithm::EcdhEsA192kw as ECDH_ES_A192KW;
 pub use EcdhEsJweAlgorithm::EcdhEsA256kw as ECDH_ES_A256KW;
 
+use crate::jwe::alg::ecdh_1pu::Ecdh1puJweAlgorithm;
+pub use Ecdh1puJweAlgorithm::Ecdh1pu as ECDH_1PU;
+pub use Ecdh1puJweAlgorithm::Ecdh1puA128kw as ECDH_1PU_A128KW;
+pub use Ecdh1puJweAlgorithm::Ecdh1puA192kw as ECDH_1PU_A192KW;
+pub use Ecdh1puJweAlgorithm::Ecdh1puA256kw as ECDH_1PU_A256KW;
+
 use crate::jwe::alg::aeskw::AeskwJweAlgorithm;
 pub use AeskwJweAlgorithm::A128kw as A128KW;
 pub use AeskwJweAlgorithm::A192kw as A192KW;
@@ -54,6 +73,9 @@ pub use RsaesJweAlgorithm::RsaOaep256 as RSA_OAEP_256;
 pub use RsaesJweAlgorithm::RsaOaep384 as RSA_OAEP_384;
 pub use RsaesJweAlgorithm::RsaOaep512 as RSA_OAEP_512;
 
+/// The payload, the header and the additional authenticated data.
+type DecryptedWithAad = (Vec<u8>, JweHeader, Option<Vec<u8>>);
+
 static DEFAULT_CONTEXT: Lazy<JweContext> = Lazy::new(|| JweContext::new());
 
 /// Return a representation of the data that is formatted by compact serialization.
@@ -71,6 +93,62 @@ pub fn serialize_compact(
     DEFAULT_CONTEXT.serialize_compact(payload, header, encrypter)
 }
 
+/// Return a representation of the data that is formatted by compact serialization
+/// with the protected header that is given as a JSON string.
+///
+/// The header is encoded as it is without re-serialization. ECDH-ES, ECDH-1PU and
+/// AES-GCM key wrap are rejected, because they add random header claims.
+///
+/// # Arguments
+///
+/// * `payload` - The payload data.
+/// * `header` - The JWE header claims as a JSON string.
+/// * `encrypter` - The JWE encrypter.
+pub fn serialize_compact_with_raw_header(
+    payload: &[u8],
+    header: &str,
+    encrypter: &dyn JweEncrypter,
+) -> Result<String, JoseError> {
+    DEFAULT_CONTEXT.serialize_compact_with_raw_header(payload, header, encrypter)
+}
+
+/// Return an upper bound on the length of the representation of a payload that is
+/// formatted by compact serialization.
+///
+/// No payload is encrypted, so a payload that is too large can be rejected before
+/// the serialization. A compressed payload is not supported.
+///
+/// # Arguments
+///
+/// * `payload_len` - The length of the payload data in bytes.
+/// * `header` - The JWE header claims.
+/// * `encrypter` - The JWE encrypter.
+pub fn estimate_compact_len(
+    payload_len: usize,
+    header: &JweHeader,
+    encrypter: &dyn JweEncrypter,
+) -> Result<usize, JoseError> {
+    DEFAULT_CONTEXT.estimate_compact_len(payload_len, header, encrypter)
+}
+
+/// Return a representation of the owned data that is formatted by compact serialization.
+///
+/// The payload buffer is encrypted in place if possible.
+/// See [`JweContext::serialize_compact_owned`].
+///
+/// # Arguments
+///
+/// * `payload` - The payload data.
+/// * `header` - The JWE heaser claims.
+/// * `encrypter` - The JWE encrypter.
+pub fn serialize_compact_owned(
+    payload: impl Into<Vec<u8>>,
+    header: &JweHeader,
+    encrypter: &dyn JweEncrypter,
+) -> Result<String, JoseError> {
+    DEFAULT_CONTEXT.serialize_compact_owned(payload, header, encrypter)
+}
+
 /// Return a representation of the data that is formatted by compact serialization.
 ///
 /// # Arguments
@@ -89,6 +167,22 @@ where
     DEFAULT_CONTEXT.serialize_compact_with_selector(payload, header, selector)
 }
 
+/// Return a representation of the data that is formatted by compact serialization
+/// and the content encryption key used to encrypt the payload.
+///
+/// # Arguments
+///
+/// * `payload` - The payload data.
+/// * `header` - The JWE heaser claims.
+/// * `encrypter` - The JWE encrypter.
+pub fn serialize_compact_with_cek_out(
+    payload: &[u8],
+    header: &JweHeader,
+    encrypter: &dyn JweEncrypter,
+) -> Result<(String, Vec<u8>), JoseError> {
+    DEFAULT_CONTEXT.serialize_compact_with_cek_out(payload, header, encrypter)
+}
+
 /// Return a representation of the data that is formatted by flattened json serialization.
 ///
 /// # Arguments
@@ -210,12 +304,42 @@ where
     DEFAULT_CONTEXT.deserialize_compact_with_selector(input, selector)
 }
 
-/// Deserialize the input that is formatted by flattened json serialization.
+/// Deserialize the input that is formatted by compact serialization with one of the decrypters.
+///
+/// # Arguments
+///
+/// * `input` - The input data.
+/// * `decrypters` - The candidate decrypters.
+pub fn deserialize_compact_with_decrypters(
+    input: impl AsRef<[u8]>,
+    decrypters: &[&dyn JweDecrypter],
+) -> Result<(Vec<u8>, JweHeader), JoseError> {
+    DEFAULT_CONTEXT.deserialize_compact_with_decrypters(input, decrypters)
+}
+
+/// Deserialize the input that is formatted by compact serialization with a key in the JWK set.
+///
+/// See [`JweContext::deserialize_compact_with_jwk_set`] for the key selection.
+///
+/// # Arguments
+///
+/// * `input` - The input data.
+/// * `jwk_set` - The JWK set.
+pub fn deserialize_compact_with_jwk_set(
+    input: &str,
+    jwk_set: &JwkSet,
+) -> Result<(Vec<u8>, JweHeader), JoseError> {
+    DEFAULT_CONTEXT.deserialize_compact_with_jwk_set(input, jwk_set)
+}
+
+/// Deserialize the input that is formatted by flattened or general json serialization.
+///
+/// For general json serialization, the recipients are tried in order and
+/// the payload of the first recipient matched with the decrypter is returned.
 ///
 /// # Arguments
 ///
 /// * `input` - The input data.
-/// * `header` - The decoded JWS header claims.
 /// * `decrypter` - The JWE decrypter.
 pub fn deserialize_json<'a>(
     input: &str,
@@ -224,7 +348,41 @@ pub fn deserialize_json<'a>(
     DEFAULT_CONTEXT.deserialize_json(input, decrypter)
 }
 
-/// Deserialize the input that is formatted by flattened json serialization.
+/// Deserialize the input that is formatted by flattened or general json serialization,
+/// and return the additional authenticated data (the aad field) in addition to
+/// the payload and the header.
+///
+/// # Arguments
+///
+/// * `input` - The input data.
+/// * `decrypter` - The JWE decrypter.
+pub fn deserialize_json_with_aad(
+    input: impl AsRef<[u8]>,
+    decrypter: &dyn JweDecrypter,
+) -> Result<DecryptedWithAad, JoseError> {
+    DEFAULT_CONTEXT.deserialize_json_with_aad(input, decrypter)
+}
+
+/// Deserialize the input that is formatted by compact or json serialization.
+///
+/// The input is deserialized as json serialization if it starts with "{"
+/// after any leading whitespace, or as compact serialization otherwise.
+///
+/// # Arguments
+///
+/// * `input` - The input data.
+/// * `decrypter` - The JWE decrypter.
+pub fn deserialize_auto(
+    input: impl AsRef<[u8]>,
+    decrypter: &dyn JweDecrypter,
+) -> Result<(Vec<u8>, JweHeader), JoseError> {
+    DEFAULT_CONTEXT.deserialize_auto(input, decrypter)
+}
+
+/// Deserialize the input that is formatted by flattened or general json serialization.
+///
+/// For general json serialization, the selector is called with the merged header
+/// of each recipient in order until it returns a decrypter.
 ///
 /// # Arguments
 ///
@@ -240,145 +398,1822 @@ where
     DEFAULT_CONTEXT.deserialize_json_with_selector(input, selector)
 }
 
+/// Deserialize the input that is formatted by flattened or general json serialization,
+/// and return the index of the recipient that was decrypted in addition to the payload
+/// and the header.
+///
+/// The selector is called with the merged header of each recipient in order until
+/// it returns a decrypter. For flattened json serialization, the index is always 0.
+///
+/// # Arguments
+///
+/// * `input` - The input data.
+/// * `selector` - a function for selecting the decrypting algorithm.
+pub fn deserialize_general_json_with_selector<'a, F>(
+    input: &str,
+    selector: F,
+) -> Result<(Vec<u8>, JweHeader, usize), JoseError>
+where
+    F: Fn(&JweHeader) -> Result<Option<&'a dyn JweDecrypter>, JoseError>,
+{
+    DEFAULT_CONTEXT.deserialize_general_json_with_selector(input, selector)
+}
+
+/// Decrypt the ciphertext read from the reader and write the payload to the writer.
+///
+/// See [`JweContext::decrypt_stream`] for the memory usage and the handling of
+/// the written data on failure.
+///
+/// # Arguments
+///
+/// * `header_b64` - The base64url encoded protected header segment as it is in
+///   the input, e.g. the first segment of a compact serialization.
+/// * `key` - The content encryption key.
+/// * `iv` - The initialization vector.
+/// * `tag` - The authentication tag.
+/// * `reader` - The reader of the ciphertext (not base64 encoded), e.g. a
+///   [`Base64UrlDecoder`](crate::util::Base64UrlDecoder) of the ciphertext segment.
+/// * `writer` - The writer of the payload.
+pub fn decrypt_stream(
+    header_b64: impl AsRef<[u8]>,
+    key: &[u8],
+    iv: Option<&[u8]>,
+    tag: Option<&[u8]>,
+    reader: impl Read,
+    writer: impl Write,
+) -> Result<u64, JoseError> {
+    DEFAULT_CONTEXT.decrypt_stream(header_b64, key, iv, tag, reader, writer)
+}
+
+/// Recover the content encryption key from the encrypted key without decrypting
+/// the ciphertext.
+///
+/// # Arguments
+///
+/// * `header` - The JWE header claims.
+/// * `encrypted_key` - The encrypted key (not base64 encoded).
+/// * `decrypter` - The JWE decrypter.
+pub fn recover_cek(
+    header: &JweHeader,
+    encrypted_key: &[u8],
+    decrypter: &dyn JweDecrypter,
+) -> Result<Vec<u8>, JoseError> {
+    DEFAULT_CONTEXT.recover_cek(header, encrypted_key, decrypter)
+}
+
+/// Return the first decrypter that matches with the alg and kid header claims.
+///
+/// A decrypter matches when its algorithm name is the same as the alg header claim and
+/// its key ID is the same as the kid header claim. A decrypter without key ID matches
+/// only a header without kid. This can be used as a part of the selector functions.
+///
+/// # Arguments
+///
+/// * `header` - The JWE header claims.
+/// * `decrypters` - The candidate decrypters.
+pub fn select_decrypter<'a>(
+    header: &JweHeader,
+    decrypters: &[&'a dyn JweDecrypter],
+) -> Option<&'a dyn JweDecrypter> {
+    let alg = header.algorithm()?;
+    decrypters
+        .iter()
+        .find(|decrypter| {
+            decrypter.algorithm().name() == alg && decrypter.key_id() == header.key_id()
+        })
+        .copied()
+}
+
 #[cfg(test)]
 mod tests {
+    use std::collections::VecDeque;
+    use std::fmt::{Debug, Display};
     use std::fs;
+    use std::io;
     use std::path::PathBuf;
+    use std::str::FromStr;
+    use std::sync::{Arc, Mutex};
 
     use anyhow::Result;
 
+    use crate::jwe::alg::aesgcmkw::AesgcmkwJweAlgorithm;
+    use crate::jwe::alg::aeskw::AeskwJweAlgorithm;
+    use crate::jwe::alg::direct::DirectJweAlgorithm;
+    use crate::jwe::alg::ecdh_1pu::Ecdh1puJweAlgorithm;
+    use crate::jwe::alg::ecdh_es::EcdhEsJweAlgorithm;
+    use crate::jwe::alg::pbes2_hmac_aeskw::Pbes2HmacAeskwJweAlgorithm;
+    use crate::jwe::alg::rsaes::RsaesJweAlgorithm;
+    use crate::jwe::enc::aescbc_hmac::AescbcHmacJweEncryption;
+    use crate::jwe::enc::aesgcm::AesgcmJweEncryption;
+    use crate::jwe::enc::xchacha20_poly1305::Xchacha20Poly1305JweEncryption;
+    use crate::jwe::enc::{A128CBC_HS256, A128GCM, A256CBC_HS512};
+    #[allow(deprecated)]
+    use crate::jwe::RSA1_5;
     use crate::jwe::{
-        self, Dir, JweAlgorithm, JweHeader, JweHeaderSet, ECDH_ES_A128KW, PBES2_HS256_A128KW,
-        RSA_OAEP,
+        self, zip, Dir, JweAlgorithm, JweCompression, JweContentEncryption, JweContext,
+        JweDecrypter, JweEncrypter, JweHeader, JweHeaderSet, A128KW, A256KW, ECDH_1PU,
+        ECDH_1PU_A128KW, ECDH_ES, ECDH_ES_A128KW, ECDH_ES_A256KW, PBES2_HS256_A128KW, RSA_OAEP,
+        RSA_OAEP_256,
     };
-    use crate::jwk::Jwk;
+    use crate::jwk::alg::ec::EcCurve;
+    use crate::jwk::{Jwk, JwkSet, KeyPair};
     use crate::util;
-    use crate::Value;
+    use crate::{JoseError, JoseRandom, Map, Value};
+
+    #[derive(Debug)]
+    struct FixedRandom(Mutex<VecDeque<Vec<u8>>>);
+
+    impl JoseRandom for FixedRandom {
+        fn fill_bytes(&self, buf: &mut [u8]) {
+            let val = self.0.lock().unwrap().pop_front().unwrap();
+            buf.copy_from_slice(&val);
+        }
+    }
 
     #[test]
-    fn test_jwe_compact_serialization() -> Result<()> {
-        for enc in vec![
+    fn test_jwe_compact_serialization_with_fixed_rng() -> Result<()> {
+        // RFC 7516 Appendix A.3
+        let cek = vec![
+            4, 211, 31, 197, 84, 157, 252, 254, 11, 100, 157, 250, 63, 170, 106, 206, 107, 124,
+            212, 45, 111, 107, 9, 219, 200, 177, 0, 240, 143, 156, 44, 207,
+        ];
+        let iv = vec![
+            3, 22, 60, 12, 43, 67, 104, 105, 108, 108, 105, 99, 111, 116, 104, 101,
+        ];
+
+        let mut context = JweContext::new();
+        assert!(context.rng().is_none());
+        assert_eq!(context, JweContext::new());
+        context.set_rng(Arc::new(FixedRandom(Mutex::new(
+            vec![cek, iv].into_iter().collect(),
+        ))));
+        assert!(context.rng().is_some());
+        assert_ne!(context, JweContext::new());
+        assert_eq!(context.clone(), context);
+
+        let jwk = Jwk::from_bytes(br#"{"kty":"oct","k":"GawgguFyGrWKav7AX4VKUg"}"#)?;
+        let encrypter = A128KW.encrypter_from_jwk(&jwk)?;
+
+        let mut header = JweHeader::new();
+        header.set_algorithm("A128KW");
+        header.set_content_encryption("A128CBC-HS256");
+        let jwe = context.serialize_compact(b"Live long and prosper.", &header, &encrypter)?;
+
+        assert_eq!(
+            jwe,
+            concat!(
+                "eyJhbGciOiJBMTI4S1ciLCJlbmMiOiJBMTI4Q0JDLUhTMjU2In0.",
+                "6KB707dM9YTIgHtLvtgWQ8mKwboJW3of9locizkDTHzBC2IlrT1oOQ.",
+                "AxY8DCtDaGlsbGljb3RoZQ.",
+                "KDlTtXchhZTGufMYmOYGS4HffxPSUrfmqCHXaI9wOGY.",
+                "U0m_YmjN04DJvceFICbCVQ"
+            )
+        );
+
+        context.remove_rng();
+        assert_eq!(context, JweContext::new());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_jwe_pbes2_salt_with_fixed_rng() -> Result<()> {
+        let cek = util::random_bytes(32);
+        let iv = util::random_bytes(16);
+        let p2s = vec![0x5A; 8];
+
+        let mut context = JweContext::new();
+        context.set_rng(Arc::new(FixedRandom(Mutex::new(
+            vec![cek, p2s, iv].into_iter().collect(),
+        ))));
+
+        let encrypter = PBES2_HS256_A128KW.encrypter_from_bytes(b"password")?;
+        let mut header = JweHeader::new();
+        header.set_content_encryption("A128CBC-HS256");
+        let jwe = context.serialize_compact(b"test payload!", &header, &encrypter)?;
+
+        let decrypter = PBES2_HS256_A128KW.decrypter_from_bytes(b"password")?;
+        let (payload, header) = context.deserialize_compact(&jwe, &decrypter)?;
+        assert_eq!(payload, b"test payload!");
+        assert_eq!(
+            header.claim("p2s"),
+            Some(&Value::String("WlpaWlpaWlo".to_string()))
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn test_jwe_algorithm_from_str() -> Result<()> {
+        fn round_trip<T>(names: &[&str]) -> Result<()>
+        where
+            T: FromStr<Err = JoseError> + Display + PartialEq + Debug,
+        {
+            for name in names {
+                let parsed: T = name.parse()?;
+                assert_eq!(&parsed.to_string(), name);
+                assert_eq!(parsed.to_string().parse::<T>()?, parsed);
+            }
+            assert!(matches!(
+                "UNKNOWN".parse::<T>(),
+                Err(JoseError::InvalidJweFormat(_))
+            ));
+            Ok(())
+        }
+
+        round_trip::<DirectJweAlgorithm>(&["dir"])?;
+        round_trip::<EcdhEsJweAlgorithm>(&[
+            "ECDH-ES",
+            "ECDH-ES+A128KW",
+            "ECDH-ES+A192KW",
+            "ECDH-ES+A256KW",
+        ])?;
+        round_trip::<Ecdh1puJweAlgorithm>(&[
+            "ECDH-1PU",
+            "ECDH-1PU+A128KW",
+            "ECDH-1PU+A192KW",
+            "ECDH-1PU+A256KW",
+        ])?;
+        round_trip::<AeskwJweAlgorithm>(&["A128KW", "A192KW", "A256KW"])?;
+        round_trip::<AesgcmkwJweAlgorithm>(&["A128GCMKW", "A192GCMKW", "A256GCMKW"])?;
+        round_trip::<Pbes2HmacAeskwJweAlgorithm>(&[
+            "PBES2-HS256+A128KW",
+            "PBES2-HS384+A192KW",
+            "PBES2-HS512+A256KW",
+        ])?;
+        round_trip::<RsaesJweAlgorithm>(&[
+            "RSA1_5",
+            "RSA-OAEP",
+            "RSA-OAEP-256",
+            "RSA-OAEP-384",
+            "RSA-OAEP-512",
+        ])?;
+        round_trip::<AescbcHmacJweEncryption>(&[
             "A128CBC-HS256",
             "A192CBC-HS384",
             "A256CBC-HS512",
-            "A128GCM",
-            "A256GCM",
-            "A256GCM",
-        ] {
-            let mut src_header = JweHeader::new();
-            src_header.set_content_encryption(enc);
-            src_header.set_token_type("JWT");
-            let src_payload = b"test payload!";
+        ])?;
+        round_trip::<AesgcmJweEncryption>(&["A128GCM", "A192GCM", "A256GCM"])?;
+        round_trip::<Xchacha20Poly1305JweEncryption>(&["XC20P"])?;
 
-            //println!("{}", enc);
+        assert_eq!("RSA-OAEP".parse::<RsaesJweAlgorithm>()?, RSA_OAEP);
+        assert!("A128GCM".parse::<AeskwJweAlgorithm>().is_err());
 
-            let alg = Dir;
-            let key = match enc {
-                "A128CBC-HS256" => util::random_bytes(32),
-                "A192CBC-HS384" => util::random_bytes(48),
-                "A256CBC-HS512" => util::random_bytes(64),
-                "A128GCM" => util::random_bytes(16),
-                "A192GCM" => util::random_bytes(24),
-                "A256GCM" => util::random_bytes(32),
-                _ => unreachable!(),
-            };
-            let encrypter = alg.encrypter_from_bytes(&key)?;
-            let jwe = jwe::serialize_compact(src_payload, &src_header, &encrypter)?;
+        Ok(())
+    }
 
-            let decrypter = alg.decrypter_from_bytes(&key)?;
-            let (dst_payload, dst_header) = jwe::deserialize_compact(&jwe, &decrypter)?;
+    #[test]
+    fn test_jwe_content_encryption_lengths() -> Result<()> {
+        assert_eq!(A256CBC_HS512.cek_len(), 64);
+        assert_eq!(A256CBC_HS512.iv_len(), 16);
+        assert_eq!(A256CBC_HS512.tag_len(), 32);
 
-            src_header.set_claim("alg", Some(Value::String(alg.name().to_string())))?;
-            assert_eq!(src_header, dst_header);
-            assert_eq!(src_payload.to_vec(), dst_payload);
+        assert_eq!(A128GCM.cek_len(), 16);
+        assert_eq!(A128GCM.iv_len(), 12);
+        assert_eq!(A128GCM.tag_len(), 16);
+
+        let encs: [&dyn JweContentEncryption; 7] = [
+            &AescbcHmacJweEncryption::A128cbcHs256,
+            &AescbcHmacJweEncryption::A192cbcHs384,
+            &AescbcHmacJweEncryption::A256cbcHs512,
+            &AesgcmJweEncryption::A128gcm,
+            &AesgcmJweEncryption::A192gcm,
+            &AesgcmJweEncryption::A256gcm,
+            &Xchacha20Poly1305JweEncryption::Xc20p,
+        ];
+        for enc in encs {
+            let key = util::random_bytes(enc.cek_len());
+            let iv = util::random_bytes(enc.iv_len());
+            let (_, tag) = enc.encrypt(&key, Some(&iv), b"abcde12345", b"test")?;
+            assert_eq!(tag.map(|val| val.len()), Some(enc.tag_len()));
+
+            let mut header = JweHeader::new();
+            header.set_content_encryption(enc.name());
+            let encrypter = Dir.encrypter_from_bytes(&key)?;
+            let decrypter = Dir.decrypter_from_bytes(&key)?;
+            let jwe = jwe::serialize_compact(b"abcde12345", &header, &encrypter)?;
+            let (payload, _) = jwe::deserialize_compact(&jwe, &decrypter)?;
+            assert_eq!(payload, b"abcde12345");
         }
 
         Ok(())
     }
 
     #[test]
-    fn test_jwe_json_serialization() -> Result<()> {
-        let alg = RSA_OAEP;
+    fn test_jwe_compact_serialization_with_raw_header() -> Result<()> {
+        let key = util::random_bytes(16);
+        let encrypter = A128KW.encrypter_from_bytes(&key)?;
+        let decrypter = A128KW.decrypter_from_bytes(&key)?;
 
-        let private_key = load_file("pem/RSA_2048bit_private.pem")?;
-        let public_key = load_file("pem/RSA_2048bit_public.pem")?;
+        let raw_header = r#"{"typ":"JWT", "enc":"A128GCM", "alg":"A128KW"}"#;
+        let jwe = jwe::serialize_compact_with_raw_header(b"test payload!", raw_header, &encrypter)?;
+        let protected = jwe.split('.').next().unwrap();
+        assert_eq!(util::base64url_decode(protected)?, raw_header.as_bytes());
 
-        let src_payload = b"test payload!";
-        let mut src_header = JweHeaderSet::new();
-        src_header.set_key_id("xxx", true);
-        src_header.set_token_type("JWT", false);
-        let mut src_rheader = JweHeader::new();
-        src_rheader.set_content_encryption("A128GCM");
+        let (payload, header) = jwe::deserialize_compact(&jwe, &decrypter)?;
+        assert_eq!(payload, b"test payload!");
+        assert_eq!(header.token_type(), Some("JWT"));
 
-        let encrypter = alg.encrypter_from_pem(&public_key)?;
-        let jwt = jwe::serialize_flattened_json(
-            src_payload,
-            Some(&src_header),
-            Some(&src_rheader),
-            None,
-            &encrypter,
-        )?;
+        // The alg header claim cannot be added without re-serialization.
+        let raw_header = r#"{"enc":"A128GCM"}"#;
+        assert!(jwe::serialize_compact_with_raw_header(b"test", raw_header, &encrypter).is_err());
 
-        let decrypter = alg.decrypter_from_pem(&private_key)?;
-        let (dst_payload, dst_header) = jwe::deserialize_json(&jwt, &decrypter)?;
+        assert!(jwe::serialize_compact_with_raw_header(b"test", "[]", &encrypter).is_err());
 
-        src_header.set_algorithm(alg.name(), true);
-        assert_eq!(
-            src_rheader.content_encryption(),
-            dst_header.content_encryption()
-        );
-        assert_eq!(src_header.key_id(), dst_header.key_id());
-        assert_eq!(src_header.token_type(), dst_header.token_type());
-        assert_eq!(src_payload.to_vec(), dst_payload);
+        // PBES2 is usable when the header has the p2s and p2c header claims.
+        let encrypter = PBES2_HS256_A128KW.encrypter_from_bytes(b"password")?;
+        let decrypter = PBES2_HS256_A128KW.decrypter_from_bytes(b"password")?;
+        let raw_header =
+            r#"{"enc":"A128GCM","alg":"PBES2-HS256+A128KW","p2s":"AAECAwQFBgc","p2c":1000}"#;
+        let jwe = jwe::serialize_compact_with_raw_header(b"test payload!", raw_header, &encrypter)?;
+        let (payload, _) = jwe::deserialize_compact(&jwe, &decrypter)?;
+        assert_eq!(payload, b"test payload!");
+
+        // The encrypters that add random header claims are rejected even if the header
+        // has the claims.
+        let private_key = Jwk::from_bytes(&load_file("jwk/EC_P-256_private.jwk")?)?;
+        let public_key = private_key.to_public_key()?;
+        let encrypters: Vec<Box<dyn JweEncrypter>> = vec![
+            Box::new(ECDH_ES.encrypter_from_jwk(&public_key)?),
+            Box::new(ECDH_ES_A128KW.encrypter_from_jwk(&public_key)?),
+            Box::new(ECDH_1PU.encrypter_from_jwk(&private_key, &public_key)?),
+            Box::new(AesgcmkwJweAlgorithm::A128gcmkw.encrypter_from_bytes(&key)?),
+        ];
+        for encrypter in &encrypters {
+            let raw_header = format!(
+                r#"{{"enc":"A128GCM","alg":"{}","epk":{},"iv":"AAAAAAAAAAAAAAAA","tag":"AAAAAAAAAAAAAAAAAAAAAA"}}"#,
+                encrypter.algorithm().name(),
+                public_key.to_string()
+            );
+            match jwe::serialize_compact_with_raw_header(b"test", &raw_header, encrypter.as_ref()) {
+                Err(JoseError::InvalidJweFormat(err)) => {
+                    assert!(err.to_string().contains("cannot be used with a raw header"))
+                }
+                result => panic!("unexpected result: {:?}", result),
+            }
+        }
 
         Ok(())
     }
 
     #[test]
-    fn test_jwe_general_json_serialization() -> Result<()> {
-        let public_key_1 = load_file("pem/RSA_2048bit_public.pem")?;
-        let public_key_2 = load_file("der/EC_P-256_spki_public.der")?;
-        let public_key_3 = load_file("jwk/oct_128bit_private.jwk")?;
+    fn test_jwe_compact_serialization_owned() -> Result<()> {
+        let key = util::random_bytes(16);
+        let encrypter = A128KW.encrypter_from_bytes(&key)?;
+        let decrypter = A128KW.decrypter_from_bytes(&key)?;
+        let src_payload = b"test payload!".repeat(100);
 
-        let private_key = load_file("der/EC_P-256_pkcs8_private.der")?;
+        for zip in [None, Some("DEF")] {
+            let mut header = JweHeader::new();
+            header.set_content_encryption("A128GCM");
+            if let Some(zip) = zip {
+                header.set_compression(zip);
+            }
 
-        let src_payload = b"test payload!";
+            let cek = util::random_bytes(16);
+            let iv = util::random_bytes(12);
+            let mut context = JweContext::new();
+            context.set_rng(Arc::new(FixedRandom(Mutex::new(
+                vec![cek.clone(), iv.clone(), cek, iv].into_iter().collect(),
+            ))));
 
-        let mut src_header = JweHeaderSet::new();
-        src_header.set_content_encryption("A128CBC-HS256", true);
-        src_header.set_token_type("JWT-1", false);
+            let borrowed = context.serialize_compact(&src_payload, &header, &encrypter)?;
+            let owned =
+                context.serialize_compact_owned(src_payload.clone(), &header, &encrypter)?;
+            assert_eq!(owned, borrowed);
 
-        let mut src_rheader_1 = JweHeader::new();
-        src_rheader_1.set_key_id("xxx-1");
-        let encrypter_1 = RSA_OAEP.encrypter_from_pem(&public_key_1)?;
+            let (dst_payload, _) = context.deserialize_compact(&owned, &decrypter)?;
+            assert_eq!(dst_payload, src_payload);
+        }
 
-        let mut src_rheader_2 = JweHeader::new();
-        src_rheader_2.set_key_id("xxx-2");
-        let encrypter_2 = ECDH_ES_A128KW.encrypter_from_der(&public_key_2)?;
+        let mut header = JweHeader::new();
+        header.set_content_encryption("A128GCM");
+        let jwe = jwe::serialize_compact_owned(src_payload.clone(), &header, &encrypter)?;
+        let (dst_payload, _) = jwe::deserialize_compact(&jwe, &decrypter)?;
+        assert_eq!(dst_payload, src_payload);
 
-        let mut src_rheader_3 = JweHeader::new();
-        src_rheader_3.set_key_id("xxx-3");
-        let encrypter_3 =
-            PBES2_HS256_A128KW.encrypter_from_jwk(&Jwk::from_bytes(&public_key_3)?)?;
+        Ok(())
+    }
 
-        let json = jwe::serialize_general_json(
-            src_payload,
+    #[test]
+    fn test_jwe_flattened_json_serialization_with_aad() -> Result<()> {
+        let key = util::random_bytes(32);
+        let encrypter = Dir.encrypter_from_bytes(&key)?;
+        let decrypter = Dir.decrypter_from_bytes(&key)?;
+
+        let mut src_header = JweHeaderSet::new();
+        src_header.set_content_encryption("A128CBC-HS256", true);
+        let json = jwe::serialize_flattened_json(
+            b"test payload!",
             Some(&src_header),
-            &vec![
-                (Some(&src_rheader_1), &*encrypter_1),
-                (Some(&src_rheader_2), &*encrypter_2),
-                (Some(&src_rheader_3), &*encrypter_3),
-            ],
             None,
+            Some(b"additional data"),
+            &encrypter,
         )?;
 
-        let decrypter = ECDH_ES_A128KW.decrypter_from_der(&private_key)?;
-        let (dst_payload, dst_header) = jwe::deserialize_json(&json, &decrypter)?;
+        let (dst_payload, _) = jwe::deserialize_json(&json, &decrypter)?;
+        assert_eq!(dst_payload, b"test payload!");
 
-        assert_eq!(dst_header.algorithm(), Some("ECDH-ES+A128KW"));
-        assert_eq!(src_header.token_type(), dst_header.token_type());
-        assert_eq!(src_rheader_2.key_id(), dst_header.key_id());
-        assert_eq!(src_payload.to_vec(), dst_payload);
+        let mut map: Map<String, Value> = serde_json::from_str(&json)?;
+        let field = |map: &Map<String, Value>, key: &str| match map.get(key) {
+            Some(Value::String(val)) => val.to_string(),
+            _ => unreachable!(),
+        };
+        let decode = |val: String| base64::decode_config(val, base64::URL_SAFE_NO_PAD);
+        assert_eq!(decode(field(&map, "aad"))?, b"additional data");
+
+        let full_aad = format!("{}.{}", field(&map, "protected"), field(&map, "aad"));
+        let message = A128CBC_HS256.decrypt(
+            &key,
+            Some(&decode(field(&map, "iv"))?),
+            &decode(field(&map, "ciphertext"))?,
+            full_aad.as_bytes(),
+            Some(&decode(field(&map, "tag"))?),
+        )?;
+        assert_eq!(message, b"test payload!");
+
+        let tampered_aad = base64::encode_config(b"additional date", base64::URL_SAFE_NO_PAD);
+        map.insert("aad".to_string(), Value::String(tampered_aad));
+        let tampered = serde_json::to_string(&map)?;
+        assert!(jwe::deserialize_json(&tampered, &decrypter).is_err());
+
+        map.remove("aad");
+        let removed = serde_json::to_string(&map)?;
+        assert!(jwe::deserialize_json(&removed, &decrypter).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_jwe_compact_serialization_with_decrypters() -> Result<()> {
+        let jwk = |kid: Option<&str>| -> Result<Jwk> {
+            let mut jwk = Jwk::generate_oct_key(16)?;
+            if let Some(kid) = kid {
+                jwk.set_key_id(kid);
+            }
+            Ok(jwk)
+        };
+        let jwk_a = jwk(Some("a"))?;
+        let jwk_b = jwk(Some("b"))?;
+        let jwk_none = jwk(None)?;
+
+        let decrypter_a = A128KW.decrypter_from_jwk(&jwk_a)?;
+        let decrypter_b = A128KW.decrypter_from_jwk(&jwk_b)?;
+        let decrypter_none = A128KW.decrypter_from_jwk(&jwk_none)?;
+        let decrypter_other_alg = A256KW.decrypter_from_jwk(&{
+            let mut jwk = Jwk::generate_oct_key(32)?;
+            jwk.set_key_id("b");
+            jwk
+        })?;
+        assert_eq!(decrypter_a.key_id(), Some("a"));
+        assert_eq!(decrypter_none.key_id(), None);
+        let decrypters: Vec<&dyn JweDecrypter> = vec![
+            &decrypter_other_alg,
+            &decrypter_a,
+            &decrypter_b,
+            &decrypter_none,
+        ];
+
+        let mut header = JweHeader::new();
+        header.set_content_encryption("A128GCM");
+        for jwk in &[&jwk_a, &jwk_b, &jwk_none] {
+            let encrypter = A128KW.encrypter_from_jwk(jwk)?;
+            let jwe = jwe::serialize_compact(b"test payload!", &header, &encrypter)?;
+
+            let (payload, dst_header) =
+                jwe::deserialize_compact_with_decrypters(&jwe, &decrypters)?;
+            assert_eq!(payload, b"test payload!");
+            assert_eq!(dst_header.key_id(), jwk.key_id());
+        }
+
+        let unknown = jwk(Some("c"))?;
+        let encrypter = A128KW.encrypter_from_jwk(&unknown)?;
+        let jwe = jwe::serialize_compact(b"test payload!", &header, &encrypter)?;
+        assert!(jwe::deserialize_compact_with_decrypters(&jwe, &decrypters).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn test_jwe_with_accepted_algorithms() -> Result<()> {
+        let private_key = load_file("pem/RSA_2048bit_private.pem")?;
+        let public_key = load_file("pem/RSA_2048bit_public.pem")?;
+
+        let mut header = JweHeader::new();
+        header.set_content_encryption("A128GCM");
+
+        let rsa1_5_encrypter = RSA1_5.encrypter_from_pem_insecure(&public_key)?;
+        let rsa1_5_decrypter = RSA1_5.decrypter_from_pem_insecure(&private_key)?;
+        let rsa1_5_jwe = jwe::serialize_compact(b"test payload!", &header, &rsa1_5_encrypter)?;
+        let rsa1_5_json = jwe::serialize_flattened_json(
+            b"test payload!",
+            None,
+            Some(&header),
+            None,
+            &rsa1_5_encrypter,
+        )?;
+
+        let oaep_encrypter = RSA_OAEP.encrypter_from_pem(&public_key)?;
+        let oaep_decrypter = RSA_OAEP.decrypter_from_pem(&private_key)?;
+        let oaep_jwe = jwe::serialize_compact(b"test payload!", &header, &oaep_encrypter)?;
+
+        let mut context = JweContext::new();
+        assert!(context.is_accepted_algorithm("RSA1_5"));
+        context.deserialize_compact(&rsa1_5_jwe, &rsa1_5_decrypter)?;
+
+        context.set_accepted_algorithms(&["RSA-OAEP", "RSA-OAEP-256"]);
+        assert!(!context.is_accepted_algorithm("RSA1_5"));
+        assert!(matches!(
+            context.deserialize_compact(&rsa1_5_jwe, &rsa1_5_decrypter),
+            Err(JoseError::InvalidJweFormat(_))
+        ));
+        assert!(matches!(
+            context.deserialize_json(&rsa1_5_json, &rsa1_5_decrypter),
+            Err(JoseError::InvalidJweFormat(_))
+        ));
+        context.deserialize_compact(&oaep_jwe, &oaep_decrypter)?;
+
+        context.set_accepted_encryptions(&["A256GCM"]);
+        assert!(matches!(
+            context.deserialize_compact(&oaep_jwe, &oaep_decrypter),
+            Err(JoseError::InvalidJweFormat(_))
+        ));
+        context.set_accepted_encryptions(&["A128GCM", "A256GCM"]);
+        context.deserialize_compact(&oaep_jwe, &oaep_decrypter)?;
+
+        context.remove_accepted_algorithms();
+        context.remove_accepted_encryptions();
+        context.deserialize_compact(&rsa1_5_jwe, &rsa1_5_decrypter)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_jwe_compact_with_malformed_segments() -> Result<()> {
+        let key = util::random_bytes(16);
+        let encrypter = A128KW.encrypter_from_bytes(&key)?;
+        let decrypter = A128KW.decrypter_from_bytes(&key)?;
+
+        let mut header = JweHeader::new();
+        header.set_content_encryption("A128GCM");
+        let jwe = jwe::serialize_compact(b"test payload!", &header, &encrypter)?;
+        let parts: Vec<&str> = jwe.split('.').collect();
+        jwe::deserialize_compact(&jwe, &decrypter)?;
+
+        for input in [
+            String::new(),
+            parts[0].to_string(),
+            parts[..4].join("."),
+            format!("{}.", jwe),
+            format!("{}.{}", jwe, parts[4]),
+            format!(".{}", parts[1..].join(".")),
+            "....".to_string(),
+            ".....".to_string(),
+            format!("!.{}", parts[1..].join(".")),
+        ] {
+            assert!(matches!(
+                jwe::deserialize_compact(&input, &decrypter),
+                Err(JoseError::InvalidJweFormat(_))
+            ));
+        }
+
+        // The encrypted key, the IV and the tag must not be empty or too short.
+        for (index, value) in [(1, ""), (1, "AAAA"), (1, "AAAAAAAAAAA"), (2, ""), (4, "")] {
+            let mut parts = parts.clone();
+            parts[index] = value;
+            assert!(jwe::deserialize_compact(&parts.join("."), &decrypter).is_err());
+        }
+
+        header.set_content_encryption("A128CBC-HS256");
+        let jwe = jwe::serialize_compact(b"test payload!", &header, &encrypter)?;
+        let mut parts: Vec<&str> = jwe.split('.').collect();
+        parts[2] = "";
+        assert!(jwe::deserialize_compact(&parts.join("."), &decrypter).is_err());
+
+        let encrypter = PBES2_HS256_A128KW.encrypter_from_bytes(b"password")?;
+        let decrypter = PBES2_HS256_A128KW.decrypter_from_bytes(b"password")?;
+        let jwe = jwe::serialize_compact(b"test payload!", &header, &encrypter)?;
+        let mut parts: Vec<&str> = jwe.split('.').collect();
+        parts[1] = "AAAA";
+        assert!(jwe::deserialize_compact(&parts.join("."), &decrypter).is_err());
+
+        let private_key = load_file("jwk/EC_P-256_private.jwk")?;
+        let public_key = load_file("jwk/EC_P-256_public.jwk")?;
+        let encrypter = ECDH_ES_A128KW.encrypter_from_jwk(&Jwk::from_bytes(&public_key)?)?;
+        let decrypter = ECDH_ES_A128KW.decrypter_from_jwk(&Jwk::from_bytes(&private_key)?)?;
+        let jwe = jwe::serialize_compact(b"test payload!", &header, &encrypter)?;
+        let mut parts: Vec<&str> = jwe.split('.').collect();
+        parts[1] = "AAAA";
+        assert!(jwe::deserialize_compact(&parts.join("."), &decrypter).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_jwe_with_duplicate_header_claims() -> Result<()> {
+        let key = util::random_bytes(16);
+        let encrypter = A128KW.encrypter_from_bytes(&key)?;
+        let decrypter = A128KW.decrypter_from_bytes(&key)?;
+
+        let mut header = JweHeader::new();
+        header.set_content_encryption("A128GCM");
+        let jwe = jwe::serialize_compact(b"test payload!", &header, &encrypter)?;
+        let mut parts: Vec<&str> = jwe.split('.').collect();
+        let header_b64 = util::base64url_encode(r#"{"alg":"A128KW","alg":"dir","enc":"A128GCM"}"#);
+        parts[0] = &header_b64;
+
+        let mut header_set = JweHeaderSet::new();
+        header_set.set_content_encryption("A128GCM", true);
+        let json = jwe::serialize_flattened_json(
+            b"test payload!",
+            Some(&header_set),
+            None,
+            None,
+            &encrypter,
+        )?;
+        let json = json.replacen(
+            r#""encrypted_key""#,
+            r#""unprotected":{"a":1,"a":2},"encrypted_key""#,
+            1,
+        );
+        jwe::deserialize_json(&json.replacen(r#""a":1,"#, "", 1), &decrypter)?;
+
+        for result in [
+            jwe::deserialize_compact(&parts.join("."), &decrypter),
+            jwe::deserialize_json(&json, &decrypter),
+        ] {
+            match result {
+                Err(JoseError::InvalidJweFormat(err)) => {
+                    assert!(err.to_string().contains("A duplicate key exists"))
+                }
+                result => panic!("unexpected result: {:?}", result),
+            }
+        }
+        assert!(JweHeader::from_bytes(br#"{"enc":"A128GCM","enc":"A256GCM"}"#).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_jwe_deserialize_auto() -> Result<()> {
+        let key = util::random_bytes(16);
+        let encrypter = A128KW.encrypter_from_bytes(&key)?;
+        let decrypter = A128KW.decrypter_from_bytes(&key)?;
+
+        let mut header = JweHeader::new();
+        header.set_content_encryption("A128GCM");
+        let compact = jwe::serialize_compact(b"compact", &header, &encrypter)?;
+        let (payload, _) = jwe::deserialize_auto(&compact, &decrypter)?;
+        assert_eq!(payload, b"compact");
+
+        let mut header = JweHeaderSet::new();
+        header.set_content_encryption("A128GCM", true);
+        let json = jwe::serialize_flattened_json(b"json", Some(&header), None, None, &encrypter)?;
+        let (payload, _) = jwe::deserialize_auto(&json, &decrypter)?;
+        assert_eq!(payload, b"json");
+        let (payload, _) = jwe::deserialize_auto(format!(" \n{}", json), &decrypter)?;
+        assert_eq!(payload, b"json");
+
+        for input in ["", "not a token", "[1,2,3]", "{\"ciphertext\":"] {
+            assert!(matches!(
+                jwe::deserialize_auto(input, &decrypter),
+                Err(JoseError::InvalidJweFormat(_))
+            ));
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_jwe_json_serialization_with_aad() -> Result<()> {
+        let key = util::random_bytes(16);
+        let encrypter = A128KW.encrypter_from_bytes(&key)?;
+        let decrypter = A128KW.decrypter_from_bytes(&key)?;
+
+        let mut header = JweHeaderSet::new();
+        header.set_content_encryption("A128GCM", true);
+        let aad = b"context: \x00\xff";
+        let json = jwe::serialize_flattened_json(
+            b"test payload!",
+            Some(&header),
+            None,
+            Some(aad),
+            &encrypter,
+        )?;
+
+        let (payload, _, dst_aad) = jwe::deserialize_json_with_aad(&json, &decrypter)?;
+        assert_eq!(payload, b"test payload!");
+        assert_eq!(dst_aad.as_deref(), Some(&aad[..]));
+
+        let json =
+            jwe::serialize_flattened_json(b"test payload!", Some(&header), None, None, &encrypter)?;
+        let (_, _, dst_aad) = jwe::deserialize_json_with_aad(&json, &decrypter)?;
+        assert_eq!(dst_aad, None);
+
+        let mut value: Map<String, Value> = serde_json::from_str(&jwe::serialize_flattened_json(
+            b"test payload!",
+            Some(&header),
+            None,
+            Some(aad),
+            &encrypter,
+        )?)?;
+        value.insert(
+            "aad".to_string(),
+            Value::String(util::base64url_encode(b"forged")),
+        );
+        let json = serde_json::to_string(&value)?;
+        assert!(jwe::deserialize_json_with_aad(&json, &decrypter).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_jwe_compact_serialization() -> Result<()> {
+        for enc in vec![
+            "A128CBC-HS256",
+            "A192CBC-HS384",
+            "A256CBC-HS512",
+            "A128GCM",
+            "A192GCM",
+            "A256GCM",
+        ] {
+            let mut src_header = JweHeader::new();
+            src_header.set_content_encryption(enc);
+            src_header.set_token_type("JWT");
+            let src_payload = b"test payload!";
+
+            //println!("{}", enc);
+
+            let alg = Dir;
+            let key = match enc {
+                "A128CBC-HS256" => util::random_bytes(32),
+                "A192CBC-HS384" => util::random_bytes(48),
+                "A256CBC-HS512" => util::random_bytes(64),
+                "A128GCM" => util::random_bytes(16),
+                "A192GCM" => util::random_bytes(24),
+                "A256GCM" => util::random_bytes(32),
+                _ => unreachable!(),
+            };
+            let encrypter = alg.encrypter_from_bytes(&key)?;
+            let jwe = jwe::serialize_compact(src_payload, &src_header, &encrypter)?;
+
+            let decrypter = alg.decrypter_from_bytes(&key)?;
+            let (dst_payload, dst_header) = jwe::deserialize_compact(&jwe, &decrypter)?;
+
+            src_header.set_claim("alg", Some(Value::String(alg.name().to_string())))?;
+            assert_eq!(src_header, dst_header);
+            assert_eq!(src_payload.to_vec(), dst_payload);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_jwe_compact_serialization_with_custom_compression() -> Result<()> {
+        #[derive(Debug, Clone)]
+        struct ReverseCompression;
+
+        impl JweCompression for ReverseCompression {
+            fn name(&self) -> &str {
+                "BR"
+            }
+
+            fn compress(&self, message: &[u8]) -> Result<Vec<u8>, io::Error> {
+                Ok(message.iter().rev().cloned().collect())
+            }
+
+            fn decompress(&self, message: &[u8]) -> Result<Vec<u8>, io::Error> {
+                Ok(message.iter().rev().cloned().collect())
+            }
+
+            fn box_clone(&self) -> Box<dyn JweCompression> {
+                Box::new(self.clone())
+            }
+        }
+
+        let mut context = JweContext::new();
+        context.add_compression(Box::new(ReverseCompression));
+
+        let mut src_header = JweHeader::new();
+        src_header.set_content_encryption("A128GCM");
+        src_header.set_compression("BR");
+        let src_payload = b"test payload!";
+
+        let key = util::random_bytes(16);
+        let encrypter = Dir.encrypter_from_bytes(&key)?;
+        let jwe = context.serialize_compact(src_payload, &src_header, &encrypter)?;
+
+        let decrypter = Dir.decrypter_from_bytes(&key)?;
+        let (dst_payload, dst_header) = context.deserialize_compact(&jwe, &decrypter)?;
+        assert_eq!(dst_header.compression(), Some("BR"));
+        assert_eq!(src_payload.to_vec(), dst_payload);
+
+        assert!(jwe::deserialize_compact(&jwe, &decrypter).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_jwe_compact_serialization_with_gzip() -> Result<()> {
+        let mut context = JweContext::new();
+        context.add_compression(Box::new(zip::GZIP));
+
+        let src_payload = b"test payload! test payload! test payload!";
+        let key = util::random_bytes(16);
+        let encrypter = Dir.encrypter_from_bytes(&key)?;
+        let decrypter = Dir.decrypter_from_bytes(&key)?;
+
+        let compressed = zip::GZIP.compress(src_payload)?;
+        assert_eq!(&compressed[..2], &[0x1f, 0x8b]);
+        assert_eq!(zip::GZIP.decompress(&compressed)?, src_payload);
+
+        let mut src_header = JweHeader::new();
+        src_header.set_content_encryption("A128GCM");
+        src_header.set_compression("GZIP");
+        let jwe = context.serialize_compact(src_payload, &src_header, &encrypter)?;
+        let (dst_payload, dst_header) = context.deserialize_compact(&jwe, &decrypter)?;
+        assert_eq!(dst_header.compression(), Some("GZIP"));
+        assert_eq!(src_payload.to_vec(), dst_payload);
+
+        // GZIP is not registered by default.
+        assert!(jwe::deserialize_compact(&jwe, &decrypter).is_err());
+
+        src_header.set_compression("DEF");
+        let jwe = context.serialize_compact(src_payload, &src_header, &encrypter)?;
+        let (dst_payload, dst_header) = jwe::deserialize_compact(&jwe, &decrypter)?;
+        assert_eq!(dst_header.compression(), Some("DEF"));
+        assert_eq!(src_payload.to_vec(), dst_payload);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_jwe_compact_serialization_with_max_decompressed_len() -> Result<()> {
+        let mut context = JweContext::new();
+        context.add_compression(Box::new(zip::GZIP));
+        assert_eq!(context.max_decompressed_len(), 10 * 1024 * 1024);
+
+        let mut other = JweContext::new();
+        assert_eq!(other, JweContext::new());
+        other.set_max_decompressed_len(1000);
+        assert_ne!(other, JweContext::new());
+
+        let src_payload = vec![b'a'; 1024 * 1024];
+        let key = util::random_bytes(16);
+        let encrypter = Dir.encrypter_from_bytes(&key)?;
+        let decrypter = Dir.decrypter_from_bytes(&key)?;
+
+        for zip in ["DEF", "GZIP"] {
+            let mut src_header = JweHeader::new();
+            src_header.set_content_encryption("A128GCM");
+            src_header.set_compression(zip);
+            let jwe = context.serialize_compact(&src_payload, &src_header, &encrypter)?;
+            assert!(jwe.len() < 10 * 1024);
+
+            context.set_max_decompressed_len(src_payload.len());
+            let (dst_payload, _) = context.deserialize_compact(&jwe, &decrypter)?;
+            assert_eq!(src_payload, dst_payload);
+
+            context.set_max_decompressed_len(src_payload.len() - 1);
+            match context.deserialize_compact(&jwe, &decrypter) {
+                Err(JoseError::InvalidJweFormat(err)) => assert_eq!(
+                    err.to_string(),
+                    format!(
+                        "The decompressed payload exceeds {} bytes.",
+                        src_payload.len() - 1
+                    )
+                ),
+                result => panic!("unexpected result: {:?}", result),
+            }
+
+            context.set_max_decompressed_len(1000);
+            let json = context.serialize_flattened_json(
+                &src_payload,
+                Some(&{
+                    let mut header = JweHeaderSet::new();
+                    header.set_content_encryption("A128GCM", true);
+                    header.set_compression(zip);
+                    header
+                }),
+                None,
+                None,
+                &encrypter,
+            )?;
+            assert!(matches!(
+                context.deserialize_json(&json, &decrypter),
+                Err(JoseError::InvalidJweFormat(_))
+            ));
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_jwe_compact_serialization_with_critical() -> Result<()> {
+        let mut src_header = JweHeader::new();
+        src_header.set_content_encryption("A128GCM");
+        src_header.set_critical(&vec!["exp"]);
+        src_header.set_claim("exp", Some(Value::from(1234567890)))?;
+        let src_payload = b"test payload!";
+
+        let key = util::random_bytes(16);
+        let encrypter = Dir.encrypter_from_bytes(&key)?;
+        let decrypter = Dir.decrypter_from_bytes(&key)?;
+        let jwe = jwe::serialize_compact(src_payload, &src_header, &encrypter)?;
+
+        match jwe::deserialize_compact(&jwe, &decrypter) {
+            Err(JoseError::InvalidJweFormat(_)) => {}
+            result => panic!("unexpected result: {:?}", result),
+        }
+
+        let mut context = JweContext::new();
+        context.add_acceptable_critical("exp");
+        let (dst_payload, dst_header) = context.deserialize_compact(&jwe, &decrypter)?;
+        assert_eq!(dst_header.critical(), Some(vec!["exp"]));
+        assert_eq!(src_payload.to_vec(), dst_payload);
+
+        src_header.set_claim("exp", None)?;
+        let jwe = jwe::serialize_compact(src_payload, &src_header, &encrypter)?;
+        match context.deserialize_compact(&jwe, &decrypter) {
+            Err(JoseError::InvalidJweFormat(_)) => {}
+            result => panic!("unexpected result: {:?}", result),
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_jwe_compact_serialization_with_cek_out() -> Result<()> {
+        let mut src_header = JweHeader::new();
+        src_header.set_content_encryption("A256GCM");
+        let src_payload = b"test payload!";
+
+        let key = util::random_bytes(32);
+        let encrypter = Dir.encrypter_from_bytes(&key)?;
+        let (_, cek) = jwe::serialize_compact_with_cek_out(src_payload, &src_header, &encrypter)?;
+        assert_eq!(cek, key);
+
+        let public_key = load_file("pem/RSA_2048bit_public.pem")?;
+        let private_key = load_file("pem/RSA_2048bit_private.pem")?;
+        let oct_key = util::random_bytes(16);
+
+        let encrypters: Vec<(Box<dyn jwe::JweEncrypter>, Box<dyn jwe::JweDecrypter>)> = vec![
+            (
+                Box::new(RSA_OAEP.encrypter_from_pem(&public_key)?),
+                Box::new(RSA_OAEP.decrypter_from_pem(&private_key)?),
+            ),
+            (
+                Box::new(A128KW.encrypter_from_bytes(&oct_key)?),
+                Box::new(A128KW.decrypter_from_bytes(&oct_key)?),
+            ),
+        ];
+        for (encrypter, decrypter) in &encrypters {
+            let (jwe, cek) =
+                jwe::serialize_compact_with_cek_out(src_payload, &src_header, encrypter.as_ref())?;
+
+            let parts: Vec<&str> = jwe.split('.').collect();
+            let encrypted_key = base64::decode_config(parts[1], base64::URL_SAFE_NO_PAD)?;
+            let (_, dst_header) = jwe::deserialize_compact(&jwe, decrypter.as_ref())?;
+            let dst_key =
+                decrypter.decrypt(Some(&encrypted_key), &jwe::enc::A256GCM, &dst_header)?;
+            assert_eq!(cek, dst_key.as_ref());
+
+            let iv = base64::decode_config(parts[2], base64::URL_SAFE_NO_PAD)?;
+            let ciphertext = base64::decode_config(parts[3], base64::URL_SAFE_NO_PAD)?;
+            let tag = base64::decode_config(parts[4], base64::URL_SAFE_NO_PAD)?;
+            let dst_payload = jwe::enc::A256GCM.decrypt(
+                &cek,
+                Some(&iv),
+                &ciphertext,
+                parts[0].as_bytes(),
+                Some(&tag),
+            )?;
+            assert_eq!(src_payload.to_vec(), dst_payload);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_jwe_compact_serialization_with_dir_and_encrypted_key() -> Result<()> {
+        let mut src_header = JweHeader::new();
+        src_header.set_content_encryption("A128GCM");
+        let src_payload = b"test payload!";
+
+        let key = util::random_bytes(16);
+        let encrypter = Dir.encrypter_from_bytes(&key)?;
+        let jwe = jwe::serialize_compact(src_payload, &src_header, &encrypter)?;
+
+        let parts: Vec<&str> = jwe.split('.').collect();
+        assert_eq!(parts[1], "");
+
+        let stray_key = base64::encode_config(util::random_bytes(16), base64::URL_SAFE_NO_PAD);
+        let jwe = format!(
+            "{}.{}.{}.{}.{}",
+            parts[0], stray_key, parts[2], parts[3], parts[4]
+        );
+
+        let decrypter = Dir.decrypter_from_bytes(&key)?;
+        let result = jwe::deserialize_compact(&jwe, &decrypter);
+        assert!(matches!(result, Err(JoseError::InvalidJweFormat(_))));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_jwe_compact_serialization_with_jwk_set() -> Result<()> {
+        let mut rsa_key = Jwk::from_bytes(&load_file("jwk/RSA_private.jwk")?)?;
+        rsa_key.set_key_id("rsa-key");
+        let mut ec_key = Jwk::from_bytes(&load_file("jwk/EC_P-256_private.jwk")?)?;
+        ec_key.set_key_id("ec-key");
+        let mut oct_key_1 = Jwk::from_bytes(&load_file("jwk/oct_128bit_private.jwk")?)?;
+        oct_key_1.set_key_id("oct-key-1");
+        let mut oct_key_2 = Jwk::from_bytes(&load_file("jwk/oct_128bit_private.jwk")?)?;
+        oct_key_2.set_key_id("oct-key-2");
+        oct_key_2.set_parameter(
+            "k",
+            Some(Value::String("MDEyMzQ1Njc4OUFCQ0RFRw".to_string())),
+        )?;
+
+        let mut keys = Vec::new();
+        for jwk in &[&rsa_key, &ec_key, &oct_key_1, &oct_key_2] {
+            keys.push(Value::Object(jwk.as_ref().clone()));
+        }
+        let mut map = Map::new();
+        map.insert("keys".to_string(), Value::Array(keys));
+        let jwk_set = JwkSet::from_map(map)?;
+
+        let mut src_header = JweHeader::new();
+        src_header.set_content_encryption("A128GCM");
+        let src_payload = b"test payload!";
+
+        let encrypters: Vec<Box<dyn jwe::JweEncrypter>> = vec![
+            Box::new(RSA_OAEP.encrypter_from_jwk(&{
+                let mut jwk = rsa_key.to_public_key()?;
+                jwk.set_key_id("rsa-key");
+                jwk
+            })?),
+            Box::new(ECDH_ES.encrypter_from_jwk(&{
+                let mut jwk = ec_key.to_public_key()?;
+                jwk.set_key_id("ec-key");
+                jwk
+            })?),
+            Box::new(A128KW.encrypter_from_jwk(&oct_key_1)?),
+            Box::new(A128KW.encrypter_from_jwk(&oct_key_2)?),
+        ];
+        for (encrypter, key_id) in
+            encrypters
+                .iter()
+                .zip(&["rsa-key", "ec-key", "oct-key-1", "oct-key-2"])
+        {
+            let jwe = jwe::serialize_compact(src_payload, &src_header, encrypter.as_ref())?;
+
+            let (dst_payload, dst_header) = jwe::deserialize_compact_with_jwk_set(&jwe, &jwk_set)?;
+            assert_eq!(dst_header.key_id(), Some(*key_id));
+            assert_eq!(src_payload.to_vec(), dst_payload);
+        }
+
+        let mut map = Map::new();
+        map.insert(
+            "keys".to_string(),
+            Value::Array(vec![Value::Object(oct_key_1.as_ref().clone())]),
+        );
+        let jwk_set = JwkSet::from_map(map)?;
+        let jwe = jwe::serialize_compact(src_payload, &src_header, encrypters[3].as_ref())?;
+        let result = jwe::deserialize_compact_with_jwk_set(&jwe, &jwk_set);
+        assert!(matches!(result, Err(JoseError::InvalidJweFormat(_))));
+
+        #[allow(deprecated)]
+        let encrypter = jwe::RSA1_5.encrypter_from_jwk_insecure(&{
+            let mut jwk = rsa_key.to_public_key()?;
+            jwk.set_key_id("rsa-key");
+            jwk
+        })?;
+        let jwk_set = JwkSet::from_map({
+            let mut map = Map::new();
+            map.insert(
+                "keys".to_string(),
+                Value::Array(vec![Value::Object(rsa_key.as_ref().clone())]),
+            );
+            map
+        })?;
+        let jwe = jwe::serialize_compact(src_payload, &src_header, &encrypter)?;
+        let err = jwe::deserialize_compact_with_jwk_set(&jwe, &jwk_set).unwrap_err();
+        assert!(err.to_string().contains("RSA1_5 is disabled"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_jwe_compact_serialization_with_mismatched_header() -> Result<()> {
+        let mut src_header = JweHeader::new();
+        src_header.set_content_encryption("A128GCM");
+        let src_payload = b"test payload!";
+
+        let key = util::random_bytes(32);
+        let encrypter = A256KW.encrypter_from_bytes(&key)?;
+        let jwe = jwe::serialize_compact(src_payload, &src_header, &encrypter)?;
+
+        let decrypter = A128KW.decrypter_from_bytes(&key[..16])?;
+        match jwe::deserialize_compact(&jwe, &decrypter) {
+            Err(JoseError::InvalidJweFormat(err)) => {
+                assert_eq!(
+                    format!("{}", err),
+                    "The JWE alg header claim is not A128KW: A256KW"
+                );
+            }
+            result => panic!("unexpected result: {:?}", result),
+        }
+
+        let parts: Vec<&str> = jwe.split('.').collect();
+        let mut header =
+            JweHeader::from_bytes(&base64::decode_config(parts[0], base64::URL_SAFE_NO_PAD)?)?;
+        header.set_content_encryption("A256GCM");
+        let header_b64 = base64::encode_config(header.to_string(), base64::URL_SAFE_NO_PAD);
+        let jwe = format!(
+            "{}.{}.{}.{}.{}",
+            header_b64, parts[1], parts[2], parts[3], parts[4]
+        );
+
+        let decrypter = A256KW.decrypter_from_bytes(&key)?;
+        match jwe::deserialize_compact(&jwe, &decrypter) {
+            Err(JoseError::InvalidJweFormat(err)) => {
+                assert_eq!(format!("{}", err), "A256GCM expects 32-byte key, got 16");
+            }
+            result => panic!("unexpected result: {:?}", result),
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_jwe_recover_cek() -> Result<()> {
+        let public_key = load_file("pem/RSA_2048bit_public.pem")?;
+        let private_key = load_file("pem/RSA_2048bit_private.pem")?;
+
+        let mut src_header = JweHeader::new();
+        src_header.set_content_encryption("A128CBC-HS256");
+        let src_payload = b"test payload!";
+
+        let encrypter = RSA_OAEP.encrypter_from_pem(&public_key)?;
+        let (jwe, cek) = jwe::serialize_compact_with_cek_out(src_payload, &src_header, &encrypter)?;
+
+        let parts: Vec<&str> = jwe.split('.').collect();
+        let header =
+            JweHeader::from_bytes(&base64::decode_config(parts[0], base64::URL_SAFE_NO_PAD)?)?;
+        let encrypted_key = base64::decode_config(parts[1], base64::URL_SAFE_NO_PAD)?;
+
+        let decrypter = RSA_OAEP.decrypter_from_pem(&private_key)?;
+        let dst_cek = jwe::recover_cek(&header, &encrypted_key, &decrypter)?;
+        assert_eq!(cek, dst_cek);
+
+        let decrypter = RSA_OAEP_256.decrypter_from_pem(&private_key)?;
+        let result = jwe::recover_cek(&header, &encrypted_key, &decrypter);
+        assert!(matches!(result, Err(JoseError::InvalidJweFormat(_))));
+
+        // An unsupported critical header claim is rejected before the key is unwrapped.
+        let mut header = header.clone();
+        header.set_critical(&vec!["exp"]);
+        header.set_claim("exp", Some(Value::Number(0.into())))?;
+        let decrypter = RSA_OAEP.decrypter_from_pem(&private_key)?;
+        let result = jwe::recover_cek(&header, &encrypted_key, &decrypter);
+        assert!(matches!(result, Err(JoseError::InvalidJweFormat(_))));
+
+        let mut context = JweContext::new();
+        context.add_acceptable_critical("exp");
+        assert_eq!(
+            context.recover_cek(&header, &encrypted_key, &decrypter)?,
+            cek
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_jwe_compact_serialization_with_a192gcm() -> Result<()> {
+        let mut src_header = JweHeader::new();
+        src_header.set_content_encryption("A192GCM");
+        let src_payload = b"test payload!";
+
+        let encrypter = Dir.encrypter_from_bytes(util::random_bytes(16))?;
+        assert!(jwe::serialize_compact(src_payload, &src_header, &encrypter).is_err());
+
+        let private_key = load_file("pem/EC_P-256_private.pem")?;
+        let public_key = load_file("pem/EC_P-256_public.pem")?;
+        let encrypter = ECDH_ES.encrypter_from_pem(&public_key)?;
+        let (jwe, cek) = jwe::serialize_compact_with_cek_out(src_payload, &src_header, &encrypter)?;
+        assert_eq!(cek.len(), 24);
+
+        let decrypter = ECDH_ES.decrypter_from_pem(&private_key)?;
+        let (dst_payload, dst_header) = jwe::deserialize_compact(&jwe, &decrypter)?;
+        assert_eq!(dst_header.content_encryption(), Some("A192GCM"));
+        assert_eq!(src_payload.to_vec(), dst_payload);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_jwe_compact_serialization_with_secp256k1() -> Result<()> {
+        let mut src_header = JweHeader::new();
+        src_header.set_content_encryption("A256GCM");
+        let src_payload = b"test payload!";
+
+        let key_pair = ECDH_ES_A256KW.generate_ec_key_pair(EcCurve::Secp256k1)?;
+        let encrypter = ECDH_ES_A256KW.encrypter_from_jwk(&key_pair.to_jwk_public_key())?;
+        let jwe = jwe::serialize_compact(src_payload, &src_header, &encrypter)?;
+
+        let decrypter = ECDH_ES_A256KW.decrypter_from_jwk(&key_pair.to_jwk_private_key())?;
+        let (dst_payload, dst_header) = jwe::deserialize_compact(&jwe, &decrypter)?;
+        assert_eq!(dst_header.algorithm(), Some("ECDH-ES+A256KW"));
+        assert_eq!(
+            dst_header.claim("epk").and_then(|epk| epk.get("crv")),
+            Some(&Value::String("secp256k1".to_string()))
+        );
+        assert_eq!(src_payload.to_vec(), dst_payload);
+
+        let private_key = load_file("jwk/EC_P-256_private.jwk")?;
+        let decrypter = ECDH_ES_A256KW.decrypter_from_jwk(&Jwk::from_bytes(&private_key)?)?;
+        match jwe::deserialize_compact(&jwe, &decrypter) {
+            Err(JoseError::InvalidJweFormat(err)) => assert_eq!(
+                err.to_string(),
+                "The crv parameter in epk header claim is invalid: secp256k1"
+            ),
+            result => panic!("unexpected result: {:?}", result),
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_jwe_estimate_compact_len() -> Result<()> {
+        let ec_public_key = load_file("jwk/EC_P-256_public.jwk")?;
+        let rsa_public_key = load_file("pem/RSA_2048bit_public.pem")?;
+        let encrypters: Vec<(&str, Box<dyn JweEncrypter>)> = vec![
+            (
+                "A256GCM",
+                Box::new(Dir.encrypter_from_bytes(util::random_bytes(32))?),
+            ),
+            (
+                "A128GCM",
+                Box::new(A128KW.encrypter_from_bytes(util::random_bytes(16))?),
+            ),
+            (
+                "A128CBC-HS256",
+                Box::new(ECDH_ES.encrypter_from_jwk(&Jwk::from_bytes(&ec_public_key)?)?),
+            ),
+            (
+                "A256CBC-HS512",
+                Box::new(RSA_OAEP.encrypter_from_pem(&rsa_public_key)?),
+            ),
+            (
+                "XC20P",
+                Box::new(PBES2_HS256_A128KW.encrypter_from_bytes(b"password")?),
+            ),
+            (
+                "A192GCM",
+                Box::new(
+                    AesgcmkwJweAlgorithm::A128gcmkw.encrypter_from_bytes(util::random_bytes(16))?,
+                ),
+            ),
+            (
+                "A128GCM",
+                Box::new({
+                    let jwk = Jwk::from_bytes(&load_file("jwk/OKP_X25519_public.jwk")?)?;
+                    let mut encrypter = ECDH_ES_A128KW.encrypter_from_jwk(&jwk)?;
+                    encrypter.set_agreement_partyuinfo("Alice");
+                    encrypter.set_agreement_partyvinfo("Bob");
+                    encrypter
+                }),
+            ),
+            (
+                "A256GCM",
+                Box::new(Ecdh1puJweAlgorithm::Ecdh1pu.encrypter_from_jwk(
+                    &Jwk::from_bytes(&load_file("jwk/EC_P-256_private.jwk")?)?,
+                    &Jwk::from_bytes(&ec_public_key)?,
+                )?),
+            ),
+            (
+                "A128CBC-HS256",
+                Box::new(ECDH_1PU_A128KW.encrypter_from_jwk(
+                    &Jwk::from_bytes(&load_file("jwk/EC_P-256_private.jwk")?)?,
+                    &Jwk::from_bytes(&ec_public_key)?,
+                )?),
+            ),
+        ];
+
+        // The estimation doesn't use the random generator.
+        let mut context = JweContext::new();
+        context.set_rng(Arc::new(FixedRandom(Mutex::new(VecDeque::new()))));
+
+        for (enc, encrypter) in &encrypters {
+            let mut header = JweHeader::new();
+            header.set_content_encryption(*enc);
+            header.set_key_id("kid");
+
+            for payload_len in [0, 1, 15, 16, 17, 1000] {
+                let payload = util::random_bytes(payload_len);
+                let estimated =
+                    context.estimate_compact_len(payload_len, &header, encrypter.as_ref())?;
+                let jwe = jwe::serialize_compact(&payload, &header, encrypter.as_ref())?;
+                assert_eq!(estimated, jwe.len(), "{}", enc);
+                assert_eq!(
+                    jwe::estimate_compact_len(payload_len, &header, encrypter.as_ref())?,
+                    estimated
+                );
+            }
+        }
+
+        let mut header = JweHeader::new();
+        header.set_content_encryption("A128GCM");
+        header.set_compression("DEF");
+        let encrypter = A128KW.encrypter_from_bytes(util::random_bytes(16))?;
+        assert!(jwe::estimate_compact_len(16, &header, &encrypter).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_jwe_compact_serialization_with_xc20p() -> Result<()> {
+        let mut src_header = JweHeader::new();
+        src_header.set_content_encryption("XC20P");
+        let src_payload = b"test payload!";
+
+        let key = util::random_bytes(32);
+        let encrypter = Dir.encrypter_from_bytes(&key)?;
+        let jwe = jwe::serialize_compact(src_payload, &src_header, &encrypter)?;
+
+        let decrypter = Dir.decrypter_from_bytes(&key)?;
+        let (dst_payload, dst_header) = jwe::deserialize_compact(&jwe, &decrypter)?;
+
+        src_header.set_claim("alg", Some(Value::String(Dir.name().to_string())))?;
+        assert_eq!(src_header, dst_header);
+        assert_eq!(src_payload.to_vec(), dst_payload);
+
+        let mut parts: Vec<&str> = jwe.split('.').collect();
+        let mut tag = base64::decode_config(parts[4], base64::URL_SAFE_NO_PAD)?;
+        tag[0] ^= 0x01;
+        let tag = base64::encode_config(&tag, base64::URL_SAFE_NO_PAD);
+        parts[4] = &tag;
+        let tampered = parts.join(".");
+
+        match jwe::deserialize_compact(&tampered, &decrypter) {
+            Err(JoseError::InvalidJweFormat(_)) => {}
+            result => panic!("unexpected result: {:?}", result),
+        }
+
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_jwe_decrypt_stream() -> Result<()> {
+        use std::io::Write;
+        use std::os::unix::net::UnixStream;
+        use std::thread;
+
+        let src_payload = util::random_bytes(10 * 1024 * 1024);
+
+        for enc in &["A128CBC-HS256", "A256GCM"] {
+            let mut src_header = JweHeader::new();
+            src_header.set_content_encryption(*enc);
+
+            let key = util::random_bytes(32);
+            let encrypter = Dir.encrypter_from_bytes(&key)?;
+            let jwe = jwe::serialize_compact(&src_payload, &src_header, &encrypter)?;
+
+            let parts: Vec<&str> = jwe.split('.').collect();
+            let header = parts[0];
+            let iv = base64::decode_config(parts[2], base64::URL_SAFE_NO_PAD)?;
+            let ciphertext = base64::decode_config(parts[3], base64::URL_SAFE_NO_PAD)?;
+            let tag = base64::decode_config(parts[4], base64::URL_SAFE_NO_PAD)?;
+
+            let (mut tx, rx) = UnixStream::pair()?;
+            let sender = thread::spawn(move || tx.write_all(&ciphertext));
+
+            let mut dst_payload = Vec::new();
+            let written =
+                jwe::decrypt_stream(header, &key, Some(&iv), Some(&tag), rx, &mut dst_payload)?;
+            sender.join().unwrap()?;
+
+            assert_eq!(written, src_payload.len() as u64);
+            assert_eq!(src_payload, dst_payload);
+
+            let mut dst_payload = Vec::new();
+            jwe::decrypt_stream(
+                header,
+                &key,
+                Some(&iv),
+                Some(&tag),
+                util::Base64UrlDecoder::new(parts[3]),
+                &mut dst_payload,
+            )?;
+            assert_eq!(src_payload, dst_payload);
+
+            let mut invalid_tag = tag.clone();
+            invalid_tag[0] ^= 0x01;
+            match jwe::decrypt_stream(
+                header,
+                &key,
+                Some(&iv),
+                Some(&invalid_tag),
+                util::Base64UrlDecoder::new(parts[3]),
+                std::io::sink(),
+            ) {
+                Err(JoseError::InvalidJweFormat(_)) => {}
+                result => panic!("unexpected result: {:?}", result),
+            }
+        }
+
+        // The header is authenticated as it is, even if it is not serialized as josekit does.
+        let key = util::random_bytes(16);
+        let header_b64 = base64::encode_config(
+            br#"{ "enc": "A128GCM", "alg": "dir" }"#,
+            base64::URL_SAFE_NO_PAD,
+        );
+        let iv = util::random_bytes(12);
+        let (ciphertext, tag) = AesgcmJweEncryption::A128gcm.encrypt(
+            &key,
+            Some(&iv),
+            b"test payload!",
+            header_b64.as_bytes(),
+        )?;
+        let mut dst_payload = Vec::new();
+        jwe::decrypt_stream(
+            &header_b64,
+            &key,
+            Some(&iv),
+            tag.as_deref(),
+            ciphertext.as_slice(),
+            &mut dst_payload,
+        )?;
+        assert_eq!(dst_payload, b"test payload!");
+
+        let mut context = JweContext::new();
+        context.set_accepted_encryptions(&["A256GCM"]);
+        match context.decrypt_stream(
+            &header_b64,
+            &key,
+            Some(&iv),
+            tag.as_deref(),
+            ciphertext.as_slice(),
+            std::io::sink(),
+        ) {
+            Err(JoseError::InvalidJweFormat(_)) => {}
+            result => panic!("unexpected result: {:?}", result),
+        }
+
+        context.set_accepted_encryptions(&["A128GCM"]);
+        context.set_accepted_algorithms(&["A128KW"]);
+        match context.decrypt_stream(
+            &header_b64,
+            &key,
+            Some(&iv),
+            tag.as_deref(),
+            ciphertext.as_slice(),
+            std::io::sink(),
+        ) {
+            Err(JoseError::InvalidJweFormat(_)) => {}
+            result => panic!("unexpected result: {:?}", result),
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_jwe_json_serialization() -> Result<()> {
+        let alg = RSA_OAEP;
+
+        let private_key = load_file("pem/RSA_2048bit_private.pem")?;
+        let public_key = load_file("pem/RSA_2048bit_public.pem")?;
+
+        let src_payload = b"test payload!";
+        let mut src_header = JweHeaderSet::new();
+        src_header.set_key_id("xxx", true);
+        src_header.set_token_type("JWT", false);
+        let mut src_rheader = JweHeader::new();
+        src_rheader.set_content_encryption("A128GCM");
+
+        let encrypter = alg.encrypter_from_pem(&public_key)?;
+        let jwt = jwe::serialize_flattened_json(
+            src_payload,
+            Some(&src_header),
+            Some(&src_rheader),
+            None,
+            &encrypter,
+        )?;
+
+        let decrypter = alg.decrypter_from_pem(&private_key)?;
+        let (dst_payload, dst_header) = jwe::deserialize_json(&jwt, &decrypter)?;
+
+        src_header.set_algorithm(alg.name(), true);
+        assert_eq!(
+            src_rheader.content_encryption(),
+            dst_header.content_encryption()
+        );
+        assert_eq!(src_header.key_id(), dst_header.key_id());
+        assert_eq!(src_header.token_type(), dst_header.token_type());
+        assert_eq!(src_payload.to_vec(), dst_payload);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_jwe_json_serialization_with_compression() -> Result<()> {
+        let alg = RSA_OAEP;
+
+        let private_key = load_file("pem/RSA_2048bit_private.pem")?;
+        let public_key = load_file("pem/RSA_2048bit_public.pem")?;
+
+        let src_payload = "test payload!".repeat(100);
+        let mut src_header = JweHeaderSet::new();
+        src_header.set_content_encryption("A128GCM", true);
+        src_header.set_compression("DEF");
+
+        let encrypter = alg.encrypter_from_pem(&public_key)?;
+        let json = jwe::serialize_flattened_json(
+            src_payload.as_bytes(),
+            Some(&src_header),
+            None,
+            None,
+            &encrypter,
+        )?;
+
+        let map: Map<String, Value> = serde_json::from_str(&json)?;
+        let ciphertext = match map.get("ciphertext") {
+            Some(Value::String(val)) => base64::decode_config(val, base64::URL_SAFE_NO_PAD)?,
+            _ => unreachable!(),
+        };
+        assert!(ciphertext.len() < src_payload.len());
+
+        let decrypter = alg.decrypter_from_pem(&private_key)?;
+        let (dst_payload, dst_header) = jwe::deserialize_json(&json, &decrypter)?;
+        assert_eq!(dst_header.compression(), Some("DEF"));
+        assert_eq!(src_payload.as_bytes(), dst_payload.as_slice());
+
+        let mut src_header = JweHeaderSet::new();
+        src_header.set_content_encryption("A128GCM", true);
+        let mut src_rheader = JweHeader::new();
+        src_rheader.set_compression("DEF");
+        let result = jwe::serialize_flattened_json(
+            src_payload.as_bytes(),
+            Some(&src_header),
+            Some(&src_rheader),
+            None,
+            &encrypter,
+        );
+        assert!(matches!(result, Err(JoseError::InvalidJweFormat(_))));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_jwe_general_json_serialization() -> Result<()> {
+        let public_key_1 = load_file("pem/RSA_2048bit_public.pem")?;
+        let public_key_2 = load_file("der/EC_P-256_spki_public.der")?;
+        let public_key_3 = load_file("jwk/oct_128bit_private.jwk")?;
+
+        let private_key = load_file("der/EC_P-256_pkcs8_private.der")?;
+
+        let src_payload = b"test payload!";
+
+        let mut src_header = JweHeaderSet::new();
+        src_header.set_content_encryption("A128CBC-HS256", true);
+        src_header.set_token_type("JWT-1", false);
+
+        let mut src_rheader_1 = JweHeader::new();
+        src_rheader_1.set_key_id("xxx-1");
+        let encrypter_1 = RSA_OAEP.encrypter_from_pem(&public_key_1)?;
+
+        let mut src_rheader_2 = JweHeader::new();
+        src_rheader_2.set_key_id("xxx-2");
+        let encrypter_2 = ECDH_ES_A128KW.encrypter_from_der(&public_key_2)?;
+
+        let mut src_rheader_3 = JweHeader::new();
+        src_rheader_3.set_key_id("xxx-3");
+        let encrypter_3 =
+            PBES2_HS256_A128KW.encrypter_from_jwk(&Jwk::from_bytes(&public_key_3)?)?;
+
+        let json = jwe::serialize_general_json(
+            src_payload,
+            Some(&src_header),
+            &vec![
+                (Some(&src_rheader_1), &*encrypter_1),
+                (Some(&src_rheader_2), &*encrypter_2),
+                (Some(&src_rheader_3), &*encrypter_3),
+            ],
+            None,
+        )?;
+
+        let decrypter = ECDH_ES_A128KW.decrypter_from_der(&private_key)?;
+        let (dst_payload, dst_header) = jwe::deserialize_json(&json, &decrypter)?;
+
+        assert_eq!(dst_header.algorithm(), Some("ECDH-ES+A128KW"));
+        assert_eq!(src_header.token_type(), dst_header.token_type());
+        assert_eq!(src_rheader_2.key_id(), dst_header.key_id());
+        assert_eq!(src_payload.to_vec(), dst_payload);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_jwe_json_serialization_with_duplicate_header() -> Result<()> {
+        let key = util::random_bytes(16);
+
+        let mut src_header = JweHeaderSet::new();
+        src_header.set_content_encryption("A128GCM", true);
+        src_header.set_token_type("JWT", false);
+
+        let encrypter = A128KW.encrypter_from_bytes(&key)?;
+        let json = jwe::serialize_flattened_json(
+            b"test payload!",
+            Some(&src_header),
+            None,
+            None,
+            &encrypter,
+        )?;
+
+        let decrypter = A128KW.decrypter_from_bytes(&key)?;
+        jwe::deserialize_json(&json, &decrypter)?;
+
+        let mut map: Map<String, Value> = serde_json::from_str(&json)?;
+        map.insert("header".to_string(), serde_json::json!({ "typ": "JOSE" }));
+        let json = serde_json::to_string(&map)?;
+        match jwe::deserialize_json(&json, &decrypter) {
+            Err(JoseError::InvalidJweFormat(err)) => {
+                assert_eq!(err.to_string(), "A duplicate key exists: typ")
+            }
+            result => panic!("unexpected result: {:?}", result),
+        }
+
+        let context = JweContext::new();
+        let protected = serde_json::json!({ "alg": "A128KW", "enc": "A128GCM" });
+        let unprotected = serde_json::json!({ "typ": "JWT" });
+        let recipient = serde_json::json!({ "kid": "key-1" });
+        let header = context.merge_header(
+            protected.as_object(),
+            unprotected.as_object(),
+            recipient.as_object(),
+        )?;
+        assert_eq!(header.algorithm(), Some("A128KW"));
+        assert_eq!(header.token_type(), Some("JWT"));
+        assert_eq!(header.key_id(), Some("key-1"));
+
+        let recipient = serde_json::json!({ "enc": "A256GCM" });
+        assert!(context
+            .merge_header(protected.as_object(), None, recipient.as_object())
+            .is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_jwe_general_json_serialization_with_single_recipient() -> Result<()> {
+        let public_key = load_file("pem/RSA_2048bit_public.pem")?;
+        let private_key = load_file("pem/RSA_2048bit_private.pem")?;
+
+        let src_payload = b"test payload!";
+
+        let mut src_header = JweHeaderSet::new();
+        src_header.set_content_encryption("A128GCM", true);
+
+        let mut src_rheader = JweHeader::new();
+        src_rheader.set_key_id("xxx-1");
+        let encrypter = RSA_OAEP.encrypter_from_pem(&public_key)?;
+
+        let json = jwe::serialize_general_json(
+            src_payload,
+            Some(&src_header),
+            &[(Some(&src_rheader), &*encrypter)],
+            None,
+        )?;
+
+        let decrypter = RSA_OAEP.decrypter_from_pem(&private_key)?;
+        let (dst_payload, dst_header) = jwe::deserialize_json(&json, &decrypter)?;
+
+        assert_eq!(dst_header.algorithm(), Some("RSA-OAEP"));
+        assert_eq!(src_rheader.key_id(), dst_header.key_id());
+        assert_eq!(src_payload.to_vec(), dst_payload);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_jwe_general_json_serialization_with_selector() -> Result<()> {
+        let public_key_1 = load_file("pem/RSA_2048bit_public.pem")?;
+        let public_key_2 = load_file("der/EC_P-256_spki_public.der")?;
+        let private_key_2 = load_file("der/EC_P-256_pkcs8_private.der")?;
+
+        let src_payload = b"test payload!";
+
+        let mut src_header = JweHeaderSet::new();
+        src_header.set_content_encryption("A128CBC-HS256", true);
+
+        let mut src_rheader_1 = JweHeader::new();
+        src_rheader_1.set_key_id("xxx-1");
+        let encrypter_1 = RSA_OAEP.encrypter_from_pem(&public_key_1)?;
+
+        let mut src_rheader_2 = JweHeader::new();
+        src_rheader_2.set_key_id("xxx-2");
+        let encrypter_2 = ECDH_ES_A128KW.encrypter_from_der(&public_key_2)?;
+
+        let json = jwe::serialize_general_json(
+            src_payload,
+            Some(&src_header),
+            &[
+                (Some(&src_rheader_1), &*encrypter_1),
+                (Some(&src_rheader_2), &*encrypter_2),
+            ],
+            None,
+        )?;
+
+        let decrypter = ECDH_ES_A128KW.decrypter_from_der(&private_key_2)?;
+        let (dst_payload, dst_header) =
+            jwe::deserialize_json_with_selector(&json, |header| match header.key_id() {
+                Some("xxx-2") => Ok(Some(&*decrypter)),
+                _ => Ok(None),
+            })?;
+
+        assert_eq!(src_rheader_2.key_id(), dst_header.key_id());
+        assert_eq!(src_payload.to_vec(), dst_payload);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_jwe_general_json_serialization_with_recipient_index() -> Result<()> {
+        let public_key = load_file("pem/RSA_2048bit_public.pem")?;
+        let private_key = load_file("pem/RSA_2048bit_private.pem")?;
+
+        let src_payload = b"test payload!";
+
+        let mut src_header = JweHeaderSet::new();
+        src_header.set_content_encryption("A128GCM", true);
+
+        let encrypter = RSA_OAEP.encrypter_from_pem(&public_key)?;
+        let mut src_rheaders = Vec::new();
+        for kid in &["tenant-1", "tenant-2", "tenant-3"] {
+            let mut src_rheader = JweHeader::new();
+            src_rheader.set_key_id(*kid);
+            src_rheaders.push(src_rheader);
+        }
+
+        let json = jwe::serialize_general_json(
+            src_payload,
+            Some(&src_header),
+            &[
+                (Some(&src_rheaders[0]), &*encrypter),
+                (Some(&src_rheaders[1]), &*encrypter),
+                (Some(&src_rheaders[2]), &*encrypter),
+            ],
+            None,
+        )?;
+
+        let decrypter = RSA_OAEP.decrypter_from_pem(&private_key)?;
+        let (dst_payload, dst_header, index) =
+            jwe::deserialize_general_json_with_selector(&json, |header| match header.key_id() {
+                Some("tenant-2") => Ok(Some(&*decrypter)),
+                _ => Ok(None),
+            })?;
+
+        assert_eq!(index, 1);
+        assert_eq!(dst_header.key_id(), Some("tenant-2"));
+        assert_eq!(src_payload.to_vec(), dst_payload);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_jwe_general_json_serialization_with_unmatched_recipients() -> Result<()> {
+        let public_key = load_file("pem/RSA_2048bit_public.pem")?;
+        let private_key = load_file("pem/RSA_2048bit_private.pem")?;
+
+        let src_payload = b"test payload!";
+
+        let mut src_header = JweHeaderSet::new();
+        src_header.set_content_encryption("A128GCM", true);
+
+        let mut src_rheader_1 = JweHeader::new();
+        src_rheader_1.set_key_id("xxx-1");
+        let mut src_rheader_2 = JweHeader::new();
+        src_rheader_2.set_key_id("xxx-2");
+        let encrypter = RSA_OAEP.encrypter_from_pem(&public_key)?;
+
+        let json = jwe::serialize_general_json(
+            src_payload,
+            Some(&src_header),
+            &[
+                (Some(&src_rheader_1), &*encrypter),
+                (Some(&src_rheader_2), &*encrypter),
+            ],
+            None,
+        )?;
+
+        let mut decrypter = RSA_OAEP.decrypter_from_pem(&private_key)?;
+        decrypter.set_key_id("xxx-3");
+        let result = jwe::deserialize_json(&json, &decrypter);
+        assert!(matches!(result, Err(JoseError::InvalidJweFormat(_))));
 
         Ok(())
     }
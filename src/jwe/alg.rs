@@ -1,6 +1,7 @@
 pub mod aesgcmkw;
 pub mod aeskw;
 pub mod direct;
+pub mod ecdh_1pu;
 pub mod ecdh_es;
 pub mod pbes2_hmac_aeskw;
 pub mod rsaes;
@@ -1,9 +1,11 @@
 use std::borrow::Cow;
 use std::fmt::Display;
 use std::ops::Deref;
+use std::str::FromStr;
 
 use anyhow::bail;
 use openssl::symm::{self, Cipher};
+use zeroize::Zeroize;
 
 use crate::jwe::{JweAlgorithm, JweContentEncryption, JweDecrypter, JweEncrypter, JweHeader};
 use crate::jwk::Jwk;
@@ -30,7 +32,8 @@ impl AesgcmkwJweAlgorithm {
 
             if private_key.len() != self.key_len() {
                 bail!(
-                    "The key size must be {}: {}",
+                    "{} expects {}-byte key, got {}",
+                    self.name(),
                     self.key_len(),
                     private_key.len()
                 );
@@ -71,7 +74,12 @@ impl AesgcmkwJweAlgorithm {
             };
 
             if k.len() != self.key_len() {
-                bail!("The key size must be {}: {}", self.key_len(), k.len());
+                bail!(
+                    "{} expects {}-byte key, got {}",
+                    self.name(),
+                    self.key_len(),
+                    k.len()
+                );
             }
 
             let key_id = jwk.key_id().map(|val| val.to_string());
@@ -94,7 +102,8 @@ impl AesgcmkwJweAlgorithm {
 
             if private_key.len() != self.key_len() {
                 bail!(
-                    "The key size must be {}: {}",
+                    "{} expects {}-byte key, got {}",
+                    self.name(),
                     self.key_len(),
                     private_key.len()
                 );
@@ -136,7 +145,12 @@ impl AesgcmkwJweAlgorithm {
             };
 
             if k.len() != self.key_len() {
-                bail!("The key size must be {}: {}", self.key_len(), k.len());
+                bail!(
+                    "{} expects {}-byte key, got {}",
+                    self.name(),
+                    self.key_len(),
+                    k.len()
+                );
             }
 
             let key_id = jwk.key_id().map(|val| val.to_string());
@@ -187,6 +201,22 @@ impl Display for AesgcmkwJweAlgorithm {
     }
 }
 
+impl FromStr for AesgcmkwJweAlgorithm {
+    type Err = JoseError;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "A128GCMKW" => Ok(Self::A128gcmkw),
+            "A192GCMKW" => Ok(Self::A192gcmkw),
+            "A256GCMKW" => Ok(Self::A256gcmkw),
+            _ => Err(JoseError::InvalidJweFormat(anyhow::anyhow!(
+                "Unknown algorithm: {}",
+                name
+            ))),
+        }
+    }
+}
+
 impl Deref for AesgcmkwJweAlgorithm {
     type Target = dyn JweAlgorithm;
 
@@ -261,11 +291,32 @@ impl JweEncrypter for AesgcmkwJweEncrypter {
         })
     }
 
+    fn estimate_encrypted_key_len(
+        &self,
+        cencryption: &dyn JweContentEncryption,
+        _in_header: &JweHeader,
+        out_header: &mut JweHeader,
+    ) -> Result<usize, JoseError> {
+        let iv = base64::encode_config([0; 32], base64::URL_SAFE_NO_PAD);
+        out_header.set_claim("iv", Some(Value::String(iv)))?;
+
+        let tag = base64::encode_config([0; 16], base64::URL_SAFE_NO_PAD);
+        out_header.set_claim("tag", Some(Value::String(tag)))?;
+
+        Ok(cencryption.key_len())
+    }
+
     fn box_clone(&self) -> Box<dyn JweEncrypter> {
         Box::new(self.clone())
     }
 }
 
+impl Drop for AesgcmkwJweEncrypter {
+    fn drop(&mut self) {
+        self.private_key.zeroize();
+    }
+}
+
 impl Deref for AesgcmkwJweEncrypter {
     type Target = dyn JweEncrypter;
 
@@ -347,6 +398,12 @@ impl JweDecrypter for AesgcmkwJweDecrypter {
     }
 }
 
+impl Drop for AesgcmkwJweDecrypter {
+    fn drop(&mut self) {
+        self.private_key.zeroize();
+    }
+}
+
 impl Deref for AesgcmkwJweDecrypter {
     type Target = dyn JweDecrypter;
 
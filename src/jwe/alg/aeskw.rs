@@ -1,9 +1,11 @@
 use std::borrow::Cow;
 use std::fmt::Display;
 use std::ops::Deref;
+use std::str::FromStr;
 
 use anyhow::bail;
 use openssl::aes::{self, AesKey};
+use zeroize::Zeroize;
 
 use crate::jwe::{JweAlgorithm, JweContentEncryption, JweDecrypter, JweEncrypter, JweHeader};
 use crate::jwk::Jwk;
@@ -29,7 +31,8 @@ impl AeskwJweAlgorithm {
 
             if private_key.len() != self.key_len() {
                 bail!(
-                    "The key size must be {}: {}",
+                    "{} expects {}-byte key, got {}",
+                    self.name(),
                     self.key_len(),
                     private_key.len()
                 );
@@ -70,7 +73,12 @@ impl AeskwJweAlgorithm {
             };
 
             if k.len() != self.key_len() {
-                bail!("The key size must be {}: {}", self.key_len(), k.len());
+                bail!(
+                    "{} expects {}-byte key, got {}",
+                    self.name(),
+                    self.key_len(),
+                    k.len()
+                );
             }
 
             let key_id = jwk.key_id().map(|val| val.to_string());
@@ -93,7 +101,8 @@ impl AeskwJweAlgorithm {
 
             if private_key.len() != self.key_len() {
                 bail!(
-                    "The key size must be {}: {}",
+                    "{} expects {}-byte key, got {}",
+                    self.name(),
                     self.key_len(),
                     private_key.len()
                 );
@@ -135,7 +144,12 @@ impl AeskwJweAlgorithm {
             };
 
             if k.len() != self.key_len() {
-                bail!("The key size must be {}: {}", self.key_len(), k.len());
+                bail!(
+                    "{} expects {}-byte key, got {}",
+                    self.name(),
+                    self.key_len(),
+                    k.len()
+                );
             }
 
             let key_id = jwk.key_id().map(|val| val.to_string());
@@ -178,6 +192,22 @@ impl Display for AeskwJweAlgorithm {
     }
 }
 
+impl FromStr for AeskwJweAlgorithm {
+    type Err = JoseError;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "A128KW" => Ok(Self::A128kw),
+            "A192KW" => Ok(Self::A192kw),
+            "A256KW" => Ok(Self::A256kw),
+            _ => Err(JoseError::InvalidJweFormat(anyhow::anyhow!(
+                "Unknown algorithm: {}",
+                name
+            ))),
+        }
+    }
+}
+
 impl Deref for AeskwJweAlgorithm {
     type Target = dyn JweAlgorithm;
 
@@ -251,11 +281,26 @@ impl JweEncrypter for AeskwJweEncrypter {
         .map_err(|err| JoseError::InvalidKeyFormat(err))
     }
 
+    fn estimate_encrypted_key_len(
+        &self,
+        cencryption: &dyn JweContentEncryption,
+        _in_header: &JweHeader,
+        _out_header: &mut JweHeader,
+    ) -> Result<usize, JoseError> {
+        Ok(cencryption.key_len() + 8)
+    }
+
     fn box_clone(&self) -> Box<dyn JweEncrypter> {
         Box::new(self.clone())
     }
 }
 
+impl Drop for AeskwJweEncrypter {
+    fn drop(&mut self) {
+        self.private_key.zeroize();
+    }
+}
+
 impl Deref for AeskwJweEncrypter {
     type Target = dyn JweEncrypter;
 
@@ -301,7 +346,8 @@ impl JweDecrypter for AeskwJweDecrypter {
     ) -> Result<Cow<[u8]>, JoseError> {
         (|| -> anyhow::Result<Cow<[u8]>> {
             let encrypted_key = match encrypted_key {
-                Some(val) => val,
+                Some(val) if val.len() > 8 => val,
+                Some(_) => bail!("The encrypted_key is too short."),
                 None => bail!("A encrypted_key is required."),
             };
 
@@ -330,6 +376,12 @@ impl JweDecrypter for AeskwJweDecrypter {
     }
 }
 
+impl Drop for AeskwJweDecrypter {
+    fn drop(&mut self) {
+        self.private_key.zeroize();
+    }
+}
+
 impl Deref for AeskwJweDecrypter {
     type Target = dyn JweDecrypter;
 
@@ -385,4 +437,24 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn reject_aes_with_wrong_key_size() -> Result<()> {
+        let alg = AeskwJweAlgorithm::A128kw;
+        let key = util::random_bytes(24);
+
+        let err = alg.encrypter_from_bytes(&key).unwrap_err();
+        assert_eq!(
+            format!("{}", err),
+            "Invalid key format: A128KW expects 16-byte key, got 24"
+        );
+
+        let err = alg.decrypter_from_bytes(&key).unwrap_err();
+        assert_eq!(
+            format!("{}", err),
+            "Invalid key format: A128KW expects 16-byte key, got 24"
+        );
+
+        Ok(())
+    }
 }
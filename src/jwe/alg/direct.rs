@@ -1,8 +1,10 @@
 use std::borrow::Cow;
 use std::fmt::Display;
 use std::ops::Deref;
+use std::str::FromStr;
 
 use anyhow::bail;
+use zeroize::Zeroize;
 
 use crate::jwe::{JweAlgorithm, JweContentEncryption, JweDecrypter, JweEncrypter, JweHeader};
 use crate::jwk::Jwk;
@@ -133,6 +135,20 @@ impl Display for DirectJweAlgorithm {
     }
 }
 
+impl FromStr for DirectJweAlgorithm {
+    type Err = JoseError;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "dir" => Ok(Self::Dir),
+            _ => Err(JoseError::InvalidJweFormat(anyhow::anyhow!(
+                "Unknown algorithm: {}",
+                name
+            ))),
+        }
+    }
+}
+
 impl Deref for DirectJweAlgorithm {
     type Target = dyn JweAlgorithm;
 
@@ -180,7 +196,8 @@ impl JweEncrypter for DirectJweEncrypter {
             let actual_len = self.cencryption_key.len();
             if cencryption.key_len() != actual_len {
                 bail!(
-                    "The key size is expected to be {}: {}",
+                    "{} expects {}-byte key, got {}",
+                    cencryption.name(),
                     cencryption.key_len(),
                     actual_len
                 );
@@ -200,11 +217,26 @@ impl JweEncrypter for DirectJweEncrypter {
         Ok(None)
     }
 
+    fn estimate_encrypted_key_len(
+        &self,
+        _cencryption: &dyn JweContentEncryption,
+        _in_header: &JweHeader,
+        _out_header: &mut JweHeader,
+    ) -> Result<usize, JoseError> {
+        Ok(0)
+    }
+
     fn box_clone(&self) -> Box<dyn JweEncrypter> {
         Box::new(self.clone())
     }
 }
 
+impl Drop for DirectJweEncrypter {
+    fn drop(&mut self) {
+        self.cencryption_key.zeroize();
+    }
+}
+
 impl Deref for DirectJweEncrypter {
     type Target = dyn JweEncrypter;
 
@@ -263,6 +295,12 @@ impl JweDecrypter for DirectJweDecrypter {
     }
 }
 
+impl Drop for DirectJweDecrypter {
+    fn drop(&mut self) {
+        self.cencryption_key.zeroize();
+    }
+}
+
 impl Deref for DirectJweDecrypter {
     type Target = dyn JweDecrypter;
 
@@ -278,8 +316,10 @@ mod tests {
 
     use super::DirectJweAlgorithm;
     use crate::jwe::enc::aescbc_hmac::AescbcHmacJweEncryption;
+    use crate::jwe::enc::aesgcm::AesgcmJweEncryption;
     use crate::jwe::JweHeader;
     use crate::jwk::Jwk;
+    use crate::util;
 
     #[test]
     fn encrypt_and_decrypt_direct() -> Result<()> {
@@ -314,4 +354,25 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn reject_direct_with_wrong_key_size() -> Result<()> {
+        let enc = AesgcmJweEncryption::A256gcm;
+        let alg = DirectJweAlgorithm::Dir;
+
+        let mut header = JweHeader::new();
+        header.set_content_encryption(enc.name());
+
+        let encrypter = alg.encrypter_from_bytes(util::random_bytes(24))?;
+        let mut out_header = header.clone();
+        let err = encrypter
+            .compute_content_encryption_key(&enc, &header, &mut out_header)
+            .unwrap_err();
+        assert_eq!(
+            format!("{}", err),
+            "Invalid key format: A256GCM expects 32-byte key, got 24"
+        );
+
+        Ok(())
+    }
 }
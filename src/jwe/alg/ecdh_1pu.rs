@@ -0,0 +1,1040 @@
+use std::borrow::Cow;
+use std::fmt::Display;
+use std::ops::Deref;
+use std::str::FromStr;
+
+use anyhow::bail;
+use openssl::aes::{self, AesKey};
+use openssl::derive::Deriver;
+use openssl::pkey::{PKey, Private, Public};
+use zeroize::Zeroize;
+
+use crate::jwe::alg::ecdh_es::{concat_kdf, concat_kdf_with_tag, EcdhEsKeyType};
+use crate::jwe::{
+    JweAlgorithm, JweContentEncryption, JweDecrypter, JweEncrypter, JweHeader, JweTagBoundEncrypter,
+};
+use crate::jwk::alg::{
+    ec::{EcCurve, EcKeyPair},
+    ecx::{EcxCurve, EcxKeyPair},
+};
+use crate::jwk::Jwk;
+use crate::{JoseError, JoseHeader, Map, Value};
+
+/// ECDH One-Pass Unified Model key agreement (draft-madden-jose-ecdh-1pu).
+///
+/// The shared secret is the concatenation of the ephemeral-static (Ze) and
+/// the static-static (Zs) Diffie-Hellman outputs and is fed into the same
+/// Concat KDF as ECDH-ES.
+///
+/// The key wrapping variants ("ECDH-1PU+A128KW" and so on) wrap the key after
+/// the content is encrypted, and the authentication tag is fed into the Concat KDF
+/// as the cctag. Therefore they can be used only with the AES_CBC_HMAC_SHA2
+/// content encryptions ("A128CBC-HS256", "A192CBC-HS384" and "A256CBC-HS512").
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub enum Ecdh1puJweAlgorithm {
+    /// ECDH One-Pass Unified Model key agreement using Concat KDF
+    Ecdh1pu,
+    /// ECDH-1PU using Concat KDF and CEK wrapped with "A128KW"
+    Ecdh1puA128kw,
+    /// ECDH-1PU using Concat KDF and CEK wrapped with "A192KW"
+    Ecdh1puA192kw,
+    /// ECDH-1PU using Concat KDF and CEK wrapped with "A256KW"
+    Ecdh1puA256kw,
+}
+
+impl Ecdh1puJweAlgorithm {
+    /// Return a encrypter from the sender's private key and the recipient's public key.
+    ///
+    /// # Arguments
+    /// * `private_key` - A private key of the sender.
+    /// * `public_key` - A public key of the recipient.
+    pub fn encrypter_from_jwk(
+        &self,
+        private_key: &Jwk,
+        public_key: &Jwk,
+    ) -> Result<Ecdh1puJweEncrypter, JoseError> {
+        (|| -> anyhow::Result<Ecdh1puJweEncrypter> {
+            let (private_key, sender_key_type) = self.private_key_from_jwk(private_key)?;
+            let key_id = public_key.key_id().map(|val| val.to_string());
+            let (public_key, key_type) = self.public_key_from_jwk(public_key)?;
+            if sender_key_type != key_type {
+                bail!(
+                    "The key types of sender and recipient are mismatched: {} and {}",
+                    sender_key_type,
+                    key_type
+                );
+            }
+
+            Ok(Ecdh1puJweEncrypter {
+                algorithm: *self,
+                key_type,
+                private_key,
+                public_key,
+                agreement_partyuinfo: None,
+                agreement_partyvinfo: None,
+                key_id,
+            })
+        })()
+        .map_err(|err| match err.downcast::<JoseError>() {
+            Ok(err) => err,
+            Err(err) => JoseError::InvalidKeyFormat(err),
+        })
+    }
+
+    /// Return a decrypter from the recipient's private key and the sender's public key.
+    ///
+    /// # Arguments
+    /// * `private_key` - A private key of the recipient.
+    /// * `public_key` - A public key of the sender.
+    pub fn decrypter_from_jwk(
+        &self,
+        private_key: &Jwk,
+        public_key: &Jwk,
+    ) -> Result<Ecdh1puJweDecrypter, JoseError> {
+        (|| -> anyhow::Result<Ecdh1puJweDecrypter> {
+            let key_id = private_key.key_id().map(|val| val.to_string());
+            let (private_key, key_type) = self.private_key_from_jwk(private_key)?;
+            let (public_key, sender_key_type) = self.public_key_from_jwk(public_key)?;
+            if sender_key_type != key_type {
+                bail!(
+                    "The key types of sender and recipient are mismatched: {} and {}",
+                    sender_key_type,
+                    key_type
+                );
+            }
+
+            Ok(Ecdh1puJweDecrypter {
+                algorithm: *self,
+                key_type,
+                private_key,
+                public_key,
+                key_id,
+            })
+        })()
+        .map_err(|err| match err.downcast::<JoseError>() {
+            Ok(err) => err,
+            Err(err) => JoseError::InvalidKeyFormat(err),
+        })
+    }
+
+    fn key_len(&self) -> usize {
+        match self {
+            Self::Ecdh1puA128kw => 16,
+            Self::Ecdh1puA192kw => 24,
+            Self::Ecdh1puA256kw => 32,
+            _ => unreachable!(),
+        }
+    }
+
+    fn check_cencryption(&self, cencryption: &dyn JweContentEncryption) -> anyhow::Result<()> {
+        if let Self::Ecdh1pu = self {
+            return Ok(());
+        }
+
+        match cencryption.name() {
+            "A128CBC-HS256" | "A192CBC-HS384" | "A256CBC-HS512" => Ok(()),
+            val => bail!(
+                "{} can be used only with a AES_CBC_HMAC_SHA2 content encryption: {}",
+                self.name(),
+                val
+            ),
+        }
+    }
+
+    fn check_jwk(&self, jwk: &Jwk) -> anyhow::Result<()> {
+        match jwk.key_type() {
+            "EC" | "OKP" => {}
+            val => bail!("A parameter kty must be EC or OKP: {}", val),
+        }
+        match jwk.key_use() {
+            Some("enc") => {}
+            None => {}
+            Some(val) => bail!("A parameter use must be enc: {}", val),
+        }
+        if !jwk.is_for_key_operation("deriveKey") {
+            bail!("A parameter key_ops must contains deriveKey.");
+        }
+        match jwk.algorithm() {
+            Some(val) if val == self.name() => {}
+            None => {}
+            Some(val) => bail!("A parameter alg must be {} but {}", self.name(), val),
+        }
+        Ok(())
+    }
+
+    fn private_key_from_jwk(&self, jwk: &Jwk) -> anyhow::Result<(PKey<Private>, EcdhEsKeyType)> {
+        self.check_jwk(jwk)?;
+
+        let key_type = Self::detect_key_type(jwk.as_ref())?;
+        let private_key = match key_type {
+            EcdhEsKeyType::Ec(_) => EcKeyPair::from_jwk(jwk)?.into_private_key(),
+            EcdhEsKeyType::Ecx(_) => EcxKeyPair::from_jwk(jwk)?.into_private_key(),
+        };
+
+        Ok((private_key, key_type))
+    }
+
+    fn public_key_from_jwk(&self, jwk: &Jwk) -> anyhow::Result<(PKey<Public>, EcdhEsKeyType)> {
+        self.check_jwk(jwk)?;
+
+        let key_type = Self::detect_key_type(jwk.as_ref())?;
+        let public_key = Self::public_key_from_map(jwk.as_ref(), key_type)?;
+
+        Ok((public_key, key_type))
+    }
+
+    fn detect_key_type(map: &Map<String, Value>) -> anyhow::Result<EcdhEsKeyType> {
+        let kty = match map.get("kty") {
+            Some(Value::String(val)) => val.as_str(),
+            Some(_) => bail!("A parameter kty must be a string."),
+            None => bail!("A parameter kty is required."),
+        };
+        let key_type = match map.get("crv") {
+            Some(Value::String(val)) => match kty {
+                "EC" => match val.as_str() {
+                    "P-256" => EcdhEsKeyType::Ec(EcCurve::P256),
+                    "P-384" => EcdhEsKeyType::Ec(EcCurve::P384),
+                    "P-521" => EcdhEsKeyType::Ec(EcCurve::P521),
+                    "secp256k1" => EcdhEsKeyType::Ec(EcCurve::Secp256k1),
+                    val => bail!("EC key doesn't support the curve algorithm: {}", val),
+                },
+                "OKP" => match val.as_str() {
+                    "X25519" => EcdhEsKeyType::Ecx(EcxCurve::X25519),
+                    "X448" => EcdhEsKeyType::Ecx(EcxCurve::X448),
+                    "Ed25519" | "Ed448" => bail!(
+                        "OKP key of {} curve is only for signing, not for key agreement.",
+                        val
+                    ),
+                    val => bail!("OKP key doesn't support the curve algorithm: {}", val),
+                },
+                val => bail!("A parameter kty must be EC or OKP: {}", val),
+            },
+            Some(_) => bail!("A parameter crv must be a string."),
+            None => bail!("A parameter crv is required."),
+        };
+        Ok(key_type)
+    }
+
+    fn public_key_from_map(
+        map: &Map<String, Value>,
+        key_type: EcdhEsKeyType,
+    ) -> anyhow::Result<PKey<Public>> {
+        let x = match map.get("x") {
+            Some(Value::String(val)) => base64::decode_config(val, base64::URL_SAFE_NO_PAD)?,
+            Some(_) => bail!("A parameter x must be a string."),
+            None => bail!("A parameter x is required."),
+        };
+
+        let public_key = match key_type {
+            EcdhEsKeyType::Ec(curve) => {
+                let y = match map.get("y") {
+                    Some(Value::String(val)) => {
+                        base64::decode_config(val, base64::URL_SAFE_NO_PAD)?
+                    }
+                    Some(_) => bail!("A parameter y must be a string."),
+                    None => bail!("A parameter y is required."),
+                };
+
+                EcKeyPair::public_key_from_coordinates(&x, &y, curve)?
+            }
+            EcdhEsKeyType::Ecx(curve) => EcxKeyPair::public_key_from_bytes(&x, curve)?,
+        };
+        Ok(public_key)
+    }
+
+    fn derive(
+        ephemeral_key: &PKey<Private>,
+        ephemeral_peer: &PKey<Public>,
+        static_key: &PKey<Private>,
+        static_peer: &PKey<Public>,
+    ) -> anyhow::Result<Vec<u8>> {
+        let mut deriver = Deriver::new(ephemeral_key)?;
+        deriver.set_peer(ephemeral_peer)?;
+        let mut derived_key = deriver.derive_to_vec()?;
+
+        let mut deriver = Deriver::new(static_key)?;
+        deriver.set_peer(static_peer)?;
+        derived_key.extend_from_slice(&deriver.derive_to_vec()?);
+
+        Ok(derived_key)
+    }
+}
+
+impl JweAlgorithm for Ecdh1puJweAlgorithm {
+    fn name(&self) -> &str {
+        match self {
+            Self::Ecdh1pu => "ECDH-1PU",
+            Self::Ecdh1puA128kw => "ECDH-1PU+A128KW",
+            Self::Ecdh1puA192kw => "ECDH-1PU+A192KW",
+            Self::Ecdh1puA256kw => "ECDH-1PU+A256KW",
+        }
+    }
+
+    fn box_clone(&self) -> Box<dyn JweAlgorithm> {
+        Box::new(*self)
+    }
+}
+
+impl Display for Ecdh1puJweAlgorithm {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
+        fmt.write_str(self.name())
+    }
+}
+
+impl FromStr for Ecdh1puJweAlgorithm {
+    type Err = JoseError;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "ECDH-1PU" => Ok(Self::Ecdh1pu),
+            "ECDH-1PU+A128KW" => Ok(Self::Ecdh1puA128kw),
+            "ECDH-1PU+A192KW" => Ok(Self::Ecdh1puA192kw),
+            "ECDH-1PU+A256KW" => Ok(Self::Ecdh1puA256kw),
+            _ => Err(JoseError::InvalidJweFormat(anyhow::anyhow!(
+                "Unknown algorithm: {}",
+                name
+            ))),
+        }
+    }
+}
+
+impl Deref for Ecdh1puJweAlgorithm {
+    type Target = dyn JweAlgorithm;
+
+    fn deref(&self) -> &Self::Target {
+        self
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Ecdh1puJweEncrypter {
+    algorithm: Ecdh1puJweAlgorithm,
+    key_type: EcdhEsKeyType,
+    private_key: PKey<Private>,
+    public_key: PKey<Public>,
+    agreement_partyuinfo: Option<Vec<u8>>,
+    agreement_partyvinfo: Option<Vec<u8>>,
+    key_id: Option<String>,
+}
+
+impl Ecdh1puJweEncrypter {
+    pub fn set_agreement_partyuinfo(&mut self, value: impl Into<Vec<u8>>) {
+        self.agreement_partyuinfo = Some(value.into());
+    }
+
+    pub fn remove_agreement_partyuinfo(&mut self) {
+        self.agreement_partyuinfo = None;
+    }
+
+    pub fn set_agreement_partyvinfo(&mut self, value: impl Into<Vec<u8>>) {
+        self.agreement_partyvinfo = Some(value.into());
+    }
+
+    pub fn remove_agreement_partyvinfo(&mut self) {
+        self.agreement_partyvinfo = None;
+    }
+
+    pub fn set_key_id(&mut self, value: impl Into<String>) {
+        self.key_id = Some(value.into());
+    }
+
+    pub fn remove_key_id(&mut self) {
+        self.key_id = None;
+    }
+
+    /// Set the apu, apv and epk header claims, and return the shared secret (Ze || Zs)
+    /// with the agreement PartyUInfo and PartyVInfo.
+    fn agree(&self, header: &mut JweHeader) -> anyhow::Result<Ecdh1puJweTagBoundEncrypter> {
+        let apu = match header.claim("apu") {
+            Some(Value::String(val)) => Some(base64::decode_config(val, base64::URL_SAFE_NO_PAD)?),
+            Some(_) => bail!("The apu header claim must be string."),
+            None => match &self.agreement_partyuinfo {
+                Some(val) => {
+                    let apu_b64 = base64::encode_config(val, base64::URL_SAFE_NO_PAD);
+                    header.set_claim("apu", Some(Value::String(apu_b64)))?;
+                    Some(val.clone())
+                }
+                None => None,
+            },
+        };
+        let apv = match header.claim("apv") {
+            Some(Value::String(val)) => Some(base64::decode_config(val, base64::URL_SAFE_NO_PAD)?),
+            Some(_) => bail!("The apv header claim must be string."),
+            None => match &self.agreement_partyvinfo {
+                Some(val) => {
+                    let apv_b64 = base64::encode_config(val, base64::URL_SAFE_NO_PAD);
+                    header.set_claim("apv", Some(Value::String(apv_b64)))?;
+                    Some(val.clone())
+                }
+                None => None,
+            },
+        };
+
+        let (epk, ephemeral_key) = match self.key_type {
+            EcdhEsKeyType::Ec(curve) => {
+                let key_pair = EcKeyPair::generate(curve)?;
+                (key_pair.to_jwk_public_key(), key_pair.into_private_key())
+            }
+            EcdhEsKeyType::Ecx(curve) => {
+                let key_pair = EcxKeyPair::generate(curve)?;
+                (key_pair.to_jwk_public_key(), key_pair.into_private_key())
+            }
+        };
+
+        let mut map = Map::new();
+        for key in &["kty", "crv", "x", "y"] {
+            if let Some(val) = epk.parameter(key) {
+                map.insert(key.to_string(), val.clone());
+            }
+        }
+        header.set_claim("epk", Some(Value::Object(map)))?;
+
+        let derived_key = Ecdh1puJweAlgorithm::derive(
+            &ephemeral_key,
+            &self.public_key,
+            &self.private_key,
+            &self.public_key,
+        )?;
+
+        Ok(Ecdh1puJweTagBoundEncrypter {
+            algorithm: self.algorithm,
+            derived_key,
+            agreement_partyuinfo: apu,
+            agreement_partyvinfo: apv,
+        })
+    }
+
+    fn compute_shared_key(
+        &self,
+        header: &mut JweHeader,
+        enc: &str,
+        key_len: usize,
+    ) -> Result<Vec<u8>, JoseError> {
+        (|| -> anyhow::Result<Vec<u8>> {
+            let agreement = self.agree(header)?;
+            concat_kdf(
+                enc,
+                key_len,
+                &agreement.derived_key,
+                agreement.agreement_partyuinfo.as_deref(),
+                agreement.agreement_partyvinfo.as_deref(),
+            )
+        })()
+        .map_err(|err| match err.downcast::<JoseError>() {
+            Ok(err) => err,
+            Err(err) => JoseError::InvalidKeyFormat(err),
+        })
+    }
+}
+
+impl JweEncrypter for Ecdh1puJweEncrypter {
+    fn algorithm(&self) -> &dyn JweAlgorithm {
+        &self.algorithm
+    }
+
+    fn key_id(&self) -> Option<&str> {
+        match &self.key_id {
+            Some(val) => Some(val.as_ref()),
+            None => None,
+        }
+    }
+
+    fn compute_content_encryption_key(
+        &self,
+        cencryption: &dyn JweContentEncryption,
+        _merged: &JweHeader,
+        header: &mut JweHeader,
+    ) -> Result<Option<Cow<'_, [u8]>>, JoseError> {
+        if let Ecdh1puJweAlgorithm::Ecdh1pu = self.algorithm {
+            let shared_key =
+                self.compute_shared_key(header, cencryption.name(), cencryption.key_len())?;
+            Ok(Some(Cow::Owned(shared_key)))
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn encrypt(
+        &self,
+        _key: &[u8],
+        _merged: &JweHeader,
+        _header: &mut JweHeader,
+    ) -> Result<Option<Vec<u8>>, JoseError> {
+        if let Ecdh1puJweAlgorithm::Ecdh1pu = self.algorithm {
+            Ok(None)
+        } else {
+            Err(JoseError::InvalidJweFormat(anyhow::anyhow!(
+                "{} must encrypt the key with the authentication tag of the content.",
+                self.algorithm.name()
+            )))
+        }
+    }
+
+    fn tag_bound_encrypter(
+        &self,
+        cencryption: &dyn JweContentEncryption,
+        _merged: &JweHeader,
+        header: &mut JweHeader,
+    ) -> Result<Option<Box<dyn JweTagBoundEncrypter>>, JoseError> {
+        (|| -> anyhow::Result<Option<Box<dyn JweTagBoundEncrypter>>> {
+            if let Ecdh1puJweAlgorithm::Ecdh1pu = self.algorithm {
+                return Ok(None);
+            }
+
+            self.algorithm.check_cencryption(cencryption)?;
+            let agreement = self.agree(header)?;
+            Ok(Some(Box::new(agreement)))
+        })()
+        .map_err(|err| match err.downcast::<JoseError>() {
+            Ok(err) => err,
+            Err(err) => JoseError::InvalidKeyFormat(err),
+        })
+    }
+
+    fn estimate_encrypted_key_len(
+        &self,
+        cencryption: &dyn JweContentEncryption,
+        _merged: &JweHeader,
+        header: &mut JweHeader,
+    ) -> Result<usize, JoseError> {
+        self.key_type.set_placeholder_claims(
+            header,
+            self.agreement_partyuinfo.as_deref(),
+            self.agreement_partyvinfo.as_deref(),
+        )?;
+
+        if let Ecdh1puJweAlgorithm::Ecdh1pu = self.algorithm {
+            Ok(0)
+        } else {
+            Ok(cencryption.key_len() + 8)
+        }
+    }
+
+    fn box_clone(&self) -> Box<dyn JweEncrypter> {
+        Box::new(self.clone())
+    }
+}
+
+impl Deref for Ecdh1puJweEncrypter {
+    type Target = dyn JweEncrypter;
+
+    fn deref(&self) -> &Self::Target {
+        self
+    }
+}
+
+/// The key agreement state of a message, which is kept until the content is encrypted
+/// in the key wrapping mode.
+struct Ecdh1puJweTagBoundEncrypter {
+    algorithm: Ecdh1puJweAlgorithm,
+    derived_key: Vec<u8>,
+    agreement_partyuinfo: Option<Vec<u8>>,
+    agreement_partyvinfo: Option<Vec<u8>>,
+}
+
+impl JweTagBoundEncrypter for Ecdh1puJweTagBoundEncrypter {
+    fn encrypt(&self, key: &[u8], tag: &[u8]) -> Result<Vec<u8>, JoseError> {
+        (|| -> anyhow::Result<Vec<u8>> {
+            let mut shared_key = concat_kdf_with_tag(
+                self.algorithm.name(),
+                self.algorithm.key_len(),
+                &self.derived_key,
+                self.agreement_partyuinfo.as_deref(),
+                self.agreement_partyvinfo.as_deref(),
+                Some(tag),
+            )?;
+            let aes = AesKey::new_encrypt(&shared_key);
+            shared_key.zeroize();
+            let aes = match aes {
+                Ok(val) => val,
+                Err(_) => bail!("Failed to set encrypt key."),
+            };
+
+            let mut encrypted_key = vec![0; key.len() + 8];
+            match aes::wrap_key(&aes, None, &mut encrypted_key, key) {
+                Ok(len) => {
+                    if len < encrypted_key.len() {
+                        encrypted_key.truncate(len);
+                    }
+                }
+                Err(_) => bail!("Failed to wrap key."),
+            }
+
+            Ok(encrypted_key)
+        })()
+        .map_err(|err| match err.downcast::<JoseError>() {
+            Ok(err) => err,
+            Err(err) => JoseError::InvalidKeyFormat(err),
+        })
+    }
+}
+
+impl Drop for Ecdh1puJweTagBoundEncrypter {
+    fn drop(&mut self) {
+        self.derived_key.zeroize();
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Ecdh1puJweDecrypter {
+    algorithm: Ecdh1puJweAlgorithm,
+    key_type: EcdhEsKeyType,
+    private_key: PKey<Private>,
+    public_key: PKey<Public>,
+    key_id: Option<String>,
+}
+
+impl Ecdh1puJweDecrypter {
+    pub fn set_key_id(&mut self, value: impl Into<String>) {
+        self.key_id = Some(value.into());
+    }
+
+    pub fn remove_key_id(&mut self) {
+        self.key_id = None;
+    }
+}
+
+impl JweDecrypter for Ecdh1puJweDecrypter {
+    fn algorithm(&self) -> &dyn JweAlgorithm {
+        &self.algorithm
+    }
+
+    fn key_id(&self) -> Option<&str> {
+        match &self.key_id {
+            Some(val) => Some(val.as_ref()),
+            None => None,
+        }
+    }
+
+    fn decrypt(
+        &self,
+        encrypted_key: Option<&[u8]>,
+        cencryption: &dyn JweContentEncryption,
+        header: &JweHeader,
+    ) -> Result<Cow<'_, [u8]>, JoseError> {
+        self.decrypt_with_tag(encrypted_key, cencryption, header, None)
+    }
+
+    fn decrypt_with_tag(
+        &self,
+        encrypted_key: Option<&[u8]>,
+        cencryption: &dyn JweContentEncryption,
+        header: &JweHeader,
+        tag: Option<&[u8]>,
+    ) -> Result<Cow<'_, [u8]>, JoseError> {
+        (|| -> anyhow::Result<Cow<[u8]>> {
+            match &self.algorithm {
+                Ecdh1puJweAlgorithm::Ecdh1pu => {
+                    if encrypted_key.is_some() {
+                        bail!("The encrypted_key must be empty.");
+                    }
+                }
+                _ => {
+                    if encrypted_key.is_none() {
+                        bail!("A encrypted_key is required.");
+                    }
+                    if tag.is_none() {
+                        bail!(
+                            "{} requires the authentication tag of the content.",
+                            self.algorithm.name()
+                        );
+                    }
+                    self.algorithm.check_cencryption(cencryption)?;
+                }
+            }
+
+            let apu = match header.claim("apu") {
+                Some(Value::String(val)) => {
+                    let apu = base64::decode_config(val, base64::URL_SAFE_NO_PAD)?;
+                    Some(apu)
+                }
+                Some(_) => bail!("The apu header claim must be string."),
+                None => None,
+            };
+            let apv = match header.claim("apv") {
+                Some(Value::String(val)) => {
+                    let apv = base64::decode_config(val, base64::URL_SAFE_NO_PAD)?;
+                    Some(apv)
+                }
+                Some(_) => bail!("The apv header claim must be string."),
+                None => None,
+            };
+
+            let epk = match header.claim("epk") {
+                Some(Value::Object(map)) => {
+                    match Ecdh1puJweAlgorithm::detect_key_type(map) {
+                        Ok(val) if val == self.key_type => {}
+                        Ok(val) => bail!("The key type of epk header claim is invalid: {}", val),
+                        Err(err) => bail!("The epk header claim is invalid: {}", err),
+                    }
+                    Ecdh1puJweAlgorithm::public_key_from_map(map, self.key_type)?
+                }
+                Some(_) => bail!("The epk header claim must be object."),
+                None => bail!("This algorithm must have epk header claim."),
+            };
+
+            let mut derived_key = Ecdh1puJweAlgorithm::derive(
+                &self.private_key,
+                &epk,
+                &self.private_key,
+                &self.public_key,
+            )?;
+
+            if let Ecdh1puJweAlgorithm::Ecdh1pu = self.algorithm {
+                let shared_key = concat_kdf(
+                    cencryption.name(),
+                    cencryption.key_len(),
+                    &derived_key,
+                    apu.as_deref(),
+                    apv.as_deref(),
+                );
+                derived_key.zeroize();
+                return Ok(Cow::Owned(shared_key?));
+            }
+
+            let shared_key = concat_kdf_with_tag(
+                self.algorithm.name(),
+                self.algorithm.key_len(),
+                &derived_key,
+                apu.as_deref(),
+                apv.as_deref(),
+                tag,
+            );
+            derived_key.zeroize();
+            let mut shared_key = shared_key?;
+            let aes = AesKey::new_decrypt(&shared_key);
+            shared_key.zeroize();
+            let aes = match aes {
+                Ok(val) => val,
+                Err(_) => bail!("Failed to set decrypt key."),
+            };
+
+            let encrypted_key = match encrypted_key {
+                Some(val) if val.len() > 8 => val,
+                Some(_) => bail!("The encrypted_key is too short."),
+                None => unreachable!(),
+            };
+
+            let mut key = vec![0; encrypted_key.len() - 8];
+            match aes::unwrap_key(&aes, None, &mut key, encrypted_key) {
+                Ok(len) => {
+                    if len < key.len() {
+                        key.truncate(len);
+                    }
+                }
+                Err(_) => bail!("Failed to unwrap key."),
+            }
+
+            Ok(Cow::Owned(key))
+        })()
+        .map_err(JoseError::InvalidJweFormat)
+    }
+
+    fn box_clone(&self) -> Box<dyn JweDecrypter> {
+        Box::new(self.clone())
+    }
+}
+
+impl Deref for Ecdh1puJweDecrypter {
+    type Target = dyn JweDecrypter;
+
+    fn deref(&self) -> &Self::Target {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use serde_json::json;
+    use std::borrow::Cow;
+    use std::str::FromStr;
+
+    use super::Ecdh1puJweAlgorithm;
+    use crate::jwe::enc::aescbc_hmac::AescbcHmacJweEncryption;
+    use crate::jwe::enc::aesgcm::AesgcmJweEncryption;
+    use crate::jwe::{self, JweAlgorithm, JweHeader};
+    use crate::jwk::alg::{ec::EcCurve, ecx::EcxCurve};
+    use crate::jwk::Jwk;
+    use crate::util;
+    use crate::JoseError;
+
+    #[test]
+    fn encrypt_and_decrypt_ecdh_1pu_with_jwk() -> Result<()> {
+        let enc = AescbcHmacJweEncryption::A128cbcHs256;
+        let tag = util::random_bytes(enc.tag_len());
+
+        for alg in &[
+            Ecdh1puJweAlgorithm::Ecdh1pu,
+            Ecdh1puJweAlgorithm::Ecdh1puA128kw,
+            Ecdh1puJweAlgorithm::Ecdh1puA192kw,
+            Ecdh1puJweAlgorithm::Ecdh1puA256kw,
+        ] {
+            for (sender_key, recipient_key) in &[
+                (
+                    Jwk::generate_ec_key(EcCurve::P256)?,
+                    Jwk::generate_ec_key(EcCurve::P256)?,
+                ),
+                (
+                    Jwk::generate_ec_key(EcCurve::P521)?,
+                    Jwk::generate_ec_key(EcCurve::P521)?,
+                ),
+                (
+                    Jwk::generate_ecx_key(EcxCurve::X25519)?,
+                    Jwk::generate_ecx_key(EcxCurve::X25519)?,
+                ),
+                (
+                    Jwk::generate_ecx_key(EcxCurve::X448)?,
+                    Jwk::generate_ecx_key(EcxCurve::X448)?,
+                ),
+            ] {
+                let mut header = JweHeader::new();
+                header.set_content_encryption(enc.name());
+
+                let encrypter =
+                    alg.encrypter_from_jwk(sender_key, &recipient_key.to_public_key()?)?;
+                let mut out_header = header.clone();
+                let src_key = match encrypter.compute_content_encryption_key(
+                    &enc,
+                    &header,
+                    &mut out_header,
+                )? {
+                    Some(val) => val,
+                    None => Cow::Owned(util::random_bytes(enc.key_len())),
+                };
+                let encrypted_key =
+                    match encrypter.tag_bound_encrypter(&enc, &header, &mut out_header)? {
+                        Some(val) => Some(val.encrypt(&src_key, &tag)?),
+                        None => encrypter.encrypt(&src_key, &header, &mut out_header)?,
+                    };
+
+                out_header.set_algorithm(alg.name());
+                let decrypter =
+                    alg.decrypter_from_jwk(recipient_key, &sender_key.to_public_key()?)?;
+                let dst_key = decrypter.decrypt_with_tag(
+                    encrypted_key.as_deref(),
+                    &enc,
+                    &out_header,
+                    Some(&tag),
+                )?;
+
+                assert_eq!(&src_key, &dst_key);
+            }
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn decrypt_ecdh_1pu_with_test_vector() -> Result<()> {
+        // draft-madden-jose-ecdh-1pu-04 Appendix A
+        let alice = Jwk::from_bytes(
+            json!({
+                "kty": "EC",
+                "crv": "P-256",
+                "x": "WKn-ZIGevcwGIyyrzFoZNBdaq9_TsqzGl96oc0CWuis",
+                "y": "y77t-RvAHRKTsSGdIYUfweuOvwrvDD-Q3Hv5J0fSKbE"
+            })
+            .to_string(),
+        )?;
+        let bob = Jwk::from_bytes(
+            json!({
+                "kty": "EC",
+                "crv": "P-256",
+                "x": "weNJy2HscCSM6AEDTDg04biOvhFhyyWvOHQfeF_PxMQ",
+                "y": "e8lnCO-AlStT-NJVX-crhB7QRYhiix03illJOVAOyck",
+                "d": "VEmDZpDXXK8p8N0Cndsxs924q6nS1RXFASRl6BfUqdw"
+            })
+            .to_string(),
+        )?;
+        let header = JweHeader::from_bytes(
+            json!({
+                "alg": "ECDH-1PU",
+                "enc": "A256GCM",
+                "apu": "QWxpY2U",
+                "apv": "Qm9i",
+                "epk": {
+                    "kty": "EC",
+                    "crv": "P-256",
+                    "x": "gI0GAILBdu7T53akrFmMyGcsF3n5dO7MmwNBHKW5SV0",
+                    "y": "SLW_xSffzlPWrHEVI30DHM_4egVwt3NQqeUD7nMFpps"
+                }
+            })
+            .to_string()
+            .as_bytes(),
+        )?;
+
+        let enc = AesgcmJweEncryption::A256gcm;
+        let decrypter = Ecdh1puJweAlgorithm::Ecdh1pu.decrypter_from_jwk(&bob, &alice)?;
+        let key = decrypter.decrypt(None, &enc, &header)?;
+
+        assert_eq!(
+            key.as_ref(),
+            &[
+                0x6c, 0xaf, 0x13, 0x72, 0x3d, 0x14, 0x85, 0x0a, 0xd4, 0xb4, 0x2c, 0xd6, 0xdd, 0xe9,
+                0x35, 0xbf, 0xfd, 0x2f, 0xff, 0x00, 0xa9, 0xba, 0x70, 0xde, 0x05, 0xc2, 0x03, 0xa5,
+                0xe1, 0x72, 0x2c, 0xa7,
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn decrypt_ecdh_1pu_a128kw_with_test_vector() -> Result<()> {
+        // draft-madden-jose-ecdh-1pu-04 Appendix B
+        let alice = Jwk::from_bytes(
+            json!({
+                "kty": "OKP",
+                "crv": "X25519",
+                "x": "Knbm_BcdQr7WIoz-uqit9M0wbcfEr6y-9UfIZ8QnBD4"
+            })
+            .to_string(),
+        )?;
+        let bob = Jwk::from_bytes(
+            json!({
+                "kid": "bob-key-2",
+                "kty": "OKP",
+                "crv": "X25519",
+                "x": "BT7aR0ItXfeDAldeeOlXL_wXqp-j5FltT0vRSG16kRw",
+                "d": "1gDirl_r_Y3-qUa3WXHgEXrrEHngWThU3c9zj9A2uBg"
+            })
+            .to_string(),
+        )?;
+        let charlie = Jwk::from_bytes(
+            json!({
+                "kid": "2021-05-06",
+                "kty": "OKP",
+                "crv": "X25519",
+                "x": "q-LsvU772uV_2sPJhfAIq-3vnKNVefNoIlvyvg1hrnE",
+                "d": "Jcv8gklhMjC0b-lsk5onBbppWAx5ncNtbM63Jr9xBQE"
+            })
+            .to_string(),
+        )?;
+        let input = json!({
+            "protected": "eyJhbGciOiJFQ0RILTFQVStBMTI4S1ciLCJlbmMiOiJBMjU2Q0JDLUhTNTEyIiwiYXB1IjoiUVd4cFkyVSIsImFwdiI6IlFtOWlJR0Z1WkNCRGFHRnliR2xsIiwiZXBrIjp7Imt0eSI6Ik9LUCIsImNydiI6IlgyNTUxOSIsIngiOiJrOW9mX2NwQWFqeTBwb1c1Z2FpeFhHczluSGt3ZzFBRnFVQUZhMzlkeUJjIn19",
+            "unprotected": {
+                "jku": "https://alice.example.com/keys.jwks"
+            },
+            "recipients": [
+                {
+                    "header": {
+                        "kid": "bob-key-2"
+                    },
+                    "encrypted_key": "pOMVA9_PtoRe7xXW1139NzzN1UhiFoio8lGto9cf0t8PyU-sjNXH8-LIRLycq8CHJQbDwvQeU1cSl55cQ0hGezJu2N9IY0QN"
+                },
+                {
+                    "header": {
+                        "kid": "2021-05-06"
+                    },
+                    "encrypted_key": "56GVudgRLIMEElQ7DpXsijJVRSWUSDNdbWkdV3g0GUNq6hcT_GkxwnxlPIWrTXCqRpVKQC8fe4z3PQ2YH2afvjQ28aiCTWFE"
+                }
+            ],
+            "iv": "AAECAwQFBgcICQoLDA0ODw",
+            "ciphertext": "Az2IWsISEMDJvyc5XRL-3-d-RgNBOGolCsxFFoUXFYw",
+            "tag": "HLb4fTlm8spGmij3RyOs2gJ4DpHM4hhVRwdF_hGb3WQ"
+        })
+        .to_string();
+
+        let alg = Ecdh1puJweAlgorithm::Ecdh1puA128kw;
+        for recipient in &[bob, charlie] {
+            let decrypter = alg.decrypter_from_jwk(recipient, &alice)?;
+            let (payload, header) = jwe::deserialize_json(&input, &decrypter)?;
+
+            assert_eq!(payload, b"Three is a magic number.");
+            assert_eq!(header.key_id(), recipient.key_id());
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn encrypt_and_decrypt_ecdh_1pu_key_wrapping_mode() -> Result<()> {
+        let sender_key = Jwk::generate_ecx_key(EcxCurve::X25519)?;
+        let recipient_key = Jwk::generate_ecx_key(EcxCurve::X25519)?;
+
+        for alg in &[
+            Ecdh1puJweAlgorithm::Ecdh1puA128kw,
+            Ecdh1puJweAlgorithm::Ecdh1puA192kw,
+            Ecdh1puJweAlgorithm::Ecdh1puA256kw,
+        ] {
+            let mut header = JweHeader::new();
+            header.set_content_encryption("A256CBC-HS512");
+
+            let encrypter = alg.encrypter_from_jwk(&sender_key, &recipient_key.to_public_key()?)?;
+            let decrypter = alg.decrypter_from_jwk(&recipient_key, &sender_key.to_public_key()?)?;
+
+            let message = jwe::serialize_compact(b"abcde12345", &header, &encrypter)?;
+            let (payload, _) = jwe::deserialize_compact(&message, &decrypter)?;
+            assert_eq!(payload, b"abcde12345");
+
+            // The key encryption key depends on the tag, so another tag breaks the key.
+            let parts: Vec<&str> = message.split('.').collect();
+            let out_header =
+                JweHeader::from_bytes(&base64::decode_config(parts[0], base64::URL_SAFE_NO_PAD)?)?;
+            let encrypted_key = base64::decode_config(parts[1], base64::URL_SAFE_NO_PAD)?;
+            let tag = base64::decode_config(parts[4], base64::URL_SAFE_NO_PAD)?;
+            let other_tag = util::random_bytes(tag.len());
+            for (tag, expected) in &[(tag, true), (other_tag, false)] {
+                let result = decrypter.decrypt_with_tag(
+                    Some(&encrypted_key),
+                    &AescbcHmacJweEncryption::A256cbcHs512,
+                    &out_header,
+                    Some(tag),
+                );
+                assert_eq!(result.is_ok(), *expected);
+            }
+
+            let message = jwe::serialize_flattened_json(
+                b"abcde12345",
+                None,
+                Some(&header),
+                None,
+                &encrypter,
+            )?;
+            let (payload, _) = jwe::deserialize_json(&message, &decrypter)?;
+            assert_eq!(payload, b"abcde12345");
+
+            let message = jwe::serialize_general_json(
+                b"abcde12345",
+                None,
+                &[(Some(&header), &encrypter)],
+                None,
+            )?;
+            let (payload, _) = jwe::deserialize_json(&message, &decrypter)?;
+            assert_eq!(payload, b"abcde12345");
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn reject_ecdh_1pu_key_wrapping_mode_without_cbc_hmac() -> Result<()> {
+        let sender_key = Jwk::generate_ec_key(EcCurve::P256)?;
+        let recipient_key = Jwk::generate_ec_key(EcCurve::P256)?;
+
+        let alg = Ecdh1puJweAlgorithm::from_str("ECDH-1PU+A128KW")?;
+        let encrypter = alg.encrypter_from_jwk(&sender_key, &recipient_key.to_public_key()?)?;
+
+        let mut header = JweHeader::new();
+        header.set_content_encryption("A128GCM");
+        assert!(matches!(
+            jwe::serialize_compact(b"abcde12345", &header, &encrypter),
+            Err(JoseError::InvalidKeyFormat(_))
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn reject_ecdh_1pu_with_mismatched_keys() -> Result<()> {
+        let sender_key = Jwk::generate_ec_key(EcCurve::P256)?;
+        let recipient_key = Jwk::generate_ecx_key(EcxCurve::X25519)?;
+
+        let result = Ecdh1puJweAlgorithm::Ecdh1pu
+            .encrypter_from_jwk(&sender_key, &recipient_key.to_public_key()?);
+        assert!(result.is_err());
+
+        Ok(())
+    }
+}
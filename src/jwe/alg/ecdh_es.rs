@@ -1,6 +1,7 @@
 use std::borrow::Cow;
 use std::fmt::Display;
 use std::ops::Deref;
+use std::str::FromStr;
 
 use anyhow::bail;
 use openssl::aes::{self, AesKey};
@@ -8,7 +9,9 @@ use openssl::derive::Deriver;
 use openssl::hash::{Hasher, MessageDigest};
 use openssl::pkey::{PKey, Private, Public};
 
-use crate::jwe::{JweAlgorithm, JweContentEncryption, JweDecrypter, JweEncrypter, JweHeader};
+use crate::jwe::{
+    JweAlgorithm, JweContentEncryption, JweDecrypter, JweEncrypter, JweHeader, DEFAULT_CONTEXT,
+};
 use crate::jwk::alg::{
     ec::{EcCurve, EcKeyPair},
     ecx::{EcxCurve, EcxKeyPair},
@@ -23,25 +26,64 @@ use crate::util::oid::{
 use crate::{JoseError, JoseHeader, Map, Value};
 
 #[derive(Debug, Eq, PartialEq, Copy, Clone)]
-enum EcdhEsKeyType {
+pub(crate) enum EcdhEsKeyType {
     Ec(EcCurve),
     Ecx(EcxCurve),
 }
 
 impl EcdhEsKeyType {
-    fn key_type(&self) -> &str {
+    pub(crate) fn key_type(&self) -> &str {
         match self {
             Self::Ec(_) => "EC",
             Self::Ecx(_) => "OKP",
         }
     }
 
-    fn curve_name(&self) -> &str {
+    pub(crate) fn curve_name(&self) -> &str {
         match self {
             Self::Ec(val) => val.name(),
             Self::Ecx(val) => val.name(),
         }
     }
+
+    /// Set the header claims that the key agreement sets, with a epk header claim that
+    /// has zero coordinates of the same length as a generated key.
+    pub(crate) fn set_placeholder_claims(
+        &self,
+        header: &mut JweHeader,
+        apu: Option<&[u8]>,
+        apv: Option<&[u8]>,
+    ) -> Result<(), JoseError> {
+        for (key, value) in &[("apu", apu), ("apv", apv)] {
+            if let (None, Some(val)) = (header.claim(key), value) {
+                let val_b64 = base64::encode_config(val, base64::URL_SAFE_NO_PAD);
+                header.set_claim(key, Some(Value::String(val_b64)))?;
+            }
+        }
+
+        let coordinate = |len: usize| {
+            Value::String(base64::encode_config(vec![0; len], base64::URL_SAFE_NO_PAD))
+        };
+        let mut map = Map::new();
+        map.insert(
+            "kty".to_string(),
+            Value::String(self.key_type().to_string()),
+        );
+        map.insert(
+            "crv".to_string(),
+            Value::String(self.curve_name().to_string()),
+        );
+        match self {
+            Self::Ec(curve) => {
+                map.insert("x".to_string(), coordinate(curve.coordinate_size()));
+                map.insert("y".to_string(), coordinate(curve.coordinate_size()));
+            }
+            Self::Ecx(curve) => {
+                map.insert("x".to_string(), coordinate(curve.key_len()));
+            }
+        }
+        header.set_claim("epk", Some(Value::Object(map)))
+    }
 }
 
 impl Display for EcdhEsKeyType {
@@ -230,13 +272,7 @@ impl EcdhEsJweAlgorithm {
                             None => bail!("A parameter y is required."),
                         };
 
-                        let mut vec = Vec::with_capacity(1 + x.len() + y.len());
-                        vec.push(0x04);
-                        vec.extend_from_slice(&x);
-                        vec.extend_from_slice(&y);
-
-                        let pkcs8 = EcKeyPair::to_pkcs8(&vec, true, curve);
-                        let public_key = PKey::public_key_from_der(&pkcs8)?;
+                        let public_key = EcKeyPair::public_key_from_coordinates(&x, &y, curve)?;
 
                         (public_key, EcdhEsKeyType::Ec(curve))
                     }
@@ -244,6 +280,10 @@ impl EcdhEsJweAlgorithm {
                         let curve = match val.as_str() {
                             "X25519" => EcxCurve::X25519,
                             "X448" => EcxCurve::X448,
+                            "Ed25519" | "Ed448" => bail!(
+                                "OKP key of {} curve is only for signing, not for key agreement.",
+                                val
+                            ),
                             val => bail!("OKP key doesn't support the curve algorithm: {}", val),
                         };
                         let x = match jwk.parameter("x") {
@@ -254,8 +294,7 @@ impl EcdhEsJweAlgorithm {
                             None => bail!("A parameter x is required."),
                         };
 
-                        let pkcs8 = EcxKeyPair::to_pkcs8(&x, true, curve);
-                        let public_key = PKey::public_key_from_der(&pkcs8)?;
+                        let public_key = EcxKeyPair::public_key_from_bytes(&x, curve)?;
 
                         (public_key, EcdhEsKeyType::Ecx(curve))
                     }
@@ -397,6 +436,10 @@ impl EcdhEsJweAlgorithm {
                         let curve = match val.as_str() {
                             "X25519" => EcxCurve::X25519,
                             "X448" => EcxCurve::X448,
+                            "Ed25519" | "Ed448" => bail!(
+                                "OKP key of {} curve is only for signing, not for key agreement.",
+                                val
+                            ),
                             val => bail!("OKP key doesn't support the curve algorithm: {}", val),
                         };
                         match jwk.curve() {
@@ -499,59 +542,6 @@ impl EcdhEsJweAlgorithm {
 
         Some(key_type)
     }
-
-    fn concat_kdf(
-        &self,
-        alg: &str,
-        shared_key_len: usize,
-        derived_key: &[u8],
-        apu: Option<&[u8]>,
-        apv: Option<&[u8]>,
-    ) -> anyhow::Result<Vec<u8>> {
-        let shared_key_len_bytes = ((shared_key_len * 8) as u32).to_be_bytes();
-        let alg_len_bytes = (alg.len() as u32).to_be_bytes();
-        let apu_len_bytes = (match apu {
-            Some(val) => val.len(),
-            None => 0,
-        } as u32)
-            .to_be_bytes();
-        let apv_len_bytes = (match apv {
-            Some(val) => val.len(),
-            None => 0,
-        } as u32)
-            .to_be_bytes();
-
-        let mut shared_key = Vec::new();
-        let md = MessageDigest::sha256();
-        let count = util::ceiling(shared_key_len, md.size());
-        for i in 0..count {
-            let mut hasher = Hasher::new(md)?;
-            hasher.update(&((i + 1) as u32).to_be_bytes())?;
-            hasher.update(&derived_key)?;
-            hasher.update(&alg_len_bytes)?;
-            hasher.update(alg.as_bytes())?;
-            hasher.update(&apu_len_bytes)?;
-            if let Some(val) = apu {
-                hasher.update(val)?;
-            }
-            hasher.update(&apv_len_bytes)?;
-            if let Some(val) = apv {
-                hasher.update(val)?;
-            }
-            hasher.update(&shared_key_len_bytes)?;
-
-            let digest = hasher.finish()?;
-            shared_key.extend(digest.to_vec());
-        }
-
-        if shared_key.len() > shared_key_len {
-            shared_key.truncate(shared_key_len);
-        } else if shared_key.len() < shared_key_len {
-            unreachable!();
-        }
-
-        Ok(shared_key)
-    }
 }
 
 impl JweAlgorithm for EcdhEsJweAlgorithm {
@@ -575,6 +565,23 @@ impl Display for EcdhEsJweAlgorithm {
     }
 }
 
+impl FromStr for EcdhEsJweAlgorithm {
+    type Err = JoseError;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "ECDH-ES" => Ok(Self::EcdhEs),
+            "ECDH-ES+A128KW" => Ok(Self::EcdhEsA128kw),
+            "ECDH-ES+A192KW" => Ok(Self::EcdhEsA192kw),
+            "ECDH-ES+A256KW" => Ok(Self::EcdhEsA256kw),
+            _ => Err(JoseError::InvalidJweFormat(anyhow::anyhow!(
+                "Unknown algorithm: {}",
+                name
+            ))),
+        }
+    }
+}
+
 impl Deref for EcdhEsJweAlgorithm {
     type Target = dyn JweAlgorithm;
 
@@ -583,6 +590,142 @@ impl Deref for EcdhEsJweAlgorithm {
     }
 }
 
+pub(crate) fn concat_kdf(
+    alg: &str,
+    shared_key_len: usize,
+    derived_key: &[u8],
+    apu: Option<&[u8]>,
+    apv: Option<&[u8]>,
+) -> anyhow::Result<Vec<u8>> {
+    concat_kdf_with_tag(alg, shared_key_len, derived_key, apu, apv, None)
+}
+
+/// The Concat KDF with the authentication tag of the content appended to SuppPubInfo
+/// as the length-prefixed cctag, as the key wrapping mode of ECDH-1PU requires.
+pub(crate) fn concat_kdf_with_tag(
+    alg: &str,
+    shared_key_len: usize,
+    derived_key: &[u8],
+    apu: Option<&[u8]>,
+    apv: Option<&[u8]>,
+    tag: Option<&[u8]>,
+) -> anyhow::Result<Vec<u8>> {
+    let shared_key_len_bytes = ((shared_key_len * 8) as u32).to_be_bytes();
+    let alg_len_bytes = (alg.len() as u32).to_be_bytes();
+    let apu_len_bytes = (match apu {
+        Some(val) => val.len(),
+        None => 0,
+    } as u32)
+        .to_be_bytes();
+    let apv_len_bytes = (match apv {
+        Some(val) => val.len(),
+        None => 0,
+    } as u32)
+        .to_be_bytes();
+
+    let mut shared_key = Vec::new();
+    let md = MessageDigest::sha256();
+    let count = util::ceiling(shared_key_len, md.size());
+    for i in 0..count {
+        let mut hasher = Hasher::new(md)?;
+        hasher.update(&((i + 1) as u32).to_be_bytes())?;
+        hasher.update(&derived_key)?;
+        hasher.update(&alg_len_bytes)?;
+        hasher.update(alg.as_bytes())?;
+        hasher.update(&apu_len_bytes)?;
+        if let Some(val) = apu {
+            hasher.update(val)?;
+        }
+        hasher.update(&apv_len_bytes)?;
+        if let Some(val) = apv {
+            hasher.update(val)?;
+        }
+        hasher.update(&shared_key_len_bytes)?;
+        if let Some(val) = tag {
+            hasher.update(&(val.len() as u32).to_be_bytes())?;
+            hasher.update(val)?;
+        }
+
+        let digest = hasher.finish()?;
+        shared_key.extend(digest.to_vec());
+    }
+
+    if shared_key.len() > shared_key_len {
+        shared_key.truncate(shared_key_len);
+    } else if shared_key.len() < shared_key_len {
+        unreachable!();
+    }
+
+    Ok(shared_key)
+}
+
+/// Return the key that is derived by the Concat KDF from the key agreement of a key pair,
+/// as the ECDH-ES encrypter and decrypter derive it.
+///
+/// For "ECDH-ES", the key is the content encryption key of the "enc" algorithm. For
+/// "ECDH-ES+A128KW" and so on, the key is the key encryption key and "enc" is not used.
+///
+/// # Arguments
+///
+/// * `private_key` - The private key of a party.
+/// * `public_key` - The public key of the other party, e.g. the epk header claim.
+/// * `alg` - The JWE alg header claim value.
+/// * `enc` - The JWE enc header claim value.
+/// * `apu` - The agreement PartyUInfo.
+/// * `apv` - The agreement PartyVInfo.
+pub fn derive_key(
+    private_key: &Jwk,
+    public_key: &Jwk,
+    alg: &str,
+    enc: &str,
+    apu: Option<&[u8]>,
+    apv: Option<&[u8]>,
+) -> Result<Vec<u8>, JoseError> {
+    (|| -> anyhow::Result<Vec<u8>> {
+        let algorithm = EcdhEsJweAlgorithm::from_str(alg)?;
+        let decrypter = algorithm.decrypter_from_jwk(private_key)?;
+        let encrypter = algorithm.encrypter_from_jwk(public_key)?;
+        if decrypter.key_type != encrypter.key_type {
+            bail!(
+                "The curve of the public key is mismatched: {}",
+                encrypter.key_type
+            );
+        }
+
+        let mut deriver = Deriver::new(&decrypter.private_key)?;
+        deriver.set_peer(&encrypter.public_key)?;
+        let derived_key = deriver.derive_to_vec()?;
+
+        let shared_key = if let EcdhEsJweAlgorithm::EcdhEs = algorithm {
+            let cencryption = match DEFAULT_CONTEXT.get_content_encryption(enc) {
+                Some(val) => val,
+                None => bail!("A content encryption is not registered: {}", enc),
+            };
+            concat_kdf(
+                cencryption.name(),
+                cencryption.key_len(),
+                &derived_key,
+                apu,
+                apv,
+            )?
+        } else {
+            concat_kdf(
+                algorithm.name(),
+                algorithm.key_len(),
+                &derived_key,
+                apu,
+                apv,
+            )?
+        };
+
+        Ok(shared_key)
+    })()
+    .map_err(|err| match err.downcast::<JoseError>() {
+        Ok(err) => err,
+        Err(err) => JoseError::InvalidKeyFormat(err),
+    })
+}
+
 #[derive(Debug, Clone)]
 pub struct EcdhEsJweEncrypter {
     algorithm: EcdhEsJweAlgorithm,
@@ -594,6 +737,9 @@ pub struct EcdhEsJweEncrypter {
 }
 
 impl EcdhEsJweEncrypter {
+    /// Set the agreement PartyUInfo that is used for the Concat KDF.
+    ///
+    /// The value is written into the apu header claim when it is not set in the header.
     pub fn set_agreement_partyuinfo(&mut self, value: impl Into<Vec<u8>>) {
         self.agreement_partyuinfo = Some(value.into());
     }
@@ -602,6 +748,9 @@ impl EcdhEsJweEncrypter {
         self.agreement_partyuinfo = None;
     }
 
+    /// Set the agreement PartyVInfo that is used for the Concat KDF.
+    ///
+    /// The value is written into the apv header claim when it is not set in the header.
     pub fn set_agreement_partyvinfo(&mut self, value: impl Into<Vec<u8>>) {
         self.agreement_partyvinfo = Some(value.into());
     }
@@ -708,13 +857,8 @@ impl EcdhEsJweEncrypter {
             deriver.set_peer(&self.public_key)?;
             let derived_key = deriver.derive_to_vec()?;
 
-            let shared_key = self.algorithm.concat_kdf(
-                alg,
-                key_len,
-                &derived_key,
-                apu.as_deref(),
-                apv.as_deref(),
-            )?;
+            let shared_key =
+                concat_kdf(alg, key_len, &derived_key, apu.as_deref(), apv.as_deref())?;
 
             Ok(shared_key)
         })()
@@ -791,6 +935,25 @@ impl JweEncrypter for EcdhEsJweEncrypter {
         })
     }
 
+    fn estimate_encrypted_key_len(
+        &self,
+        cencryption: &dyn JweContentEncryption,
+        _merged: &JweHeader,
+        header: &mut JweHeader,
+    ) -> Result<usize, JoseError> {
+        self.key_type.set_placeholder_claims(
+            header,
+            self.agreement_partyuinfo.as_deref(),
+            self.agreement_partyvinfo.as_deref(),
+        )?;
+
+        if let EcdhEsJweAlgorithm::EcdhEs = self.algorithm {
+            Ok(0)
+        } else {
+            Ok(cencryption.key_len() + 8)
+        }
+    }
+
     fn box_clone(&self) -> Box<dyn JweEncrypter> {
         Box::new(self.clone())
     }
@@ -914,13 +1077,7 @@ impl JweDecrypter for EcdhEsJweDecrypter {
                                 None => bail!("The x parameter in epk header claim is required."),
                             };
 
-                            let mut vec = Vec::with_capacity(1 + x.len() + y.len());
-                            vec.push(0x04);
-                            vec.extend_from_slice(&x);
-                            vec.extend_from_slice(&y);
-
-                            let pkcs8 = EcKeyPair::to_pkcs8(&vec, true, *curve);
-                            PKey::public_key_from_der(&pkcs8)?
+                            EcKeyPair::public_key_from_coordinates(&x, &y, *curve)?
                         }
                         EcdhEsKeyType::Ecx(curve) => {
                             let x = match map.get("x") {
@@ -933,8 +1090,7 @@ impl JweDecrypter for EcdhEsJweDecrypter {
                                 None => bail!("The x parameter in epk header claim is required."),
                             };
 
-                            let pkcs8 = EcxKeyPair::to_pkcs8(&x, true, *curve);
-                            PKey::public_key_from_der(&pkcs8)?
+                            EcxKeyPair::public_key_from_bytes(&x, *curve)?
                         }
                     }
                 }
@@ -948,7 +1104,7 @@ impl JweDecrypter for EcdhEsJweDecrypter {
 
             // concat KDF
             if let EcdhEsJweAlgorithm::EcdhEs = self.algorithm {
-                let shared_key = self.algorithm.concat_kdf(
+                let shared_key = concat_kdf(
                     cencryption.name(),
                     cencryption.key_len(),
                     &derived_key,
@@ -957,7 +1113,7 @@ impl JweDecrypter for EcdhEsJweDecrypter {
                 )?;
                 Ok(Cow::Owned(shared_key))
             } else {
-                let shared_key = self.algorithm.concat_kdf(
+                let shared_key = concat_kdf(
                     self.algorithm.name(),
                     self.algorithm.key_len(),
                     &derived_key,
@@ -971,7 +1127,8 @@ impl JweDecrypter for EcdhEsJweDecrypter {
                 };
 
                 let encrypted_key = match encrypted_key {
-                    Some(val) => val,
+                    Some(val) if val.len() > 8 => val,
+                    Some(_) => bail!("The encrypted_key is too short."),
                     None => unreachable!(),
                 };
 
@@ -1007,6 +1164,8 @@ impl Deref for EcdhEsJweDecrypter {
 #[cfg(test)]
 mod tests {
     use anyhow::Result;
+    use openssl::derive::Deriver;
+    use serde_json::json;
     use std::borrow::Cow;
     use std::fs;
     use std::path::PathBuf;
@@ -1014,10 +1173,99 @@ mod tests {
     use super::{EcdhEsJweAlgorithm, EcdhEsKeyType};
     use crate::jwe::enc::aescbc_hmac::AescbcHmacJweEncryption;
     use crate::jwe::enc::aesgcm::AesgcmJweEncryption;
-    use crate::jwe::JweHeader;
+    use crate::jwe::{self, JweHeader};
     use crate::jwk::alg::{ec::EcCurve, ecx::EcxCurve};
     use crate::jwk::Jwk;
     use crate::util;
+    use crate::{JoseError, Value};
+
+    #[test]
+    fn encrypt_and_decrypt_ecdh_es_with_agreement_partyinfo() -> Result<()> {
+        let enc = AesgcmJweEncryption::A128gcm;
+        let alg = EcdhEsJweAlgorithm::EcdhEs;
+
+        let private_key = load_file("der/EC_P-256_pkcs8_private.der")?;
+        let public_key = load_file("der/EC_P-256_spki_public.der")?;
+
+        let mut header = JweHeader::new();
+        header.set_content_encryption(enc.name());
+
+        let mut encrypter = alg.encrypter_from_der(&public_key)?;
+        encrypter.set_agreement_partyuinfo("Alice");
+        encrypter.set_agreement_partyvinfo("Bob");
+        let mut out_header = header.clone();
+        let src_key = encrypter
+            .compute_content_encryption_key(&enc, &header, &mut out_header)?
+            .unwrap()
+            .into_owned();
+        out_header.set_algorithm(alg.name());
+
+        assert_eq!(out_header.agreement_partyuinfo(), Some(b"Alice".to_vec()));
+        assert_eq!(out_header.agreement_partyvinfo(), Some(b"Bob".to_vec()));
+
+        let decrypter = alg.decrypter_from_der(&private_key)?;
+        let dst_key = decrypter.decrypt(None, &enc, &out_header)?;
+        assert_eq!(src_key, dst_key.into_owned());
+
+        let mut out_header_without_partyinfo = out_header.clone();
+        out_header_without_partyinfo.set_claim("apu", None)?;
+        out_header_without_partyinfo.set_claim("apv", None)?;
+        let dst_key = decrypter.decrypt(None, &enc, &out_header_without_partyinfo)?;
+        assert_ne!(src_key, dst_key.into_owned());
+
+        Ok(())
+    }
+
+    #[test]
+    fn derive_key_ecdh_es_as_encrypter() -> Result<()> {
+        let private_key = Jwk::from_bytes(load_file("jwk/EC_P-256_private.jwk")?)?;
+        let public_key = Jwk::from_bytes(load_file("jwk/EC_P-256_public.jwk")?)?;
+
+        let mut header = JweHeader::new();
+        header.set_content_encryption("A128GCM");
+
+        let mut encrypter = EcdhEsJweAlgorithm::EcdhEs.encrypter_from_jwk(&public_key)?;
+        encrypter.set_agreement_partyuinfo("Alice");
+        encrypter.set_agreement_partyvinfo("Bob");
+        let (jwe, cek) = jwe::serialize_compact_with_cek_out(b"payload", &header, &encrypter)?;
+
+        let header = util::base64url_decode(jwe.split('.').next().unwrap())?;
+        let header = JweHeader::from_bytes(&header)?;
+        let epk = match header.claim("epk") {
+            Some(Value::Object(val)) => Jwk::from_map(val.clone())?,
+            _ => unreachable!(),
+        };
+        let key = super::derive_key(
+            &private_key,
+            &epk,
+            "ECDH-ES",
+            "A128GCM",
+            Some(b"Alice"),
+            Some(b"Bob"),
+        )?;
+        assert_eq!(key, cek);
+
+        let key = super::derive_key(&private_key, &epk, "ECDH-ES", "A128GCM", None, None)?;
+        assert_ne!(key, cek);
+
+        let kek = super::derive_key(&private_key, &epk, "ECDH-ES+A256KW", "A128GCM", None, None)?;
+        assert_eq!(kek.len(), 32);
+
+        let x25519_public_key = Jwk::from_bytes(load_file("jwk/OKP_X25519_public.jwk")?)?;
+        assert!(matches!(
+            super::derive_key(
+                &private_key,
+                &x25519_public_key,
+                "ECDH-ES",
+                "A128GCM",
+                None,
+                None
+            ),
+            Err(JoseError::InvalidKeyFormat(_))
+        ));
+
+        Ok(())
+    }
 
     #[test]
     fn encrypt_and_decrypt_ecdh_es_with_pkcs8_der() -> Result<()> {
@@ -1271,6 +1519,120 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn decrypt_ecdh_es_with_x25519_test_vector() -> Result<()> {
+        // The key pairs and the shared secret are from RFC 7748 section 6.1, which
+        // RFC 8037 appendix A.6 also uses. The RFCs stop at the shared secret, so the
+        // content encryption key and the wrapped key below are computed from it by
+        // the Concat KDF of this module.
+        let private_key = Jwk::from_bytes(
+            json!({
+                "kty": "OKP",
+                "crv": "X25519",
+                "kid": "Bob",
+                "x": "3p7bfXt9wbTTW2HC7OQ1Nz-DQ8hbeGdNrfx-FG-IK08",
+                "d": "XasIfmJKikt54X-Lg4AO5m87sSkmGLb9HC-LJ_-I4Os"
+            })
+            .to_string(),
+        )?;
+        let epk = json!({
+            "kty": "OKP",
+            "crv": "X25519",
+            "x": "hSDwCYkwp1R0i33ctD73Wg2_Og0mOBr066SpjqqbTmo"
+        });
+
+        let enc = AesgcmJweEncryption::A128gcm;
+        let header = JweHeader::from_bytes(
+            json!({ "alg": "ECDH-ES", "enc": "A128GCM", "kid": "Bob", "epk": epk })
+                .to_string()
+                .as_bytes(),
+        )?;
+        let decrypter = EcdhEsJweAlgorithm::EcdhEs.decrypter_from_jwk(&private_key)?;
+        let encrypter =
+            EcdhEsJweAlgorithm::EcdhEs.encrypter_from_jwk(&Jwk::from_bytes(epk.to_string())?)?;
+        let mut deriver = Deriver::new(&decrypter.private_key)?;
+        deriver.set_peer(&encrypter.public_key)?;
+        let shared_secret = deriver.derive_to_vec()?;
+        assert_eq!(
+            shared_secret,
+            [
+                0x4a, 0x5d, 0x9d, 0x5b, 0xa4, 0xce, 0x2d, 0xe1, 0x72, 0x8e, 0x3b, 0xf4, 0x80, 0x35,
+                0x0f, 0x25, 0xe0, 0x7e, 0x21, 0xc9, 0x47, 0xd1, 0x9e, 0x33, 0x76, 0xf0, 0x9b, 0x3c,
+                0x1e, 0x16, 0x17, 0x42,
+            ]
+        );
+
+        let key = decrypter.decrypt(None, &enc, &header)?;
+        assert_eq!(
+            key.as_ref(),
+            super::concat_kdf("A128GCM", 16, &shared_secret, None, None)?.as_slice()
+        );
+        assert_eq!(
+            key.as_ref(),
+            &[
+                0xea, 0x3f, 0xf6, 0x4f, 0x86, 0xe8, 0x3c, 0x4e, 0x53, 0xd0, 0x5e, 0x80, 0x4a, 0xee,
+                0x7a, 0x5e,
+            ]
+        );
+
+        let enc = AesgcmJweEncryption::A256gcm;
+        let header = JweHeader::from_bytes(
+            json!({ "alg": "ECDH-ES+A256KW", "enc": "A256GCM", "kid": "Bob", "epk": epk })
+                .to_string()
+                .as_bytes(),
+        )?;
+        let encrypted_key = [
+            0xa3, 0xea, 0xe8, 0x8c, 0x9b, 0x67, 0xd6, 0xb1, 0x18, 0xf2, 0xbe, 0x13, 0x01, 0xaf,
+            0xb0, 0xb8, 0x22, 0x5d, 0x38, 0xe9, 0x19, 0x72, 0xfc, 0xdb, 0x4f, 0x21, 0x81, 0xa1,
+            0x7b, 0xa9, 0x57, 0x72, 0x84, 0x9b, 0xd0, 0xdc, 0x89, 0x0b, 0x89, 0xd7,
+        ];
+        let decrypter = EcdhEsJweAlgorithm::EcdhEsA256kw.decrypter_from_jwk(&private_key)?;
+        let key = decrypter.decrypt(Some(&encrypted_key), &enc, &header)?;
+        assert_eq!(key.as_ref(), (0..32).collect::<Vec<u8>>().as_slice());
+
+        Ok(())
+    }
+
+    #[test]
+    fn reject_ecdh_es_with_ed25519_key() -> Result<()> {
+        let private_key = Jwk::from_bytes(load_file("jwk/OKP_Ed25519_private.jwk")?)?;
+        let public_key = Jwk::from_bytes(load_file("jwk/OKP_Ed25519_public.jwk")?)?;
+
+        match EcdhEsJweAlgorithm::EcdhEs.encrypter_from_jwk(&public_key) {
+            Err(JoseError::InvalidKeyFormat(err)) => {
+                assert!(err.to_string().contains("only for signing"))
+            }
+            result => panic!("unexpected result: {:?}", result),
+        }
+        match EcdhEsJweAlgorithm::EcdhEs.decrypter_from_jwk(&private_key) {
+            Err(JoseError::InvalidKeyFormat(err)) => {
+                assert!(err.to_string().contains("only for signing"))
+            }
+            result => panic!("unexpected result: {:?}", result),
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn reject_ecdh_es_with_x448_key_of_wrong_length() -> Result<()> {
+        let x25519_public_key = Jwk::from_bytes(load_file("jwk/OKP_X25519_public.jwk")?)?;
+        let mut public_key = Jwk::from_bytes(load_file("jwk/OKP_X448_public.jwk")?)?;
+        public_key.set_parameter("x", x25519_public_key.parameter("x").cloned())?;
+
+        match EcdhEsJweAlgorithm::EcdhEs.encrypter_from_jwk(&public_key) {
+            Err(JoseError::InvalidKeyFormat(err)) => {
+                assert_eq!(
+                    err.to_string(),
+                    "The length of the X448 public key must be 56: 32"
+                )
+            }
+            result => panic!("unexpected result: {:?}", result),
+        }
+
+        Ok(())
+    }
+
     fn load_file(path: &str) -> Result<Vec<u8>> {
         let mut pb = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
         pb.push("data");
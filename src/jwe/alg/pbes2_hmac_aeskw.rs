@@ -2,15 +2,17 @@ use std::borrow::Cow;
 use std::convert::TryFrom;
 use std::fmt::Display;
 use std::ops::Deref;
+use std::str::FromStr;
 
 use anyhow::bail;
 use openssl::aes::{self, AesKey};
 use openssl::pkcs5;
+use zeroize::Zeroize;
 
 use crate::jwe::{JweAlgorithm, JweContentEncryption, JweDecrypter, JweEncrypter, JweHeader};
 use crate::jwk::Jwk;
-use crate::util::{self, HashAlgorithm};
-use crate::{JoseError, JoseHeader, Number, Value};
+use crate::util::HashAlgorithm;
+use crate::{JoseError, JoseHeader, JoseRandom, Number, OsRandom, Value};
 
 #[derive(Debug, Eq, PartialEq, Copy, Clone)]
 pub enum Pbes2HmacAeskwJweAlgorithm {
@@ -101,6 +103,7 @@ impl Pbes2HmacAeskwJweAlgorithm {
             Ok(Pbes2HmacAeskwJweDecrypter {
                 algorithm: self.clone(),
                 private_key,
+                max_iter_count: 1000000,
                 key_id: None,
             })
         })()
@@ -142,6 +145,7 @@ impl Pbes2HmacAeskwJweAlgorithm {
             Ok(Pbes2HmacAeskwJweDecrypter {
                 algorithm: self.clone(),
                 private_key: k,
+                max_iter_count: 1000000,
                 key_id,
             })
         })()
@@ -185,6 +189,22 @@ impl Display for Pbes2HmacAeskwJweAlgorithm {
     }
 }
 
+impl FromStr for Pbes2HmacAeskwJweAlgorithm {
+    type Err = JoseError;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "PBES2-HS256+A128KW" => Ok(Self::Pbes2Hs256A128kw),
+            "PBES2-HS384+A192KW" => Ok(Self::Pbes2Hs384A192kw),
+            "PBES2-HS512+A256KW" => Ok(Self::Pbes2Hs512A256kw),
+            _ => Err(JoseError::InvalidJweFormat(anyhow::anyhow!(
+                "Unknown algorithm: {}",
+                name
+            ))),
+        }
+    }
+}
+
 impl Deref for Pbes2HmacAeskwJweAlgorithm {
     type Target = dyn JweAlgorithm;
 
@@ -203,6 +223,15 @@ pub struct Pbes2HmacAeskwJweEncrypter {
 }
 
 impl Pbes2HmacAeskwJweEncrypter {
+    /// Set the length of the salt that is generated as p2s header claim. The default value is 8.
+    ///
+    /// # Arguments
+    ///
+    /// * `salt_len` - The salt length. It must be 8 or more (RFC 7518 section 4.8.1.1).
+    ///
+    /// # Panics
+    ///
+    /// Panics if the salt length is less than 8.
     pub fn set_salt_len(&mut self, salt_len: usize) {
         if salt_len < 8 {
             panic!("salt_len must be 8 or more: {}", salt_len);
@@ -210,6 +239,15 @@ impl Pbes2HmacAeskwJweEncrypter {
         self.salt_len = salt_len;
     }
 
+    /// Set the iteration count that is written as p2c header claim. The default value is 1000.
+    ///
+    /// # Arguments
+    ///
+    /// * `iter_count` - The iteration count. It must be 1000 or more (RFC 7518 section 4.8.1.2).
+    ///
+    /// # Panics
+    ///
+    /// Panics if the iteration count is less than 1000.
     pub fn set_iter_count(&mut self, iter_count: usize) {
         if iter_count < 1000 {
             panic!("iter_count must be 1000 or more: {}", iter_count);
@@ -252,6 +290,16 @@ impl JweEncrypter for Pbes2HmacAeskwJweEncrypter {
         key: &[u8],
         in_header: &JweHeader,
         out_header: &mut JweHeader,
+    ) -> Result<Option<Vec<u8>>, JoseError> {
+        self.encrypt_with_rng(key, in_header, out_header, &OsRandom)
+    }
+
+    fn encrypt_with_rng(
+        &self,
+        key: &[u8],
+        in_header: &JweHeader,
+        out_header: &mut JweHeader,
+        rng: &dyn JoseRandom,
     ) -> Result<Option<Vec<u8>>, JoseError> {
         (|| -> anyhow::Result<Option<Vec<u8>>> {
             let p2s = match in_header.claim("p2s") {
@@ -264,7 +312,7 @@ impl JweEncrypter for Pbes2HmacAeskwJweEncrypter {
                 }
                 Some(_) => bail!("The p2s header claim must be string."),
                 None => {
-                    let p2s = util::random_bytes(self.salt_len);
+                    let p2s = rng.random_bytes(self.salt_len);
                     let p2s_b64 = base64::encode_config(&p2s, base64::URL_SAFE_NO_PAD);
                     out_header.set_claim("p2s", Some(Value::String(p2s_b64)))?;
                     p2s
@@ -312,11 +360,35 @@ impl JweEncrypter for Pbes2HmacAeskwJweEncrypter {
         .map_err(|err| JoseError::InvalidKeyFormat(err))
     }
 
+    fn estimate_encrypted_key_len(
+        &self,
+        cencryption: &dyn JweContentEncryption,
+        in_header: &JweHeader,
+        out_header: &mut JweHeader,
+    ) -> Result<usize, JoseError> {
+        if in_header.claim("p2s").is_none() {
+            let p2s_b64 = base64::encode_config(vec![0; self.salt_len], base64::URL_SAFE_NO_PAD);
+            out_header.set_claim("p2s", Some(Value::String(p2s_b64)))?;
+        }
+        if in_header.claim("p2c").is_none() {
+            let p2c = self.iter_count;
+            out_header.set_claim("p2c", Some(Value::Number(Number::from(p2c))))?;
+        }
+
+        Ok(cencryption.key_len() + 8)
+    }
+
     fn box_clone(&self) -> Box<dyn JweEncrypter> {
         Box::new(self.clone())
     }
 }
 
+impl Drop for Pbes2HmacAeskwJweEncrypter {
+    fn drop(&mut self) {
+        self.private_key.zeroize();
+    }
+}
+
 impl Deref for Pbes2HmacAeskwJweEncrypter {
     type Target = dyn JweEncrypter;
 
@@ -329,10 +401,23 @@ impl Deref for Pbes2HmacAeskwJweEncrypter {
 pub struct Pbes2HmacAeskwJweDecrypter {
     algorithm: Pbes2HmacAeskwJweAlgorithm,
     private_key: Vec<u8>,
+    max_iter_count: usize,
     key_id: Option<String>,
 }
 
 impl Pbes2HmacAeskwJweDecrypter {
+    /// Set the maximum value of p2c header claim to be accepted. The default value is 1000000.
+    ///
+    /// A JWE that has a larger p2c header claim is rejected before the key derivation
+    /// to avoid spending unbounded CPU time.
+    ///
+    /// # Arguments
+    ///
+    /// * `max_iter_count` - The maximum iteration count.
+    pub fn set_max_iter_count(&mut self, max_iter_count: usize) {
+        self.max_iter_count = max_iter_count;
+    }
+
     pub fn set_key_id(&mut self, value: impl Into<String>) {
         self.key_id = Some(value.into());
     }
@@ -362,7 +447,8 @@ impl JweDecrypter for Pbes2HmacAeskwJweDecrypter {
     ) -> Result<Cow<[u8]>, JoseError> {
         (|| -> anyhow::Result<Cow<[u8]>> {
             let encrypted_key = match encrypted_key {
-                Some(val) => val,
+                Some(val) if val.len() > 8 => val,
+                Some(_) => bail!("The encrypted_key is too short."),
                 None => bail!("A encrypted_key value is required."),
             };
 
@@ -382,9 +468,16 @@ impl JweDecrypter for Pbes2HmacAeskwJweDecrypter {
                     Some(val) => usize::try_from(val)?,
                     None => bail!("Overflow u64 value: {}", val),
                 },
-                Some(_) => bail!("The p2s header claim must be string."),
+                Some(_) => bail!("The p2c header claim must be number."),
                 None => bail!("The p2c header claim is required."),
             };
+            if p2c > self.max_iter_count {
+                bail!(
+                    "The p2c header claim must be {} or less: {}",
+                    self.max_iter_count,
+                    p2c
+                );
+            }
 
             let mut salt = Vec::with_capacity(self.algorithm().name().len() + 1 + p2s.len());
             salt.extend_from_slice(self.algorithm().name().as_bytes());
@@ -420,6 +513,12 @@ impl JweDecrypter for Pbes2HmacAeskwJweDecrypter {
     }
 }
 
+impl Drop for Pbes2HmacAeskwJweDecrypter {
+    fn drop(&mut self) {
+        self.private_key.zeroize();
+    }
+}
+
 impl Deref for Pbes2HmacAeskwJweDecrypter {
     type Target = dyn JweDecrypter;
 
@@ -436,9 +535,10 @@ mod tests {
 
     use super::Pbes2HmacAeskwJweAlgorithm;
     use crate::jwe::enc::aescbc_hmac::AescbcHmacJweEncryption;
-    use crate::jwe::JweHeader;
+    use crate::jwe::{self, JweHeader};
     use crate::jwk::Jwk;
     use crate::util;
+    use crate::{JoseError, JoseHeader, Value};
 
     #[test]
     fn encrypt_and_decrypt_pbes2_hmac() -> Result<()> {
@@ -476,4 +576,73 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn reject_pbes2_hmac_with_too_large_p2c() -> Result<()> {
+        let enc = AescbcHmacJweEncryption::A128cbcHs256;
+        let alg = Pbes2HmacAeskwJweAlgorithm::Pbes2Hs256A128kw;
+        let key = util::random_bytes(8);
+
+        let mut header = JweHeader::new();
+        header.set_content_encryption(enc.name());
+
+        let encrypter = alg.encrypter_from_bytes(&key)?;
+        let mut out_header = header.clone();
+        let src_key = util::random_bytes(enc.key_len());
+        let encrypted_key = encrypter.encrypt(&src_key, &header, &mut out_header)?;
+
+        let mut decrypter = alg.decrypter_from_bytes(&key)?;
+        out_header.set_claim("p2c", Some(json!(2_000_000_000u64)))?;
+        match decrypter.decrypt(encrypted_key.as_deref(), &enc, &out_header) {
+            Err(JoseError::InvalidJweFormat(_)) => {}
+            result => panic!("unexpected result: {:?}", result),
+        }
+
+        decrypter.set_max_iter_count(1000);
+        out_header.set_claim("p2c", Some(json!(1001)))?;
+        assert!(decrypter
+            .decrypt(encrypted_key.as_deref(), &enc, &out_header)
+            .is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn encrypt_and_decrypt_pbes2_hmac_with_custom_parameters() -> Result<()> {
+        let alg = Pbes2HmacAeskwJweAlgorithm::Pbes2Hs256A128kw;
+        let key = util::random_bytes(8);
+
+        let mut header = JweHeader::new();
+        header.set_content_encryption("A128CBC-HS256");
+        let src_payload = b"test payload!";
+
+        let mut encrypter = alg.encrypter_from_bytes(&key)?;
+        encrypter.set_salt_len(16);
+        encrypter.set_iter_count(250000);
+        let jwe = jwe::serialize_compact(src_payload, &header, &encrypter)?;
+
+        let decrypter = alg.decrypter_from_bytes(&key)?;
+        let (dst_payload, dst_header) = jwe::deserialize_compact(&jwe, &decrypter)?;
+        assert_eq!(dst_header.claim("p2c"), Some(&json!(250000)));
+        match dst_header.claim("p2s") {
+            Some(Value::String(val)) => {
+                assert_eq!(
+                    base64::decode_config(val, base64::URL_SAFE_NO_PAD)?.len(),
+                    16
+                )
+            }
+            val => panic!("unexpected p2s: {:?}", val),
+        }
+        assert_eq!(src_payload.to_vec(), dst_payload);
+
+        Ok(())
+    }
+
+    #[test]
+    #[should_panic]
+    fn reject_pbes2_hmac_with_too_short_salt() {
+        let alg = Pbes2HmacAeskwJweAlgorithm::Pbes2Hs256A128kw;
+        let mut encrypter = alg.encrypter_from_bytes(b"password").unwrap();
+        encrypter.set_salt_len(7);
+    }
 }
@@ -1,6 +1,7 @@
 use std::borrow::Cow;
 use std::fmt::Display;
 use std::ops::Deref;
+use std::str::FromStr;
 
 use anyhow::bail;
 use openssl::hash::MessageDigest;
@@ -13,6 +14,10 @@ use crate::util;
 use crate::util::der::{DerBuilder, DerType};
 use crate::{JoseError, Value};
 
+/// The error message for any failure of RSA-OAEP decryption, so that it does not
+/// tell a padding error from a length error.
+const DECRYPTION_FAILURE_MESSAGE: &str = "Failed to decrypt the encrypted key.";
+
 #[derive(Debug, Eq, PartialEq, Copy, Clone)]
 pub enum RsaesJweAlgorithm {
     /// RSAES-PKCS1-v1_5
@@ -97,9 +102,30 @@ impl RsaesJweAlgorithm {
         })
     }
 
+    /// Return a encrypter from a DER encoded key.
+    ///
+    /// RSA1_5 is rejected, because it is vulnerable to the Bleichenbacher attack.
+    /// Use [`RsaesJweAlgorithm::encrypter_from_der_insecure`] to use it anyway.
+    ///
+    /// # Arguments
+    ///
+    /// * `input` - a DER encoded key
     pub fn encrypter_from_der(
         &self,
         input: impl AsRef<[u8]>,
+    ) -> Result<RsaesJweEncrypter, JoseError> {
+        self.check_secure("encrypter_from_der")?;
+        self.encrypter_from_der_insecure(input)
+    }
+
+    /// Return a encrypter from a DER encoded key, allowing RSA1_5.
+    ///
+    /// # Arguments
+    ///
+    /// * `input` - a DER encoded key
+    pub fn encrypter_from_der_insecure(
+        &self,
+        input: impl AsRef<[u8]>,
     ) -> Result<RsaesJweEncrypter, JoseError> {
         (|| -> anyhow::Result<RsaesJweEncrypter> {
             let spki_der_vec;
@@ -127,9 +153,30 @@ impl RsaesJweAlgorithm {
         .map_err(|err| JoseError::InvalidKeyFormat(err))
     }
 
+    /// Return a encrypter from a PEM encoded key.
+    ///
+    /// RSA1_5 is rejected, because it is vulnerable to the Bleichenbacher attack.
+    /// Use [`RsaesJweAlgorithm::encrypter_from_pem_insecure`] to use it anyway.
+    ///
+    /// # Arguments
+    ///
+    /// * `input` - a PEM encoded key
     pub fn encrypter_from_pem(
         &self,
         input: impl AsRef<[u8]>,
+    ) -> Result<RsaesJweEncrypter, JoseError> {
+        self.check_secure("encrypter_from_pem")?;
+        self.encrypter_from_pem_insecure(input)
+    }
+
+    /// Return a encrypter from a PEM encoded key, allowing RSA1_5.
+    ///
+    /// # Arguments
+    ///
+    /// * `input` - a PEM encoded key
+    pub fn encrypter_from_pem_insecure(
+        &self,
+        input: impl AsRef<[u8]>,
     ) -> Result<RsaesJweEncrypter, JoseError> {
         (|| -> anyhow::Result<RsaesJweEncrypter> {
             let (alg, data) = util::parse_pem(input.as_ref())?;
@@ -160,7 +207,25 @@ impl RsaesJweAlgorithm {
         .map_err(|err| JoseError::InvalidKeyFormat(err))
     }
 
+    /// Return a encrypter from a JWK.
+    ///
+    /// RSA1_5 is rejected, because it is vulnerable to the Bleichenbacher attack.
+    /// Use [`RsaesJweAlgorithm::encrypter_from_jwk_insecure`] to use it anyway.
+    ///
+    /// # Arguments
+    ///
+    /// * `jwk` - a JWK
     pub fn encrypter_from_jwk(&self, jwk: &Jwk) -> Result<RsaesJweEncrypter, JoseError> {
+        self.check_secure("encrypter_from_jwk")?;
+        self.encrypter_from_jwk_insecure(jwk)
+    }
+
+    /// Return a encrypter from a JWK, allowing RSA1_5.
+    ///
+    /// # Arguments
+    ///
+    /// * `jwk` - a JWK
+    pub fn encrypter_from_jwk_insecure(&self, jwk: &Jwk) -> Result<RsaesJweEncrypter, JoseError> {
         (|| -> anyhow::Result<RsaesJweEncrypter> {
             match jwk.key_type() {
                 val if val == "RSA" => {}
@@ -218,9 +283,30 @@ impl RsaesJweAlgorithm {
         .map_err(|err| JoseError::InvalidKeyFormat(err))
     }
 
+    /// Return a decrypter from a DER encoded key.
+    ///
+    /// RSA1_5 is rejected, because it is vulnerable to the Bleichenbacher attack.
+    /// Use [`RsaesJweAlgorithm::decrypter_from_der_insecure`] to use it anyway.
+    ///
+    /// # Arguments
+    ///
+    /// * `input` - a DER encoded key
     pub fn decrypter_from_der(
         &self,
         input: impl AsRef<[u8]>,
+    ) -> Result<RsaesJweDecrypter, JoseError> {
+        self.check_secure("decrypter_from_der")?;
+        self.decrypter_from_der_insecure(input)
+    }
+
+    /// Return a decrypter from a DER encoded key, allowing RSA1_5.
+    ///
+    /// # Arguments
+    ///
+    /// * `input` - a DER encoded key
+    pub fn decrypter_from_der_insecure(
+        &self,
+        input: impl AsRef<[u8]>,
     ) -> Result<RsaesJweDecrypter, JoseError> {
         let key_pair = self.key_pair_from_der(input.as_ref())?;
         Ok(RsaesJweDecrypter {
@@ -230,9 +316,30 @@ impl RsaesJweAlgorithm {
         })
     }
 
+    /// Return a decrypter from a PEM encoded key.
+    ///
+    /// RSA1_5 is rejected, because it is vulnerable to the Bleichenbacher attack.
+    /// Use [`RsaesJweAlgorithm::decrypter_from_pem_insecure`] to use it anyway.
+    ///
+    /// # Arguments
+    ///
+    /// * `input` - a PEM encoded key
     pub fn decrypter_from_pem(
         &self,
         input: impl AsRef<[u8]>,
+    ) -> Result<RsaesJweDecrypter, JoseError> {
+        self.check_secure("decrypter_from_pem")?;
+        self.decrypter_from_pem_insecure(input)
+    }
+
+    /// Return a decrypter from a PEM encoded key, allowing RSA1_5.
+    ///
+    /// # Arguments
+    ///
+    /// * `input` - a PEM encoded key
+    pub fn decrypter_from_pem_insecure(
+        &self,
+        input: impl AsRef<[u8]>,
     ) -> Result<RsaesJweDecrypter, JoseError> {
         let key_pair = self.key_pair_from_pem(input.as_ref())?;
         Ok(RsaesJweDecrypter {
@@ -242,7 +349,25 @@ impl RsaesJweAlgorithm {
         })
     }
 
+    /// Return a decrypter from a JWK.
+    ///
+    /// RSA1_5 is rejected, because it is vulnerable to the Bleichenbacher attack.
+    /// Use [`RsaesJweAlgorithm::decrypter_from_jwk_insecure`] to use it anyway.
+    ///
+    /// # Arguments
+    ///
+    /// * `jwk` - a JWK
     pub fn decrypter_from_jwk(&self, jwk: &Jwk) -> Result<RsaesJweDecrypter, JoseError> {
+        self.check_secure("decrypter_from_jwk")?;
+        self.decrypter_from_jwk_insecure(jwk)
+    }
+
+    /// Return a decrypter from a JWK, allowing RSA1_5.
+    ///
+    /// # Arguments
+    ///
+    /// * `jwk` - a JWK
+    pub fn decrypter_from_jwk_insecure(&self, jwk: &Jwk) -> Result<RsaesJweDecrypter, JoseError> {
         (|| -> anyhow::Result<RsaesJweDecrypter> {
             match jwk.key_use() {
                 Some(val) if val == "enc" => {}
@@ -274,6 +399,17 @@ impl RsaesJweAlgorithm {
         })()
         .map_err(|err| JoseError::InvalidKeyFormat(err))
     }
+
+    #[allow(deprecated)]
+    fn check_secure(&self, method: &str) -> Result<(), JoseError> {
+        if let Self::Rsa1_5 = self {
+            return Err(JoseError::InvalidKeyFormat(anyhow::anyhow!(
+                "RSA1_5 is vulnerable to the Bleichenbacher attack. Use {}_insecure to opt in.",
+                method
+            )));
+        }
+        Ok(())
+    }
 }
 
 impl JweAlgorithm for RsaesJweAlgorithm {
@@ -299,6 +435,25 @@ impl Display for RsaesJweAlgorithm {
     }
 }
 
+impl FromStr for RsaesJweAlgorithm {
+    type Err = JoseError;
+
+    #[allow(deprecated)]
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "RSA1_5" => Ok(Self::Rsa1_5),
+            "RSA-OAEP" => Ok(Self::RsaOaep),
+            "RSA-OAEP-256" => Ok(Self::RsaOaep256),
+            "RSA-OAEP-384" => Ok(Self::RsaOaep384),
+            "RSA-OAEP-512" => Ok(Self::RsaOaep512),
+            _ => Err(JoseError::InvalidJweFormat(anyhow::anyhow!(
+                "Unknown algorithm: {}",
+                name
+            ))),
+        }
+    }
+}
+
 impl Deref for RsaesJweAlgorithm {
     type Target = dyn JweAlgorithm;
 
@@ -389,6 +544,15 @@ impl JweEncrypter for RsaesJweEncrypter {
         .map_err(|err| JoseError::InvalidKeyFormat(err))
     }
 
+    fn estimate_encrypted_key_len(
+        &self,
+        _cencryption: &dyn JweContentEncryption,
+        _in_header: &JweHeader,
+        _out_header: &mut JweHeader,
+    ) -> Result<usize, JoseError> {
+        Ok(self.public_key.size())
+    }
+
     fn box_clone(&self) -> Box<dyn JweEncrypter> {
         Box::new(self.clone())
     }
@@ -431,11 +595,17 @@ impl JweDecrypter for RsaesJweDecrypter {
         }
     }
 
+    /// Decrypt the encrypted key.
+    ///
+    /// To resist chosen ciphertext attacks (RFC 7516 Section 11.5), a RSA1_5 key that
+    /// is failed to decrypt or has a wrong length is replaced with a random key, so
+    /// the failure is detected only by the authentication of the content. The other
+    /// algorithms return the same error for any failure.
     #[allow(deprecated)]
     fn decrypt(
         &self,
         encrypted_key: Option<&[u8]>,
-        _cencryption: &dyn JweContentEncryption,
+        cencryption: &dyn JweContentEncryption,
         _header: &JweHeader,
     ) -> Result<Cow<[u8]>, JoseError> {
         (|| -> anyhow::Result<Cow<[u8]>> {
@@ -445,39 +615,61 @@ impl JweDecrypter for RsaesJweDecrypter {
             };
 
             let rsa = self.private_key.rsa()?;
-            let key = match self.algorithm {
-                RsaesJweAlgorithm::Rsa1_5 => {
-                    let mut key = vec![0; rsa.size() as usize];
-                    let len = rsa.private_decrypt(&encrypted_key, &mut key, Padding::PKCS1)?;
-                    key.truncate(len);
-                    key
-                }
-                RsaesJweAlgorithm::RsaOaep => {
-                    let mut key = vec![0; rsa.size() as usize];
-                    let len = rsa.private_decrypt(&encrypted_key, &mut key, Padding::PKCS1_OAEP)?;
-                    key.truncate(len);
-                    key
+            if let RsaesJweAlgorithm::Rsa1_5 = self.algorithm {
+                let random_key = util::random_bytes(cencryption.key_len());
+                let mut key = vec![0; rsa.size() as usize];
+                let key = match rsa.private_decrypt(encrypted_key, &mut key, Padding::PKCS1) {
+                    Ok(len) if len == random_key.len() => {
+                        key.truncate(len);
+                        key
+                    }
+                    _ => random_key,
+                };
+                return Ok(Cow::Owned(key));
+            }
+
+            let decrypted = if encrypted_key.len() != rsa.size() as usize {
+                None
+            } else {
+                Some(match self.algorithm {
+                    RsaesJweAlgorithm::RsaOaep => {
+                        let mut key = vec![0; rsa.size() as usize];
+                        rsa.private_decrypt(encrypted_key, &mut key, Padding::PKCS1_OAEP)
+                            .map(|len| {
+                                key.truncate(len);
+                                key
+                            })
+                    }
+                    RsaesJweAlgorithm::RsaOaep256 => openssl_rsa_oaep::pkey_private_decrypt(
+                        &self.private_key,
+                        encrypted_key,
+                        MessageDigest::sha256(),
+                    ),
+                    RsaesJweAlgorithm::RsaOaep384 => openssl_rsa_oaep::pkey_private_decrypt(
+                        &self.private_key,
+                        encrypted_key,
+                        MessageDigest::sha384(),
+                    ),
+                    RsaesJweAlgorithm::RsaOaep512 => openssl_rsa_oaep::pkey_private_decrypt(
+                        &self.private_key,
+                        encrypted_key,
+                        MessageDigest::sha512(),
+                    ),
+                    RsaesJweAlgorithm::Rsa1_5 => unreachable!(),
+                })
+            };
+
+            let key = match decrypted {
+                Some(Ok(val)) => val,
+                Some(Err(err)) => {
+                    return Err(anyhow::Error::new(err).context(DECRYPTION_FAILURE_MESSAGE))
                 }
-                RsaesJweAlgorithm::RsaOaep256 => openssl_rsa_oaep::pkey_private_decrypt(
-                    &self.private_key,
-                    &encrypted_key,
-                    MessageDigest::sha256(),
-                )?,
-                RsaesJweAlgorithm::RsaOaep384 => openssl_rsa_oaep::pkey_private_decrypt(
-                    &self.private_key,
-                    &encrypted_key,
-                    MessageDigest::sha384(),
-                )?,
-                RsaesJweAlgorithm::RsaOaep512 => openssl_rsa_oaep::pkey_private_decrypt(
-                    &self.private_key,
-                    &encrypted_key,
-                    MessageDigest::sha512(),
-                )?,
+                None => bail!(DECRYPTION_FAILURE_MESSAGE),
             };
 
             Ok(Cow::Owned(key))
         })()
-        .map_err(|err| JoseError::InvalidJweFormat(err))
+        .map_err(JoseError::InvalidJweFormat)
     }
 
     fn box_clone(&self) -> Box<dyn JweDecrypter> {
@@ -496,6 +688,8 @@ impl Deref for RsaesJweDecrypter {
 #[cfg(test)]
 mod tests {
     use anyhow::Result;
+    use openssl::error::ErrorStack;
+    use std::error::Error;
     use std::fs;
     use std::path::PathBuf;
 
@@ -504,6 +698,7 @@ mod tests {
     use crate::jwe::JweHeader;
     use crate::jwk::Jwk;
     use crate::util;
+    use crate::JoseError;
 
     #[test]
     #[allow(deprecated)]
@@ -526,12 +721,12 @@ mod tests {
             let mut header = JweHeader::new();
             header.set_content_encryption(enc.name());
 
-            let encrypter = alg.encrypter_from_jwk(&public_key)?;
+            let encrypter = alg.encrypter_from_jwk_insecure(&public_key)?;
             let mut out_header = header.clone();
             let src_key = util::random_bytes(enc.key_len());
             let encrypted_key = encrypter.encrypt(&src_key, &header, &mut out_header)?;
 
-            let decrypter = alg.decrypter_from_jwk(&private_key)?;
+            let decrypter = alg.decrypter_from_jwk_insecure(&private_key)?;
             let dst_key = decrypter.decrypt(encrypted_key.as_deref(), &enc, &out_header)?;
 
             assert_eq!(&src_key as &[u8], &dst_key as &[u8]);
@@ -540,6 +735,150 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    #[allow(deprecated)]
+    fn rsaes_rsa1_5_requires_opt_in() -> Result<()> {
+        let alg = RsaesJweAlgorithm::Rsa1_5;
+
+        let private_key = Jwk::from_bytes(&load_file("jwk/RSA_private.jwk")?)?;
+        let public_key = Jwk::from_bytes(&load_file("jwk/RSA_public.jwk")?)?;
+        let private_pem = load_file("pem/RSA_2048bit_private.pem")?;
+        let public_pem = load_file("pem/RSA_2048bit_public.pem")?;
+
+        assert!(matches!(
+            alg.encrypter_from_jwk(&public_key),
+            Err(JoseError::InvalidKeyFormat(_))
+        ));
+        assert!(matches!(
+            alg.decrypter_from_jwk(&private_key),
+            Err(JoseError::InvalidKeyFormat(_))
+        ));
+        assert!(alg.encrypter_from_pem(&public_pem).is_err());
+        assert!(alg.decrypter_from_pem(&private_pem).is_err());
+        let err = alg.decrypter_from_jwk(&private_key).unwrap_err();
+        assert!(err.to_string().contains("decrypter_from_jwk_insecure"));
+
+        alg.encrypter_from_jwk_insecure(&public_key)?;
+        alg.decrypter_from_jwk_insecure(&private_key)?;
+        alg.encrypter_from_pem_insecure(&public_pem)?;
+        alg.decrypter_from_pem_insecure(&private_pem)?;
+
+        RsaesJweAlgorithm::RsaOaep.decrypter_from_jwk(&private_key)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn decrypt_rsaes_error_source() -> Result<()> {
+        let enc = AescbcHmacJweEncryption::A128cbcHs256;
+        let alg = RsaesJweAlgorithm::RsaOaep;
+
+        let private_key = Jwk::from_bytes(&load_file("jwk/RSA_private.jwk")?)?;
+        let public_key = Jwk::from_bytes(&load_file("jwk/RSA_public.jwk")?)?;
+
+        let mut header = JweHeader::new();
+        header.set_content_encryption(enc.name());
+
+        let encrypter = alg.encrypter_from_jwk(&public_key)?;
+        let mut out_header = header.clone();
+        let src_key = util::random_bytes(enc.key_len());
+        let mut encrypted_key = encrypter
+            .encrypt(&src_key, &header, &mut out_header)?
+            .unwrap();
+        encrypted_key[0] ^= 0x01;
+
+        let decrypter = alg.decrypter_from_jwk(&private_key)?;
+        let err = decrypter
+            .decrypt(Some(&encrypted_key), &enc, &out_header)
+            .unwrap_err();
+        assert!(matches!(err, JoseError::InvalidJweFormat(_)));
+
+        let source = err.source().unwrap().source().unwrap();
+        assert!(source.downcast_ref::<ErrorStack>().is_some());
+        assert!(!source.to_string().is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn decrypt_rsaes_uniform_error() -> Result<()> {
+        let enc = AescbcHmacJweEncryption::A128cbcHs256;
+
+        let private_key = Jwk::from_bytes(&load_file("jwk/RSA_private.jwk")?)?;
+        let public_key = Jwk::from_bytes(&load_file("jwk/RSA_public.jwk")?)?;
+
+        for alg in [
+            RsaesJweAlgorithm::RsaOaep,
+            RsaesJweAlgorithm::RsaOaep256,
+            RsaesJweAlgorithm::RsaOaep384,
+            RsaesJweAlgorithm::RsaOaep512,
+        ] {
+            let mut header = JweHeader::new();
+            header.set_content_encryption(enc.name());
+
+            let encrypter = alg.encrypter_from_jwk(&public_key)?;
+            let mut out_header = header.clone();
+            let src_key = util::random_bytes(enc.key_len());
+            let encrypted_key = encrypter
+                .encrypt(&src_key, &header, &mut out_header)?
+                .unwrap();
+            let decrypter = alg.decrypter_from_jwk(&private_key)?;
+
+            let mut padding_error = encrypted_key.clone();
+            padding_error[0] ^= 0x01;
+            let padding_error = decrypter
+                .decrypt(Some(&padding_error), &enc, &out_header)
+                .unwrap_err();
+
+            let length_error = &encrypted_key[1..];
+            let length_error = decrypter
+                .decrypt(Some(length_error), &enc, &out_header)
+                .unwrap_err();
+
+            assert!(matches!(padding_error, JoseError::InvalidJweFormat(_)));
+            assert!(matches!(length_error, JoseError::InvalidJweFormat(_)));
+            assert_eq!(padding_error.to_string(), length_error.to_string());
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn decrypt_rsaes_rsa1_5_with_random_key() -> Result<()> {
+        let enc = AescbcHmacJweEncryption::A128cbcHs256;
+        let alg = RsaesJweAlgorithm::Rsa1_5;
+
+        let private_key = Jwk::from_bytes(&load_file("jwk/RSA_private.jwk")?)?;
+        let public_key = Jwk::from_bytes(&load_file("jwk/RSA_public.jwk")?)?;
+
+        let mut header = JweHeader::new();
+        header.set_content_encryption(enc.name());
+
+        let encrypter = alg.encrypter_from_jwk_insecure(&public_key)?;
+        let mut out_header = header.clone();
+        let src_key = util::random_bytes(enc.key_len());
+        let encrypted_key = encrypter
+            .encrypt(&src_key, &header, &mut out_header)?
+            .unwrap();
+        let decrypter = alg.decrypter_from_jwk_insecure(&private_key)?;
+
+        let mut tampered = encrypted_key.clone();
+        tampered[0] ^= 0x01;
+        for encrypted_key in [&tampered, &encrypted_key[1..]] {
+            let dst_key = decrypter.decrypt(Some(encrypted_key), &enc, &out_header)?;
+            assert_eq!(dst_key.len(), enc.key_len());
+            assert_ne!(&dst_key as &[u8], &src_key as &[u8]);
+        }
+
+        // A key of the other length is also replaced.
+        let other_enc = AescbcHmacJweEncryption::A256cbcHs512;
+        let dst_key = decrypter.decrypt(Some(&encrypted_key), &other_enc, &out_header)?;
+        assert_eq!(dst_key.len(), other_enc.key_len());
+
+        Ok(())
+    }
+
     fn load_file(path: &str) -> Result<Vec<u8>> {
         let mut pb = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
         pb.push("data");
@@ -1,5 +1,6 @@
 pub mod aescbc_hmac;
 pub mod aesgcm;
+pub mod xchacha20_poly1305;
 
 use crate::jwe::enc::aescbc_hmac::AescbcHmacJweEncryption;
 pub use AescbcHmacJweEncryption::A128cbcHs256 as A128CBC_HS256;
@@ -10,3 +11,6 @@ use crate::jwe::enc::aesgcm::AesgcmJweEncryption;
 pub use AesgcmJweEncryption::A128gcm as A128GCM;
 pub use AesgcmJweEncryption::A192gcm as A192GCM;
 pub use AesgcmJweEncryption::A256gcm as A256GCM;
+
+use crate::jwe::enc::xchacha20_poly1305::Xchacha20Poly1305JweEncryption;
+pub use Xchacha20Poly1305JweEncryption::Xc20p as XC20P;
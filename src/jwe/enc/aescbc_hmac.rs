@@ -1,14 +1,22 @@
-use std::{fmt::Display, ops::Deref};
+use std::{
+    fmt::Display,
+    io::{ErrorKind, Read, Write},
+    ops::Deref,
+    str::FromStr,
+};
 
 use anyhow::bail;
 use openssl::{
     hash::MessageDigest,
     pkey::{PKey, Private},
     sign::Signer,
-    symm::{self, Cipher},
+    symm::{self, Cipher, Crypter, Mode},
 };
 
-use crate::{jwe::JweContentEncryption, JoseError};
+use crate::{
+    jwe::{jwe_content_encryption::STREAM_CHUNK_SIZE, JweContentEncryption},
+    util, JoseError,
+};
 
 #[derive(Debug, Eq, PartialEq, Copy, Clone)]
 pub enum AescbcHmacJweEncryption {
@@ -29,6 +37,55 @@ impl AescbcHmacJweEncryption {
         }
     }
 
+    fn message_digest(&self) -> (MessageDigest, usize) {
+        match self {
+            Self::A128cbcHs256 => (MessageDigest::sha256(), 16),
+            Self::A192cbcHs384 => (MessageDigest::sha384(), 24),
+            Self::A256cbcHs512 => (MessageDigest::sha512(), 32),
+        }
+    }
+
+    /// Return the IV if it has the length of this algorithm, or an error as a malformed JWE.
+    fn check_iv_len<'a>(&self, iv: Option<&'a [u8]>) -> Result<&'a [u8], JoseError> {
+        (|| -> anyhow::Result<&'a [u8]> {
+            let iv = match iv {
+                Some(val) => val,
+                None => bail!("A iv value is required."),
+            };
+
+            if iv.len() != self.iv_len() {
+                bail!("The length of iv must be {}: {}", self.iv_len(), iv.len());
+            }
+
+            Ok(iv)
+        })()
+        .map_err(JoseError::InvalidJweFormat)
+    }
+
+    /// Return the tag if it has the length of this algorithm, which is the half of
+    /// the HMAC output, or an error as a malformed JWE.
+    fn check_tag_len<'a>(&self, tag: Option<&'a [u8]>) -> Result<&'a [u8], JoseError> {
+        (|| -> anyhow::Result<&'a [u8]> {
+            let tag = match tag {
+                Some(val) => val,
+                None => bail!("A tag value is required."),
+            };
+
+            let (_, tlen) = self.message_digest();
+            if tag.len() != tlen {
+                bail!(
+                    "The tag length of {} must be {}: {}",
+                    self.name(),
+                    tlen,
+                    tag.len()
+                );
+            }
+
+            Ok(tag)
+        })()
+        .map_err(JoseError::InvalidJweFormat)
+    }
+
     fn calcurate_tag(
         &self,
         aad: &[u8],
@@ -36,11 +93,7 @@ impl AescbcHmacJweEncryption {
         ciphertext: &[u8],
         mac_key: &[u8],
     ) -> Result<Vec<u8>, JoseError> {
-        let (message_digest, tlen) = match self {
-            Self::A128cbcHs256 => (MessageDigest::sha256(), 16),
-            Self::A192cbcHs384 => (MessageDigest::sha384(), 24),
-            Self::A256cbcHs512 => (MessageDigest::sha512(), 32),
-        };
+        let (message_digest, tlen) = self.message_digest();
 
         let pkey = (|| -> anyhow::Result<PKey<Private>> {
             let pkey = PKey::hmac(mac_key)?;
@@ -89,6 +142,17 @@ impl JweContentEncryption for AescbcHmacJweEncryption {
         16
     }
 
+    fn tag_len(&self) -> usize {
+        let (_, tlen) = self.message_digest();
+        tlen
+    }
+
+    fn ciphertext_len(&self, message_len: usize) -> usize {
+        // PKCS#7 padding always adds 1 to block_size bytes.
+        let block_size = self.cipher().block_size();
+        (message_len / block_size + 1) * block_size
+    }
+
     fn encrypt(
         &self,
         key: &[u8],
@@ -100,7 +164,8 @@ impl JweContentEncryption for AescbcHmacJweEncryption {
             let expected_len = self.key_len();
             if key.len() != expected_len {
                 bail!(
-                    "The length of content encryption key must be {}: {}",
+                    "{} expects {}-byte key, got {}",
+                    self.name(),
                     expected_len,
                     key.len()
                 );
@@ -129,34 +194,31 @@ impl JweContentEncryption for AescbcHmacJweEncryption {
         aad: &[u8],
         tag: Option<&[u8]>,
     ) -> Result<Vec<u8>, JoseError> {
-        let (message, mac_key) = (|| -> anyhow::Result<(Vec<u8>, &[u8])> {
+        let (mac_key, enc_key) = (|| -> anyhow::Result<(&[u8], &[u8])> {
             let expected_len = self.key_len();
             if key.len() != expected_len {
                 bail!(
-                    "The length of content encryption key must be {}: {}",
+                    "{} expects {}-byte key, got {}",
+                    self.name(),
                     expected_len,
                     key.len()
                 );
             }
 
             let mac_key_len = expected_len / 2;
-            let mac_key = &key[0..mac_key_len];
-            let enc_key = &key[mac_key_len..];
-
-            let cipher = self.cipher();
-            let message = symm::decrypt(cipher, enc_key, iv, encrypted_message)?;
-            Ok((message, mac_key))
+            Ok((&key[0..mac_key_len], &key[mac_key_len..]))
         })()
         .map_err(|err| JoseError::InvalidKeyFormat(err))?;
 
-        (|| -> anyhow::Result<()> {
-            let tag = match tag {
-                Some(val) => val,
-                None => bail!("A tag value is required."),
-            };
+        let iv = Some(self.check_iv_len(iv)?);
 
-            let calc_tag = self.calcurate_tag(aad, iv, &encrypted_message, mac_key)?;
-            if calc_tag.as_slice() != tag {
+        // The tag is verified before the decryption so that the padding of
+        // a forged ciphertext is never examined.
+        let tag = self.check_tag_len(tag)?;
+
+        (|| -> anyhow::Result<()> {
+            let calc_tag = self.calcurate_tag(aad, iv, encrypted_message, mac_key)?;
+            if !util::constant_time_eq(&calc_tag, tag) {
                 bail!("The tag doesn't match.");
             }
 
@@ -164,9 +226,90 @@ impl JweContentEncryption for AescbcHmacJweEncryption {
         })()
         .map_err(|err| JoseError::InvalidSignature(err))?;
 
+        let cipher = self.cipher();
+        let message = symm::decrypt(cipher, enc_key, iv, encrypted_message)
+            .map_err(|err| JoseError::InvalidKeyFormat(err.into()))?;
+
         Ok(message)
     }
 
+    /// Decrypt the encrypted message read from the reader and write the message to the writer.
+    ///
+    /// The MAC is computed while the input is decrypted, so the message except for
+    /// the last block is written before the tag is verified. The written data must be
+    /// discarded when this function returns an error.
+    fn decrypt_stream(
+        &self,
+        key: &[u8],
+        iv: Option<&[u8]>,
+        reader: &mut dyn Read,
+        writer: &mut dyn Write,
+        aad: &[u8],
+        tag: Option<&[u8]>,
+    ) -> Result<u64, JoseError> {
+        let iv = Some(self.check_iv_len(iv)?);
+        let tag = self.check_tag_len(tag)?;
+
+        (|| -> anyhow::Result<u64> {
+            let expected_len = self.key_len();
+            if key.len() != expected_len {
+                bail!(
+                    "{} expects {}-byte key, got {}",
+                    self.name(),
+                    expected_len,
+                    key.len()
+                );
+            }
+
+            let mac_key_len = expected_len / 2;
+            let mac_key = &key[0..mac_key_len];
+            let enc_key = &key[mac_key_len..];
+
+            let (message_digest, tlen) = self.message_digest();
+            let pkey = PKey::hmac(mac_key)?;
+            let mut signer = Signer::new(message_digest, &pkey)?;
+            signer.update(aad)?;
+            if let Some(val) = iv {
+                signer.update(val)?;
+            }
+
+            let cipher = self.cipher();
+            let mut crypter = Crypter::new(cipher, Mode::Decrypt, enc_key, iv)?;
+
+            let mut input = vec![0; STREAM_CHUNK_SIZE];
+            let mut output = vec![0; STREAM_CHUNK_SIZE + cipher.block_size()];
+            let mut written = 0;
+            loop {
+                let len = match reader.read(&mut input) {
+                    Ok(0) => break,
+                    Ok(val) => val,
+                    Err(err) if err.kind() == ErrorKind::Interrupted => continue,
+                    Err(err) => return Err(err.into()),
+                };
+                signer.update(&input[..len])?;
+                let len = crypter.update(&input[..len], &mut output)?;
+                writer.write_all(&output[..len])?;
+                written += len as u64;
+            }
+
+            let aad_bits = ((aad.len() * 8) as u64).to_be_bytes();
+            signer.update(&aad_bits)?;
+            let mut calc_tag = signer.sign_to_vec()?;
+            calc_tag.truncate(tlen);
+            if !util::constant_time_eq(&calc_tag, tag) {
+                bail!("The tag doesn't match.");
+            }
+
+            let len = crypter.finalize(&mut output)?;
+            writer.write_all(&output[..len])?;
+            written += len as u64;
+
+            writer.flush()?;
+            Ok(written)
+        })()
+        .map_err(JoseError::InvalidJweFormat)
+    }
+
     fn box_clone(&self) -> Box<dyn JweContentEncryption> {
         Box::new(self.clone())
     }
@@ -178,6 +321,22 @@ impl Display for AescbcHmacJweEncryption {
     }
 }
 
+impl FromStr for AescbcHmacJweEncryption {
+    type Err = JoseError;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "A128CBC-HS256" => Ok(Self::A128cbcHs256),
+            "A192CBC-HS384" => Ok(Self::A192cbcHs384),
+            "A256CBC-HS512" => Ok(Self::A256cbcHs512),
+            _ => Err(JoseError::InvalidJweFormat(anyhow::anyhow!(
+                "Unknown content encryption: {}",
+                name
+            ))),
+        }
+    }
+}
+
 impl Deref for AescbcHmacJweEncryption {
     type Target = dyn JweContentEncryption;
 
@@ -191,7 +350,9 @@ mod tests {
     use anyhow::Result;
 
     use super::AescbcHmacJweEncryption;
+    use crate::jwe::JweContentEncryption;
     use crate::util;
+    use crate::JoseError;
 
     #[test]
     fn encrypt_and_decrypt_aes_cbc_hmac() -> Result<()> {
@@ -220,4 +381,53 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn decrypt_aes_cbc_hmac_with_invalid_tag() -> Result<()> {
+        let message = b"abcde12345";
+        let aad = b"test";
+
+        for enc in [
+            AescbcHmacJweEncryption::A128cbcHs256,
+            AescbcHmacJweEncryption::A192cbcHs384,
+            AescbcHmacJweEncryption::A256cbcHs512,
+        ] {
+            let key = util::random_bytes(enc.key_len());
+            let iv = util::random_bytes(enc.iv_len());
+
+            let (encrypted_message, tag) = enc.encrypt(&key, Some(&iv), message, aad)?;
+            let tag = tag.unwrap();
+
+            let mut flipped_tag = tag.clone();
+            flipped_tag[0] ^= 0x01;
+            let result = enc.decrypt(&key, Some(&iv), &encrypted_message, aad, Some(&flipped_tag));
+            assert!(matches!(result, Err(JoseError::InvalidSignature(_))));
+
+            let short_tag = &tag[..tag.len() - 1];
+            let result = enc.decrypt(&key, Some(&iv), &encrypted_message, aad, Some(short_tag));
+            assert!(matches!(result, Err(JoseError::InvalidJweFormat(_))));
+
+            let mut long_tag = tag.clone();
+            long_tag.push(0);
+            let result = enc.decrypt(&key, Some(&iv), &encrypted_message, aad, Some(&long_tag));
+            assert!(matches!(result, Err(JoseError::InvalidJweFormat(_))));
+
+            let result = enc.decrypt(&key, Some(&iv), &encrypted_message, aad, None);
+            assert!(matches!(result, Err(JoseError::InvalidJweFormat(_))));
+
+            let mut writer = Vec::new();
+            let result = enc.decrypt_stream(
+                &key,
+                Some(&iv),
+                &mut encrypted_message.as_slice(),
+                &mut writer,
+                aad,
+                Some(short_tag),
+            );
+            assert!(matches!(result, Err(JoseError::InvalidJweFormat(_))));
+            assert!(writer.is_empty());
+        }
+
+        Ok(())
+    }
 }
@@ -1,9 +1,13 @@
 use std::fmt::Display;
+use std::io::{ErrorKind, Read, Write};
 use std::ops::Deref;
+use std::str::FromStr;
 
 use anyhow::bail;
-use openssl::symm::{self, Cipher};
+use openssl::cipher::CipherRef;
+use openssl::symm::{self, Cipher, Crypter, Mode};
 
+use crate::jwe::jwe_content_encryption::{self, STREAM_CHUNK_SIZE};
 use crate::jwe::JweContentEncryption;
 use crate::JoseError;
 
@@ -25,6 +29,14 @@ impl AesgcmJweEncryption {
             Self::A256gcm => Cipher::aes_256_gcm(),
         }
     }
+
+    fn cipher_ref(&self) -> &'static CipherRef {
+        match self {
+            Self::A128gcm => openssl::cipher::Cipher::aes_128_gcm(),
+            Self::A192gcm => openssl::cipher::Cipher::aes_192_gcm(),
+            Self::A256gcm => openssl::cipher::Cipher::aes_256_gcm(),
+        }
+    }
 }
 
 impl JweContentEncryption for AesgcmJweEncryption {
@@ -48,6 +60,10 @@ impl JweContentEncryption for AesgcmJweEncryption {
         12
     }
 
+    fn tag_len(&self) -> usize {
+        16
+    }
+
     fn encrypt(
         &self,
         key: &[u8],
@@ -59,7 +75,8 @@ impl JweContentEncryption for AesgcmJweEncryption {
             let expected_len = self.key_len();
             if key.len() != expected_len {
                 bail!(
-                    "The length of content encryption key must be {}: {}",
+                    "{} expects {}-byte key, got {}",
+                    self.name(),
                     expected_len,
                     key.len()
                 );
@@ -73,6 +90,42 @@ impl JweContentEncryption for AesgcmJweEncryption {
         .map_err(|err| JoseError::InvalidKeyFormat(err))
     }
 
+    fn encrypt_in_place(
+        &self,
+        key: &[u8],
+        iv: Option<&[u8]>,
+        message: Vec<u8>,
+        aad: &[u8],
+    ) -> Result<(Vec<u8>, Option<Vec<u8>>), JoseError> {
+        (|| -> anyhow::Result<(Vec<u8>, Option<Vec<u8>>)> {
+            let expected_len = self.key_len();
+            if key.len() != expected_len {
+                bail!(
+                    "{} expects {}-byte key, got {}",
+                    self.name(),
+                    expected_len,
+                    key.len()
+                );
+            }
+
+            let iv = match iv {
+                Some(val) if val.len() == self.iv_len() => val,
+                Some(val) => bail!("The length of iv must be {}: {}", self.iv_len(), val.len()),
+                None => bail!("A iv value is required."),
+            };
+
+            let (encrypted_message, tag) = jwe_content_encryption::encrypt_aead_in_place(
+                self.cipher_ref(),
+                key,
+                iv,
+                message,
+                aad,
+            )?;
+            Ok((encrypted_message, Some(tag)))
+        })()
+        .map_err(JoseError::InvalidKeyFormat)
+    }
+
     fn decrypt(
         &self,
         key: &[u8],
@@ -85,24 +138,99 @@ impl JweContentEncryption for AesgcmJweEncryption {
             let expected_len = self.key_len();
             if key.len() != expected_len {
                 bail!(
-                    "The length of content encryption key must be {}: {}",
+                    "{} expects {}-byte key, got {}",
+                    self.name(),
                     expected_len,
                     key.len()
                 );
             }
 
+            let iv = match iv {
+                Some(val) if val.len() == self.iv_len() => val,
+                Some(val) => bail!("The length of iv must be {}: {}", self.iv_len(), val.len()),
+                None => bail!("A iv value is required."),
+            };
+
             let tag = match tag {
                 Some(val) => val,
                 None => bail!("A tag value is required."),
             };
 
             let cipher = self.cipher();
-            let message = symm::decrypt_aead(cipher, key, iv, aad, encrypted_message, tag)?;
+            let message = symm::decrypt_aead(cipher, key, Some(iv), aad, encrypted_message, tag)?;
             Ok(message)
         })()
         .map_err(|err| JoseError::InvalidJweFormat(err))
     }
 
+    /// Decrypt the encrypted message read from the reader and write the message to the writer.
+    ///
+    /// GCM outputs the message before the tag is verified at the end of the input, so
+    /// the written data must be discarded when this function returns an error.
+    fn decrypt_stream(
+        &self,
+        key: &[u8],
+        iv: Option<&[u8]>,
+        reader: &mut dyn Read,
+        writer: &mut dyn Write,
+        aad: &[u8],
+        tag: Option<&[u8]>,
+    ) -> Result<u64, JoseError> {
+        (|| -> anyhow::Result<u64> {
+            let expected_len = self.key_len();
+            if key.len() != expected_len {
+                bail!(
+                    "{} expects {}-byte key, got {}",
+                    self.name(),
+                    expected_len,
+                    key.len()
+                );
+            }
+
+            let iv = match iv {
+                Some(val) if val.len() == self.iv_len() => val,
+                Some(val) => bail!("The length of iv must be {}: {}", self.iv_len(), val.len()),
+                None => bail!("A iv value is required."),
+            };
+
+            let tag = match tag {
+                Some(val) => val,
+                None => bail!("A tag value is required."),
+            };
+
+            let cipher = self.cipher();
+            let mut crypter = Crypter::new(cipher, Mode::Decrypt, key, Some(iv))?;
+            crypter.aad_update(aad)?;
+
+            let mut input = vec![0; STREAM_CHUNK_SIZE];
+            let mut output = vec![0; STREAM_CHUNK_SIZE + cipher.block_size()];
+            let mut written = 0;
+            loop {
+                let len = match reader.read(&mut input) {
+                    Ok(0) => break,
+                    Ok(val) => val,
+                    Err(err) if err.kind() == ErrorKind::Interrupted => continue,
+                    Err(err) => return Err(err.into()),
+                };
+                let len = crypter.update(&input[..len], &mut output)?;
+                writer.write_all(&output[..len])?;
+                written += len as u64;
+            }
+
+            crypter.set_tag(tag)?;
+            let len = match crypter.finalize(&mut output) {
+                Ok(val) => val,
+                Err(_) => bail!("The tag doesn't match."),
+            };
+            writer.write_all(&output[..len])?;
+            written += len as u64;
+
+            writer.flush()?;
+            Ok(written)
+        })()
+        .map_err(JoseError::InvalidJweFormat)
+    }
+
     fn box_clone(&self) -> Box<dyn JweContentEncryption> {
         Box::new(self.clone())
     }
@@ -114,6 +242,22 @@ impl Display for AesgcmJweEncryption {
     }
 }
 
+impl FromStr for AesgcmJweEncryption {
+    type Err = JoseError;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "A128GCM" => Ok(Self::A128gcm),
+            "A192GCM" => Ok(Self::A192gcm),
+            "A256GCM" => Ok(Self::A256gcm),
+            _ => Err(JoseError::InvalidJweFormat(anyhow::anyhow!(
+                "Unknown content encryption: {}",
+                name
+            ))),
+        }
+    }
+}
+
 impl Deref for AesgcmJweEncryption {
     type Target = dyn JweContentEncryption;
 
@@ -156,4 +300,28 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn encrypt_in_place_aes_gcm() -> Result<()> {
+        let message = b"abcde12345".repeat(10);
+        let aad = b"test";
+
+        for enc in [
+            AesgcmJweEncryption::A128gcm,
+            AesgcmJweEncryption::A192gcm,
+            AesgcmJweEncryption::A256gcm,
+        ] {
+            let key = util::random_bytes(enc.key_len());
+            let iv = util::random_bytes(enc.iv_len());
+
+            let expected = enc.encrypt(&key, Some(&iv), &message, aad)?;
+            let buffer = message.clone();
+            let ptr = buffer.as_ptr();
+            let actual = enc.encrypt_in_place(&key, Some(&iv), buffer, aad)?;
+            assert_eq!(actual, expected);
+            assert_eq!(actual.0.as_ptr(), ptr);
+        }
+
+        Ok(())
+    }
 }
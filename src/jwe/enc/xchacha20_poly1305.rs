@@ -0,0 +1,310 @@
+use std::fmt::Display;
+use std::ops::Deref;
+use std::str::FromStr;
+
+use anyhow::bail;
+use openssl::symm::{self, Cipher};
+
+use crate::jwe::jwe_content_encryption;
+use crate::jwe::JweContentEncryption;
+use crate::JoseError;
+
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub enum Xchacha20Poly1305JweEncryption {
+    /// XChaCha20-Poly1305 using 256-bit key and 192-bit nonce
+    Xc20p,
+}
+
+impl Xchacha20Poly1305JweEncryption {
+    /// Derive the ChaCha20-Poly1305 subkey and nonce from the XChaCha20 key and nonce
+    /// (draft-irtf-cfrg-xchacha section 2.3).
+    fn derive_subkey(key: &[u8], iv: &[u8]) -> anyhow::Result<([u8; 32], [u8; 12])> {
+        if iv.len() != 24 {
+            bail!("The length of iv must be 24: {}", iv.len());
+        }
+
+        let subkey = hchacha20(key, &iv[..16]);
+        let mut nonce = [0; 12];
+        nonce[4..].copy_from_slice(&iv[16..]);
+        Ok((subkey, nonce))
+    }
+}
+
+impl JweContentEncryption for Xchacha20Poly1305JweEncryption {
+    fn name(&self) -> &str {
+        match self {
+            Self::Xc20p => "XC20P",
+        }
+    }
+
+    fn key_len(&self) -> usize {
+        32
+    }
+
+    fn iv_len(&self) -> usize {
+        24
+    }
+
+    fn tag_len(&self) -> usize {
+        16
+    }
+
+    fn encrypt(
+        &self,
+        key: &[u8],
+        iv: Option<&[u8]>,
+        message: &[u8],
+        aad: &[u8],
+    ) -> Result<(Vec<u8>, Option<Vec<u8>>), JoseError> {
+        (|| -> anyhow::Result<(Vec<u8>, Option<Vec<u8>>)> {
+            let expected_len = self.key_len();
+            if key.len() != expected_len {
+                bail!(
+                    "{} expects {}-byte key, got {}",
+                    self.name(),
+                    expected_len,
+                    key.len()
+                );
+            }
+
+            let iv = match iv {
+                Some(val) => val,
+                None => bail!("A iv value is required."),
+            };
+            let (subkey, nonce) = Self::derive_subkey(key, iv)?;
+
+            let cipher = Cipher::chacha20_poly1305();
+            let mut tag = [0; 16];
+            let encrypted_message =
+                symm::encrypt_aead(cipher, &subkey, Some(&nonce), aad, message, &mut tag)?;
+            Ok((encrypted_message, Some(tag.to_vec())))
+        })()
+        .map_err(JoseError::InvalidKeyFormat)
+    }
+
+    fn encrypt_in_place(
+        &self,
+        key: &[u8],
+        iv: Option<&[u8]>,
+        message: Vec<u8>,
+        aad: &[u8],
+    ) -> Result<(Vec<u8>, Option<Vec<u8>>), JoseError> {
+        (|| -> anyhow::Result<(Vec<u8>, Option<Vec<u8>>)> {
+            let expected_len = self.key_len();
+            if key.len() != expected_len {
+                bail!(
+                    "{} expects {}-byte key, got {}",
+                    self.name(),
+                    expected_len,
+                    key.len()
+                );
+            }
+
+            let iv = match iv {
+                Some(val) => val,
+                None => bail!("A iv value is required."),
+            };
+            let (subkey, nonce) = Self::derive_subkey(key, iv)?;
+
+            let (encrypted_message, tag) = jwe_content_encryption::encrypt_aead_in_place(
+                openssl::cipher::Cipher::chacha20_poly1305(),
+                &subkey,
+                &nonce,
+                message,
+                aad,
+            )?;
+            Ok((encrypted_message, Some(tag)))
+        })()
+        .map_err(JoseError::InvalidKeyFormat)
+    }
+
+    fn decrypt(
+        &self,
+        key: &[u8],
+        iv: Option<&[u8]>,
+        encrypted_message: &[u8],
+        aad: &[u8],
+        tag: Option<&[u8]>,
+    ) -> Result<Vec<u8>, JoseError> {
+        (|| -> anyhow::Result<Vec<u8>> {
+            let expected_len = self.key_len();
+            if key.len() != expected_len {
+                bail!(
+                    "{} expects {}-byte key, got {}",
+                    self.name(),
+                    expected_len,
+                    key.len()
+                );
+            }
+
+            let iv = match iv {
+                Some(val) => val,
+                None => bail!("A iv value is required."),
+            };
+            let tag = match tag {
+                Some(val) if val.len() == 16 => val,
+                Some(val) => bail!("The length of tag must be 16: {}", val.len()),
+                None => bail!("A tag value is required."),
+            };
+            let (subkey, nonce) = Self::derive_subkey(key, iv)?;
+
+            let cipher = Cipher::chacha20_poly1305();
+            let message =
+                symm::decrypt_aead(cipher, &subkey, Some(&nonce), aad, encrypted_message, tag)?;
+            Ok(message)
+        })()
+        .map_err(JoseError::InvalidJweFormat)
+    }
+
+    fn box_clone(&self) -> Box<dyn JweContentEncryption> {
+        Box::new(*self)
+    }
+}
+
+impl Display for Xchacha20Poly1305JweEncryption {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
+        fmt.write_str(self.name())
+    }
+}
+
+impl FromStr for Xchacha20Poly1305JweEncryption {
+    type Err = JoseError;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "XC20P" => Ok(Self::Xc20p),
+            _ => Err(JoseError::InvalidJweFormat(anyhow::anyhow!(
+                "Unknown content encryption: {}",
+                name
+            ))),
+        }
+    }
+}
+
+impl Deref for Xchacha20Poly1305JweEncryption {
+    type Target = dyn JweContentEncryption;
+
+    fn deref(&self) -> &Self::Target {
+        self
+    }
+}
+
+fn hchacha20(key: &[u8], nonce: &[u8]) -> [u8; 32] {
+    let mut state = [0u32; 16];
+    state[0] = 0x61707865;
+    state[1] = 0x3320646e;
+    state[2] = 0x79622d32;
+    state[3] = 0x6b206574;
+    for i in 0..8 {
+        state[4 + i] =
+            u32::from_le_bytes([key[i * 4], key[i * 4 + 1], key[i * 4 + 2], key[i * 4 + 3]]);
+    }
+    for i in 0..4 {
+        state[12 + i] = u32::from_le_bytes([
+            nonce[i * 4],
+            nonce[i * 4 + 1],
+            nonce[i * 4 + 2],
+            nonce[i * 4 + 3],
+        ]);
+    }
+
+    for _ in 0..10 {
+        quarter_round(&mut state, 0, 4, 8, 12);
+        quarter_round(&mut state, 1, 5, 9, 13);
+        quarter_round(&mut state, 2, 6, 10, 14);
+        quarter_round(&mut state, 3, 7, 11, 15);
+        quarter_round(&mut state, 0, 5, 10, 15);
+        quarter_round(&mut state, 1, 6, 11, 12);
+        quarter_round(&mut state, 2, 7, 8, 13);
+        quarter_round(&mut state, 3, 4, 9, 14);
+    }
+
+    let mut subkey = [0; 32];
+    for (i, word) in state[0..4].iter().chain(state[12..16].iter()).enumerate() {
+        subkey[i * 4..i * 4 + 4].copy_from_slice(&word.to_le_bytes());
+    }
+    subkey
+}
+
+fn quarter_round(state: &mut [u32; 16], a: usize, b: usize, c: usize, d: usize) {
+    state[a] = state[a].wrapping_add(state[b]);
+    state[d] = (state[d] ^ state[a]).rotate_left(16);
+    state[c] = state[c].wrapping_add(state[d]);
+    state[b] = (state[b] ^ state[c]).rotate_left(12);
+    state[a] = state[a].wrapping_add(state[b]);
+    state[d] = (state[d] ^ state[a]).rotate_left(8);
+    state[c] = state[c].wrapping_add(state[d]);
+    state[b] = (state[b] ^ state[c]).rotate_left(7);
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+
+    use super::{hchacha20, Xchacha20Poly1305JweEncryption};
+    use crate::util;
+
+    #[test]
+    fn encrypt_and_decrypt_xchacha20_poly1305() -> Result<()> {
+        let message = b"abcde12345";
+        let aad = b"test";
+
+        let enc = Xchacha20Poly1305JweEncryption::Xc20p;
+        let key = util::random_bytes(enc.key_len());
+        let iv = util::random_bytes(enc.iv_len());
+
+        let (encrypted_message, tag) = enc.encrypt(&key, Some(&iv), message, aad)?;
+        let decrypted_message = enc.decrypt(
+            &key,
+            Some(&iv),
+            &encrypted_message,
+            &aad[..],
+            tag.as_deref(),
+        )?;
+
+        assert_eq!(&message[..], &decrypted_message[..]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn encrypt_in_place_xchacha20_poly1305() -> Result<()> {
+        let message = b"abcde12345".repeat(10);
+        let aad = b"test";
+
+        let enc = Xchacha20Poly1305JweEncryption::Xc20p;
+        let key = util::random_bytes(enc.key_len());
+        let iv = util::random_bytes(enc.iv_len());
+
+        let expected = enc.encrypt(&key, Some(&iv), &message, aad)?;
+        let buffer = message.clone();
+        let ptr = buffer.as_ptr();
+        let actual = enc.encrypt_in_place(&key, Some(&iv), buffer, aad)?;
+        assert_eq!(actual, expected);
+        assert_eq!(actual.0.as_ptr(), ptr);
+
+        Ok(())
+    }
+
+    #[test]
+    fn hchacha20_with_test_vector() -> Result<()> {
+        // draft-irtf-cfrg-xchacha section 2.2.1
+        let key: Vec<u8> = (0..32).collect();
+        let nonce = [
+            0x00, 0x00, 0x00, 0x09, 0x00, 0x00, 0x00, 0x4a, 0x00, 0x00, 0x00, 0x00, 0x31, 0x41,
+            0x59, 0x27,
+        ];
+
+        let subkey = hchacha20(&key, &nonce);
+        assert_eq!(
+            &subkey,
+            &[
+                0x82, 0x41, 0x3b, 0x42, 0x27, 0xb2, 0x7b, 0xfe, 0xd3, 0x0e, 0x42, 0x50, 0x8a, 0x87,
+                0x7d, 0x73, 0xa0, 0xf9, 0xe4, 0xd5, 0x8a, 0x74, 0xa8, 0x53, 0xc1, 0x2e, 0xc4, 0x13,
+                0x26, 0xd3, 0xec, 0xdc,
+            ]
+        );
+
+        Ok(())
+    }
+}
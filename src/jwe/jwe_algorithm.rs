@@ -1,8 +1,10 @@
 use std::borrow::Cow;
 use std::fmt::Debug;
 
+use anyhow::anyhow;
+
 use crate::jwe::{JweContentEncryption, JweHeader};
-use crate::JoseError;
+use crate::{JoseError, JoseRandom};
 
 /// Represent a algorithm of JWE alg header claim.
 pub trait JweAlgorithm: Debug + Send + Sync {
@@ -62,6 +64,74 @@ pub trait JweEncrypter: Debug + Send + Sync {
         out_header: &mut JweHeader,
     ) -> Result<Option<Vec<u8>>, JoseError>;
 
+    /// Return a encypted key, drawing the random values of the algorithm from the generator.
+    ///
+    /// The default implementation ignores the generator and calls `encrypt`. The
+    /// PBES2 encrypter uses it for the p2s salt.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The content encryption key
+    /// * `in_header` - the input header
+    /// * `out_header` - the output header
+    /// * `rng` - the random generator
+    fn encrypt_with_rng(
+        &self,
+        key: &[u8],
+        in_header: &JweHeader,
+        out_header: &mut JweHeader,
+        rng: &dyn JoseRandom,
+    ) -> Result<Option<Vec<u8>>, JoseError> {
+        let _ = rng;
+        self.encrypt(key, in_header, out_header)
+    }
+
+    /// Return an upper bound on the length of the encrypted key in bytes without
+    /// encrypting a key.
+    ///
+    /// The header claims that `encrypt` would set are set to placeholders of the same
+    /// length, e.g. a epk header claim with zero coordinates, so the output header has
+    /// the length of the actual one. The default implementation returns an error.
+    ///
+    /// # Arguments
+    ///
+    /// * `cencryption` - The content encryption method.
+    /// * `in_header` - the input header
+    /// * `out_header` - the output header
+    fn estimate_encrypted_key_len(
+        &self,
+        _cencryption: &dyn JweContentEncryption,
+        _in_header: &JweHeader,
+        _out_header: &mut JweHeader,
+    ) -> Result<usize, JoseError> {
+        Err(JoseError::InvalidJweFormat(anyhow!(
+            "The encrypter doesn't support the estimation of the length: {}",
+            self.algorithm().name()
+        )))
+    }
+
+    /// Return a encrypter of the content encryption key that is called with the
+    /// authentication tag after the content is encrypted, for the algorithms that bind
+    /// the tag to the encrypted key, e.g. "ECDH-1PU+A128KW".
+    ///
+    /// The header claims are set here, so the header is fixed before the content is
+    /// encrypted, and `encrypt` is not called when a encrypter is returned. The default
+    /// implementation returns None.
+    ///
+    /// # Arguments
+    ///
+    /// * `cencryption` - The content encryption method.
+    /// * `in_header` - the input header
+    /// * `out_header` - the output header
+    fn tag_bound_encrypter(
+        &self,
+        _cencryption: &dyn JweContentEncryption,
+        _in_header: &JweHeader,
+        _out_header: &mut JweHeader,
+    ) -> Result<Option<Box<dyn JweTagBoundEncrypter>>, JoseError> {
+        Ok(None)
+    }
+
     fn box_clone(&self) -> Box<dyn JweEncrypter>;
 }
 
@@ -71,6 +141,20 @@ impl Clone for Box<dyn JweEncrypter> {
     }
 }
 
+/// Encrypt a content encryption key with the authentication tag of the content.
+///
+/// It is returned by `JweEncrypter::tag_bound_encrypter` and keeps the key agreement
+/// state of a message until the content is encrypted.
+pub trait JweTagBoundEncrypter: Send + Sync {
+    /// Return a encypted key.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The content encryption key
+    /// * `tag` - The authentication tag of the content
+    fn encrypt(&self, key: &[u8], tag: &[u8]) -> Result<Vec<u8>, JoseError>;
+}
+
 pub trait JweDecrypter: Debug + Send + Sync {
     /// Return the source algorithm instance.
     fn algorithm(&self) -> &dyn JweAlgorithm;
@@ -93,6 +177,28 @@ pub trait JweDecrypter: Debug + Send + Sync {
         header: &JweHeader,
     ) -> Result<Cow<[u8]>, JoseError>;
 
+    /// Return a decrypted key, using the authentication tag of the content.
+    ///
+    /// The algorithms that bind the tag to the encrypted key, e.g. "ECDH-1PU+A128KW",
+    /// override it. The default implementation ignores the tag and calls `decrypt`.
+    ///
+    /// # Arguments
+    ///
+    /// * `encrypted_key` - The encrypted key.
+    /// * `cencryption` - The content encryption method.
+    /// * `header` - The header
+    /// * `tag` - The authentication tag of the content.
+    fn decrypt_with_tag(
+        &self,
+        encrypted_key: Option<&[u8]>,
+        cencryption: &dyn JweContentEncryption,
+        header: &JweHeader,
+        tag: Option<&[u8]>,
+    ) -> Result<Cow<'_, [u8]>, JoseError> {
+        let _ = tag;
+        self.decrypt(encrypted_key, cencryption, header)
+    }
+
     fn box_clone(&self) -> Box<dyn JweDecrypter>;
 }
 
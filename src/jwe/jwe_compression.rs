@@ -1,6 +1,6 @@
 use std::cmp::Eq;
 use std::fmt::Debug;
-use std::io;
+use std::io::{self, Read};
 
 /// Represent a algorithm of JWE zip header claim.
 pub trait JweCompression: Debug + Send + Sync {
@@ -11,12 +11,48 @@ pub trait JweCompression: Debug + Send + Sync {
 
     fn decompress(&self, message: &[u8]) -> Result<Vec<u8>, io::Error>;
 
+    /// Decompress the message, failing once the decompressed data exceeds the limit.
+    ///
+    /// The default implementation decompresses the whole message before the length is
+    /// checked, so an implementation should override it to stop at the limit.
+    ///
+    /// # Arguments
+    ///
+    /// * `message` - The compressed data.
+    /// * `limit` - The maximum length of the decompressed data in bytes.
+    fn decompress_with_limit(&self, message: &[u8], limit: usize) -> Result<Vec<u8>, io::Error> {
+        let vec = self.decompress(message)?;
+        if vec.len() > limit {
+            return Err(limit_exceeded(limit));
+        }
+        Ok(vec)
+    }
+
     fn box_clone(&self) -> Box<dyn JweCompression>;
 }
 
+/// Read the decompressed data from the decoder, reading no more than one byte past the limit.
+pub(crate) fn read_with_limit(decoder: impl Read, limit: usize) -> Result<Vec<u8>, io::Error> {
+    let mut vec = Vec::new();
+    decoder
+        .take((limit as u64).saturating_add(1))
+        .read_to_end(&mut vec)?;
+    if vec.len() > limit {
+        return Err(limit_exceeded(limit));
+    }
+    Ok(vec)
+}
+
+fn limit_exceeded(limit: usize) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("The decompressed payload exceeds {} bytes.", limit),
+    )
+}
+
 impl PartialEq for Box<dyn JweCompression> {
     fn eq(&self, other: &Self) -> bool {
-        self == other
+        self.name() == other.name()
     }
 }
 
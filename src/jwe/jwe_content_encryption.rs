@@ -1,17 +1,80 @@
 use std::cmp::Eq;
 use std::fmt::Debug;
+use std::io::{Read, Write};
+
+use anyhow::anyhow;
+use openssl::cipher::CipherRef;
+use openssl::cipher_ctx::CipherCtx;
 
 use crate::JoseError;
 
+/// The size of the chunks read from the input of a stream decryption.
+pub(crate) const STREAM_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Encrypt the message with an AEAD stream cipher in its own buffer.
+///
+/// Return the buffer that holds the encrypted message, and the 16-byte tag.
+pub(crate) fn encrypt_aead_in_place(
+    cipher: &CipherRef,
+    key: &[u8],
+    iv: &[u8],
+    mut message: Vec<u8>,
+    aad: &[u8],
+) -> anyhow::Result<(Vec<u8>, Vec<u8>)> {
+    let mut ctx = CipherCtx::new()?;
+    ctx.encrypt_init(Some(cipher), None, None)?;
+    ctx.set_iv_length(iv.len())?;
+    ctx.encrypt_init(None, Some(key), Some(iv))?;
+    ctx.cipher_update(aad, None)?;
+
+    let len = message.len();
+    let len = ctx.cipher_update_inplace(&mut message, len)?;
+    let len = len + ctx.cipher_final(&mut message[len..])?;
+    message.truncate(len);
+
+    let mut tag = vec![0; 16];
+    ctx.tag(&mut tag)?;
+    Ok((message, tag))
+}
+
 /// Represent a algorithm of JWE enc header claim.
 pub trait JweContentEncryption: Debug + Send + Sync {
     /// Return the "enc" (encryption) header parameter value of JWE.
     fn name(&self) -> &str;
 
+    /// Return the length of the content encryption key (CEK) in bytes.
     fn key_len(&self) -> usize;
 
+    /// Return the length of the content encryption key (CEK) in bytes.
+    ///
+    /// This is the same as `key_len`, e.g. the length of a key of the dir algorithm.
+    fn cek_len(&self) -> usize {
+        self.key_len()
+    }
+
+    /// Return the length of the initialization vector in bytes.
     fn iv_len(&self) -> usize;
 
+    /// Return the length of the authentication tag in bytes.
+    ///
+    /// The default is 0, as for a content encryption without a tag. The built-in
+    /// content encryptions override it.
+    fn tag_len(&self) -> usize {
+        0
+    }
+
+    /// Return the length of the ciphertext of a message in bytes.
+    ///
+    /// The default is the same as the length of the message, as for a stream cipher.
+    /// A block cipher with padding must override it.
+    ///
+    /// # Arguments
+    ///
+    /// * `message_len` - The length of the message in bytes.
+    fn ciphertext_len(&self, message_len: usize) -> usize {
+        message_len
+    }
+
     fn encrypt(
         &self,
         key: &[u8],
@@ -20,6 +83,20 @@ pub trait JweContentEncryption: Debug + Send + Sync {
         aad: &[u8],
     ) -> Result<(Vec<u8>, Option<Vec<u8>>), JoseError>;
 
+    /// Encrypt the owned message, reusing its buffer for the encrypted message if possible.
+    ///
+    /// The default implementation encrypts the message by `encrypt`, so a new buffer
+    /// is allocated.
+    fn encrypt_in_place(
+        &self,
+        key: &[u8],
+        iv: Option<&[u8]>,
+        message: Vec<u8>,
+        aad: &[u8],
+    ) -> Result<(Vec<u8>, Option<Vec<u8>>), JoseError> {
+        self.encrypt(key, iv, &message, aad)
+    }
+
     fn decrypt(
         &self,
         key: &[u8],
@@ -29,12 +106,33 @@ pub trait JweContentEncryption: Debug + Send + Sync {
         tag: Option<&[u8]>,
     ) -> Result<Vec<u8>, JoseError>;
 
+    /// Decrypt the encrypted message read from the reader and write the message to the writer.
+    ///
+    /// The input is processed in fixed size chunks, so the memory usage doesn't depend on
+    /// the length of the message. Return the number of bytes written to the writer.
+    ///
+    /// The default implementation returns an error.
+    fn decrypt_stream(
+        &self,
+        _key: &[u8],
+        _iv: Option<&[u8]>,
+        _reader: &mut dyn Read,
+        _writer: &mut dyn Write,
+        _aad: &[u8],
+        _tag: Option<&[u8]>,
+    ) -> Result<u64, JoseError> {
+        Err(JoseError::InvalidJweFormat(anyhow!(
+            "The content encryption doesn't support stream decryption: {}",
+            self.name()
+        )))
+    }
+
     fn box_clone(&self) -> Box<dyn JweContentEncryption>;
 }
 
 impl PartialEq for Box<dyn JweContentEncryption> {
     fn eq(&self, other: &Self) -> bool {
-        self == other
+        self.name() == other.name()
     }
 }
 
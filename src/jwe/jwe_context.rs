@@ -2,28 +2,49 @@ use std::borrow::Cow;
 use std::cmp::Eq;
 use std::collections::{BTreeMap, BTreeSet};
 use std::fmt::Debug;
+use std::io::{Read, Write};
+use std::sync::Arc;
 
 use anyhow::bail;
+use zeroize::Zeroizing;
 
-use crate::jwe::enc::{A128CBC_HS256, A128GCM, A192CBC_HS384, A192GCM, A256CBC_HS512, A256GCM};
+use crate::jwe::enc::{
+    A128CBC_HS256, A128GCM, A192CBC_HS384, A192GCM, A256CBC_HS512, A256GCM, XC20P,
+};
 use crate::jwe::zip::Def;
 use crate::jwe::{
-    JweCompression, JweContentEncryption, JweDecrypter, JweEncrypter, JweHeader, JweHeaderSet,
+    select_decrypter, Dir, JweCompression, JweContentEncryption, JweDecrypter, JweEncrypter,
+    JweHeader, JweHeaderSet, A128GCMKW, A128KW, A192GCMKW, A192KW, A256GCMKW, A256KW, ECDH_ES,
+    ECDH_ES_A128KW, ECDH_ES_A192KW, ECDH_ES_A256KW, PBES2_HS256_A128KW, PBES2_HS384_A192KW,
+    PBES2_HS512_A256KW, RSA_OAEP, RSA_OAEP_256, RSA_OAEP_384, RSA_OAEP_512,
 };
+use crate::jwk::{Jwk, JwkSet};
 use crate::util;
-use crate::{JoseError, JoseHeader, Map, Value};
+use crate::{JoseError, JoseHeader, JoseRandom, Map, OsRandom, Value};
+
+/// The default maximum length of a decompressed payload, 10 MiB.
+const DEFAULT_MAX_DECOMPRESSED_LEN: usize = 10 * 1024 * 1024;
 
-#[derive(Debug, Eq, PartialEq, Clone)]
+/// The payload, the header and the additional authenticated data.
+type DecryptedWithAad = (Vec<u8>, JweHeader, Option<Vec<u8>>);
+
+#[derive(Debug, Clone)]
 pub struct JweContext {
     acceptable_criticals: BTreeSet<String>,
+    accepted_algorithms: Option<BTreeSet<String>>,
+    accepted_encryptions: Option<BTreeSet<String>>,
     compressions: BTreeMap<String, Box<dyn JweCompression>>,
     content_encryptions: BTreeMap<String, Box<dyn JweContentEncryption>>,
+    max_decompressed_len: usize,
+    rng: Option<Arc<dyn JoseRandom>>,
 }
 
 impl JweContext {
     pub fn new() -> Self {
         Self {
             acceptable_criticals: BTreeSet::new(),
+            accepted_algorithms: None,
+            accepted_encryptions: None,
             compressions: {
                 let compressions: Vec<Box<dyn JweCompression>> = vec![Box::new(Def)];
 
@@ -41,6 +62,7 @@ impl JweContext {
                     Box::new(A128GCM),
                     Box::new(A192GCM),
                     Box::new(A256GCM),
+                    Box::new(XC20P),
                 ];
 
                 let mut map = BTreeMap::new();
@@ -49,6 +71,57 @@ impl JweContext {
                 }
                 map
             },
+            max_decompressed_len: DEFAULT_MAX_DECOMPRESSED_LEN,
+            rng: None,
+        }
+    }
+
+    /// Return the maximum length of a decompressed payload in bytes.
+    pub fn max_decompressed_len(&self) -> usize {
+        self.max_decompressed_len
+    }
+
+    /// Set the maximum length of a decompressed payload in bytes.
+    ///
+    /// The default is 10 MiB. A compressed payload can expand enormously, so the
+    /// deserialization stops decompressing and fails once the limit is exceeded.
+    ///
+    /// # Arguments
+    ///
+    /// * `max_decompressed_len` - The maximum length of a decompressed payload
+    pub fn set_max_decompressed_len(&mut self, max_decompressed_len: usize) {
+        self.max_decompressed_len = max_decompressed_len;
+    }
+
+    /// Return the random generator for content encryption keys and initialization vectors,
+    /// or None when OsRandom is used.
+    pub fn rng(&self) -> Option<&dyn JoseRandom> {
+        self.rng.as_deref()
+    }
+
+    /// Set a random generator for content encryption keys, initialization vectors and salts.
+    ///
+    /// OsRandom is used when no generator is set. The encrypters are called by
+    /// `encrypt_with_rng`, so the p2s salt of PBES2 is also drawn from it. Other
+    /// randomness used inside a key management algorithm, such as the ephemeral key of
+    /// ECDH-ES or the padding of RSAES, doesn't come from this generator.
+    ///
+    /// # Arguments
+    ///
+    /// * `rng` - a random generator
+    pub fn set_rng(&mut self, rng: Arc<dyn JoseRandom>) {
+        self.rng = Some(rng);
+    }
+
+    /// Remove the random generator, so OsRandom is used again.
+    pub fn remove_rng(&mut self) {
+        self.rng = None;
+    }
+
+    fn random(&self) -> &dyn JoseRandom {
+        match &self.rng {
+            Some(rng) => rng.as_ref(),
+            None => &OsRandom,
         }
     }
 
@@ -79,6 +152,67 @@ impl JweContext {
         self.acceptable_criticals.remove(name);
     }
 
+    /// Test a alg header claim value is accepted by the deserialization.
+    ///
+    /// Any algorithm is accepted unless set_accepted_algorithms is called.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - a alg header claim value
+    pub fn is_accepted_algorithm(&self, name: &str) -> bool {
+        match &self.accepted_algorithms {
+            Some(val) => val.contains(name),
+            None => true,
+        }
+    }
+
+    /// Set the alg header claim values that are accepted by the deserialization.
+    ///
+    /// A JWE that has another algorithm is rejected before the key is decrypted,
+    /// e.g. set a list without "RSA1_5" to forbid it.
+    ///
+    /// # Arguments
+    ///
+    /// * `names` - the accepted alg header claim values
+    pub fn set_accepted_algorithms(&mut self, names: &[&str]) {
+        self.accepted_algorithms = Some(names.iter().map(|val| val.to_string()).collect());
+    }
+
+    /// Remove the restriction of alg header claim values.
+    pub fn remove_accepted_algorithms(&mut self) {
+        self.accepted_algorithms = None;
+    }
+
+    /// Test a enc header claim value is accepted by the deserialization.
+    ///
+    /// Any content encryption is accepted unless set_accepted_encryptions is called.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - a enc header claim value
+    pub fn is_accepted_encryption(&self, name: &str) -> bool {
+        match &self.accepted_encryptions {
+            Some(val) => val.contains(name),
+            None => true,
+        }
+    }
+
+    /// Set the enc header claim values that are accepted by the deserialization.
+    ///
+    /// A JWE that has another content encryption is rejected before the key is decrypted.
+    ///
+    /// # Arguments
+    ///
+    /// * `names` - the accepted enc header claim values
+    pub fn set_accepted_encryptions(&mut self, names: &[&str]) {
+        self.accepted_encryptions = Some(names.iter().map(|val| val.to_string()).collect());
+    }
+
+    /// Remove the restriction of enc header claim values.
+    pub fn remove_accepted_encryptions(&mut self) {
+        self.accepted_encryptions = None;
+    }
+
     /// Get a compression algorithm for zip header claim value.
     ///
     /// # Arguments
@@ -93,6 +227,10 @@ impl JweContext {
 
     /// Add a compression algorithm for zip header claim name.
     ///
+    /// The serialization and deserialization functions use the registered compression
+    /// that has the same name as the zip header claim. A compression with the same name
+    /// as a built-in one (e.g. DEF) replaces it.
+    ///
     /// # Arguments
     ///
     /// * `compression` - a compression algorithm
@@ -157,6 +295,31 @@ impl JweContext {
         self.serialize_compact_with_selector(payload, header, |_header| Some(encrypter))
     }
 
+    /// Return a representation of the owned data that is formatted by compact serialization.
+    ///
+    /// The result is the same as serialize_compact. The payload buffer is encrypted in
+    /// place when the content encryption supports it (AES GCM and XChaCha20-Poly1305),
+    /// so the payload is not copied. When a zip header claim is set, the compressed
+    /// buffer is encrypted in place instead.
+    ///
+    /// # Arguments
+    ///
+    /// * `payload` - The payload data.
+    /// * `header` - The JWE heaser claims.
+    /// * `encrypter` - The JWE encrypter.
+    pub fn serialize_compact_owned(
+        &self,
+        payload: impl Into<Vec<u8>>,
+        header: &JweHeader,
+        encrypter: &dyn JweEncrypter,
+    ) -> Result<String, JoseError> {
+        let (message, _) =
+            self.serialize_compact_with_key(Cow::Owned(payload.into()), header, None, |_header| {
+                Some(encrypter)
+            })?;
+        Ok(message)
+    }
+
     /// Return a representation of the data that is formatted by compact serialization.
     ///
     /// # Arguments
@@ -173,7 +336,95 @@ impl JweContext {
     where
         F: Fn(&JweHeader) -> Option<&'a dyn JweEncrypter>,
     {
-        (|| -> anyhow::Result<String> {
+        let (message, _) =
+            self.serialize_compact_with_key(Cow::Borrowed(payload), header, None, selector)?;
+        Ok(message)
+    }
+
+    /// Return a representation of the data that is formatted by compact serialization
+    /// and the content encryption key used to encrypt the payload.
+    ///
+    /// The returned key is the same bytes as the key given to the content encryption,
+    /// whether it is generated randomly or derived by the encrypter.
+    ///
+    /// # Arguments
+    ///
+    /// * `payload` - The payload data.
+    /// * `header` - The JWE heaser claims.
+    /// * `encrypter` - The JWE encrypter.
+    pub fn serialize_compact_with_cek_out(
+        &self,
+        payload: &[u8],
+        header: &JweHeader,
+        encrypter: &dyn JweEncrypter,
+    ) -> Result<(String, Vec<u8>), JoseError> {
+        let (message, key) =
+            self.serialize_compact_with_key(Cow::Borrowed(payload), header, None, |_header| {
+                Some(encrypter)
+            })?;
+        Ok((message, key.to_vec()))
+    }
+
+    /// Return a representation of the data that is formatted by compact serialization
+    /// with the protected header that is given as a JSON string.
+    ///
+    /// The header is encoded as it is without re-serialization, so the member order
+    /// and the whitespace are kept byte-for-byte. Therefore the header must already have
+    /// all of the header claims that the encrypter would set, e.g. "alg" and "kid".
+    ///
+    /// Only the encrypters that add no per-message header claim can be used: "dir",
+    /// AES key wrap, RSAES, and PBES2 with the p2s and p2c header claims given by the
+    /// caller. ECDH-ES, ECDH-1PU and AES-GCM key wrap ("A128GCMKW" and so on) are
+    /// rejected, because their "epk", "iv" and "tag" header claims are random.
+    ///
+    /// # Arguments
+    ///
+    /// * `payload` - The payload data.
+    /// * `header` - The JWE header claims as a JSON string.
+    /// * `encrypter` - The JWE encrypter.
+    pub fn serialize_compact_with_raw_header(
+        &self,
+        payload: &[u8],
+        header: &str,
+        encrypter: &dyn JweEncrypter,
+    ) -> Result<String, JoseError> {
+        let parsed = (|| -> anyhow::Result<JweHeader> {
+            let name = encrypter.algorithm().name();
+            if name.starts_with("ECDH-") || name.ends_with("GCMKW") {
+                bail!(
+                    "{} cannot be used with a raw header, because it adds random header claims.",
+                    name
+                );
+            }
+
+            let map = util::parse_json_object(header.as_bytes())?;
+            Ok(JweHeader::from_map(map)?)
+        })()
+        .map_err(|err| match err.downcast::<JoseError>() {
+            Ok(err) => err,
+            Err(err) => JoseError::InvalidJweFormat(err),
+        })?;
+
+        let (message, _) = self.serialize_compact_with_key(
+            Cow::Borrowed(payload),
+            &parsed,
+            Some(header.as_bytes()),
+            |_header| Some(encrypter),
+        )?;
+        Ok(message)
+    }
+
+    fn serialize_compact_with_key<'a, F>(
+        &self,
+        payload: Cow<[u8]>,
+        header: &JweHeader,
+        raw_header: Option<&[u8]>,
+        selector: F,
+    ) -> Result<(String, Zeroizing<Vec<u8>>), JoseError>
+    where
+        F: Fn(&JweHeader) -> Option<&'a dyn JweEncrypter>,
+    {
+        (|| -> anyhow::Result<(String, Zeroizing<Vec<u8>>)> {
             let encrypter = match selector(header) {
                 Some(val) => val,
                 None => bail!("A encrypter is not found."),
@@ -198,16 +449,25 @@ impl JweContext {
             let mut out_header = header.clone();
 
             let key_len = cencryption.key_len();
-            let key = match encrypter.compute_content_encryption_key(
-                cencryption,
-                &header,
-                &mut out_header,
-            )? {
-                Some(val) => val,
-                None => Cow::Owned(util::random_bytes(key_len)),
-            };
+            let key = Zeroizing::new(
+                match encrypter.compute_content_encryption_key(
+                    cencryption,
+                    &header,
+                    &mut out_header,
+                )? {
+                    Some(val) => val.into_owned(),
+                    None => self.random().random_bytes(key_len),
+                },
+            );
 
-            let encrypted_key = encrypter.encrypt(&key, &header, &mut out_header)?;
+            let tag_bound_encrypter =
+                encrypter.tag_bound_encrypter(cencryption, header, &mut out_header)?;
+            let encrypted_key = match tag_bound_encrypter {
+                Some(_) => None,
+                None => {
+                    encrypter.encrypt_with_rng(&key, &header, &mut out_header, self.random())?
+                }
+            };
             if let None = header.claim("kid") {
                 if let Some(key_id) = encrypter.key_id() {
                     out_header.set_key_id(key_id);
@@ -216,27 +476,48 @@ impl JweContext {
 
             out_header.set_algorithm(encrypter.algorithm().name());
 
-            let header_bytes = serde_json::to_vec(out_header.claims_set())?;
-            let header_b64 = base64::encode_config(header_bytes, base64::URL_SAFE_NO_PAD);
+            let header_b64 = match raw_header {
+                Some(val) => {
+                    if &out_header != header {
+                        bail!(
+                            "The header must have all of the header claims set by the encrypter."
+                        );
+                    }
+                    base64::encode_config(val, base64::URL_SAFE_NO_PAD)
+                }
+                None => {
+                    let header_bytes = serde_json::to_vec(out_header.claims_set())?;
+                    base64::encode_config(header_bytes, base64::URL_SAFE_NO_PAD)
+                }
+            };
 
-            let compressed;
             let content = if let Some(compression) = compression {
-                compressed = compression.compress(payload)?;
-                &compressed
+                let compressed = compression.compress(&payload)?;
+                drop(payload);
+                Cow::Owned(compressed)
             } else {
                 payload
             };
 
             let iv_vec;
             let iv = if cencryption.iv_len() > 0 {
-                iv_vec = util::random_bytes(cencryption.iv_len());
+                iv_vec = self.random().random_bytes(cencryption.iv_len());
                 Some(iv_vec.as_slice())
             } else {
                 None
             };
 
-            let (ciphertext, tag) =
-                cencryption.encrypt(&key, iv, content, header_b64.as_bytes())?;
+            let (ciphertext, tag) = match content {
+                Cow::Owned(val) => {
+                    cencryption.encrypt_in_place(&key, iv, val, header_b64.as_bytes())?
+                }
+                Cow::Borrowed(val) => cencryption.encrypt(&key, iv, val, header_b64.as_bytes())?,
+            };
+
+            let encrypted_key = match tag_bound_encrypter {
+                Some(val) => Some(val.encrypt(&key, tag.as_deref().unwrap_or_default())?),
+                None => encrypted_key,
+            };
 
             let mut capacity = 4;
             capacity += header_b64.len();
@@ -268,7 +549,70 @@ impl JweContext {
                 base64::encode_config_buf(val, base64::URL_SAFE_NO_PAD, &mut message);
             }
 
-            Ok(message)
+            Ok((message, key))
+        })()
+        .map_err(|err| match err.downcast::<JoseError>() {
+            Ok(err) => err,
+            Err(err) => JoseError::InvalidJweFormat(err),
+        })
+    }
+
+    /// Return an upper bound on the length of the representation of a payload that is
+    /// formatted by compact serialization.
+    ///
+    /// The lengths of the header claims and the encrypted key are computed from the
+    /// algorithm and the key size, so neither a key nor the payload is encrypted and
+    /// the random generator is not used. A compressed payload is not supported because
+    /// its length is unknown until it is compressed.
+    ///
+    /// # Arguments
+    ///
+    /// * `payload_len` - The length of the payload data in bytes.
+    /// * `header` - The JWE header claims.
+    /// * `encrypter` - The JWE encrypter.
+    pub fn estimate_compact_len(
+        &self,
+        payload_len: usize,
+        header: &JweHeader,
+        encrypter: &dyn JweEncrypter,
+    ) -> Result<usize, JoseError> {
+        (|| -> anyhow::Result<usize> {
+            let cencryption = match header.content_encryption() {
+                Some(enc) => match self.get_content_encryption(enc) {
+                    Some(val) => val,
+                    None => bail!("A content encryption is not registered: {}", enc),
+                },
+                None => bail!("A enc header claim is required."),
+            };
+
+            if let Some(zip) = header.compression() {
+                bail!(
+                    "The length of a compressed payload cannot be estimated: {}",
+                    zip
+                );
+            }
+
+            let mut out_header = header.clone();
+
+            let encrypted_key_len =
+                encrypter.estimate_encrypted_key_len(cencryption, header, &mut out_header)?;
+            if header.claim("kid").is_none() {
+                if let Some(key_id) = encrypter.key_id() {
+                    out_header.set_key_id(key_id);
+                }
+            }
+
+            out_header.set_algorithm(encrypter.algorithm().name());
+
+            let header_len = serde_json::to_vec(out_header.claims_set())?.len();
+
+            let mut len = 4;
+            len += util::ceiling(header_len * 4, 3);
+            len += util::ceiling(encrypted_key_len * 4, 3);
+            len += util::ceiling(cencryption.iv_len() * 4, 3);
+            len += util::ceiling(cencryption.ciphertext_len(payload_len) * 4, 3);
+            len += util::ceiling(cencryption.tag_len() * 4, 3);
+            Ok(len)
         })()
         .map_err(|err| match err.downcast::<JoseError>() {
             Ok(err) => err,
@@ -356,7 +700,7 @@ impl JweContext {
             let mut encrypter_list = Vec::new();
 
             let mut selected_cencryption: Option<&dyn JweContentEncryption> = None;
-            let mut selected_key: Option<Cow<[u8]>> = None;
+            let mut selected_key: Option<Zeroizing<Vec<u8>>> = None;
             for (i, recipient_header) in recipient_headers.iter().enumerate() {
                 let mut merged_map = merged_map.clone();
 
@@ -371,6 +715,10 @@ impl JweContext {
 
                 let merged = JweHeader::from_map(merged_map)?;
 
+                if compression.is_none() && merged.claim("zip").is_some() {
+                    bail!("The zip header claim must be in protected.");
+                }
+
                 let cencryption = match merged.claim("enc") {
                     Some(Value::String(enc)) => {
                         if let Some(selected_cencryption) = selected_cencryption {
@@ -409,11 +757,11 @@ impl JweContext {
                     &mut recipient_header,
                 )? {
                     if let Some(selected_key) = &selected_key {
-                        if key.as_ref() != selected_key.as_ref() {
+                        if key.as_ref() != selected_key.as_slice() {
                             bail!("A content encryption key must be only one.");
                         }
                     } else {
-                        selected_key = Some(key);
+                        selected_key = Some(Zeroizing::new(key.into_owned()));
                     }
                 };
 
@@ -441,13 +789,13 @@ impl JweContext {
                 None => bail!("A enc header claim is required."),
             };
 
-            let key = match &selected_key {
-                Some(val) => Cow::Borrowed(val.as_ref()),
-                None => Cow::Owned(util::random_bytes(cencryption.key_len())),
+            let key = match selected_key {
+                Some(val) => val,
+                None => Zeroizing::new(self.random().random_bytes(cencryption.key_len())),
             };
 
             let iv = if cencryption.iv_len() > 0 {
-                Some(util::random_bytes(cencryption.iv_len()))
+                Some(self.random().random_bytes(cencryption.iv_len()))
             } else {
                 None
             };
@@ -532,7 +880,13 @@ impl JweContext {
                 let mut header = &mut recipient_header_list[i];
                 let encrypter = encrypter_list[i];
 
-                let encrypted_key = encrypter.encrypt(&key, &merged, &mut header)?;
+                let encrypted_key =
+                    match encrypter.tag_bound_encrypter(cencryption, merged, header)? {
+                        Some(val) => Some(val.encrypt(&key, tag.as_deref().unwrap_or_default())?),
+                        None => {
+                            encrypter.encrypt_with_rng(&key, &merged, &mut header, self.random())?
+                        }
+                    };
 
                 let mut writed = false;
                 if header.len() > 0 {
@@ -542,12 +896,14 @@ impl JweContext {
                     writed = true;
                 }
 
-                json.push_str(if writed { "," } else { "{" });
-                json.push_str("\"encrypted_key\":\"");
                 if let Some(val) = encrypted_key {
+                    json.push_str(if writed { "," } else { "{" });
+                    json.push_str("\"encrypted_key\":\"");
                     base64::encode_config_buf(&val, base64::URL_SAFE_NO_PAD, &mut json);
+                    json.push('"');
+                    writed = true;
                 }
-                json.push_str("\"}");
+                json.push_str(if writed { "}" } else { "{}" });
             }
             json.push_str("]");
 
@@ -660,6 +1016,10 @@ impl JweContext {
 
             let merged = JweHeader::from_map(merged_map)?;
 
+            if compression.is_none() && merged.claim("zip").is_some() {
+                bail!("The zip header claim must be in protected.");
+            }
+
             let cencryption = match merged.claim("enc") {
                 Some(Value::String(enc)) => match self.get_content_encryption(enc) {
                     Some(val) => val,
@@ -679,16 +1039,23 @@ impl JweContext {
                 None => JweHeader::new(),
             };
 
-            let key = match encrypter.compute_content_encryption_key(
-                cencryption,
-                &merged,
-                &mut protected,
-            )? {
-                Some(val) => val,
-                None => Cow::Owned(util::random_bytes(cencryption.key_len())),
-            };
+            let key = Zeroizing::new(
+                match encrypter.compute_content_encryption_key(
+                    cencryption,
+                    &merged,
+                    &mut protected,
+                )? {
+                    Some(val) => val.into_owned(),
+                    None => self.random().random_bytes(cencryption.key_len()),
+                },
+            );
 
-            let encrypted_key = encrypter.encrypt(&key, &merged, &mut protected)?;
+            let tag_bound_encrypter =
+                encrypter.tag_bound_encrypter(cencryption, &merged, &mut protected)?;
+            let encrypted_key = match tag_bound_encrypter {
+                Some(_) => None,
+                None => encrypter.encrypt_with_rng(&key, &merged, &mut protected, self.random())?,
+            };
 
             match merged.algorithm() {
                 Some(val) if val == encrypter.algorithm().name() => {}
@@ -706,7 +1073,7 @@ impl JweContext {
 
             let iv_vec;
             let iv = if cencryption.iv_len() > 0 {
-                iv_vec = util::random_bytes(cencryption.iv_len());
+                iv_vec = self.random().random_bytes(cencryption.iv_len());
                 Some(iv_vec.as_slice())
             } else {
                 None
@@ -753,6 +1120,11 @@ impl JweContext {
 
             let (ciphertext, tag) = cencryption.encrypt(&key, iv, content, full_aad.as_bytes())?;
 
+            let encrypted_key = match tag_bound_encrypter {
+                Some(val) => Some(val.encrypt(&key, tag.as_deref().unwrap_or_default())?),
+                None => encrypted_key,
+            };
+
             let mut writed = false;
             let mut json = String::new();
             if let Some(val) = protected_b64 {
@@ -780,22 +1152,28 @@ impl JweContext {
                     json.push_str(if writed { "," } else { "{" });
                     json.push_str("\"header\":");
                     json.push_str(&header);
+                    writed = true;
                 }
             }
 
-            json.push_str(",\"encrypted_key\":\"");
             if let Some(val) = encrypted_key {
+                json.push_str(if writed { "," } else { "{" });
+                json.push_str("\"encrypted_key\":\"");
                 base64::encode_config_buf(&val, base64::URL_SAFE_NO_PAD, &mut json);
+                json.push('"');
+                writed = true;
             }
-            json.push_str("\"");
 
             if let Some(val) = aad_b64 {
-                json.push_str(",\"aad\":\"");
+                json.push_str(if writed { "," } else { "{" });
+                json.push_str("\"aad\":\"");
                 json.push_str(&val);
                 json.push_str("\"");
+                writed = true;
             }
 
-            json.push_str(",\"iv\":\"");
+            json.push_str(if writed { "," } else { "{" });
+            json.push_str("\"iv\":\"");
             if let Some(val) = iv {
                 base64::encode_config_buf(&val, base64::URL_SAFE_NO_PAD, &mut json);
             }
@@ -862,6 +1240,9 @@ impl JweContext {
             }
 
             let header_b64 = &input[0..indexies[0]];
+            if header_b64.is_empty() {
+                bail!("The JWE protected header must not be empty.");
+            }
 
             let encrypted_key_b64 = &input[(indexies[0] + 1)..(indexies[1])];
             let encrypted_key_vec;
@@ -895,9 +1276,12 @@ impl JweContext {
             };
 
             let header = base64::decode_config(header_b64, base64::URL_SAFE_NO_PAD)?;
-            let merged: Map<String, Value> = serde_json::from_slice(&header)?;
+            let merged = util::parse_json_object(&header)?;
             let merged = JweHeader::from_map(merged)?;
 
+            self.check_critical(Some(merged.claims_set()), &merged)?;
+            self.check_accepted(&merged)?;
+
             let decrypter = match selector(&merged)? {
                 Some(val) => val,
                 None => bail!("A decrypter is not found."),
@@ -941,10 +1325,15 @@ impl JweContext {
                 None => {}
             }
 
-            let key = decrypter.decrypt(encrypted_key, cencryption, &merged)?;
+            let key = Zeroizing::new(
+                decrypter
+                    .decrypt_with_tag(encrypted_key, cencryption, &merged, tag)?
+                    .into_owned(),
+            );
             if key.len() != cencryption.key_len() {
                 bail!(
-                    "The key size is expected to be {}: {}",
+                    "{} expects {}-byte key, got {}",
+                    cencryption.name(),
                     cencryption.key_len(),
                     key.len()
                 );
@@ -952,7 +1341,7 @@ impl JweContext {
 
             let content = cencryption.decrypt(&key, iv, &ciphertext, header_b64, tag)?;
             let content = match compression {
-                Some(val) => val.decompress(&content)?,
+                Some(val) => val.decompress_with_limit(&content, self.max_decompressed_len)?,
                 None => content,
             };
 
@@ -964,41 +1353,294 @@ impl JweContext {
         })
     }
 
-    /// Deserialize the input that is formatted by flattened json serialization.
+    /// Deserialize the input that is formatted by compact serialization with one of the decrypters.
+    ///
+    /// The decrypter is selected by [`jwe::select_decrypter`](crate::jwe::select_decrypter).
     ///
     /// # Arguments
     ///
     /// * `input` - The input data.
-    /// * `decrypter` - The JWE decrypter.
-    pub fn deserialize_json<'a>(
+    /// * `decrypters` - The candidate decrypters.
+    pub fn deserialize_compact_with_decrypters(
         &self,
         input: impl AsRef<[u8]>,
-        decrypter: &'a dyn JweDecrypter,
+        decrypters: &[&dyn JweDecrypter],
     ) -> Result<(Vec<u8>, JweHeader), JoseError> {
-        self.deserialize_json_with_selector(input, |header| {
-            match header.algorithm() {
-                Some(val) => {
+        self.deserialize_compact_with_selector(input, |header| {
+            Ok(select_decrypter(header, decrypters))
+        })
+    }
+
+    /// Deserialize the input that is formatted by compact serialization with a key in the JWK set.
+    ///
+    /// The candidate keys are selected by the kid header claim, or the keys without kid
+    /// are used when the header has no kid. A decrypter of the alg header claim is created from
+    /// each candidate that is usable for it, and the payload of the first one that succeeds
+    /// is returned. RSA, EC, OKP and oct keys are supported, but the RSA1_5 algorithm is
+    /// rejected.
+    ///
+    /// # Arguments
+    ///
+    /// * `input` - The input data.
+    /// * `jwk_set` - The JWK set.
+    pub fn deserialize_compact_with_jwk_set(
+        &self,
+        input: impl AsRef<[u8]>,
+        jwk_set: &JwkSet,
+    ) -> Result<(Vec<u8>, JweHeader), JoseError> {
+        (|| -> anyhow::Result<(Vec<u8>, JweHeader)> {
+            let input = input.as_ref();
+            let header_b64 = match input.iter().position(|b| *b == b'.') {
+                Some(pos) => &input[..pos],
+                None => bail!(
+                    "The compact serialization form of JWE must be five parts separated by colon."
+                ),
+            };
+            let header = base64::decode_config(header_b64, base64::URL_SAFE_NO_PAD)?;
+            let header = JweHeader::from_bytes(&header)?;
+
+            let alg = match header.algorithm() {
+                Some(val) => val,
+                None => bail!("The JWE alg header claim is required."),
+            };
+
+            let candidates = match header.key_id() {
+                Some(key_id) => jwk_set.get(key_id),
+                None => jwk_set
+                    .keys()
+                    .into_iter()
+                    .filter(|jwk| jwk.key_id().is_none())
+                    .collect(),
+            };
+
+            let mut last_err = None;
+            for jwk in candidates {
+                let decrypter = match decrypter_from_jwk(alg, jwk)? {
+                    Some(val) => val,
+                    None => continue,
+                };
+
+                match self.deserialize_compact(input, &*decrypter) {
+                    Ok(val) => return Ok(val),
+                    Err(err) => last_err = Some(err),
+                }
+            }
+
+            match last_err {
+                Some(err) => Err(err.into()),
+                None => bail!("A JWK that matched with the header claims is not found."),
+            }
+        })()
+        .map_err(|err| match err.downcast::<JoseError>() {
+            Ok(err) => err,
+            Err(err) => JoseError::InvalidJweFormat(err),
+        })
+    }
+
+    /// Decrypt the ciphertext read from the reader and write the payload to the writer.
+    ///
+    /// The ciphertext is read in chunks of 64 KiB, so the memory usage is bounded by
+    /// a few chunks regardless of the payload size. The protected header segment is the
+    /// additional authenticated data as it is in compact serialization, and its "alg" and
+    /// "enc" header claims must be accepted by the context. The payload is written before
+    /// the authentication tag is verified at the end of the input, so the written data
+    /// must be discarded when this function returns an error. Compressed payloads are not
+    /// supported.
+    ///
+    /// # Arguments
+    ///
+    /// * `header_b64` - The base64url encoded protected header segment as it is in
+    ///   the input, e.g. the first segment of a compact serialization.
+    /// * `key` - The content encryption key.
+    /// * `iv` - The initialization vector.
+    /// * `tag` - The authentication tag.
+    /// * `reader` - The reader of the ciphertext (not base64 encoded), e.g. a
+    ///   [`Base64UrlDecoder`](crate::util::Base64UrlDecoder) of the ciphertext segment.
+    /// * `writer` - The writer of the payload.
+    pub fn decrypt_stream(
+        &self,
+        header_b64: impl AsRef<[u8]>,
+        key: &[u8],
+        iv: Option<&[u8]>,
+        tag: Option<&[u8]>,
+        mut reader: impl Read,
+        mut writer: impl Write,
+    ) -> Result<u64, JoseError> {
+        (|| -> anyhow::Result<u64> {
+            let header_b64 = header_b64.as_ref();
+            let header = base64::decode_config(header_b64, base64::URL_SAFE_NO_PAD)?;
+            let header = util::parse_json_object(&header)?;
+            let header = JweHeader::from_map(header)?;
+
+            self.check_critical(Some(header.claims_set()), &header)?;
+            self.check_accepted(&header)?;
+
+            let cencryption = match header.claim("enc") {
+                Some(Value::String(val)) => match self.get_content_encryption(val) {
+                    Some(val2) => val2,
+                    None => bail!("A content encryption is not registered: {}", val),
+                },
+                Some(_) => bail!("A enc header claim must be a string."),
+                None => bail!("A enc header claim is required."),
+            };
+
+            if header.claim("zip").is_some() {
+                bail!("A compressed payload cannot be decrypted as stream.");
+            }
+
+            let written =
+                cencryption.decrypt_stream(key, iv, &mut reader, &mut writer, header_b64, tag)?;
+            Ok(written)
+        })()
+        .map_err(|err| match err.downcast::<JoseError>() {
+            Ok(err) => err,
+            Err(err) => JoseError::InvalidJweFormat(err),
+        })
+    }
+
+    /// Recover the content encryption key from the encrypted key without decrypting
+    /// the ciphertext.
+    ///
+    /// Only the key management step of the decryption is done, so this is useful to
+    /// check whether a failure comes from the key management or the content encryption.
+    /// The algorithms that bind the authentication tag to the encrypted key, e.g.
+    /// "ECDH-1PU+A128KW", are not supported because the tag is not given.
+    ///
+    /// The header is treated as the protected header, and its "crit" header claim is
+    /// checked as it is by `deserialize_compact`.
+    ///
+    /// # Arguments
+    ///
+    /// * `header` - The JWE header claims.
+    /// * `encrypted_key` - The encrypted key (not base64 encoded). It is empty for
+    ///   direct key agreement and direct encryption.
+    /// * `decrypter` - The JWE decrypter.
+    pub fn recover_cek(
+        &self,
+        header: &JweHeader,
+        encrypted_key: &[u8],
+        decrypter: &dyn JweDecrypter,
+    ) -> Result<Vec<u8>, JoseError> {
+        (|| -> anyhow::Result<Vec<u8>> {
+            self.check_critical(Some(header.claims_set()), header)?;
+            self.check_accepted(header)?;
+
+            let cencryption = match header.claim("enc") {
+                Some(Value::String(val)) => match self.get_content_encryption(val) {
+                    Some(val2) => val2,
+                    None => bail!("A content encryption is not registered: {}", val),
+                },
+                Some(_) => bail!("A enc header claim must be a string."),
+                None => bail!("A enc header claim is required."),
+            };
+
+            match header.claim("alg") {
+                Some(Value::String(val)) => {
                     let expected_alg = decrypter.algorithm().name();
                     if val != expected_alg {
-                        return Ok(None);
+                        bail!("The JWE alg header claim is not {}: {}", expected_alg, val);
                     }
                 }
-                _ => return Ok(None),
+                Some(_) => bail!("A alg header claim must be a string."),
+                None => bail!("The JWE alg header claim is required."),
             }
 
-            match decrypter.key_id() {
-                Some(expected) => match header.key_id() {
-                    Some(actual) if expected == actual => {}
-                    _ => return Ok(None),
-                },
-                None => {}
+            let encrypted_key = if !encrypted_key.is_empty() {
+                Some(encrypted_key)
+            } else {
+                None
+            };
+
+            let key = decrypter.decrypt(encrypted_key, cencryption, header)?;
+            if key.len() != cencryption.key_len() {
+                bail!(
+                    "{} expects {}-byte key, got {}",
+                    cencryption.name(),
+                    cencryption.key_len(),
+                    key.len()
+                );
             }
 
-            Ok(Some(decrypter))
+            Ok(key.into_owned())
+        })()
+        .map_err(|err| match err.downcast::<JoseError>() {
+            Ok(err) => err,
+            Err(err) => JoseError::InvalidJweFormat(err),
         })
     }
 
-    /// Deserialize the input that is formatted by flattened json serialization.
+    /// Deserialize the input that is formatted by flattened or general json serialization.
+    ///
+    /// For general json serialization, the recipients are tried in order and
+    /// the payload of the first recipient matched with the decrypter is returned.
+    ///
+    /// # Arguments
+    ///
+    /// * `input` - The input data.
+    /// * `decrypter` - The JWE decrypter.
+    pub fn deserialize_json<'a>(
+        &self,
+        input: impl AsRef<[u8]>,
+        decrypter: &'a dyn JweDecrypter,
+    ) -> Result<(Vec<u8>, JweHeader), JoseError> {
+        self.deserialize_json_with_selector(input, |header| Ok(match_decrypter(header, decrypter)))
+    }
+
+    /// Deserialize the input that is formatted by flattened or general json serialization,
+    /// and return the additional authenticated data (the aad field) in addition to
+    /// the payload and the header.
+    ///
+    /// The aad is returned only after it has been authenticated by the decryption.
+    ///
+    /// # Arguments
+    ///
+    /// * `input` - The input data.
+    /// * `decrypter` - The JWE decrypter.
+    pub fn deserialize_json_with_aad(
+        &self,
+        input: impl AsRef<[u8]>,
+        decrypter: &dyn JweDecrypter,
+    ) -> Result<DecryptedWithAad, JoseError> {
+        let (payload, header, _, aad_b64) = self
+            .deserialize_json_with_raw_aad(input, |header| {
+                Ok(match_decrypter(header, decrypter))
+            })?;
+        let aad = match aad_b64 {
+            Some(val) => Some(
+                base64::decode_config(val, base64::URL_SAFE_NO_PAD)
+                    .map_err(|err| JoseError::InvalidJweFormat(err.into()))?,
+            ),
+            None => None,
+        };
+        Ok((payload, header, aad))
+    }
+
+    /// Deserialize the input that is formatted by compact or json serialization.
+    ///
+    /// The input is deserialized as json serialization if it starts with "{"
+    /// after any leading whitespace, or as compact serialization otherwise.
+    ///
+    /// # Arguments
+    ///
+    /// * `input` - The input data.
+    /// * `decrypter` - The JWE decrypter.
+    pub fn deserialize_auto(
+        &self,
+        input: impl AsRef<[u8]>,
+        decrypter: &dyn JweDecrypter,
+    ) -> Result<(Vec<u8>, JweHeader), JoseError> {
+        let input = input.as_ref();
+        if util::is_json_object(input) {
+            self.deserialize_json(input, decrypter)
+        } else {
+            self.deserialize_compact(input, decrypter)
+        }
+    }
+
+    /// Deserialize the input that is formatted by flattened or general json serialization.
+    ///
+    /// For general json serialization, the selector is called with the merged header
+    /// of each recipient in order until it returns a decrypter.
     ///
     /// # Arguments
     ///
@@ -1012,9 +1654,44 @@ impl JweContext {
     where
         F: Fn(&JweHeader) -> Result<Option<&'a dyn JweDecrypter>, JoseError>,
     {
-        (|| -> anyhow::Result<(Vec<u8>, JweHeader)> {
+        let (payload, header, _) = self.deserialize_general_json_with_selector(input, selector)?;
+        Ok((payload, header))
+    }
+
+    /// Deserialize the input that is formatted by flattened or general json serialization,
+    /// and return the index of the recipient that was decrypted in addition to the payload
+    /// and the header.
+    ///
+    /// The selector is called with the merged header of each recipient in order until
+    /// it returns a decrypter. For flattened json serialization, the index is always 0.
+    ///
+    /// # Arguments
+    ///
+    /// * `input` - The input data.
+    /// * `selector` - a function for selecting the decrypting algorithm.
+    pub fn deserialize_general_json_with_selector<'a, F>(
+        &self,
+        input: impl AsRef<[u8]>,
+        selector: F,
+    ) -> Result<(Vec<u8>, JweHeader, usize), JoseError>
+    where
+        F: Fn(&JweHeader) -> Result<Option<&'a dyn JweDecrypter>, JoseError>,
+    {
+        let (payload, header, index, _) = self.deserialize_json_with_raw_aad(input, selector)?;
+        Ok((payload, header, index))
+    }
+
+    fn deserialize_json_with_raw_aad<'a, F>(
+        &self,
+        input: impl AsRef<[u8]>,
+        selector: F,
+    ) -> Result<(Vec<u8>, JweHeader, usize, Option<String>), JoseError>
+    where
+        F: Fn(&JweHeader) -> Result<Option<&'a dyn JweDecrypter>, JoseError>,
+    {
+        (|| -> anyhow::Result<(Vec<u8>, JweHeader, usize, Option<String>)> {
             let input = input.as_ref();
-            let mut map: Map<String, Value> = serde_json::from_slice(input)?;
+            let mut map = util::parse_json_object(input)?;
 
             let (protected, protected_b64) = match map.remove("protected") {
                 Some(Value::String(val)) => {
@@ -1022,7 +1699,7 @@ impl JweContext {
                         bail!("The protected field must be empty.");
                     }
                     let vec = base64::decode_config(&val, base64::URL_SAFE_NO_PAD)?;
-                    let json: Map<String, Value> = serde_json::from_slice(&vec)?;
+                    let json = util::parse_json_object(&vec)?;
                     (Some(json), Some(val))
                 }
                 Some(_) => bail!("The protected field must be a string."),
@@ -1041,7 +1718,7 @@ impl JweContext {
             let aad_b64 = match map.remove("aad") {
                 Some(Value::String(val)) => {
                     if val.len() == 0 {
-                        bail!("The JWE aad field must be empty.");
+                        bail!("The JWE aad field must not be empty.");
                     } else if !util::is_base64_url_safe_nopad(&val) {
                         bail!("The JWE aad field must be a base64 string.");
                     }
@@ -1108,14 +1785,15 @@ impl JweContext {
                 }
             };
 
-            for mut recipient in recipients {
+            let mut rejected = None;
+            for (index, mut recipient) in recipients.into_iter().enumerate() {
                 let header = recipient.remove("header");
 
                 let encrypted_key_vec;
                 let encrypted_key = match recipient.get("encrypted_key") {
                     Some(Value::String(val)) => {
                         if val.len() == 0 {
-                            bail!("The encrypted_key field must be empty.");
+                            bail!("The encrypted_key field must not be empty.");
                         }
                         encrypted_key_vec = base64::decode_config(&val, base64::URL_SAFE_NO_PAD)?;
                         Some(encrypted_key_vec.as_slice())
@@ -1124,34 +1802,21 @@ impl JweContext {
                     None => None,
                 };
 
-                let mut merged = match header {
-                    Some(Value::Object(val)) => val,
-                    Some(_) => bail!("The protected field must be a object."),
-                    None => Map::new(),
+                let header = match header {
+                    Some(Value::Object(val)) => Some(val),
+                    Some(_) => bail!("The header field must be a object."),
+                    None => None,
                 };
 
-                if let Some(val) = &unprotected {
-                    for (key, value) in val {
-                        if merged.contains_key(key) {
-                            bail!("A duplicate key exists: {}", key);
-                        } else {
-                            merged.insert(key.clone(), value.clone());
-                        }
-                    }
-                }
+                let merged =
+                    self.merge_header(protected.as_ref(), unprotected.as_ref(), header.as_ref())?;
 
-                if let Some(val) = &protected {
-                    for (key, value) in val {
-                        if merged.contains_key(key) {
-                            bail!("A duplicate key exists: {}", key);
-                        } else {
-                            merged.insert(key.clone(), value.clone());
-                        }
-                    }
+                self.check_critical(protected.as_ref(), &merged)?;
+                if let Err(err) = self.check_accepted(&merged) {
+                    rejected = Some(err);
+                    continue;
                 }
 
-                let merged = JweHeader::from_map(merged)?;
-
                 let decrypter = match selector(&merged)? {
                     Some(val) => val,
                     None => continue,
@@ -1167,11 +1832,17 @@ impl JweContext {
                 };
 
                 let compression = match merged.claim("zip") {
-                    Some(Value::String(val)) => match self.get_compression(val) {
-                        Some(val2) => Some(val2),
-                        None => bail!("A compression algorithm is not registered: {}", val),
-                    },
-                    Some(_) => bail!("A enc header claim must be string."),
+                    Some(Value::String(val)) => {
+                        match &protected {
+                            Some(val2) if val2.contains_key("zip") => {}
+                            _ => bail!("The zip header claim must be in protected."),
+                        }
+                        match self.get_compression(val) {
+                            Some(val2) => Some(val2),
+                            None => bail!("A compression algorithm is not registered: {}", val),
+                        }
+                    }
+                    Some(_) => bail!("A zip header claim must be string."),
                     None => None,
                 };
 
@@ -1198,15 +1869,20 @@ impl JweContext {
                     Some(val) => val,
                     None => String::new(),
                 };
-                if let Some(val) = aad_b64 {
+                if let Some(val) = &aad_b64 {
                     full_aad.push_str(".");
-                    full_aad.push_str(&val);
+                    full_aad.push_str(val);
                 }
 
-                let key = decrypter.decrypt(encrypted_key, cencryption, &merged)?;
+                let key = Zeroizing::new(
+                    decrypter
+                        .decrypt_with_tag(encrypted_key, cencryption, &merged, tag)?
+                        .into_owned(),
+                );
                 if key.len() != cencryption.key_len() {
                     bail!(
-                        "The key size is expected to be {}: {}",
+                        "{} expects {}-byte key, got {}",
+                        cencryption.name(),
                         cencryption.key_len(),
                         key.len()
                     );
@@ -1215,18 +1891,211 @@ impl JweContext {
                 let content =
                     cencryption.decrypt(&key, iv, &ciphertext, full_aad.as_bytes(), tag)?;
                 let content = match compression {
-                    Some(val) => val.decompress(&content)?,
+                    Some(val) => val.decompress_with_limit(&content, self.max_decompressed_len)?,
                     None => content,
                 };
 
-                return Ok((content, merged));
+                return Ok((content, merged, index, aad_b64));
+            }
+
+            match rejected {
+                Some(err) => Err(err),
+                None => bail!("A recipient that matched the header claims is not found."),
+            }
+        })()
+        .map_err(|err| match err.downcast::<JoseError>() {
+            Ok(err) => err,
+            Err(err) => JoseError::InvalidJweFormat(err),
+        })
+    }
+
+    /// Return the JOSE header that is merged from the three header layers
+    /// of the JSON serialization.
+    ///
+    /// The header parameter names must be disjoint across the layers (RFC 7516 Section 7.2.1),
+    /// so a duplicate name is rejected. The merged header is the one that is given to
+    /// the selector in the JSON deserialization.
+    ///
+    /// # Arguments
+    ///
+    /// * `protected` - The decoded JWE protected header.
+    /// * `unprotected` - The JWE shared unprotected header.
+    /// * `recipient` - The JWE per-recipient unprotected header.
+    pub fn merge_header(
+        &self,
+        protected: Option<&Map<String, Value>>,
+        unprotected: Option<&Map<String, Value>>,
+        recipient: Option<&Map<String, Value>>,
+    ) -> Result<JweHeader, JoseError> {
+        (|| -> anyhow::Result<JweHeader> {
+            let mut merged = Map::new();
+            for layer in [recipient, unprotected, protected].iter().flatten() {
+                for (key, value) in layer.iter() {
+                    if merged.contains_key(key) {
+                        bail!("A duplicate key exists: {}", key);
+                    }
+                    merged.insert(key.clone(), value.clone());
+                }
             }
 
-            bail!("A recipient that matched the header claims is not found.");
+            Ok(JweHeader::from_map(merged)?)
         })()
         .map_err(|err| match err.downcast::<JoseError>() {
             Ok(err) => err,
             Err(err) => JoseError::InvalidJweFormat(err),
         })
     }
+
+    fn check_accepted(&self, merged: &JweHeader) -> anyhow::Result<()> {
+        if self.accepted_algorithms.is_some() {
+            match merged.claim("alg") {
+                Some(Value::String(val)) if self.is_accepted_algorithm(val) => {}
+                Some(Value::String(val)) => {
+                    bail!("The JWE alg header claim is not accepted: {}", val)
+                }
+                Some(_) => bail!("A alg header claim must be a string."),
+                None => bail!("The JWE alg header claim is required."),
+            }
+        }
+        if self.accepted_encryptions.is_some() {
+            match merged.claim("enc") {
+                Some(Value::String(val)) if self.is_accepted_encryption(val) => {}
+                Some(Value::String(val)) => {
+                    bail!("The JWE enc header claim is not accepted: {}", val)
+                }
+                Some(_) => bail!("A enc header claim must be a string."),
+                None => bail!("A enc header claim is required."),
+            }
+        }
+        Ok(())
+    }
+
+    fn check_critical(
+        &self,
+        protected: Option<&Map<String, Value>>,
+        merged: &JweHeader,
+    ) -> anyhow::Result<()> {
+        match merged.claim("crit") {
+            Some(Value::Array(vals)) => {
+                match protected {
+                    Some(val) if val.contains_key("crit") => {}
+                    _ => bail!("The JWE crit header claim must be in protected."),
+                }
+
+                for val in vals {
+                    match val {
+                        Value::String(name) => {
+                            if !self.is_acceptable_critical(name) {
+                                bail!("The critical name '{}' is not supported.", name);
+                            }
+                            if merged.claim(name).is_none() {
+                                bail!("The critical header claim is not found: {}", name);
+                            }
+                        }
+                        _ => bail!("The JWE crit header claim must be a array of string."),
+                    }
+                }
+            }
+            Some(_) => bail!("The JWE crit header claim must be a array of string."),
+            None => {}
+        }
+        Ok(())
+    }
+}
+
+impl PartialEq for JweContext {
+    fn eq(&self, other: &Self) -> bool {
+        self.acceptable_criticals == other.acceptable_criticals
+            && self.accepted_algorithms == other.accepted_algorithms
+            && self.accepted_encryptions == other.accepted_encryptions
+            && self.compressions == other.compressions
+            && self.content_encryptions == other.content_encryptions
+            && self.max_decompressed_len == other.max_decompressed_len
+            && match (&self.rng, &other.rng) {
+                (Some(val1), Some(val2)) => Arc::ptr_eq(val1, val2),
+                (None, None) => true,
+                _ => false,
+            }
+    }
+}
+
+impl Eq for JweContext {}
+
+/// Return the decrypter if its algorithm and key ID match the header.
+fn match_decrypter<'a>(
+    header: &JweHeader,
+    decrypter: &'a dyn JweDecrypter,
+) -> Option<&'a dyn JweDecrypter> {
+    if header.algorithm() != Some(decrypter.algorithm().name()) {
+        return None;
+    }
+
+    match decrypter.key_id() {
+        Some(expected) if header.key_id() != Some(expected) => None,
+        _ => Some(decrypter),
+    }
+}
+
+/// Create a decrypter of the algorithm from the JWK.
+///
+/// Return None when the JWK is not usable for the algorithm.
+fn decrypter_from_jwk(alg: &str, jwk: &Jwk) -> anyhow::Result<Option<Box<dyn JweDecrypter>>> {
+    let decrypter: Result<Box<dyn JweDecrypter>, JoseError> = match alg {
+        "dir" => Dir.decrypter_from_jwk(jwk).map(|val| Box::new(val) as _),
+        "ECDH-ES" => ECDH_ES
+            .decrypter_from_jwk(jwk)
+            .map(|val| Box::new(val) as _),
+        "ECDH-ES+A128KW" => ECDH_ES_A128KW
+            .decrypter_from_jwk(jwk)
+            .map(|val| Box::new(val) as _),
+        "ECDH-ES+A192KW" => ECDH_ES_A192KW
+            .decrypter_from_jwk(jwk)
+            .map(|val| Box::new(val) as _),
+        "ECDH-ES+A256KW" => ECDH_ES_A256KW
+            .decrypter_from_jwk(jwk)
+            .map(|val| Box::new(val) as _),
+        "A128KW" => A128KW.decrypter_from_jwk(jwk).map(|val| Box::new(val) as _),
+        "A192KW" => A192KW.decrypter_from_jwk(jwk).map(|val| Box::new(val) as _),
+        "A256KW" => A256KW.decrypter_from_jwk(jwk).map(|val| Box::new(val) as _),
+        "A128GCMKW" => A128GCMKW
+            .decrypter_from_jwk(jwk)
+            .map(|val| Box::new(val) as _),
+        "A192GCMKW" => A192GCMKW
+            .decrypter_from_jwk(jwk)
+            .map(|val| Box::new(val) as _),
+        "A256GCMKW" => A256GCMKW
+            .decrypter_from_jwk(jwk)
+            .map(|val| Box::new(val) as _),
+        "PBES2-HS256+A128KW" => PBES2_HS256_A128KW
+            .decrypter_from_jwk(jwk)
+            .map(|val| Box::new(val) as _),
+        "PBES2-HS384+A192KW" => PBES2_HS384_A192KW
+            .decrypter_from_jwk(jwk)
+            .map(|val| Box::new(val) as _),
+        "PBES2-HS512+A256KW" => PBES2_HS512_A256KW
+            .decrypter_from_jwk(jwk)
+            .map(|val| Box::new(val) as _),
+        "RSA1_5" => {
+            bail!("RSA1_5 is disabled with a JWK set because of the Bleichenbacher attack.")
+        }
+        "RSA-OAEP" => RSA_OAEP
+            .decrypter_from_jwk(jwk)
+            .map(|val| Box::new(val) as _),
+        "RSA-OAEP-256" => RSA_OAEP_256
+            .decrypter_from_jwk(jwk)
+            .map(|val| Box::new(val) as _),
+        "RSA-OAEP-384" => RSA_OAEP_384
+            .decrypter_from_jwk(jwk)
+            .map(|val| Box::new(val) as _),
+        "RSA-OAEP-512" => RSA_OAEP_512
+            .decrypter_from_jwk(jwk)
+            .map(|val| Box::new(val) as _),
+        _ => bail!("The JWE alg is not supported with a JWK set: {}", alg),
+    };
+
+    match decrypter {
+        Ok(val) => Ok(Some(val)),
+        Err(JoseError::InvalidKeyFormat(_)) => Ok(None),
+        Err(err) => Err(err.into()),
+    }
 }
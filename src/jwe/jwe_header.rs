@@ -2,14 +2,28 @@ use std::cmp::Eq;
 use std::convert::Into;
 use std::fmt::{Debug, Display};
 use std::ops::Deref;
+use std::str::FromStr;
 
 use anyhow::bail;
 
+use crate::jwe::alg::aesgcmkw::AesgcmkwJweAlgorithm;
+use crate::jwe::alg::aeskw::AeskwJweAlgorithm;
+use crate::jwe::alg::direct::DirectJweAlgorithm;
+use crate::jwe::alg::ecdh_1pu::Ecdh1puJweAlgorithm;
+use crate::jwe::alg::ecdh_es::EcdhEsJweAlgorithm;
+use crate::jwe::alg::pbes2_hmac_aeskw::Pbes2HmacAeskwJweAlgorithm;
+use crate::jwe::alg::rsaes::RsaesJweAlgorithm;
+use crate::jwe::{JweAlgorithm, JweCompression, JweContentEncryption, DEFAULT_CONTEXT};
 use crate::jwk::Jwk;
 use crate::util;
 use crate::{JoseError, JoseHeader, Map, Value};
 
 /// Represent JWE header claims
+///
+/// Two headers are equal when they have the same claims, regardless of the order
+/// of the members and the whitespace of the JSON they are parsed from. The members
+/// of a nested object are also compared regardless of the order, while the elements
+/// of an array are compared in order.
 #[derive(Debug, Eq, PartialEq, Clone)]
 pub struct JweHeader {
     claims: Map<String, Value>,
@@ -28,7 +42,7 @@ impl JweHeader {
     /// * `value` - The json style header claims
     pub fn from_bytes(value: &[u8]) -> Result<Self, JoseError> {
         let claims = (|| -> anyhow::Result<Map<String, Value>> {
-            let claims: Map<String, Value> = serde_json::from_slice(value)?;
+            let claims = util::parse_json_object(value)?;
             Ok(claims)
         })()
         .map_err(|err| JoseError::InvalidJson(err))?;
@@ -69,6 +83,35 @@ impl JweHeader {
         }
     }
 
+    /// Set a key management algorithm as the value for algorithm header claim (alg).
+    ///
+    /// # Arguments
+    ///
+    /// * `value` - a key management algorithm
+    pub fn set_key_management_algorithm(&mut self, value: &dyn JweAlgorithm) {
+        self.set_algorithm(value.name());
+    }
+
+    /// Return the built-in key management algorithm for algorithm header claim (alg).
+    ///
+    /// None is returned when the claim is missing or doesn't name a built-in algorithm.
+    pub fn key_management_algorithm(&self) -> Option<Box<dyn JweAlgorithm>> {
+        fn parse<T: JweAlgorithm + FromStr + 'static>(name: &str) -> Option<Box<dyn JweAlgorithm>> {
+            name.parse::<T>()
+                .ok()
+                .map(|val| Box::new(val) as Box<dyn JweAlgorithm>)
+        }
+
+        let name = self.algorithm()?;
+        parse::<DirectJweAlgorithm>(name)
+            .or_else(|| parse::<AeskwJweAlgorithm>(name))
+            .or_else(|| parse::<AesgcmkwJweAlgorithm>(name))
+            .or_else(|| parse::<EcdhEsJweAlgorithm>(name))
+            .or_else(|| parse::<Ecdh1puJweAlgorithm>(name))
+            .or_else(|| parse::<Pbes2HmacAeskwJweAlgorithm>(name))
+            .or_else(|| parse::<RsaesJweAlgorithm>(name))
+    }
+
     /// Set a value for content encryption header claim (enc).
     ///
     /// # Arguments
@@ -87,6 +130,26 @@ impl JweHeader {
         }
     }
 
+    /// Set a content encryption algorithm as the value for content encryption header claim (enc).
+    ///
+    /// # Arguments
+    ///
+    /// * `value` - a content encryption algorithm
+    pub fn set_content_encryption_algorithm(&mut self, value: &dyn JweContentEncryption) {
+        self.set_content_encryption(value.name());
+    }
+
+    /// Return the built-in content encryption algorithm for content encryption header claim (enc).
+    ///
+    /// None is returned when the claim is missing or doesn't name a algorithm
+    /// registered in the default JWE context.
+    pub fn content_encryption_algorithm(&self) -> Option<Box<dyn JweContentEncryption>> {
+        let name = self.content_encryption()?;
+        DEFAULT_CONTEXT
+            .get_content_encryption(name)
+            .map(|val| val.box_clone())
+    }
+
     /// Set a value for compression header claim (zip).
     ///
     /// # Arguments
@@ -105,6 +168,26 @@ impl JweHeader {
         }
     }
 
+    /// Set a compression algorithm as the value for compression header claim (zip).
+    ///
+    /// # Arguments
+    ///
+    /// * `value` - a compression algorithm
+    pub fn set_compression_algorithm(&mut self, value: &dyn JweCompression) {
+        self.set_compression(value.name());
+    }
+
+    /// Return the built-in compression algorithm for compression header claim (zip).
+    ///
+    /// None is returned when the claim is missing or doesn't name a algorithm
+    /// registered in the default JWE context.
+    pub fn compression_algorithm(&self) -> Option<Box<dyn JweCompression>> {
+        let name = self.compression()?;
+        DEFAULT_CONTEXT
+            .get_compression(name)
+            .map(|val| val.box_clone())
+    }
+
     /// Set a value for JWK set URL header claim (jku).
     ///
     /// # Arguments
@@ -125,12 +208,15 @@ impl JweHeader {
 
     /// Set a value for JWK header claim (jwk).
     ///
+    /// The private parameters of the JWK (e.g. "d") are removed, because the header
+    /// is not encrypted.
+    ///
     /// # Arguments
     ///
     /// * `value` - a JWK
     pub fn set_jwk(&mut self, value: Jwk) {
         let key = "jwk";
-        let value: Map<String, Value> = value.into();
+        let value: Map<String, Value> = value.without_private_parameters().into();
         self.claims.insert(key.to_string(), Value::Object(value));
     }
 
@@ -174,7 +260,7 @@ impl JweHeader {
         for val in values {
             vec.push(Value::String(base64::encode_config(
                 val.as_ref(),
-                base64::URL_SAFE_NO_PAD,
+                base64::STANDARD,
             )));
         }
         self.claims.insert(key.to_string(), Value::Array(vec));
@@ -188,7 +274,7 @@ impl JweHeader {
                 for val in vals {
                     match val {
                         Value::String(val2) => {
-                            match base64::decode_config(val2, base64::URL_SAFE_NO_PAD) {
+                            match base64::decode_config(val2, base64::STANDARD) {
                                 Ok(val3) => vec.push(val3.clone()),
                                 Err(_) => return None,
                             }
@@ -415,6 +501,33 @@ impl JweHeader {
         }
     }
 
+    /// Set a value for ephemeral public key header claim (epk).
+    ///
+    /// The private parameters of the JWK (e.g. "d") are removed, so an ephemeral key
+    /// pair can be given as it is.
+    ///
+    /// # Arguments
+    ///
+    /// * `value` - a ephemeral public key
+    pub fn set_ephemeral_public_key(&mut self, value: Jwk) {
+        let key = "epk";
+        let value: Map<String, Value> = value.without_private_parameters().into();
+        self.claims.insert(key.to_string(), Value::Object(value));
+    }
+
+    /// Return the value for ephemeral public key header claim (epk).
+    ///
+    /// An error is returned when the header claim is not a valid JWK.
+    pub fn ephemeral_public_key(&self) -> Result<Option<Jwk>, JoseError> {
+        match self.claims.get("epk") {
+            Some(Value::Object(vals)) => Ok(Some(Jwk::from_map(vals.clone())?)),
+            Some(_) => Err(JoseError::InvalidJweFormat(anyhow::anyhow!(
+                "The epk header claim must be object."
+            ))),
+            None => Ok(None),
+        }
+    }
+
     /// Set a value for issuer header claim (iss).
     ///
     /// # Arguments
@@ -516,6 +629,22 @@ impl JweHeader {
         Ok(())
     }
 
+    /// Remove the header claim of a specified key and return its value.
+    ///
+    /// The order of the remaining header claims is kept.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - a key name of header claim
+    pub fn remove_claim(&mut self, key: &str) -> Option<Value> {
+        self.claims.shift_remove(key)
+    }
+
+    /// Return an iterator over the key names and the values of header claims.
+    pub fn claims(&self) -> impl Iterator<Item = (&str, &Value)> {
+        self.claims.iter().map(|(key, value)| (key.as_str(), value))
+    }
+
     /// Return values for header claims set
     pub fn claims_set(&self) -> &Map<String, Value> {
         &self.claims
@@ -592,9 +721,9 @@ impl JweHeader {
                     }
                     _ => bail!("The JWE {} header claim must be a array.", key),
                 },
-                "jwk" => match &value {
+                "jwk" | "epk" => match &value {
                     Value::Object(vals) => Jwk::check_map(vals)?,
-                    _ => bail!("The JWE {} header claim must be a string.", key),
+                    _ => bail!("The JWE {} header claim must be a object.", key),
                 },
                 _ => {}
             }
@@ -651,8 +780,12 @@ mod tests {
     use anyhow::Result;
     use serde_json::json;
 
-    use crate::jwe::JweHeader;
+    use crate::jwe::enc::A256GCM;
+    use crate::jwe::zip::DEF;
+    use crate::jwe::{JweHeader, ECDH_ES_A128KW, RSA_OAEP_256};
+    use crate::jwk::alg::ec::EcCurve;
     use crate::jwk::Jwk;
+    use crate::Value;
 
     #[test]
     fn test_new_jwe_header() -> Result<()> {
@@ -675,6 +808,7 @@ mod tests {
         header.set_nonce(b"nonce");
         header.set_agreement_partyuinfo(b"apu");
         header.set_agreement_partyvinfo(b"apv");
+        header.set_ephemeral_public_key(jwk.clone());
         header.set_issuer("iss");
         header.set_subject("sub");
         header.set_claim("header_claim", Some(json!("header_claim")))?;
@@ -704,6 +838,7 @@ mod tests {
         assert!(matches!(header.nonce(), Some(val) if val == b"nonce".to_vec()));
         assert!(matches!(header.agreement_partyuinfo(), Some(val) if val == b"apu".to_vec()));
         assert!(matches!(header.agreement_partyvinfo(), Some(val) if val == b"apv".to_vec()));
+        assert!(matches!(header.ephemeral_public_key()?, Some(val) if val == jwk));
         assert!(matches!(header.issuer(), Some("iss")));
         assert!(matches!(header.subject(), Some("sub")));
         assert!(matches!(header.critical(), Some(vals) if vals == vec!["crit0", "crit1"]));
@@ -711,4 +846,141 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_jwe_header_agreement_claims_are_base64url_encoded() -> Result<()> {
+        let mut header = JweHeader::new();
+        header.set_agreement_partyuinfo(b"Alice");
+        header.set_agreement_partyvinfo([0xfb, 0xff]);
+
+        let json: serde_json::Value = serde_json::from_str(&header.to_string())?;
+        assert_eq!(json["apu"], json!("QWxpY2U"));
+        assert_eq!(json["apv"], json!("-_8"));
+
+        let header = JweHeader::from_bytes(header.to_string().as_bytes())?;
+        assert_eq!(header.agreement_partyuinfo(), Some(b"Alice".to_vec()));
+        assert_eq!(header.agreement_partyvinfo(), Some(vec![0xfb, 0xff]));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_jwe_header_ephemeral_public_key() -> Result<()> {
+        let mut header = JweHeader::new();
+        assert_eq!(header.ephemeral_public_key()?, None);
+
+        let private_key = Jwk::generate_ec_key(EcCurve::P256)?;
+        header.set_ephemeral_public_key(private_key.clone());
+        assert_eq!(header.claim("epk").unwrap().get("d"), None);
+
+        let header = JweHeader::from_bytes(header.to_string().as_bytes())?;
+        assert_eq!(
+            header.ephemeral_public_key()?,
+            Some(private_key.to_public_key()?)
+        );
+
+        let mut header = JweHeader::new();
+        assert!(header
+            .set_claim("epk", Some(json!({ "crv": "P-256" })))
+            .is_err());
+        assert_eq!(header.ephemeral_public_key()?, None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_jwe_header_typed_algorithms() -> Result<()> {
+        let mut header = JweHeader::new();
+        assert!(header.content_encryption_algorithm().is_none());
+        assert!(header.compression_algorithm().is_none());
+
+        header.set_content_encryption_algorithm(&A256GCM);
+        header.set_compression_algorithm(&DEF);
+        assert_eq!(header.content_encryption(), Some("A256GCM"));
+        assert_eq!(header.compression(), Some("DEF"));
+
+        let enc = header.content_encryption_algorithm().unwrap();
+        assert_eq!(enc.name(), "A256GCM");
+        assert_eq!(enc.key_len(), 32);
+        assert_eq!(header.compression_algorithm().unwrap().name(), "DEF");
+
+        header.set_content_encryption("A256GCM-UNKNOWN");
+        header.set_compression("UNKNOWN");
+        assert!(header.content_encryption_algorithm().is_none());
+        assert!(header.compression_algorithm().is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_jwe_header_key_management_algorithm() -> Result<()> {
+        let mut header = JweHeader::new();
+        assert!(header.key_management_algorithm().is_none());
+
+        header.set_algorithm("RSA-OAEP-256");
+        let alg = header.key_management_algorithm().unwrap();
+        assert_eq!(alg.name(), "RSA-OAEP-256");
+        assert_eq!(alg.name(), RSA_OAEP_256.name());
+
+        header.set_key_management_algorithm(&ECDH_ES_A128KW);
+        assert_eq!(header.algorithm(), Some("ECDH-ES+A128KW"));
+        assert_eq!(
+            header
+                .key_management_algorithm()
+                .map(|alg| alg.name().to_string()),
+            Some("ECDH-ES+A128KW".to_string())
+        );
+
+        for name in ["dir", "A256GCMKW", "ECDH-1PU", "PBES2-HS256+A128KW"] {
+            header.set_algorithm(name);
+            assert_eq!(header.key_management_algorithm().unwrap().name(), name);
+        }
+
+        header.set_algorithm("RSA-OAEP-UNKNOWN");
+        assert!(header.key_management_algorithm().is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_jwe_header_remove_claim() -> Result<()> {
+        let mut header = JweHeader::from_bytes(
+            br#"{"alg":"A","enc":"A128GCM","kid":"key-1","typ":"JWT","a":1}"#,
+        )?;
+
+        assert_eq!(header.remove_claim("kid"), Some(json!("key-1")));
+        assert_eq!(header.key_id(), None);
+        assert_eq!(header.remove_claim("kid"), None);
+
+        let claims: Vec<(&str, &Value)> = header.claims().collect();
+        assert_eq!(
+            claims,
+            vec![
+                ("alg", &json!("A")),
+                ("enc", &json!("A128GCM")),
+                ("typ", &json!("JWT")),
+                ("a", &json!(1)),
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_jwe_header_equality() -> Result<()> {
+        let header1 = JweHeader::from_bytes(
+            br#"{"alg":"A","enc":"A128GCM","jwk":{"kty":"EC","crv":"P-256"},"crit":["a","b"],"a":1}"#,
+        )?;
+        let header2 = JweHeader::from_bytes(
+            br#"{ "a": 1, "crit": ["a", "b"], "jwk": {"crv": "P-256", "kty": "EC"}, "enc":"A128GCM","alg": "A" }"#,
+        )?;
+        assert_eq!(header1, header2);
+
+        let header3 = JweHeader::from_bytes(
+            br#"{"alg":"A","enc":"A128GCM","jwk":{"kty":"EC","crv":"P-256"},"crit":["b","a"],"a":1}"#,
+        )?;
+        assert_ne!(header1, header3);
+
+        Ok(())
+    }
 }
@@ -122,12 +122,15 @@ impl JweHeaderSet {
 
     /// Set a value for JWK header claim (jwk).
     ///
+    /// The private parameters of the JWK (e.g. "d") are removed, because the header
+    /// is not encrypted.
+    ///
     /// # Arguments
     ///
     /// * `value` - a JWK
     pub fn set_jwk(&mut self, value: Jwk, protection: bool) {
         let key = "jwk";
-        let value: Map<String, Value> = value.into();
+        let value: Map<String, Value> = value.without_private_parameters().into();
         if protection {
             self.unprotected.remove(key);
             self.protected.insert(key.to_string(), Value::Object(value));
@@ -184,7 +187,7 @@ impl JweHeaderSet {
         let key = "x5c";
         let vec = values
             .iter()
-            .map(|v| Value::String(base64::encode_config(v.as_ref(), base64::URL_SAFE_NO_PAD)))
+            .map(|v| Value::String(base64::encode_config(v.as_ref(), base64::STANDARD)))
             .collect();
         if protection {
             self.unprotected.remove(key);
@@ -203,7 +206,7 @@ impl JweHeaderSet {
                 for val in vals {
                     match val {
                         Value::String(val2) => {
-                            match base64::decode_config(val2, base64::URL_SAFE_NO_PAD) {
+                            match base64::decode_config(val2, base64::STANDARD) {
                                 Ok(val3) => vec.push(val3.clone()),
                                 Err(_) => return None,
                             }
@@ -370,7 +373,7 @@ impl JweHeaderSet {
         let key = "crit";
         let vec = values
             .iter()
-            .map(|v| Value::String(base64::encode_config(v.as_ref(), base64::URL_SAFE_NO_PAD)))
+            .map(|v| Value::String(v.as_ref().to_string()))
             .collect();
         self.unprotected.remove(key);
         self.protected.insert(key.to_string(), Value::Array(vec));
@@ -0,0 +1,120 @@
+use crate::jwe::{JweEncrypter, JweHeader, JweHeaderSet, DEFAULT_CONTEXT};
+use crate::JoseError;
+
+/// A list of JWE recipients for general json serialization.
+///
+/// Each recipient has an encrypter and optional per-recipient unprotected
+/// header claims, e.g. its own "kid" header claim.
+#[derive(Debug, Clone, Default)]
+pub struct JweMultiEncrypter {
+    recipients: Vec<(Option<JweHeader>, Box<dyn JweEncrypter>)>,
+}
+
+impl JweMultiEncrypter {
+    /// Return a new empty JweMultiEncrypter instance.
+    pub fn new() -> Self {
+        Self {
+            recipients: Vec::new(),
+        }
+    }
+
+    /// Add a recipient.
+    ///
+    /// The header becomes the unprotected header of the recipient. The "alg"
+    /// header claim and the "kid" header claim of the encrypter are set to it
+    /// when the merged header claims don't have them.
+    ///
+    /// # Arguments
+    ///
+    /// * `encrypter` - a JWE encrypter
+    /// * `header` - The JWE unprotected header claims for the recipient.
+    pub fn add_recipient(&mut self, encrypter: Box<dyn JweEncrypter>, header: Option<JweHeader>) {
+        self.recipients.push((header, encrypter));
+    }
+
+    /// Return the number of the recipients.
+    pub fn len(&self) -> usize {
+        self.recipients.len()
+    }
+
+    /// Return whether there is no recipient.
+    pub fn is_empty(&self) -> bool {
+        self.recipients.is_empty()
+    }
+
+    /// Return a representation of the data that is formatted by general json serialization
+    /// for the recipients.
+    ///
+    /// # Arguments
+    ///
+    /// * `payload` - The payload data.
+    /// * `header` - The JWE shared protected and unprotected header claims.
+    /// * `aad` - The JWE additional authenticated data.
+    pub fn serialize_general_json(
+        &self,
+        payload: &[u8],
+        header: Option<&JweHeaderSet>,
+        aad: Option<&[u8]>,
+    ) -> Result<String, JoseError> {
+        let recipients: Vec<(Option<&JweHeader>, &dyn JweEncrypter)> = self
+            .recipients
+            .iter()
+            .map(|(header, encrypter)| (header.as_ref(), encrypter.as_ref()))
+            .collect();
+        DEFAULT_CONTEXT.serialize_general_json(payload, header, &recipients, aad)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+
+    use super::JweMultiEncrypter;
+    use crate::jwe::{self, JweHeader, JweHeaderSet, A128KW};
+    use crate::util;
+    use crate::Value;
+
+    #[test]
+    fn test_multi_encrypter() -> Result<()> {
+        let key_1 = util::random_bytes(16);
+        let key_2 = util::random_bytes(16);
+
+        let mut encrypter = JweMultiEncrypter::new();
+        assert!(encrypter.is_empty());
+
+        let mut header_1 = JweHeader::new();
+        header_1.set_key_id("kid-1");
+        encrypter.add_recipient(
+            Box::new(A128KW.encrypter_from_bytes(&key_1)?),
+            Some(header_1),
+        );
+
+        let mut encrypter_2 = A128KW.encrypter_from_bytes(&key_2)?;
+        encrypter_2.set_key_id("kid-2");
+        encrypter.add_recipient(Box::new(encrypter_2), None);
+        assert_eq!(encrypter.len(), 2);
+
+        let mut header = JweHeaderSet::new();
+        header.set_content_encryption("A128GCM", true);
+        let json = encrypter.serialize_general_json(b"test payload!", Some(&header), None)?;
+
+        let value: Value = serde_json::from_str(&json)?;
+        let kids: Vec<&Value> = value["recipients"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|recipient| &recipient["header"]["kid"])
+            .collect();
+        assert_eq!(kids, vec!["kid-1", "kid-2"]);
+
+        for (kid, key) in [("kid-1", &key_1), ("kid-2", &key_2)] {
+            let mut decrypter = A128KW.decrypter_from_bytes(key)?;
+            decrypter.set_key_id(kid);
+            let (payload, header) = jwe::deserialize_json(&json, &decrypter)?;
+            assert_eq!(payload, b"test payload!");
+            assert_eq!(header.key_id(), Some(kid));
+        }
+
+        Ok(())
+    }
+}
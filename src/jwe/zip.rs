@@ -1,4 +1,8 @@
 pub mod deflate;
+pub mod gzip;
 
 pub use crate::jwe::zip::deflate::DeflateJweCompression::Def;
 pub use Def as DEF;
+
+pub use crate::jwe::zip::gzip::GzipJweCompression::Gzip;
+pub use Gzip as GZIP;
@@ -6,6 +6,7 @@ use flate2::read::DeflateDecoder;
 use flate2::write::DeflateEncoder;
 use flate2::Compression;
 
+use crate::jwe::jwe_compression::read_with_limit;
 use crate::jwe::JweCompression;
 
 #[derive(Debug, Eq, PartialEq, Copy, Clone)]
@@ -35,6 +36,10 @@ impl JweCompression for DeflateJweCompression {
         Ok(vec)
     }
 
+    fn decompress_with_limit(&self, data: &[u8], limit: usize) -> Result<Vec<u8>, io::Error> {
+        read_with_limit(DeflateDecoder::new(data), limit)
+    }
+
     fn box_clone(&self) -> Box<dyn JweCompression> {
         Box::new(self.clone())
     }
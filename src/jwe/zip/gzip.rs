@@ -0,0 +1,62 @@
+use std::fmt::Display;
+use std::io::{self, Read, Write};
+use std::ops::Deref;
+
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+
+use crate::jwe::jwe_compression::read_with_limit;
+use crate::jwe::JweCompression;
+
+/// A compression that is not registered by default, because "GZIP" is not
+/// a registered zip header value. Add it to a JweContext to opt in.
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub enum GzipJweCompression {
+    /// Compression with the gzip [RFC1952] file format
+    Gzip,
+}
+
+impl JweCompression for GzipJweCompression {
+    fn name(&self) -> &str {
+        match self {
+            Self::Gzip => "GZIP",
+        }
+    }
+
+    fn compress(&self, message: &[u8]) -> Result<Vec<u8>, io::Error> {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(message)?;
+        let vec = encoder.finish()?;
+        Ok(vec)
+    }
+
+    fn decompress(&self, data: &[u8]) -> Result<Vec<u8>, io::Error> {
+        let mut decoder = GzDecoder::new(data);
+        let mut vec = Vec::new();
+        decoder.read_to_end(&mut vec)?;
+        Ok(vec)
+    }
+
+    fn decompress_with_limit(&self, data: &[u8], limit: usize) -> Result<Vec<u8>, io::Error> {
+        read_with_limit(GzDecoder::new(data), limit)
+    }
+
+    fn box_clone(&self) -> Box<dyn JweCompression> {
+        Box::new(*self)
+    }
+}
+
+impl Display for GzipJweCompression {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
+        fmt.write_str(self.name())
+    }
+}
+
+impl Deref for GzipJweCompression {
+    type Target = dyn JweCompression;
+
+    fn deref(&self) -> &Self::Target {
+        self
+    }
+}
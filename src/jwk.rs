@@ -4,11 +4,15 @@ pub mod alg;
 
 mod jwk;
 mod jwk_set;
+#[cfg(feature = "jwks-client")]
+mod jwk_set_cache;
 mod key_info;
 mod key_pair;
 
 pub use crate::jwk::jwk::Jwk;
 pub use crate::jwk::jwk_set::JwkSet;
+#[cfg(feature = "jwks-client")]
+pub use crate::jwk::jwk_set_cache::JwkSetCache;
 pub use crate::jwk::key_info::KeyAlg;
 pub use crate::jwk::key_info::KeyFormat;
 pub use crate::jwk::key_info::KeyInfo;
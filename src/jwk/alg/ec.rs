@@ -3,9 +3,9 @@ use std::ops::Deref;
 
 use anyhow::bail;
 use openssl::bn::{BigNum, BigNumContext};
-use openssl::ec::{EcGroup, EcKey};
+use openssl::ec::{EcGroup, EcKey, EcPoint};
 use openssl::nid::Nid;
-use openssl::pkey::{PKey, Private};
+use openssl::pkey::{PKey, Private, Public};
 
 use crate::jwk::{Jwk, KeyPair};
 use crate::util;
@@ -52,13 +52,47 @@ impl EcCurve {
         }
     }
 
-    fn coordinate_size(&self) -> usize {
+    /// Return the byte length of a coordinate of a EC point on the curve.
+    pub fn coordinate_size(&self) -> usize {
         match self {
             Self::P256 | Self::Secp256k1 => 32,
             Self::P384 => 48,
             Self::P521 => 66,
         }
     }
+
+    /// Normalize a coordinate of a EC point to the byte length of the curve.
+    ///
+    /// The x and y parameters of a JWK must have the full length of a coordinate
+    /// (RFC 7518 Section 6.2.1.2), so a shorter value is rejected when the JWK is imported.
+    /// Some libraries drop the leading zero bytes, and such a value can be repaired
+    /// by this function before the import: a shorter value is left-padded with zeros,
+    /// the redundant leading zeros of a longer value are removed, and a value that
+    /// is still longer than the coordinate size is rejected.
+    ///
+    /// # Arguments
+    ///
+    /// * `input` - A big-endian coordinate value.
+    pub fn normalize_coordinate(&self, input: &[u8]) -> Result<Vec<u8>, JoseError> {
+        let coordinate_size = self.coordinate_size();
+        let start = input
+            .iter()
+            .position(|b| *b != 0)
+            .unwrap_or(input.len())
+            .min(input.len().saturating_sub(coordinate_size));
+        let input = &input[start..];
+        if input.len() > coordinate_size {
+            return Err(JoseError::InvalidKeyFormat(anyhow::anyhow!(
+                "The length of the EC point coordinate must be {} or less: {}",
+                coordinate_size,
+                input.len()
+            )));
+        }
+
+        let mut vec = vec![0; coordinate_size - input.len()];
+        vec.extend_from_slice(input);
+        Ok(vec)
+    }
 }
 
 impl Display for EcCurve {
@@ -201,6 +235,8 @@ impl EcKeyPair {
             };
 
             let public_key = if let (Some(x), Some(y)) = (x, y) {
+                Self::public_key_from_coordinates(&x, &y, curve)?;
+
                 let mut public_key = Vec::with_capacity(1 + x.len() + y.len());
                 public_key.push(0x04);
                 public_key.extend_from_slice(&x);
@@ -358,6 +394,42 @@ impl EcKeyPair {
         jwk
     }
 
+    /// Create a public key from the affine coordinates of a EC point.
+    ///
+    /// The coordinates must have the length of the curve and the point must be on the curve,
+    /// otherwise the key is rejected to prevent invalid curve attacks.
+    pub(crate) fn public_key_from_coordinates(
+        x: &[u8],
+        y: &[u8],
+        curve: EcCurve,
+    ) -> anyhow::Result<PKey<Public>> {
+        let coordinate_size = curve.coordinate_size();
+        if x.len() != coordinate_size || y.len() != coordinate_size {
+            bail!(
+                "The length of the EC point coordinates must be {}: x = {}, y = {}",
+                coordinate_size,
+                x.len(),
+                y.len()
+            );
+        }
+
+        let ec_group = EcGroup::from_curve_name(curve.nid())?;
+        let x = BigNum::from_slice(x)?;
+        let y = BigNum::from_slice(y)?;
+        let mut ctx = BigNumContext::new()?;
+        let mut point = EcPoint::new(&ec_group)?;
+        let ec_key = match point.set_affine_coordinates_gfp(&ec_group, &x, &y, &mut ctx) {
+            Ok(()) => EcKey::from_public_key(&ec_group, &point)?,
+            Err(_) => bail!("The EC point is not on the curve: {}", curve),
+        };
+        // EC_KEY_check_key rejects the point at infinity and the point that is not on the curve.
+        if ec_key.check_key().is_err() {
+            bail!("The EC point is not on the curve: {}", curve);
+        }
+
+        Ok(PKey::from_ec_key(ec_key)?)
+    }
+
     pub(crate) fn detect_pkcs8(input: impl AsRef<[u8]>, is_public: bool) -> Option<EcCurve> {
         let curve;
         let mut reader = DerReader::from_reader(input.as_ref());
@@ -550,6 +622,120 @@ mod tests {
     use anyhow::Result;
 
     use super::{EcCurve, EcKeyPair};
+    use crate::jwk::Jwk;
+    use crate::jws::ES256;
+    use crate::{JoseError, Value};
+
+    #[test]
+    fn test_ec_public_key_from_coordinates() -> Result<()> {
+        let x = base64::decode_config(
+            "f83OJ3D2xF1Bg8vub9tLe1gHMzV76e8Tus9uPHvRVEU",
+            base64::URL_SAFE_NO_PAD,
+        )?;
+        let y = base64::decode_config(
+            "x_FEzRu9m36HLN_tue659LNpXW6pCyStikYjKIWI5a0",
+            base64::URL_SAFE_NO_PAD,
+        )?;
+        EcKeyPair::public_key_from_coordinates(&x, &y, EcCurve::P256)?;
+
+        let mut invalid_y = y.clone();
+        invalid_y[31] ^= 0x01;
+        assert!(EcKeyPair::public_key_from_coordinates(&x, &invalid_y, EcCurve::P256).is_err());
+        assert!(EcKeyPair::public_key_from_coordinates(&x, &y, EcCurve::Secp256k1).is_err());
+        assert!(EcKeyPair::public_key_from_coordinates(&x[1..], &y, EcCurve::P256).is_err());
+
+        let mut jwk = Jwk::new("EC");
+        jwk.set_curve("P-256");
+        jwk.set_parameter(
+            "x",
+            Some(Value::String(base64::encode_config(
+                &x,
+                base64::URL_SAFE_NO_PAD,
+            ))),
+        )?;
+        jwk.set_parameter(
+            "y",
+            Some(Value::String(base64::encode_config(
+                &invalid_y,
+                base64::URL_SAFE_NO_PAD,
+            ))),
+        )?;
+        match ES256.verifier_from_jwk(&jwk) {
+            Err(JoseError::InvalidKeyFormat(_)) => {}
+            _ => unreachable!(),
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_ec_normalize_coordinate() -> Result<()> {
+        // The x coordinate of 379 * G on P-256 has a leading zero byte.
+        let x = base64::decode_config(
+            "VUOJSvPQDtfXQKvb11yWsGh3t4fbX3Dup4uQqNfACg",
+            base64::URL_SAFE_NO_PAD,
+        )?;
+        let y = base64::decode_config(
+            "u0yFo9jqKe-q-iRAaRLdhNWxTcMr9lbvbGvVil2UP5I",
+            base64::URL_SAFE_NO_PAD,
+        )?;
+        assert_eq!(x.len(), 31);
+
+        let mut jwk = Jwk::new("EC");
+        jwk.set_curve("P-256");
+        jwk.set_parameter(
+            "x",
+            Some(Value::String(base64::encode_config(
+                &x,
+                base64::URL_SAFE_NO_PAD,
+            ))),
+        )?;
+        jwk.set_parameter(
+            "y",
+            Some(Value::String(base64::encode_config(
+                &y,
+                base64::URL_SAFE_NO_PAD,
+            ))),
+        )?;
+        assert!(matches!(
+            ES256.verifier_from_jwk(&jwk),
+            Err(JoseError::InvalidKeyFormat(_))
+        ));
+
+        let normalized = EcCurve::P256.normalize_coordinate(&x)?;
+        assert_eq!(normalized.len(), 32);
+        assert_eq!(normalized[0], 0);
+        assert_eq!(&normalized[1..], &x as &[u8]);
+        jwk.set_parameter(
+            "x",
+            Some(Value::String(base64::encode_config(
+                &normalized,
+                base64::URL_SAFE_NO_PAD,
+            ))),
+        )?;
+        let verifier = ES256.verifier_from_jwk(&jwk)?;
+
+        let mut d = vec![0; 30];
+        d.extend_from_slice(&[0x01, 0x7B]);
+        jwk.set_parameter(
+            "d",
+            Some(Value::String(base64::encode_config(
+                &d,
+                base64::URL_SAFE_NO_PAD,
+            ))),
+        )?;
+        let signer = ES256.signer_from_jwk(&jwk)?;
+        let signature = signer.sign(b"abcde")?;
+        verifier.verify(b"abcde", &signature)?;
+
+        let mut padded = vec![0; 3];
+        padded.extend_from_slice(&normalized);
+        assert_eq!(EcCurve::P256.normalize_coordinate(&padded)?, normalized);
+        assert_eq!(EcCurve::P256.normalize_coordinate(&[])?, vec![0; 32]);
+        assert!(EcCurve::P256.normalize_coordinate(&[0xFF; 33]).is_err());
+
+        Ok(())
+    }
 
     #[test]
     fn test_ec_jwt() -> Result<()> {
@@ -2,7 +2,7 @@ use std::fmt::Display;
 use std::ops::Deref;
 
 use anyhow::bail;
-use openssl::pkey::{PKey, Private};
+use openssl::pkey::{PKey, Private, Public};
 
 use crate::jwk::{Jwk, KeyPair};
 use crate::util;
@@ -24,6 +24,14 @@ impl EdCurve {
         }
     }
 
+    /// Return the byte length of the private and public keys.
+    pub fn key_len(&self) -> usize {
+        match self {
+            Self::Ed25519 => 32,
+            Self::Ed448 => 57,
+        }
+    }
+
     pub fn oid(&self) -> &ObjectIdentifier {
         match self {
             Self::Ed25519 => &*OID_ED25519,
@@ -184,6 +192,13 @@ impl EdKeyPair {
                 Some(_) => bail!("A parameter d must be a string."),
                 None => bail!("A parameter d is required."),
             };
+            if d.len() != curve.key_len() {
+                bail!(
+                    "The length of parameter d must be {}: {}",
+                    curve.key_len(),
+                    d.len()
+                );
+            }
 
             let mut builder = DerBuilder::new();
             builder.append_octed_string_from_bytes(&d);
@@ -338,6 +353,21 @@ impl EdKeyPair {
         jwk
     }
 
+    /// Create a public key from the raw bytes of a EdDSA public key.
+    pub(crate) fn public_key_from_bytes(x: &[u8], curve: EdCurve) -> anyhow::Result<PKey<Public>> {
+        if x.len() != curve.key_len() {
+            bail!(
+                "The length of the {} public key must be {}: {}",
+                curve,
+                curve.key_len(),
+                x.len()
+            );
+        }
+
+        let pkcs8 = Self::to_pkcs8(x, true, curve);
+        Ok(PKey::public_key_from_der(&pkcs8)?)
+    }
+
     pub(crate) fn detect_pkcs8(input: impl AsRef<[u8]>, is_public: bool) -> Option<EdCurve> {
         let curve;
         let mut reader = DerReader::from_reader(input.as_ref());
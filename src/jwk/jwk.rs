@@ -2,15 +2,35 @@ use std::fmt::Display;
 use std::io::Read;
 use std::string::ToString;
 
-use anyhow::bail;
+use anyhow::{anyhow, bail};
+use openssl::bn::{BigNum, BigNumContext};
+use openssl::hash;
+use openssl::pkey::PKey;
 
 use crate::jwk::alg::ec::{EcCurve, EcKeyPair};
 use crate::jwk::alg::ecx::{EcxCurve, EcxKeyPair};
 use crate::jwk::alg::ed::{EdCurve, EdKeyPair};
 use crate::jwk::alg::rsa::RsaKeyPair;
-use crate::util;
+use crate::jwk::KeyPair;
+use crate::util::der::{DerBuilder, DerReader, DerType};
+use crate::util::{self, HashAlgorithm};
 use crate::{JoseError, Map, Value};
 
+/// The parameters of a private key or a symmetric key.
+const PRIVATE_KEY_PARAMETERS: [&str; 8] = ["d", "p", "q", "dp", "dq", "qi", "oth", "k"];
+
+/// The operations of a private key and the dual operations of its public key.
+const PUBLIC_KEY_OPERATIONS: [(&str, &str); 8] = [
+    ("sign", "verify"),
+    ("verify", "verify"),
+    ("decrypt", "encrypt"),
+    ("encrypt", "encrypt"),
+    ("unwrapKey", "wrapKey"),
+    ("wrapKey", "wrapKey"),
+    ("deriveKey", "deriveKey"),
+    ("deriveBits", "deriveBits"),
+];
+
 /// Represents JWK object.
 #[derive(Debug, Eq, PartialEq, Clone)]
 pub struct Jwk {
@@ -57,11 +77,118 @@ impl Jwk {
         })
     }
 
+    /// Create a JWK from a key that is a DER encoded PKCS#8 PrivateKeyInfo or SubjectPublicKeyInfo.
+    ///
+    /// The key type is detected from the algorithm identifier of the key. RSA, EC
+    /// (P-256, P-384, P-521 and secp256k1) and OKP (Ed25519, Ed448, X25519 and X448)
+    /// keys are supported. A private key is converted to a JWK that has both
+    /// the private and public parameters.
+    ///
+    /// # Arguments
+    /// * `input` - A key that is a DER encoded PKCS#8 PrivateKeyInfo or SubjectPublicKeyInfo.
+    pub fn from_der(input: impl AsRef<[u8]>) -> Result<Self, JoseError> {
+        (|| -> anyhow::Result<Self> {
+            let input = input.as_ref();
+
+            if RsaKeyPair::detect_pkcs8(input, false).is_some() {
+                return Ok(RsaKeyPair::from_der(input)?.to_jwk_key_pair());
+            } else if let Some(curve) = EcKeyPair::detect_pkcs8(input, false) {
+                return Ok(EcKeyPair::from_der(input, Some(curve))?.to_jwk_key_pair());
+            } else if EdKeyPair::detect_pkcs8(input, false).is_some() {
+                return Ok(EdKeyPair::from_der(input)?.to_jwk_key_pair());
+            } else if EcxKeyPair::detect_pkcs8(input, false).is_some() {
+                return Ok(EcxKeyPair::from_der(input)?.to_jwk_key_pair());
+            }
+
+            let jwk = if RsaKeyPair::detect_pkcs8(input, true).is_some() {
+                let rsa = PKey::public_key_from_der(input)?.rsa()?;
+
+                let mut jwk = Jwk::new("RSA");
+                jwk.map.insert(
+                    "n".to_string(),
+                    Value::String(base64::encode_config(
+                        rsa.n().to_vec(),
+                        base64::URL_SAFE_NO_PAD,
+                    )),
+                );
+                jwk.map.insert(
+                    "e".to_string(),
+                    Value::String(base64::encode_config(
+                        rsa.e().to_vec(),
+                        base64::URL_SAFE_NO_PAD,
+                    )),
+                );
+                jwk
+            } else if let Some(curve) = EcKeyPair::detect_pkcs8(input, true) {
+                let ec_key = PKey::public_key_from_der(input)?.ec_key()?;
+                let coordinate_size = curve.coordinate_size();
+
+                let mut x = BigNum::new()?;
+                let mut y = BigNum::new()?;
+                let mut ctx = BigNumContext::new()?;
+                ec_key
+                    .public_key()
+                    .affine_coordinates_gfp(ec_key.group(), &mut x, &mut y, &mut ctx)?;
+
+                let mut jwk = Jwk::new("EC");
+                jwk.map
+                    .insert("crv".to_string(), Value::String(curve.to_string()));
+                jwk.map.insert(
+                    "x".to_string(),
+                    Value::String(base64::encode_config(
+                        util::num_to_vec(&x, coordinate_size),
+                        base64::URL_SAFE_NO_PAD,
+                    )),
+                );
+                jwk.map.insert(
+                    "y".to_string(),
+                    Value::String(base64::encode_config(
+                        util::num_to_vec(&y, coordinate_size),
+                        base64::URL_SAFE_NO_PAD,
+                    )),
+                );
+                jwk
+            } else {
+                let curve = if let Some(curve) = EdKeyPair::detect_pkcs8(input, true) {
+                    curve.name().to_string()
+                } else if let Some(curve) = EcxKeyPair::detect_pkcs8(input, true) {
+                    curve.name().to_string()
+                } else {
+                    bail!("The key must be a PKCS#8 PrivateKeyInfo or SubjectPublicKeyInfo of supported algorithm.");
+                };
+
+                let mut jwk = Jwk::new("OKP");
+                jwk.map.insert("crv".to_string(), Value::String(curve));
+                jwk.map.insert(
+                    "x".to_string(),
+                    Value::String(base64::encode_config(
+                        Self::spki_public_key(input)?,
+                        base64::URL_SAFE_NO_PAD,
+                    )),
+                );
+                jwk
+            };
+            Ok(jwk)
+        })()
+        .map_err(|err| match err.downcast::<JoseError>() {
+            Ok(err) => err,
+            Err(err) => JoseError::InvalidKeyFormat(err),
+        })
+    }
+
     /// Generate a new oct type JWK.
     ///
+    /// The key length must be 1 or more.
+    ///
     /// # Arguments
     /// * `key_len` - A key byte length
     pub fn generate_oct_key(key_len: u8) -> Result<Self, JoseError> {
+        if key_len == 0 {
+            return Err(JoseError::InvalidKeyFormat(anyhow!(
+                "The key length must be 1 or more."
+            )));
+        }
+
         let k = util::random_bytes(key_len as usize);
 
         let mut jwk = Self::new("oct");
@@ -74,9 +201,18 @@ impl Jwk {
 
     /// Generate a new RSA type JWK.
     ///
+    /// The key bits must be between 2048 and 16384.
+    ///
     /// # Arguments
     /// * `bits` - A key bits size
     pub fn generate_rsa_key(bits: u32) -> Result<Self, JoseError> {
+        if !(2048..=16384).contains(&bits) {
+            return Err(JoseError::InvalidKeyFormat(anyhow!(
+                "The key bits must be between 2048 and 16384: {}",
+                bits
+            )));
+        }
+
         let key_pair = RsaKeyPair::generate(bits)?;
         Ok(key_pair.to_jwk_key_pair())
     }
@@ -108,45 +244,37 @@ impl Jwk {
         Ok(key_pair.to_jwk_key_pair())
     }
 
-    /// Generate private key from private key.
+    /// Generate public key from private key.
+    ///
+    /// The private parameters are removed, and the "kid", "use", "alg" and x509 parameters
+    /// are kept. The operations of the "key_ops" parameter are mapped to the ones that are
+    /// done with the public key ("sign" to "verify", "decrypt" to "encrypt" and "unwrapKey"
+    /// to "wrapKey"), and the parameter is removed if no operation remains. The "oct" key
+    /// type is rejected because it doesn't have public key.
     pub fn to_public_key(&self) -> Result<Self, JoseError> {
         (|| -> anyhow::Result<Jwk> {
-            let jwk = match self.key_type() {
+            let mut jwk = match self.key_type() {
                 "oct" => bail!("The key type 'oct' doesn't have public key."),
                 "RSA" => {
                     let mut jwk = Jwk::new("RSA");
-                    match self.map.get("use") {
-                        Some(Value::String(val)) => {
-                            jwk.map
-                                .insert("use".to_string(), Value::String(val.clone()));
-                        }
-                        _ => {}
-                    }
                     match self.map.get("e") {
                         Some(Value::String(val)) => {
                             jwk.map.insert("e".to_string(), Value::String(val.clone()));
                         }
-                        Some(_) => bail!("The parameter 'x' must be a string."),
+                        Some(_) => bail!("The parameter 'e' must be a string."),
                         None => bail!("The key type 'RSA' must have parameter 'e'."),
                     }
                     match self.map.get("n") {
                         Some(Value::String(val)) => {
                             jwk.map.insert("n".to_string(), Value::String(val.clone()));
                         }
-                        Some(_) => bail!("The parameter 'x' must be a string."),
+                        Some(_) => bail!("The parameter 'n' must be a string."),
                         None => bail!("The key type 'RSA' must have parameter 'n'."),
                     }
                     jwk
                 }
                 "EC" => {
                     let mut jwk = Jwk::new("EC");
-                    match self.map.get("use") {
-                        Some(Value::String(val)) => {
-                            jwk.map
-                                .insert("use".to_string(), Value::String(val.clone()));
-                        }
-                        _ => {}
-                    }
                     match self.map.get("crv") {
                         Some(Value::String(val)) => match val.as_str() {
                             "P-256" | "P-384" | "P-521" | "secp256k1" => {
@@ -169,20 +297,13 @@ impl Jwk {
                         Some(Value::String(val)) => {
                             jwk.map.insert("y".to_string(), Value::String(val.clone()));
                         }
-                        Some(_) => bail!("The parameter 'x' must be a string."),
+                        Some(_) => bail!("The parameter 'y' must be a string."),
                         None => bail!("The key type 'EC' must have parameter 'y'."),
                     }
                     jwk
                 }
                 "OKP" => {
                     let mut jwk = Jwk::new("OKP");
-                    match self.map.get("use") {
-                        Some(Value::String(val)) => {
-                            jwk.map
-                                .insert("use".to_string(), Value::String(val.clone()));
-                        }
-                        _ => {}
-                    }
                     match self.map.get("crv") {
                         Some(Value::String(val)) => match val.as_str() {
                             "Ed25519" | "Ed448" | "X25519" | "X448" => {
@@ -192,7 +313,7 @@ impl Jwk {
                             val => bail!("Unknown curve: {}", val),
                         },
                         Some(_) => bail!("The parameter 'crv' must be a string."),
-                        None => bail!("The key type 'EC' must have parameter 'crv'."),
+                        None => bail!("The key type 'OKP' must have parameter 'crv'."),
                     }
                     match self.map.get("x") {
                         Some(Value::String(val)) => {
@@ -205,9 +326,270 @@ impl Jwk {
                 }
                 val => bail!("Unknown key type: {}", val),
             };
+
+            for key in &["kid", "use", "alg", "x5u", "x5c", "x5t", "x5t#S256"] {
+                if let Some(val) = self.map.get(*key) {
+                    jwk.map.insert(key.to_string(), val.clone());
+                }
+            }
+            if let Some(Value::Array(vals)) = self.map.get("key_ops") {
+                let mut public_vals = Vec::new();
+                for val in vals {
+                    let public_val = match val {
+                        Value::String(val) => PUBLIC_KEY_OPERATIONS
+                            .iter()
+                            .find(|(private_op, _)| private_op == val)
+                            .map(|(_, public_op)| Value::String(public_op.to_string())),
+                        _ => None,
+                    };
+                    if let Some(public_val) = public_val {
+                        if !public_vals.contains(&public_val) {
+                            public_vals.push(public_val);
+                        }
+                    }
+                }
+                if !public_vals.is_empty() {
+                    jwk.map
+                        .insert("key_ops".to_string(), Value::Array(public_vals));
+                }
+            }
             Ok(jwk)
         })()
-        .map_err(|err| JoseError::InvalidJwkFormat(err))
+        .map_err(JoseError::InvalidJwkFormat)
+    }
+
+    /// Return the JWK that the private parameters and the symmetric key value are removed.
+    ///
+    /// Unlike `to_public_key`, other parameters are kept as they are, so this is used
+    /// where a key is published as given, e.g. the jwk header claim.
+    pub(crate) fn without_private_parameters(mut self) -> Self {
+        for key in &PRIVATE_KEY_PARAMETERS {
+            self.map.remove(*key);
+        }
+        self
+    }
+
+    /// Return the JWK thumbprint (RFC 7638) that is computed with the hash algorithm.
+    ///
+    /// The thumbprint is the hash of the JSON object that has only the required
+    /// members of the key type in lexicographic order, so the private parameters
+    /// and the optional parameters like kid don't change the value.
+    ///
+    /// # Arguments
+    /// * `hash` - A hash algorithm
+    pub fn thumbprint(&self, hash: HashAlgorithm) -> Result<Vec<u8>, JoseError> {
+        (|| -> anyhow::Result<Vec<u8>> {
+            let mut map = Map::new();
+            for member in Self::required_members(self.key_type())? {
+                match self.map.get(*member) {
+                    Some(Value::String(val)) => {
+                        map.insert(member.to_string(), Value::String(val.clone()));
+                    }
+                    Some(_) => bail!("The parameter '{}' must be a string.", member),
+                    None => bail!(
+                        "The key type '{}' must have parameter '{}'.",
+                        self.key_type(),
+                        member
+                    ),
+                }
+            }
+
+            let json = util::to_canonical_json(&Value::Object(map));
+            let digest = hash::hash(hash.message_digest(), json.as_bytes())?;
+            Ok(digest.to_vec())
+        })()
+        .map_err(JoseError::InvalidJwkFormat)
+    }
+
+    /// Return the JWK thumbprint URI (RFC 9278) that is computed with the hash algorithm.
+    ///
+    /// It has the form "urn:ietf:params:oauth:jwk-thumbprint:<hash>:<thumbprint>".
+    /// Only the hash algorithms in the IANA "Named Information Hash Algorithm" registry
+    /// (SHA-256, SHA-384 and SHA-512) are supported.
+    ///
+    /// # Arguments
+    /// * `hash` - A hash algorithm
+    pub fn thumbprint_uri(&self, hash: HashAlgorithm) -> Result<String, JoseError> {
+        let hash_name = match hash {
+            HashAlgorithm::Sha256 => "sha-256",
+            HashAlgorithm::Sha384 => "sha-384",
+            HashAlgorithm::Sha512 => "sha-512",
+            _ => {
+                return Err(JoseError::InvalidJwkFormat(anyhow!(
+                    "The hash algorithm is not supported for a thumbprint URI: {}",
+                    hash
+                )))
+            }
+        };
+
+        let thumbprint = self.thumbprint(hash)?;
+        Ok(format!(
+            "urn:ietf:params:oauth:jwk-thumbprint:{}:{}",
+            hash_name,
+            base64::encode_config(&thumbprint, base64::URL_SAFE_NO_PAD)
+        ))
+    }
+
+    /// Validate the JWK.
+    ///
+    /// This checks the format of the parameters, the required parameters of the key type,
+    /// and that the "use" parameter doesn't conflict with the "key_ops" parameter (RFC 7517 section 4.3).
+    pub fn validate(&self) -> Result<(), JoseError> {
+        Self::check_map(&self.map)?;
+
+        (|| -> anyhow::Result<()> {
+            for member in Self::required_members(self.key_type())? {
+                if !self.map.contains_key(*member) {
+                    bail!(
+                        "The key type '{}' must have parameter '{}'.",
+                        self.key_type(),
+                        member
+                    );
+                }
+            }
+
+            if let Some(key_operations) = self.key_operations() {
+                for (i, key_operation) in key_operations.iter().enumerate() {
+                    if key_operations[..i].contains(key_operation) {
+                        bail!(
+                            "The JWK key_ops parameter must not contain duplicate values: {}",
+                            key_operation
+                        );
+                    }
+                }
+
+                let allowed_key_operations: &[&str] = match self.key_use() {
+                    Some("sig") => &["sign", "verify"],
+                    Some("enc") => &[
+                        "encrypt",
+                        "decrypt",
+                        "wrapKey",
+                        "unwrapKey",
+                        "deriveKey",
+                        "deriveBits",
+                    ],
+                    _ => return Ok(()),
+                };
+                for key_operation in key_operations {
+                    if !allowed_key_operations.contains(&key_operation) {
+                        bail!(
+                            "The JWK key_ops parameter conflicts with the use parameter '{}': {}",
+                            self.key_use().unwrap_or_default(),
+                            key_operation
+                        );
+                    }
+                }
+            }
+
+            Ok(())
+        })()
+        .map_err(JoseError::InvalidJwkFormat)
+    }
+
+    /// Return a private key that is a DER encoded PKCS#8 PrivateKeyInfo.
+    ///
+    /// EC keys are also exported as PKCS#8, not as SEC1 ECPrivateKey.
+    /// The "oct" key type is rejected because it can't be encoded as PKCS#8.
+    pub fn to_der_private_key(&self) -> Result<Vec<u8>, JoseError> {
+        (|| -> anyhow::Result<Vec<u8>> {
+            let pem = self.to_key_pair()?.to_pem_private_key();
+            let (_, der) = util::parse_pem(&pem)?;
+            Ok(der)
+        })()
+        .map_err(|err| match err.downcast::<JoseError>() {
+            Ok(err) => err,
+            Err(err) => JoseError::InvalidKeyFormat(err),
+        })
+    }
+
+    /// Return a private key of common PEM format.
+    ///
+    /// Common PEM format is a DER and base64 encoded PKCS#8 PrivateKeyInfo
+    /// that surrounded by "-----BEGIN/END PRIVATE KEY----".
+    /// The "oct" key type is rejected because it can't be encoded as PKCS#8.
+    pub fn to_pem_private_key(&self) -> Result<Vec<u8>, JoseError> {
+        Ok(self.to_key_pair()?.to_pem_private_key())
+    }
+
+    /// Return a public key that is a DER encoded SubjectPublicKeyInfo.
+    ///
+    /// The public key is made from the public parameters, so both private and public JWKs
+    /// can be exported. The "oct" key type is rejected because it doesn't have public key.
+    pub fn to_der_public_key(&self) -> Result<Vec<u8>, JoseError> {
+        (|| -> anyhow::Result<Vec<u8>> {
+            let jwk = self.to_public_key()?;
+            let decode = |key: &str| -> anyhow::Result<Vec<u8>> {
+                match jwk.map.get(key) {
+                    Some(Value::String(val)) => {
+                        Ok(base64::decode_config(val, base64::URL_SAFE_NO_PAD)?)
+                    }
+                    _ => bail!("The parameter '{}' must be a string.", key),
+                }
+            };
+
+            let spki = match jwk.key_type() {
+                "RSA" => {
+                    let mut builder = DerBuilder::new();
+                    builder.begin(DerType::Sequence);
+                    {
+                        builder.append_integer_from_be_slice(&decode("n")?, true);
+                        builder.append_integer_from_be_slice(&decode("e")?, true);
+                    }
+                    builder.end();
+                    RsaKeyPair::to_pkcs8(&builder.build(), true)
+                }
+                "EC" => {
+                    let curve = match jwk.curve() {
+                        Some("P-256") => EcCurve::P256,
+                        Some("P-384") => EcCurve::P384,
+                        Some("P-521") => EcCurve::P521,
+                        Some("secp256k1") => EcCurve::Secp256k1,
+                        _ => unreachable!(),
+                    };
+                    let mut public_key = vec![0x04];
+                    public_key.extend_from_slice(&decode("x")?);
+                    public_key.extend_from_slice(&decode("y")?);
+                    EcKeyPair::to_pkcs8(&public_key, true, curve)
+                }
+                "OKP" => {
+                    let x = decode("x")?;
+                    match jwk.curve() {
+                        Some("Ed25519") => EdKeyPair::to_pkcs8(&x, true, EdCurve::Ed25519),
+                        Some("Ed448") => EdKeyPair::to_pkcs8(&x, true, EdCurve::Ed448),
+                        Some("X25519") => EcxKeyPair::to_pkcs8(&x, true, EcxCurve::X25519),
+                        Some("X448") => EcxKeyPair::to_pkcs8(&x, true, EcxCurve::X448),
+                        _ => unreachable!(),
+                    }
+                }
+                _ => unreachable!(),
+            };
+
+            // Check that the public key is valid.
+            PKey::public_key_from_der(&spki)?;
+            Ok(spki)
+        })()
+        .map_err(|err| match err.downcast::<JoseError>() {
+            Ok(JoseError::InvalidJwkFormat(err)) => JoseError::InvalidKeyFormat(err),
+            Ok(err) => err,
+            Err(err) => JoseError::InvalidKeyFormat(err),
+        })
+    }
+
+    /// Return a public key of common PEM format.
+    ///
+    /// Common PEM format is a DER and base64 encoded SubjectPublicKeyInfo
+    /// that surrounded by "-----BEGIN/END PUBLIC KEY----".
+    /// The "oct" key type is rejected because it doesn't have public key.
+    pub fn to_pem_public_key(&self) -> Result<Vec<u8>, JoseError> {
+        (|| -> anyhow::Result<Vec<u8>> {
+            let spki = self.to_der_public_key()?;
+            let pem = PKey::public_key_from_der(&spki)?.public_key_to_pem()?;
+            Ok(pem)
+        })()
+        .map_err(|err| match err.downcast::<JoseError>() {
+            Ok(err) => err,
+            Err(err) => JoseError::InvalidKeyFormat(err),
+        })
     }
 
     /// Set a value for a key type parameter (kty).
@@ -391,10 +773,7 @@ impl Jwk {
     pub fn set_x509_certificate_chain(&mut self, values: &Vec<impl AsRef<[u8]>>) {
         let mut vec = Vec::with_capacity(values.len());
         for val in values {
-            vec.push(Value::String(base64::encode_config(
-                &val,
-                base64::URL_SAFE_NO_PAD,
-            )));
+            vec.push(Value::String(base64::encode_config(&val, base64::STANDARD)));
         }
         self.map.insert("x5c".to_string(), Value::Array(vec));
     }
@@ -407,7 +786,7 @@ impl Jwk {
                 for val in vals {
                     match val {
                         Value::String(val2) => {
-                            match base64::decode_config(val2, base64::URL_SAFE_NO_PAD) {
+                            match base64::decode_config(val2, base64::STANDARD) {
                                 Ok(val3) => vec.push(val3),
                                 Err(_) => return None,
                             }
@@ -532,16 +911,15 @@ impl Jwk {
                     }
                     _ => bail!("The JWK {} parameter must be a array of string.", key),
                 },
-                "x5t" | "x5t#S256" | "k" | "d" | "p" | "q" | "dp" | "dq" | "qi" | "x" | "y" => {
-                    match &value {
-                        Value::String(val) => {
-                            if !util::is_base64_url_safe_nopad(val) {
-                                bail!("The JWK {} parameter must be a base64 string.", key);
-                            }
+                "x5t" | "x5t#S256" | "k" | "n" | "e" | "d" | "p" | "q" | "dp" | "dq" | "qi"
+                | "x" | "y" => match &value {
+                    Value::String(val) => {
+                        if !util::is_base64_url_safe_nopad(val) {
+                            bail!("The JWK {} parameter must be a base64 string.", key);
                         }
-                        _ => bail!("The JWK {} parameter must be a string.", key),
                     }
-                }
+                    _ => bail!("The JWK {} parameter must be a string.", key),
+                },
                 "x5c" => match &value {
                     Value::Array(vals) => {
                         for val in vals {
@@ -567,6 +945,63 @@ impl Jwk {
         })()
         .map_err(|err| JoseError::InvalidJwkFormat(err))
     }
+
+    fn required_members(key_type: &str) -> anyhow::Result<&'static [&'static str]> {
+        let members: &[&str] = match key_type {
+            "EC" => &["crv", "kty", "x", "y"],
+            "RSA" => &["e", "kty", "n"],
+            "oct" => &["k", "kty"],
+            "OKP" => &["crv", "kty", "x"],
+            val => bail!("Unknown key type: {}", val),
+        };
+        Ok(members)
+    }
+
+    fn to_key_pair(&self) -> Result<Box<dyn KeyPair>, JoseError> {
+        let key_pair: Box<dyn KeyPair> = match self.key_type() {
+            "RSA" => Box::new(RsaKeyPair::from_jwk(self)?),
+            "EC" => Box::new(EcKeyPair::from_jwk(self)?),
+            "OKP" => match self.curve() {
+                Some("X25519") | Some("X448") => Box::new(EcxKeyPair::from_jwk(self)?),
+                _ => Box::new(EdKeyPair::from_jwk(self)?),
+            },
+            val => {
+                return Err(JoseError::InvalidKeyFormat(anyhow!(
+                    "The key type '{}' can't be encoded as PKCS#8.",
+                    val
+                )))
+            }
+        };
+        Ok(key_pair)
+    }
+
+    fn spki_public_key(input: &[u8]) -> anyhow::Result<Vec<u8>> {
+        let mut reader = DerReader::from_bytes(&input);
+
+        match reader.next()? {
+            Some(DerType::Sequence) => {}
+            _ => bail!("Invalid SubjectPublicKeyInfo."),
+        }
+        match reader.next()? {
+            Some(DerType::Sequence) => {}
+            _ => bail!("Invalid SubjectPublicKeyInfo."),
+        }
+        match reader.next()? {
+            Some(DerType::ObjectIdentifier) => {}
+            _ => bail!("Invalid SubjectPublicKeyInfo."),
+        }
+        match reader.next()? {
+            Some(DerType::EndOfContents) => {}
+            _ => bail!("Invalid SubjectPublicKeyInfo."),
+        }
+        match reader.next()? {
+            Some(DerType::BitString) => match reader.to_bit_vec()? {
+                (val, 0) => Ok(val),
+                _ => bail!("Invalid SubjectPublicKeyInfo."),
+            },
+            _ => bail!("Invalid SubjectPublicKeyInfo."),
+        }
+    }
 }
 
 impl AsRef<Map<String, Value>> for Jwk {
@@ -587,3 +1022,333 @@ impl Display for Jwk {
         fmt.write_str(&val)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use serde_json::json;
+    use std::fs;
+    use std::path::PathBuf;
+
+    use super::Jwk;
+    use crate::jwe::{self, JweHeader, ECDH_ES};
+    use crate::jwk::alg::ec::EcCurve;
+    use crate::jwk::alg::ecx::EcxCurve;
+    use crate::jwk::alg::ed::EdCurve;
+    use crate::jws::{EdDSA, ES256, ES256K, ES384, ES512, HS256, RS256};
+    use crate::util::{self, SHA_1, SHA_256, SHA_384, SHA_512};
+    use crate::{JoseError, Map, Value};
+
+    #[test]
+    fn test_jwk_generate_keys() -> Result<()> {
+        let message = b"abcde12345";
+
+        let mut jwk = Jwk::generate_oct_key(32)?;
+        jwk.set_key_id("oct-key");
+        assert_eq!(jwk.key_type(), "oct");
+        assert_eq!(jwk.key_id(), Some("oct-key"));
+        assert_eq!(jwk.key_value().map(|val| val.len()), Some(32));
+        let signature = HS256.signer_from_jwk(&jwk)?.sign(message)?;
+        HS256.verifier_from_jwk(&jwk)?.verify(message, &signature)?;
+
+        let jwk = Jwk::generate_rsa_key(2048)?;
+        assert_eq!(jwk.key_type(), "RSA");
+        let signature = RS256.signer_from_jwk(&jwk)?.sign(message)?;
+        RS256
+            .verifier_from_jwk(&jwk.to_public_key()?)?
+            .verify(message, &signature)?;
+
+        for (curve, alg) in &[
+            (EcCurve::P256, ES256),
+            (EcCurve::P384, ES384),
+            (EcCurve::P521, ES512),
+            (EcCurve::Secp256k1, ES256K),
+        ] {
+            let jwk = Jwk::generate_ec_key(*curve)?;
+            assert_eq!(jwk.key_type(), "EC");
+            assert_eq!(jwk.curve(), Some(curve.name()));
+            let signature = alg.signer_from_jwk(&jwk)?.sign(message)?;
+            alg.verifier_from_jwk(&jwk.to_public_key()?)?
+                .verify(message, &signature)?;
+        }
+
+        for curve in &[EdCurve::Ed25519, EdCurve::Ed448] {
+            let jwk = Jwk::generate_ed_key(*curve)?;
+            assert_eq!(jwk.key_type(), "OKP");
+            assert_eq!(jwk.curve(), Some(curve.name()));
+            let signature = EdDSA.signer_from_jwk(&jwk)?.sign(message)?;
+            EdDSA
+                .verifier_from_jwk(&jwk.to_public_key()?)?
+                .verify(message, &signature)?;
+        }
+
+        for curve in &[EcxCurve::X25519, EcxCurve::X448] {
+            let jwk = Jwk::generate_ecx_key(*curve)?;
+            assert_eq!(jwk.key_type(), "OKP");
+            assert_eq!(jwk.curve(), Some(curve.name()));
+            let mut header = JweHeader::new();
+            header.set_content_encryption("A128GCM");
+            let encrypter = ECDH_ES.encrypter_from_jwk(&jwk.to_public_key()?)?;
+            let jwt = jwe::serialize_compact(message, &header, &encrypter)?;
+            let decrypter = ECDH_ES.decrypter_from_jwk(&jwk)?;
+            let (payload, _) = jwe::deserialize_compact(&jwt, &decrypter)?;
+            assert_eq!(&payload[..], &message[..]);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_jwk_to_public_key() -> Result<()> {
+        let private_jwks = vec![
+            Jwk::generate_rsa_key(2048)?,
+            Jwk::generate_ec_key(EcCurve::P256)?,
+            Jwk::generate_ed_key(EdCurve::Ed25519)?,
+            Jwk::generate_ecx_key(EcxCurve::X25519)?,
+        ];
+
+        for mut private_jwk in private_jwks {
+            private_jwk.set_key_id("key-1");
+            private_jwk.set_key_use("sig");
+            private_jwk.set_algorithm("test");
+            private_jwk.set_key_operations(vec!["sign", "verify", "decrypt", "wrapKey"]);
+
+            let public_jwk = private_jwk.to_public_key()?;
+            assert_eq!(public_jwk.key_type(), private_jwk.key_type());
+            assert_eq!(public_jwk.key_id(), Some("key-1"));
+            assert_eq!(public_jwk.key_use(), Some("sig"));
+            assert_eq!(public_jwk.algorithm(), Some("test"));
+            assert_eq!(
+                public_jwk.key_operations(),
+                Some(vec!["verify", "encrypt", "wrapKey"])
+            );
+            assert!(private_jwk.parameter("d").is_some());
+            for key in &["d", "p", "q", "dp", "dq", "qi"] {
+                assert_eq!(public_jwk.parameter(key), None);
+            }
+        }
+
+        let mut private_jwk = Jwk::generate_ed_key(EdCurve::Ed25519)?;
+        private_jwk.set_key_operations(vec!["sign"]);
+        let public_jwk = private_jwk.to_public_key()?;
+        assert_eq!(public_jwk.key_operations(), Some(vec!["verify"]));
+
+        private_jwk.set_key_operations(vec!["unknown"]);
+        let public_jwk = private_jwk.to_public_key()?;
+        assert_eq!(public_jwk.parameter("key_ops"), None);
+
+        assert!(Jwk::generate_oct_key(16)?.to_public_key().is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_jwk_to_public_key_with_derive_key_operation() -> Result<()> {
+        let message = b"abcde12345";
+
+        let mut private_jwk = Jwk::generate_ec_key(EcCurve::P256)?;
+        private_jwk.set_key_operations(vec!["deriveKey"]);
+        let public_jwk = private_jwk.to_public_key()?;
+        assert_eq!(public_jwk.key_operations(), Some(vec!["deriveKey"]));
+
+        let mut header = JweHeader::new();
+        header.set_content_encryption("A128GCM");
+        let encrypter = ECDH_ES.encrypter_from_jwk(&public_jwk)?;
+        let jwt = jwe::serialize_compact(message, &header, &encrypter)?;
+        let decrypter = ECDH_ES.decrypter_from_jwk(&private_jwk)?;
+        let (payload, _) = jwe::deserialize_compact(&jwt, &decrypter)?;
+        assert_eq!(&payload[..], &message[..]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_jwk_from_der() -> Result<()> {
+        for (name, key_type, curve) in &[
+            ("RSA_2048bit", "RSA", None),
+            ("EC_P-256", "EC", Some("P-256")),
+            ("EC_P-384", "EC", Some("P-384")),
+            ("EC_P-521", "EC", Some("P-521")),
+            ("EC_secp256k1", "EC", Some("secp256k1")),
+            ("ED25519", "OKP", Some("Ed25519")),
+            ("ED448", "OKP", Some("Ed448")),
+            ("X25519", "OKP", Some("X25519")),
+            ("X448", "OKP", Some("X448")),
+        ] {
+            let private_der = load_file(&format!("der/{}_pkcs8_private.der", name))?;
+            let private_jwk = Jwk::from_der(&private_der)?;
+            assert_eq!(private_jwk.key_type(), *key_type);
+            assert_eq!(private_jwk.curve(), *curve);
+            assert!(private_jwk.parameter("d").is_some());
+
+            let public_der = load_file(&format!("der/{}_spki_public.der", name))?;
+            let public_jwk = Jwk::from_der(&public_der)?;
+            assert_eq!(public_jwk.key_type(), *key_type);
+            assert_eq!(public_jwk.curve(), *curve);
+            assert_eq!(public_jwk.parameter("d"), None);
+            for key in &["n", "e", "x", "y"] {
+                assert_eq!(public_jwk.parameter(key), private_jwk.parameter(key));
+            }
+        }
+
+        let raw_der = load_file("der/RSA_2048bit_raw_private.der")?;
+        assert!(Jwk::from_der(&raw_der).is_err());
+        assert!(Jwk::from_der(b"\x30\x00").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_jwk_to_pem_and_der() -> Result<()> {
+        let jwks = vec![
+            Jwk::generate_rsa_key(2048)?,
+            Jwk::generate_ec_key(EcCurve::P256)?,
+            Jwk::generate_ec_key(EcCurve::P384)?,
+            Jwk::generate_ec_key(EcCurve::P521)?,
+            Jwk::generate_ec_key(EcCurve::Secp256k1)?,
+            Jwk::generate_ed_key(EdCurve::Ed25519)?,
+            Jwk::generate_ed_key(EdCurve::Ed448)?,
+            Jwk::generate_ecx_key(EcxCurve::X25519)?,
+            Jwk::generate_ecx_key(EcxCurve::X448)?,
+        ];
+
+        for jwk in jwks {
+            let private_pem = jwk.to_pem_private_key()?;
+            let (alg, private_der) = util::parse_pem(&private_pem)?;
+            assert_eq!(alg, "PRIVATE KEY");
+            assert_eq!(private_der, jwk.to_der_private_key()?);
+            assert_eq!(Jwk::from_der(&private_der)?, jwk);
+
+            let public_jwk = jwk.to_public_key()?;
+            let public_pem = public_jwk.to_pem_public_key()?;
+            let (alg, public_der) = util::parse_pem(&public_pem)?;
+            assert_eq!(alg, "PUBLIC KEY");
+            assert_eq!(public_der, public_jwk.to_der_public_key()?);
+            assert_eq!(public_der, jwk.to_der_public_key()?);
+            assert_eq!(
+                Jwk::from_der(&public_der)?.as_ref().get("x"),
+                public_jwk.parameter("x")
+            );
+            assert_eq!(
+                Jwk::from_der(&public_der)?.as_ref().get("n"),
+                public_jwk.parameter("n")
+            );
+            assert!(public_jwk.to_der_private_key().is_err());
+        }
+
+        let oct_jwk = Jwk::generate_oct_key(16)?;
+        assert!(oct_jwk.to_pem_private_key().is_err());
+        assert!(oct_jwk.to_der_private_key().is_err());
+        assert!(oct_jwk.to_pem_public_key().is_err());
+        assert!(oct_jwk.to_der_public_key().is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_jwk_validate() -> Result<()> {
+        let mut jwk = Jwk::generate_rsa_key(2048)?;
+        jwk.validate()?;
+
+        jwk.set_key_use("sig");
+        jwk.set_key_operations(vec!["sign", "verify"]);
+        jwk.validate()?;
+
+        jwk.set_key_operations(vec!["sign", "encrypt"]);
+        match jwk.validate() {
+            Err(JoseError::InvalidJwkFormat(err)) => assert_eq!(
+                err.to_string(),
+                "The JWK key_ops parameter conflicts with the use parameter 'sig': encrypt"
+            ),
+            result => panic!("unexpected result: {:?}", result),
+        }
+
+        jwk.set_key_operations(vec!["sign", "sign"]);
+        assert!(jwk.validate().is_err());
+
+        let mut map: Map<String, Value> = jwk.into();
+        map.remove("n");
+        let jwk = Jwk::from_map(map)?;
+        match jwk.validate() {
+            Err(JoseError::InvalidJwkFormat(err)) => assert_eq!(
+                err.to_string(),
+                "The key type 'RSA' must have parameter 'n'."
+            ),
+            result => panic!("unexpected result: {:?}", result),
+        }
+
+        assert!(Jwk::from_bytes(r#"{"kty":"RSA","n":"a","e":"AQAB"}"#).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_jwk_generate_keys_with_invalid_size() -> Result<()> {
+        assert!(Jwk::generate_oct_key(0).is_err());
+        assert!(Jwk::generate_rsa_key(1024).is_err());
+        assert!(Jwk::generate_rsa_key(32768).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_jwk_thumbprint() -> Result<()> {
+        // RFC 7638 section 3.1
+        let jwk = Jwk::from_bytes(
+            json!({
+                "kty": "RSA",
+                "n": "0vx7agoebGcQSuuPiLJXZptN9nndrQmbXEps2aiAFbWhM78LhWx4cbbfAAtVT86zwu1RK7aPFFxuhDR1L6tSoc_BJECPebWKRXjBZCiFV4n3oknjhMstn64tZ_2W-5JsGY4Hc5n9yBXArwl93lqt7_RN5w6Cf0h4QyQ5v-65YGjQR0_FDW2QvzqY368QQMicAtaSqzs8KJZgnYb9c7d0zgdAZHzu6qMQvRL5hajrn1n91CbOpbISD08qNLyrdkt-bFTWhAI4vMQFh6WeZu0fM4lFd2NcRwr3XPksINHaQ-G_xBniIqbw0Ls1jF44-csFCur-kEgU8awapJzKnqDKgw",
+                "e": "AQAB",
+                "alg": "RS256",
+                "kid": "2011-04-29"
+            })
+            .to_string(),
+        )?;
+
+        let thumbprint = jwk.thumbprint(SHA_256)?;
+        assert_eq!(
+            base64::encode_config(&thumbprint, base64::URL_SAFE_NO_PAD),
+            "NzbLsXh8uDCcd-6MNwXF4W_7noWXFZAfHkxZsRGC9Xs"
+        );
+        assert_eq!(jwk.thumbprint(SHA_384)?.len(), 48);
+        assert_eq!(jwk.thumbprint(SHA_512)?.len(), 64);
+
+        let mut public_jwk = jwk.to_public_key()?;
+        public_jwk.set_key_id("other");
+        assert_eq!(public_jwk.thumbprint(SHA_256)?, thumbprint);
+
+        let ec_jwk = Jwk::generate_ec_key(crate::jwk::alg::ec::EcCurve::P256)?;
+        assert_eq!(
+            ec_jwk.thumbprint(SHA_256)?,
+            ec_jwk.to_public_key()?.thumbprint(SHA_256)?
+        );
+
+        assert_eq!(
+            jwk.thumbprint_uri(SHA_256)?,
+            "urn:ietf:params:oauth:jwk-thumbprint:sha-256:NzbLsXh8uDCcd-6MNwXF4W_7noWXFZAfHkxZsRGC9Xs"
+        );
+        assert!(jwk
+            .thumbprint_uri(SHA_384)?
+            .starts_with("urn:ietf:params:oauth:jwk-thumbprint:sha-384:"));
+        assert!(jwk
+            .thumbprint_uri(SHA_512)?
+            .starts_with("urn:ietf:params:oauth:jwk-thumbprint:sha-512:"));
+        assert!(jwk.thumbprint_uri(SHA_1).is_err());
+
+        let mut invalid_jwk = Jwk::new("oct");
+        assert!(invalid_jwk.thumbprint(SHA_256).is_err());
+        invalid_jwk.set_key_value(b"secret");
+        assert_eq!(invalid_jwk.thumbprint(SHA_256)?.len(), 32);
+
+        Ok(())
+    }
+
+    fn load_file(path: &str) -> Result<Vec<u8>> {
+        let mut pb = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        pb.push("data");
+        pb.push(path);
+
+        let data = fs::read(&pb)?;
+        Ok(data)
+    }
+}
@@ -2,6 +2,7 @@ use std::collections::BTreeMap;
 use std::fmt::Display;
 use std::io::Read;
 use std::ops::Bound::Included;
+use std::ops::Index;
 use std::string::ToString;
 use std::sync::Arc;
 
@@ -20,9 +21,12 @@ pub struct JwkSet {
 
 impl JwkSet {
     pub fn new() -> Self {
+        let mut params = Map::new();
+        params.insert("keys".to_string(), Value::Array(Vec::new()));
+
         Self {
             keys: Vec::new(),
-            params: Map::new(),
+            params,
             kid_map: BTreeMap::new(),
         }
     }
@@ -65,6 +69,73 @@ impl JwkSet {
         })
     }
 
+    /// Return the JWK set from map, skipping the keys that cannot be used.
+    ///
+    /// A key is skipped when it is not a valid JWK or its key type is not supported
+    /// (oct, RSA, EC or OKP). The skipped keys are not included in the set, and the
+    /// error of each skipped key is returned in the order of the keys parameter.
+    ///
+    /// # Arguments
+    /// * `map` - A JWK set map.
+    pub fn from_map_lenient(
+        mut map: Map<String, Value>,
+    ) -> Result<(Self, Vec<JoseError>), JoseError> {
+        (|| -> anyhow::Result<(Self, Vec<JoseError>)> {
+            let vals = match map.remove("keys") {
+                Some(Value::Array(vals)) => vals,
+                Some(_) => bail!("The JWT keys parameter must be a array."),
+                None => bail!("The JWK set must have a keys parameter."),
+            };
+
+            let mut keys = Vec::new();
+            let mut errors = Vec::new();
+            for val in vals {
+                let result = match val {
+                    Value::Object(val) => Jwk::from_map(val).and_then(|jwk| match jwk.key_type() {
+                        "oct" | "RSA" | "EC" | "OKP" => Ok(jwk),
+                        val => Err(JoseError::InvalidJwkFormat(anyhow::anyhow!(
+                            "The JWK kty parameter is not supported: {}",
+                            val
+                        ))),
+                    }),
+                    _ => Err(JoseError::InvalidJwkFormat(anyhow::anyhow!(
+                        "An element of the JWK set keys parameter must be a object."
+                    ))),
+                };
+                match result {
+                    Ok(jwk) => keys.push(Value::Object(jwk.into())),
+                    Err(err) => errors.push(err),
+                }
+            }
+            map.insert("keys".to_string(), Value::Array(keys));
+
+            Ok((Self::from_map(map)?, errors))
+        })()
+        .map_err(|err| match err.downcast::<JoseError>() {
+            Ok(err) => err,
+            Err(err) => JoseError::InvalidJwkFormat(err),
+        })
+    }
+
+    /// Return the JWK set from bytes, skipping the keys that cannot be used.
+    ///
+    /// See [`JwkSet::from_map_lenient`] for the details.
+    ///
+    /// # Arguments
+    /// * `input` - A JWK set JSON.
+    pub fn from_bytes_lenient(
+        input: impl AsRef<[u8]>,
+    ) -> Result<(Self, Vec<JoseError>), JoseError> {
+        (|| -> anyhow::Result<(Self, Vec<JoseError>)> {
+            let keys: Map<String, Value> = serde_json::from_slice(input.as_ref())?;
+            Ok(Self::from_map_lenient(keys)?)
+        })()
+        .map_err(|err| match err.downcast::<JoseError>() {
+            Ok(err) => err,
+            Err(err) => JoseError::InvalidJwkFormat(err),
+        })
+    }
+
     pub fn from_reader(input: &mut dyn Read) -> Result<Self, JoseError> {
         (|| -> anyhow::Result<Self> {
             let keys: Map<String, Value> = serde_json::from_reader(input)?;
@@ -99,10 +170,67 @@ impl JwkSet {
         vec
     }
 
+    /// Return the first JWK that has the key ID.
+    ///
+    /// # Arguments
+    /// * `key_id` - A key ID
+    pub fn get_by_kid(&self, key_id: &str) -> Option<&Jwk> {
+        self.get(key_id).into_iter().next()
+    }
+
+    /// Return the JWKs that match all of the specified conditions in the order of the set.
+    ///
+    /// A JWK that doesn't have a "alg" parameter matches any algorithm, and a JWK that
+    /// doesn't have a "key_ops" parameter matches any key operation.
+    ///
+    /// # Arguments
+    /// * `key_id` - A key ID (kid)
+    /// * `key_type` - A key type (kty)
+    /// * `algorithm` - A algorithm (alg)
+    /// * `key_operation` - A key operation (key_ops)
+    pub fn select(
+        &self,
+        key_id: Option<&str>,
+        key_type: Option<&str>,
+        algorithm: Option<&str>,
+        key_operation: Option<&str>,
+    ) -> Vec<&Jwk> {
+        self.keys
+            .iter()
+            .map(|jwk| jwk.as_ref())
+            .filter(|jwk| match key_id {
+                Some(val) => jwk.key_id() == Some(val),
+                None => true,
+            })
+            .filter(|jwk| match key_type {
+                Some(val) => jwk.key_type() == val,
+                None => true,
+            })
+            .filter(|jwk| match (algorithm, jwk.algorithm()) {
+                (Some(val), Some(jwk_val)) => jwk_val == val,
+                _ => true,
+            })
+            .filter(|jwk| match key_operation {
+                Some(val) => jwk.is_for_key_operation(val),
+                None => true,
+            })
+            .collect()
+    }
+
     pub fn keys(&self) -> Vec<&Jwk> {
         self.keys.iter().map(|e| e.as_ref()).collect()
     }
 
+    /// Return the number of the JWKs.
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    /// Return whether the JWK set has no JWK.
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
     pub fn push_key(&mut self, jwk: Jwk) {
         match self.params.get_mut("keys") {
             Some(Value::Array(keys)) => {
@@ -119,17 +247,58 @@ impl JwkSet {
         self.keys.push(jwk);
     }
 
+    /// Add the JWK to the end of the JWK set.
+    ///
+    /// This is the same as push_key.
+    ///
+    /// # Arguments
+    /// * `jwk` - A JWK
+    pub fn add(&mut self, jwk: Jwk) {
+        self.push_key(jwk);
+    }
+
     pub fn remove_key(&mut self, jwk: &Jwk) {
         let index = self.keys.iter().position(|e| e.as_ref() == jwk);
         if let Some(index) = index {
-            match self.params.get_mut("keys") {
-                Some(Value::Array(keys)) => {
-                    keys.remove(index);
-                }
-                _ => unreachable!(),
+            self.remove_at(index);
+        }
+    }
+
+    /// Remove the first JWK that has the key ID and return it.
+    ///
+    /// # Arguments
+    /// * `key_id` - A key ID
+    pub fn remove_by_kid(&mut self, key_id: &str) -> Option<Jwk> {
+        let index = self.keys.iter().position(|e| e.key_id() == Some(key_id));
+        index.map(|index| self.remove_at(index))
+    }
+
+    fn remove_at(&mut self, index: usize) -> Jwk {
+        match self.params.get_mut("keys") {
+            Some(Value::Array(keys)) => {
+                keys.remove(index);
             }
-            self.keys.remove(index);
+            _ => unreachable!(),
         }
+        let jwk = self.keys.remove(index);
+
+        // The indexes of the following keys are shifted.
+        self.kid_map.clear();
+        for (i, jwk) in self.keys.iter().enumerate() {
+            if let Some(kid) = jwk.key_id() {
+                self.kid_map.insert((kid.to_string(), i), Arc::clone(jwk));
+            }
+        }
+
+        Arc::try_unwrap(jwk).unwrap_or_else(|jwk| jwk.as_ref().clone())
+    }
+}
+
+impl Index<usize> for JwkSet {
+    type Output = Jwk;
+
+    fn index(&self, index: usize) -> &Jwk {
+        &self.keys[index]
     }
 }
 
@@ -170,6 +339,7 @@ mod tests {
     use super::*;
     use anyhow::Result;
     use std::fs::File;
+    use std::io::Read;
     use std::path::PathBuf;
 
     #[test]
@@ -186,6 +356,139 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_jwk_set_select() -> Result<()> {
+        let jwks = JwkSet::from_bytes(
+            serde_json::json!({
+                "keys": [
+                    {"kty": "oct", "kid": "a", "alg": "HS256", "k": "MDEyMzQ1Njc4OUFCQ0RFRg"},
+                    {"kty": "oct", "kid": "b", "alg": "HS384", "k": "MDEyMzQ1Njc4OUFCQ0RFRg"},
+                    {"kty": "oct", "kid": "c", "key_ops": ["verify"], "k": "MDEyMzQ1Njc4OUFCQ0RFRg"},
+                    {"kty": "EC", "kid": "a", "crv": "P-256", "x": "AA", "y": "AA"},
+                ]
+            })
+            .to_string(),
+        )?;
+
+        let key_ids = |jwks: Vec<&Jwk>| -> Vec<(String, String)> {
+            jwks.iter()
+                .map(|jwk| {
+                    (
+                        jwk.key_id().unwrap().to_string(),
+                        jwk.key_type().to_string(),
+                    )
+                })
+                .collect()
+        };
+
+        assert_eq!(jwks.select(None, None, None, None).len(), 4);
+        assert_eq!(
+            key_ids(jwks.select(Some("a"), None, None, None)),
+            vec![
+                ("a".to_string(), "oct".to_string()),
+                ("a".to_string(), "EC".to_string())
+            ]
+        );
+        assert_eq!(
+            key_ids(jwks.select(Some("a"), Some("EC"), None, None)),
+            vec![("a".to_string(), "EC".to_string())]
+        );
+        assert_eq!(
+            key_ids(jwks.select(None, Some("oct"), Some("HS384"), None)),
+            vec![
+                ("b".to_string(), "oct".to_string()),
+                ("c".to_string(), "oct".to_string())
+            ]
+        );
+        assert_eq!(
+            key_ids(jwks.select(None, Some("oct"), None, Some("sign"))),
+            vec![
+                ("a".to_string(), "oct".to_string()),
+                ("b".to_string(), "oct".to_string())
+            ]
+        );
+        assert!(jwks.select(Some("d"), None, None, None).is_empty());
+
+        assert_eq!(
+            jwks.get_by_kid("b").and_then(|jwk| jwk.algorithm()),
+            Some("HS384")
+        );
+        assert_eq!(jwks.get_by_kid("a").map(|jwk| jwk.key_type()), Some("oct"));
+        assert!(jwks.get_by_kid("d").is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_jwk_set_add_and_remove() -> Result<()> {
+        let mut jwks = JwkSet::new();
+        assert!(jwks.is_empty());
+        assert_eq!(jwks.to_string(), r#"{"keys":[]}"#);
+
+        for kid in ["a", "b", "c"] {
+            let mut jwk = Jwk::generate_oct_key(16)?;
+            jwk.set_key_id(kid);
+            jwks.push_key(jwk);
+        }
+        assert_eq!(jwks.len(), 3);
+        assert_eq!(jwks[1].key_id(), Some("b"));
+
+        let removed = jwks.remove_by_kid("a").unwrap();
+        assert_eq!(removed.key_id(), Some("a"));
+        assert!(jwks.remove_by_kid("a").is_none());
+        assert!(jwks.get_by_kid("a").is_none());
+        assert_eq!(jwks.get_by_kid("c").and_then(|jwk| jwk.key_id()), Some("c"));
+
+        let jwk = jwks[0].clone();
+        jwks.remove_key(&jwk);
+        assert_eq!(jwks.len(), 1);
+        assert!(jwks.get_by_kid("b").is_none());
+
+        let mut jwk = Jwk::generate_oct_key(16)?;
+        jwk.set_key_id("d");
+        jwks.add(jwk);
+        assert_eq!(jwks.len(), 2);
+        assert_eq!(jwks[1].key_id(), Some("d"));
+
+        let jwks = JwkSet::from_bytes(jwks.to_string())?;
+        let key_ids: Vec<Option<&str>> = jwks.keys().iter().map(|jwk| jwk.key_id()).collect();
+        assert_eq!(key_ids, vec![Some("c"), Some("d")]);
+        assert_eq!(jwks.get_by_kid("d").and_then(|jwk| jwk.key_id()), Some("d"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_jwk_set_from_bytes_lenient() -> Result<()> {
+        let mut public_key = String::new();
+        load_file("jwk/RSA_public.jwk")?.read_to_string(&mut public_key)?;
+        let mut public_key: Value = serde_json::from_str(&public_key)?;
+        public_key["kid"] = Value::String("rsa".to_string());
+
+        let input = serde_json::json!({
+            "keys": [
+                {"kty": "unknown", "kid": "bogus"},
+                public_key,
+                "not a key",
+            ]
+        })
+        .to_string();
+        assert!(JwkSet::from_bytes(&input).is_err());
+
+        let (jwks, errors) = JwkSet::from_bytes_lenient(&input)?;
+        assert_eq!(jwks.keys().len(), 1);
+        assert_eq!(errors.len(), 2);
+        assert!(matches!(errors[0], JoseError::InvalidJwkFormat(_)));
+        assert!(jwks.get_by_kid("bogus").is_none());
+
+        let jwk = jwks.get_by_kid("rsa").unwrap();
+        crate::jws::RS256.verifier_from_jwk(jwk)?;
+
+        assert!(JwkSet::from_bytes_lenient("{}").is_err());
+
+        Ok(())
+    }
+
     fn load_file(path: &str) -> Result<File> {
         let mut pb = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
         pb.push("data");
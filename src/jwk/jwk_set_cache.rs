@@ -0,0 +1,257 @@
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use anyhow::{bail, Context};
+
+use crate::jwk::{Jwk, JwkSet};
+use crate::JoseError;
+
+impl JwkSet {
+    /// Return a JWK set that is fetched from the URL.
+    ///
+    /// # Arguments
+    /// * `url` - A URL of the JWK set (e.g. jwks_uri of OpenID Connect)
+    pub async fn fetch(url: &str) -> Result<Self, JoseError> {
+        let (jwk_set, _) = fetch_with_max_age(url).await?;
+        Ok(jwk_set)
+    }
+}
+
+/// The longest time a JWK set is cached, which is one year as an HTTP response
+/// that never expires (RFC 2616 Section 14.21).
+const MAX_CACHE_AGE: Duration = Duration::from_secs(365 * 24 * 60 * 60);
+
+/// Represents a JWK set that is fetched from the URL and cached in memory.
+///
+/// The JWK set is reused while the max-age of the Cache-Control response header
+/// is not elapsed. A max-age longer than one year is shortened to one year.
+#[derive(Debug)]
+pub struct JwkSetCache {
+    url: String,
+    default_max_age: Duration,
+    entry: Mutex<Option<(Arc<JwkSet>, Instant)>>,
+}
+
+impl JwkSetCache {
+    /// Return a cache of the JWK set at the URL.
+    ///
+    /// Nothing is fetched until the JWK set is requested by `get` or `select_by_kid`.
+    ///
+    /// # Arguments
+    /// * `url` - A URL of the JWK set (e.g. jwks_uri of OpenID Connect)
+    pub fn new(url: impl Into<String>) -> Self {
+        Self {
+            url: url.into(),
+            default_max_age: Duration::from_secs(0),
+            entry: Mutex::new(None),
+        }
+    }
+
+    /// Return the URL of the JWK set.
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// Set the max age that is used when the response has no Cache-Control max-age.
+    /// The default value is zero, so such a JWK set is fetched every time.
+    ///
+    /// # Arguments
+    /// * `default_max_age` - A max age
+    pub fn set_default_max_age(&mut self, default_max_age: Duration) {
+        self.default_max_age = default_max_age;
+    }
+
+    /// Return the max age that is used when the response has no Cache-Control max-age.
+    pub fn default_max_age(&self) -> Duration {
+        self.default_max_age
+    }
+
+    /// Return the cached JWK set, or fetch it when it is not cached or is expired.
+    ///
+    /// A request that fails before a response is read, e.g. by a connection error or
+    /// a timeout, returns InvalidJwkFormat with the message "Failed to fetch the JWK
+    /// set", and the `reqwest::Error` is kept as the source of the error.
+    pub async fn get(&self) -> Result<Arc<JwkSet>, JoseError> {
+        if let Some((jwk_set, expires_at)) = &*self.entry.lock().unwrap() {
+            if Instant::now() < *expires_at {
+                return Ok(Arc::clone(jwk_set));
+            }
+        }
+
+        let (jwk_set, max_age) = fetch_with_max_age(&self.url).await?;
+        let jwk_set = Arc::new(jwk_set);
+        let max_age = max_age.unwrap_or(self.default_max_age).min(MAX_CACHE_AGE);
+        let expires_at = Instant::now() + max_age;
+        *self.entry.lock().unwrap() = Some((Arc::clone(&jwk_set), expires_at));
+
+        Ok(jwk_set)
+    }
+
+    /// Return the first JWK that has the key ID in the cached JWK set.
+    ///
+    /// # Arguments
+    /// * `key_id` - A key ID
+    pub async fn select_by_kid(&self, key_id: &str) -> Result<Option<Jwk>, JoseError> {
+        let jwk_set = self.get().await?;
+        Ok(jwk_set.get_by_kid(key_id).cloned())
+    }
+
+    /// Remove the cached JWK set.
+    pub fn clear(&self) {
+        *self.entry.lock().unwrap() = None;
+    }
+}
+
+async fn fetch_with_max_age(url: &str) -> Result<(JwkSet, Option<Duration>), JoseError> {
+    async {
+        let response = reqwest::get(url)
+            .await
+            .with_context(|| format!("Failed to fetch the JWK set: {}", url))?;
+        if !response.status().is_success() {
+            bail!("The JWK set cannot be fetched: {}", response.status());
+        }
+
+        let max_age = response
+            .headers()
+            .get(reqwest::header::CACHE_CONTROL)
+            .and_then(|val| val.to_str().ok())
+            .and_then(parse_max_age);
+
+        let body = response
+            .bytes()
+            .await
+            .with_context(|| format!("Failed to fetch the JWK set: {}", url))?;
+        let jwk_set = JwkSet::from_bytes(&body)?;
+
+        Ok((jwk_set, max_age))
+    }
+    .await
+    .map_err(|err: anyhow::Error| match err.downcast::<JoseError>() {
+        Ok(err) => err,
+        Err(err) => JoseError::InvalidJwkFormat(err),
+    })
+}
+
+fn parse_max_age(cache_control: &str) -> Option<Duration> {
+    let mut max_age = None;
+    for directive in cache_control.split(',') {
+        let directive = directive.trim().to_ascii_lowercase();
+        if directive == "no-store" || directive == "no-cache" {
+            return Some(Duration::from_secs(0));
+        } else if let Some(val) = directive.strip_prefix("max-age=") {
+            max_age = val.trim_matches('"').parse().ok().map(Duration::from_secs);
+        }
+    }
+    max_age
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use anyhow::Result;
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::thread;
+
+    #[test]
+    fn test_parse_max_age() {
+        assert_eq!(parse_max_age("max-age=60"), Some(Duration::from_secs(60)));
+        assert_eq!(
+            parse_max_age("public, Max-Age=3600, must-revalidate"),
+            Some(Duration::from_secs(3600))
+        );
+        assert_eq!(
+            parse_max_age("max-age=60, no-cache"),
+            Some(Duration::from_secs(0))
+        );
+        assert_eq!(parse_max_age("public"), None);
+        assert_eq!(parse_max_age("max-age=abc"), None);
+    }
+
+    #[tokio::test]
+    async fn test_jwk_set_cache() -> Result<()> {
+        let (url, count) = start_server("max-age=60");
+        let cache = JwkSetCache::new(&url);
+
+        let jwk = cache.select_by_kid("1").await?;
+        assert_eq!(jwk.as_ref().and_then(|jwk| jwk.key_id()), Some("1"));
+        assert!(cache.select_by_kid("unknown").await?.is_none());
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+
+        cache.clear();
+        cache.select_by_kid("1").await?;
+        assert_eq!(count.load(Ordering::SeqCst), 2);
+
+        let (url, count) = start_server("no-cache");
+        let cache = JwkSetCache::new(&url);
+        cache.select_by_kid("1").await?;
+        cache.select_by_kid("1").await?;
+        assert_eq!(count.load(Ordering::SeqCst), 2);
+
+        let jwk_set = JwkSet::fetch(&url).await?;
+        assert_eq!(jwk_set.keys().len(), 1);
+
+        let (url, count) = start_server("max-age=18446744073709551615");
+        let cache = JwkSetCache::new(&url);
+        cache.select_by_kid("1").await?;
+        cache.select_by_kid("1").await?;
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+
+        let (url, count) = start_server("public");
+        let mut cache = JwkSetCache::new(&url);
+        cache.set_default_max_age(Duration::MAX);
+        cache.select_by_kid("1").await?;
+        cache.select_by_kid("1").await?;
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_jwk_set_cache_with_transport_error() -> Result<()> {
+        // Nothing listens on the port after the listener is dropped.
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let url = format!("http://{}/jwks", listener.local_addr()?);
+        drop(listener);
+
+        let cache = JwkSetCache::new(&url);
+        match cache.get().await {
+            Err(JoseError::InvalidJwkFormat(err)) => {
+                assert!(err.to_string().starts_with("Failed to fetch the JWK set"));
+                assert!(err.downcast_ref::<reqwest::Error>().is_some());
+            }
+            result => panic!("unexpected result: {:?}", result),
+        }
+
+        Ok(())
+    }
+
+    fn start_server(cache_control: &str) -> (String, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/jwks", listener.local_addr().unwrap());
+        let count = Arc::new(AtomicUsize::new(0));
+
+        let body = r#"{"keys":[{"kty":"oct","kid":"1","k":"MDEyMzQ1Njc4OUFCQ0RFRg"}]}"#;
+        let response = format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nCache-Control: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            cache_control,
+            body.len(),
+            body
+        );
+
+        let server_count = Arc::clone(&count);
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut buf = [0; 1024];
+                let _ = stream.read(&mut buf);
+                server_count.fetch_add(1, Ordering::SeqCst);
+                stream.write_all(response.as_bytes()).unwrap();
+            }
+        });
+
+        (url, count)
+    }
+}
@@ -5,8 +5,10 @@ mod jws_algorithm;
 mod jws_context;
 mod jws_header;
 mod jws_header_set;
+mod multi_verifier;
 
 use once_cell::sync::Lazy;
+use openssl::x509::X509;
 
 use crate::JoseError;
 
@@ -16,6 +18,7 @@ pub use crate::jws::jws_algorithm::JwsVerifier;
 pub use crate::jws::jws_context::JwsContext;
 pub use crate::jws::jws_header::JwsHeader;
 pub use crate::jws::jws_header_set::JwsHeaderSet;
+pub use crate::jws::multi_verifier::MultiVerifier;
 
 use crate::jws::alg::hmac::HmacJwsAlgorithm;
 pub use HmacJwsAlgorithm::Hs256 as HS256;
@@ -41,6 +44,9 @@ pub use EcdsaJwsAlgorithm::Es512 as ES512;
 use crate::jws::alg::eddsa::EddsaJwsAlgorithm;
 pub use EddsaJwsAlgorithm::Eddsa as EdDSA;
 
+/// The payload and the verification result of each signature.
+type SignatureResults = (Vec<u8>, Vec<(JwsHeader, bool)>);
+
 static DEFAULT_CONTEXT: Lazy<JwsContext> = Lazy::new(|| JwsContext::new());
 
 /// Return a representation of the data that is formatted by compact serialization.
@@ -76,6 +82,24 @@ where
     DEFAULT_CONTEXT.serialize_compact_with_selector(payload, header, selector)
 }
 
+/// Return a representation of the data that is formatted by compact serialization
+/// with the protected header that is given as a JSON string.
+///
+/// The header is encoded as it is without re-serialization.
+///
+/// # Arguments
+///
+/// * `payload` - The payload data.
+/// * `header` - The JWS header claims as a JSON string.
+/// * `signer` - The JWS signer.
+pub fn serialize_compact_with_raw_header(
+    payload: &[u8],
+    header: &str,
+    signer: &dyn JwsSigner,
+) -> Result<String, JoseError> {
+    DEFAULT_CONTEXT.serialize_compact_with_raw_header(payload, header, signer)
+}
+
 /// Return a representation of the data that is formatted by general json serialization.
 ///
 /// # Arguments
@@ -109,6 +133,41 @@ where
     DEFAULT_CONTEXT.serialize_general_json_with_selecter(payload, headers, selector)
 }
 
+/// Return a representation of the data that is formatted by general json serialization
+/// with a signature for each pair of the protected and unprotected header claims.
+///
+/// # Arguments
+///
+/// * `payload` - The payload data.
+/// * `headers` - The JWS protected and unprotected header claims pairs.
+/// * `selector` - a function for selecting the signing algorithm.
+pub fn serialize_general_json_with_selectors<'a, F>(
+    payload: &[u8],
+    headers: &[(Option<&JwsHeader>, Option<&JwsHeader>)],
+    selector: F,
+) -> Result<String, JoseError>
+where
+    F: Fn(usize, &JwsHeader) -> Option<&'a dyn JwsSigner>,
+{
+    DEFAULT_CONTEXT.serialize_general_json_with_selectors(payload, headers, selector)
+}
+
+/// Return a representation of the data that is formatted by compact serialization
+/// with detached content (RFC 7515 Appendix F).
+///
+/// # Arguments
+///
+/// * `payload` - The payload data.
+/// * `header` - The JWS heaser claims.
+/// * `signer` - The JWS signer.
+pub fn serialize_compact_detached(
+    payload: &[u8],
+    header: &JwsHeader,
+    signer: &dyn JwsSigner,
+) -> Result<String, JoseError> {
+    DEFAULT_CONTEXT.serialize_compact_detached(payload, header, signer)
+}
+
 /// Return a representation of the data that is formatted by flattened json serialization.
 ///
 /// # Arguments
@@ -142,6 +201,17 @@ where
     DEFAULT_CONTEXT.serialize_flattened_json_with_selector(payload, header, selector)
 }
 
+/// Return the JWS signing input (the ASCII string of BASE64URL(header) + "." + payload)
+/// that is computed in the same way as the compact serialization.
+///
+/// # Arguments
+///
+/// * `payload` - The payload data.
+/// * `header` - The JWS heaser claims.
+pub fn signing_input(payload: &[u8], header: &JwsHeader) -> Result<String, JoseError> {
+    DEFAULT_CONTEXT.signing_input(payload, header)
+}
+
 /// Deserialize the input that is formatted by compact serialization.
 ///
 /// # Arguments
@@ -173,6 +243,35 @@ where
     DEFAULT_CONTEXT.deserialize_compact_with_selector(input, selector)
 }
 
+/// Deserialize the inputs that are formatted by compact serialization with the same
+/// verifier, and return the result for each input in the same order.
+///
+/// # Arguments
+///
+/// * `inputs` - The input data.
+/// * `verifier` - The JWS verifier.
+pub fn verify_batch(
+    inputs: &[impl AsRef<[u8]>],
+    verifier: &dyn JwsVerifier,
+) -> Vec<Result<(Vec<u8>, JwsHeader), JoseError>> {
+    DEFAULT_CONTEXT.verify_batch(inputs, verifier)
+}
+
+/// Deserialize the input that is formatted by compact serialization with detached content.
+///
+/// # Arguments
+///
+/// * `input` - The input data.
+/// * `payload` - The detached payload data.
+/// * `verifier` - The JWS verifier.
+pub fn deserialize_compact_detached(
+    input: impl AsRef<[u8]>,
+    payload: &[u8],
+    verifier: &dyn JwsVerifier,
+) -> Result<JwsHeader, JoseError> {
+    DEFAULT_CONTEXT.deserialize_compact_detached(input, payload, verifier)
+}
+
 /// Deserialize the input that is formatted by json serialization.
 ///
 /// # Arguments
@@ -187,6 +286,22 @@ pub fn deserialize_json<'a>(
     DEFAULT_CONTEXT.deserialize_json(input, verifier)
 }
 
+/// Deserialize the input that is formatted by compact or json serialization.
+///
+/// The input is deserialized as json serialization if it starts with "{"
+/// after any leading whitespace, or as compact serialization otherwise.
+///
+/// # Arguments
+///
+/// * `input` - The input data.
+/// * `verifier` - The JWS verifier.
+pub fn deserialize_auto(
+    input: impl AsRef<[u8]>,
+    verifier: &dyn JwsVerifier,
+) -> Result<(Vec<u8>, JwsHeader), JoseError> {
+    DEFAULT_CONTEXT.deserialize_auto(input, verifier)
+}
+
 /// Deserialize the input that is formatted by json serialization.
 ///
 /// # Arguments
@@ -204,15 +319,182 @@ where
     DEFAULT_CONTEXT.deserialize_json_with_selector(input, selector)
 }
 
+/// Deserialize the input that is formatted by json serialization, and return
+/// the verification result of each signature.
+///
+/// # Arguments
+///
+/// * `input` - The input data.
+/// * `selector` - a function for selecting the verifying algorithm.
+pub fn deserialize_general_json_with_selector<'a, F>(
+    input: impl AsRef<[u8]>,
+    selector: F,
+) -> Result<SignatureResults, JoseError>
+where
+    F: Fn(&JwsHeader) -> Result<Option<&'a dyn JwsVerifier>, JoseError>,
+{
+    DEFAULT_CONTEXT.deserialize_general_json_with_selector(input, selector)
+}
+
+/// Return a verifier of the algorithm from the public key of a X.509 certificate
+/// that is formatted by PEM.
+///
+/// The certificate itself is not validated.
+///
+/// # Arguments
+///
+/// * `alg` - a JWS algorithm name, e.g. "RS256"
+/// * `input` - A X.509 certificate surrounded by "-----BEGIN/END CERTIFICATE-----".
+pub fn verifier_from_x509_pem(
+    alg: &str,
+    input: impl AsRef<[u8]>,
+) -> Result<Box<dyn JwsVerifier>, JoseError> {
+    let cert =
+        X509::from_pem(input.as_ref()).map_err(|err| JoseError::InvalidKeyFormat(err.into()))?;
+    verifier_from_x509(alg, &cert)
+}
+
+/// Return a verifier of the algorithm from the public key of a DER encoded
+/// X.509 certificate.
+///
+/// The certificate itself is not validated.
+///
+/// # Arguments
+///
+/// * `alg` - a JWS algorithm name, e.g. "RS256"
+/// * `input` - A DER encoded X.509 certificate, e.g. an element of the x5c header claim.
+pub fn verifier_from_x509_der(
+    alg: &str,
+    input: impl AsRef<[u8]>,
+) -> Result<Box<dyn JwsVerifier>, JoseError> {
+    let cert =
+        X509::from_der(input.as_ref()).map_err(|err| JoseError::InvalidKeyFormat(err.into()))?;
+    verifier_from_x509(alg, &cert)
+}
+
+fn verifier_from_x509(alg: &str, cert: &X509) -> Result<Box<dyn JwsVerifier>, JoseError> {
+    let public_key = cert
+        .public_key()
+        .map_err(|err| JoseError::InvalidKeyFormat(err.into()))?;
+    multi_verifier::verifier_from_public_key(alg, &public_key)
+}
+
 #[cfg(test)]
 mod tests {
+    use std::fmt::{Debug, Display};
     use std::fs;
     use std::path::PathBuf;
+    use std::str::FromStr;
+    use std::sync::Arc;
 
     use anyhow::Result;
+    use openssl::hash::MessageDigest;
+    use openssl::pkey::PKey;
+    use openssl::sign::Signer;
 
-    use crate::jws::{self, EdDSA, JwsHeader, JwsHeaderSet, ES256, RS256};
-    use crate::Value;
+    use crate::jws::alg::ecdsa::EcdsaJwsAlgorithm;
+    use crate::jws::{
+        self, EdDSA, JwsAlgorithm, JwsContext, JwsHeader, JwsHeaderSet, JwsSigner, JwsVerifier,
+        ES256, ES256K, ES384, ES512, HS256, HS384, HS512, PS256, PS384, PS512, RS256, RS384, RS512,
+    };
+    use crate::util;
+    use crate::{JoseError, JoseRandom, Value};
+
+    #[test]
+    fn test_jws_compact_with_malformed_segments() -> Result<()> {
+        let key = b"0123456789ABCDEF0123456789ABCDEF";
+        let signer = HS256.signer_from_bytes(key)?;
+        let verifier = HS256.verifier_from_bytes(key)?;
+
+        let jws = jws::serialize_compact(b"test payload!", &JwsHeader::new(), &signer)?;
+        let parts: Vec<&str> = jws.split('.').collect();
+        jws::deserialize_compact(&jws, &verifier)?;
+
+        for input in [
+            String::new(),
+            parts[0].to_string(),
+            format!("{}.{}", parts[0], parts[1]),
+            format!("{}.", jws),
+            format!("{}.{}", jws, parts[1]),
+            format!("{}.{}.{}", jws, parts[1], parts[2]),
+            format!(".{}.{}", parts[1], parts[2]),
+            "..".to_string(),
+            "...".to_string(),
+            format!("!.{}.{}", parts[1], parts[2]),
+        ] {
+            assert!(matches!(
+                jws::deserialize_compact(&input, &verifier),
+                Err(JoseError::InvalidJwsFormat(_))
+            ));
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_jws_with_duplicate_header_claims() -> Result<()> {
+        let key = b"0123456789ABCDEF0123456789ABCDEF";
+        let signer = HS256.signer_from_bytes(key)?;
+        let verifier = HS256.verifier_from_bytes(key)?;
+
+        let header_b64 = util::base64url_encode(r#"{"alg":"HS256","alg":"HS256"}"#);
+        let payload_b64 = util::base64url_encode("test payload!");
+        let message = format!("{}.{}", header_b64, payload_b64);
+        let signature_b64 = util::base64url_encode(signer.sign(message.as_bytes())?);
+
+        let compact = format!("{}.{}", message, signature_b64);
+        let flattened_json = format!(
+            r#"{{"protected":"{}","payload":"{}","signature":"{}"}}"#,
+            header_b64, payload_b64, signature_b64
+        );
+        let unprotected_json = format!(
+            r#"{{"protected":"{}","header":{{"kid":"a","kid":"b"}},"payload":"{}","signature":"{}"}}"#,
+            util::base64url_encode(r#"{"alg":"HS256"}"#),
+            payload_b64,
+            signature_b64
+        );
+
+        for result in [
+            jws::deserialize_compact(&compact, &verifier),
+            jws::deserialize_json(&flattened_json, &verifier),
+            jws::deserialize_json(&unprotected_json, &verifier),
+        ] {
+            match result {
+                Err(JoseError::InvalidJwsFormat(err)) => {
+                    assert!(err.to_string().contains("A duplicate key exists"))
+                }
+                result => panic!("unexpected result: {:?}", result),
+            }
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_jws_deserialize_auto() -> Result<()> {
+        let key = b"0123456789ABCDEF0123456789ABCDEF";
+        let signer = HS256.signer_from_bytes(key)?;
+        let verifier = HS256.verifier_from_bytes(key)?;
+
+        let compact = jws::serialize_compact(b"compact", &JwsHeader::new(), &signer)?;
+        let (payload, _) = jws::deserialize_auto(&compact, &verifier)?;
+        assert_eq!(payload, b"compact");
+
+        let json = jws::serialize_flattened_json(b"json", &JwsHeaderSet::new(), &signer)?;
+        let (payload, _) = jws::deserialize_auto(&json, &verifier)?;
+        assert_eq!(payload, b"json");
+        let (payload, _) = jws::deserialize_auto(format!(" \n{}", json), &verifier)?;
+        assert_eq!(payload, b"json");
+
+        for input in ["", "not a token", "[1,2,3]", "{\"payload\":"] {
+            assert!(matches!(
+                jws::deserialize_auto(input, &verifier),
+                Err(JoseError::InvalidJwsFormat(_))
+            ));
+        }
+
+        Ok(())
+    }
 
     #[test]
     fn test_jws_compact_serialization() -> Result<()> {
@@ -237,6 +519,271 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_jws_with_external_signer() -> Result<()> {
+        #[derive(Debug, Clone)]
+        struct ExternalSigner {
+            key: Vec<u8>,
+        }
+
+        impl JwsSigner for ExternalSigner {
+            fn algorithm(&self) -> &dyn JwsAlgorithm {
+                &HS256
+            }
+
+            fn key_id(&self) -> Option<&str> {
+                Some("external-key")
+            }
+
+            fn signature_len(&self) -> usize {
+                32
+            }
+
+            fn sign(&self, message: &[u8]) -> Result<Vec<u8>, JoseError> {
+                (|| -> anyhow::Result<Vec<u8>> {
+                    let key = PKey::hmac(&self.key)?;
+                    let mut signer = Signer::new(MessageDigest::sha256(), &key)?;
+                    signer.update(message)?;
+                    Ok(signer.sign_to_vec()?)
+                })()
+                .map_err(JoseError::InvalidSignature)
+            }
+
+            fn box_clone(&self) -> Box<dyn JwsSigner> {
+                Box::new(self.clone())
+            }
+        }
+
+        let key = b"0123456789ABCDEF0123456789ABCDEF".to_vec();
+        let signer = ExternalSigner { key: key.clone() };
+
+        let header = JwsHeader::new();
+        let jws = jws::serialize_compact(b"test payload!", &header, &signer)?;
+
+        let verifier = HS256.verifier_from_bytes(&key)?;
+        let (payload, header) = jws::deserialize_compact(&jws, &verifier)?;
+        assert_eq!(payload, b"test payload!");
+        assert_eq!(header.algorithm(), Some("HS256"));
+        assert_eq!(header.key_id(), Some("external-key"));
+
+        let mut expected_signer = HS256.signer_from_bytes(&key)?;
+        expected_signer.set_key_id("external-key");
+        let expected =
+            jws::serialize_compact(b"test payload!", &JwsHeader::new(), &expected_signer)?;
+        assert_eq!(jws, expected);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_jws_algorithm_from_str() -> Result<()> {
+        fn round_trip<T>(algs: &[T]) -> Result<()>
+        where
+            T: FromStr<Err = JoseError> + Display + PartialEq + Debug,
+        {
+            for alg in algs {
+                let parsed: T = alg.to_string().parse()?;
+                assert_eq!(&parsed, alg);
+                assert_eq!(parsed.to_string(), alg.to_string());
+            }
+            assert!(matches!(
+                "UNKNOWN".parse::<T>(),
+                Err(JoseError::UnsupportedSignatureAlgorithm(_))
+            ));
+            Ok(())
+        }
+
+        round_trip(&[HS256, HS384, HS512])?;
+        round_trip(&[RS256, RS384, RS512])?;
+        round_trip(&[PS256, PS384, PS512])?;
+        round_trip(&[ES256, ES256K, ES384, ES512])?;
+        round_trip(&[EdDSA])?;
+        assert_eq!("ES256K".parse::<EcdsaJwsAlgorithm>()?, ES256K);
+        assert!("es256".parse::<EcdsaJwsAlgorithm>().is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_jws_compact_serialization_with_raw_header() -> Result<()> {
+        let key = b"0123456789ABCDEF0123456789ABCDEF";
+        let signer = HS256.signer_from_bytes(key)?;
+        let verifier = HS256.verifier_from_bytes(key)?;
+
+        let raw_header = r#"{"typ":"JWT",  "b":1,"alg":"HS256","a":0}"#;
+        let jws = jws::serialize_compact_with_raw_header(b"test payload!", raw_header, &signer)?;
+        let protected = jws.split('.').next().unwrap();
+        assert_eq!(
+            base64::decode_config(protected, base64::URL_SAFE_NO_PAD)?,
+            raw_header.as_bytes()
+        );
+
+        let (payload, header) = jws::deserialize_compact(&jws, &verifier)?;
+        assert_eq!(payload, b"test payload!");
+        assert_eq!(header.token_type(), Some("JWT"));
+
+        for raw_header in [r#"{"typ":"JWT"}"#, r#"{"alg":"HS384"}"#, "{", "[]"] {
+            assert!(jws::serialize_compact_with_raw_header(b"test", raw_header, &signer).is_err());
+        }
+
+        let mut signer = signer;
+        signer.set_key_id("key-1");
+        assert!(
+            jws::serialize_compact_with_raw_header(b"test", r#"{"alg":"HS256"}"#, &signer).is_err()
+        );
+        jws::serialize_compact_with_raw_header(
+            b"test",
+            r#"{"alg":"HS256","kid":"key-1"}"#,
+            &signer,
+        )?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_jws_signing_input() -> Result<()> {
+        let mut header = JwsHeader::new();
+        header.set_algorithm("HS256");
+        header.set_token_type("JWT");
+        let payload = br#"{"sub":"1234567890"}"#;
+
+        let signing_input = jws::signing_input(payload, &header)?;
+        assert_eq!(
+            signing_input,
+            "eyJhbGciOiJIUzI1NiIsInR5cCI6IkpXVCJ9.eyJzdWIiOiIxMjM0NTY3ODkwIn0"
+        );
+
+        let signer = HS256.signer_from_bytes(b"0123456789ABCDEF0123456789ABCDEF")?;
+        let jws = jws::serialize_compact(payload, &header, &signer)?;
+        assert!(jws.starts_with(&format!("{}.", signing_input)));
+
+        header.set_base64url_encode_payload(false);
+        header.set_critical(&vec!["b64"]);
+        let signing_input = jws::signing_input(b"$.02", &header)?;
+        assert!(signing_input.ends_with(".$.02"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_jws_verify_batch() -> Result<()> {
+        let alg = ES256;
+
+        let private_key = load_file("pem/EC_P-256_private.pem")?;
+        let public_key = load_file("pem/EC_P-256_public.pem")?;
+
+        let header = JwsHeader::new();
+        let signer = alg.signer_from_pem(&private_key)?;
+        let mut tokens = Vec::new();
+        for i in 0..1000 {
+            let payload = format!("payload {}", i);
+            tokens.push(jws::serialize_compact(
+                payload.as_bytes(),
+                &header,
+                &signer,
+            )?);
+        }
+        let mut tampered = tokens[0].clone();
+        tampered.push('A');
+        tokens.push(tampered);
+
+        let verifier = alg.verifier_from_pem(&public_key)?;
+        let results = jws::verify_batch(&tokens, &verifier);
+        assert_eq!(results.len(), 1001);
+        for (i, result) in results.iter().take(1000).enumerate() {
+            let (payload, _) = result.as_ref().map_err(|err| anyhow::anyhow!("{}", err))?;
+            assert_eq!(payload, format!("payload {}", i).as_bytes());
+        }
+        assert!(results[1000].is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_jws_compact_serialization_with_detached_payload() -> Result<()> {
+        let alg = RS256;
+
+        let private_key = load_file("pem/RSA_2048bit_private.pem")?;
+        let public_key = load_file("pem/RSA_2048bit_public.pem")?;
+
+        let src_header = JwsHeader::new();
+        let src_payload = b"test payload!";
+        let signer = alg.signer_from_pem(&private_key)?;
+        let jws = jws::serialize_compact(src_payload, &src_header, &signer)?;
+        let detached = jws::serialize_compact_detached(src_payload, &src_header, &signer)?;
+
+        let parts: Vec<&str> = jws.split('.').collect();
+        assert_eq!(detached, format!("{}..{}", parts[0], parts[2]));
+
+        let verifier = alg.verifier_from_pem(&public_key)?;
+        let dst_header = jws::deserialize_compact_detached(&detached, src_payload, &verifier)?;
+        assert_eq!(dst_header.algorithm(), Some("RS256"));
+
+        let result = jws::deserialize_compact_detached(&detached, b"test payload?", &verifier);
+        assert!(matches!(result, Err(JoseError::InvalidSignature(_))));
+
+        let result = jws::deserialize_compact_detached(&jws, src_payload, &verifier);
+        assert!(matches!(result, Err(JoseError::InvalidJwsFormat(_))));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_jws_compact_serialization_with_unencoded_payload() -> Result<()> {
+        // RFC 7797 section 4
+        let key = base64::decode_config(
+            "AyM1SysPpbyDfgZld3umj1qzKObwVMkoqQ-EstJQLr_T-1qS0gZH75aKtMN3Yj0iPS4hcgUuTwjAzZr1Z9CAow",
+            base64::URL_SAFE_NO_PAD,
+        )?;
+        let src_payload = b"$.02";
+        let signer = HS256.signer_from_bytes(&key)?;
+        let verifier = HS256.verifier_from_bytes(&key)?;
+        let mut context = JwsContext::new();
+        context.add_acceptable_critical("b64");
+
+        let src_header = JwsHeader::new();
+        let jws = jws::serialize_compact(src_payload, &src_header, &signer)?;
+        assert_eq!(
+            jws,
+            "eyJhbGciOiJIUzI1NiJ9.JC4wMg.5mvfOroL-g7HyqJoozehmsaqmvTYGEq5jTI1gVvoEoQ"
+        );
+        let (dst_payload, _) = jws::deserialize_compact(&jws, &verifier)?;
+        assert_eq!(src_payload.to_vec(), dst_payload);
+
+        let mut src_header = JwsHeader::new();
+        src_header.set_algorithm("HS256");
+        src_header.set_base64url_encode_payload(false);
+        src_header.set_critical(&vec!["b64"]);
+        let jws = jws::serialize_compact_detached(src_payload, &src_header, &signer)?;
+        assert_eq!(
+            jws,
+            "eyJhbGciOiJIUzI1NiIsImI2NCI6ZmFsc2UsImNyaXQiOlsiYjY0Il19..A5dxf2s96_n5FLueVuW1Z_vh161FwXZC4YLPff6dmDY"
+        );
+        let dst_header = context.deserialize_compact_detached(&jws, src_payload, &verifier)?;
+        assert_eq!(dst_header.base64url_encode_payload(), Some(false));
+
+        let result = jws::deserialize_compact_detached(&jws, src_payload, &verifier);
+        assert!(matches!(result, Err(JoseError::InvalidJwsFormat(_))));
+
+        let result = jws::serialize_compact(src_payload, &src_header, &signer);
+        assert!(matches!(result, Err(JoseError::InvalidJwsFormat(_))));
+
+        let jws = jws::serialize_compact(b"$02", &src_header, &signer)?;
+        assert!(jws.contains(".$02."));
+        let (dst_payload, _) = context.deserialize_compact(&jws, &verifier)?;
+        assert_eq!(b"$02".to_vec(), dst_payload);
+
+        let mut src_header = JwsHeaderSet::new();
+        src_header.set_critical(&vec!["b64"]);
+        src_header.set_base64url_encode_payload(false);
+        let json = jws::serialize_flattened_json(src_payload, &src_header, &signer)?;
+        assert!(json.contains("\"payload\":\"$.02\""));
+        let (dst_payload, _) = context.deserialize_json(&json, &verifier)?;
+        assert_eq!(src_payload.to_vec(), dst_payload);
+
+        Ok(())
+    }
+
     #[test]
     fn test_jws_json_serialization() -> Result<()> {
         let alg = RS256;
@@ -307,6 +854,295 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_jws_general_json_serialization_with_selectors() -> Result<()> {
+        let signer_1 = RS256.signer_from_pem(&load_file("pem/RSA_2048bit_private.pem")?)?;
+        let signer_2 = ES256.signer_from_pem(&load_file("pem/EC_P-256_private.pem")?)?;
+
+        let src_payload = b"test payload!";
+
+        let mut protected_1 = JwsHeader::new();
+        protected_1.set_algorithm("RS256");
+        let mut unprotected_1 = JwsHeader::new();
+        unprotected_1.set_key_id("xxx-1");
+
+        let mut protected_2 = JwsHeader::new();
+        protected_2.set_algorithm("ES256");
+        protected_2.set_key_id("xxx-2");
+
+        let json = jws::serialize_general_json_with_selectors(
+            src_payload,
+            &[
+                (Some(&protected_1), Some(&unprotected_1)),
+                (Some(&protected_2), None),
+            ],
+            |_i, header| match header.algorithm() {
+                Some("RS256") => Some(&*signer_1),
+                Some("ES256") => Some(&*signer_2),
+                _ => None,
+            },
+        )?;
+
+        let verifiers: Vec<(Box<dyn JwsVerifier>, &str)> = vec![
+            (
+                Box::new(RS256.verifier_from_pem(&load_file("pem/RSA_2048bit_public.pem")?)?),
+                "xxx-1",
+            ),
+            (
+                Box::new(ES256.verifier_from_pem(&load_file("pem/EC_P-256_public.pem")?)?),
+                "xxx-2",
+            ),
+        ];
+        for (verifier, kid) in &verifiers {
+            let (dst_payload, dst_header) = jws::deserialize_json(&json, verifier.as_ref())?;
+            assert_eq!(dst_header.algorithm(), Some(verifier.algorithm().name()));
+            assert_eq!(dst_header.key_id(), Some(*kid));
+            assert_eq!(src_payload.to_vec(), dst_payload);
+        }
+
+        let mut protected_3 = JwsHeader::new();
+        protected_3.set_algorithm("HS256");
+        assert!(jws::serialize_general_json_with_selectors(
+            src_payload,
+            &[(Some(&protected_3), None)],
+            |_i, _header| None,
+        )
+        .is_err());
+        assert!(jws::serialize_general_json_with_selectors(
+            src_payload,
+            &[(Some(&protected_2), Some(&protected_2))],
+            |_i, _header| Some(&*signer_2),
+        )
+        .is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_jws_general_json_serialization_with_signature_results() -> Result<()> {
+        let private_key_1 = load_file("pem/RSA_2048bit_private.pem")?;
+        let public_key_1 = load_file("pem/RSA_2048bit_public.pem")?;
+        let private_key_2 = load_file("pem/EC_P-256_private.pem")?;
+        let wrong_public_key_2 = ES256.generate_key_pair()?.to_der_public_key();
+
+        let src_payload = b"test payload!";
+
+        let mut src_header_1 = JwsHeaderSet::new();
+        src_header_1.set_key_id("xxx-1", true);
+        let signer_1 = RS256.signer_from_pem(&private_key_1)?;
+
+        let mut src_header_2 = JwsHeaderSet::new();
+        src_header_2.set_key_id("xxx-2", true);
+        let signer_2 = ES256.signer_from_pem(&private_key_2)?;
+
+        let json = jws::serialize_general_json(
+            src_payload,
+            &[(&src_header_1, &*signer_1), (&src_header_2, &*signer_2)],
+        )?;
+
+        let verifier_1 = RS256.verifier_from_pem(&public_key_1)?;
+        let verifier_2 = ES256.verifier_from_der(&wrong_public_key_2)?;
+        let (dst_payload, results) =
+            jws::deserialize_general_json_with_selector(&json, |header| match header.key_id() {
+                Some("xxx-1") => Ok(Some(&verifier_1)),
+                Some("xxx-2") => Ok(Some(&verifier_2)),
+                _ => Ok(None),
+            })?;
+
+        assert_eq!(src_payload.to_vec(), dst_payload);
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0].0.key_id(), Some("xxx-1"));
+        assert!(results[0].1);
+        assert_eq!(results[1].0.key_id(), Some("xxx-2"));
+        assert!(!results[1].1);
+
+        Ok(())
+    }
+
+    #[derive(Debug)]
+    struct FixedRandom(Vec<u8>);
+
+    impl JoseRandom for FixedRandom {
+        fn fill_bytes(&self, buf: &mut [u8]) {
+            buf.copy_from_slice(&self.0[..buf.len()]);
+        }
+    }
+
+    #[test]
+    fn test_jws_with_accepted_algorithms() -> Result<()> {
+        let private_key = load_file("pem/EC_P-256_private.pem")?;
+        let public_key = load_file("pem/EC_P-256_public.pem")?;
+        let es256_signer = ES256.signer_from_pem(&private_key)?;
+        let es256_verifier = ES256.verifier_from_pem(&public_key)?;
+        // The public key is known by everyone, so a HS256 token can be forged with it.
+        let hs256_signer = HS256.signer_from_bytes(&public_key)?;
+        let hs256_verifier = HS256.verifier_from_bytes(&public_key)?;
+
+        let mut context = JwsContext::new();
+        context.set_accepted_algorithms(&["ES256"]);
+        assert!(context.is_accepted_algorithm("ES256"));
+        assert!(!context.is_accepted_algorithm("HS256"));
+
+        let header = JwsHeader::new();
+        let selector = |header: &JwsHeader| -> Result<Option<&dyn JwsVerifier>, JoseError> {
+            Ok(match header.algorithm() {
+                Some("ES256") => Some(&es256_verifier),
+                Some("HS256") => Some(&hs256_verifier),
+                _ => None,
+            })
+        };
+
+        let forged = context.serialize_compact(b"test payload!", &header, &hs256_signer)?;
+        assert!(jws::deserialize_compact_with_selector(&forged, selector).is_ok());
+        assert!(matches!(
+            context.deserialize_compact_with_selector(&forged, selector),
+            Err(JoseError::InvalidJwsFormat(_))
+        ));
+
+        let forged = context.serialize_flattened_json(
+            b"test payload!",
+            &JwsHeaderSet::new(),
+            &hs256_signer,
+        )?;
+        assert!(context
+            .deserialize_json_with_selector(&forged, selector)
+            .is_err());
+
+        let jws = context.serialize_compact(b"test payload!", &header, &es256_signer)?;
+        let (payload, _) = context.deserialize_compact_with_selector(&jws, selector)?;
+        assert_eq!(payload, b"test payload!");
+
+        context.remove_accepted_algorithms();
+        assert!(context.is_accepted_algorithm("HS256"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_jws_verifier_from_x509() -> Result<()> {
+        for (alg, private_key, cert) in [
+            (
+                "RS256",
+                "pem/RSA_2048bit_private.pem",
+                "pem/RSA_2048bit_certificate.pem",
+            ),
+            (
+                "ES256",
+                "pem/EC_P-256_private.pem",
+                "pem/EC_P-256_certificate.pem",
+            ),
+            (
+                "EdDSA",
+                "pem/ED25519_private.pem",
+                "pem/ED25519_certificate.pem",
+            ),
+        ] {
+            let private_key = load_file(private_key)?;
+            let signer: Box<dyn JwsSigner> = match alg {
+                "RS256" => Box::new(RS256.signer_from_pem(&private_key)?),
+                "ES256" => Box::new(ES256.signer_from_pem(&private_key)?),
+                _ => Box::new(EdDSA.signer_from_pem(&private_key)?),
+            };
+            let cert = load_file(cert)?;
+            let der = openssl::x509::X509::from_pem(&cert)?.to_der()?;
+
+            let jws = jws::serialize_compact(b"test payload!", &JwsHeader::new(), &*signer)?;
+
+            let verifier = jws::verifier_from_x509_pem(alg, &cert)?;
+            assert_eq!(verifier.algorithm().name(), alg);
+            let (payload, _) = jws::deserialize_compact(&jws, &*verifier)?;
+            assert_eq!(payload, b"test payload!");
+
+            let verifier = jws::verifier_from_x509_der(alg, &der)?;
+            let (payload, _) = jws::deserialize_compact(&jws, &*verifier)?;
+            assert_eq!(payload, b"test payload!");
+        }
+
+        let cert = load_file("pem/EC_P-256_certificate.pem")?;
+        for alg in ["RS256", "ES384", "EdDSA", "HS256"] {
+            assert!(matches!(
+                jws::verifier_from_x509_pem(alg, &cert),
+                Err(JoseError::InvalidKeyFormat(_))
+            ));
+        }
+        assert!(matches!(
+            jws::verifier_from_x509_pem("UNKNOWN", &cert),
+            Err(JoseError::UnsupportedSignatureAlgorithm(_))
+        ));
+        assert!(jws::verifier_from_x509_der("ES256", b"not a certificate").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_jws_compact_serialization_with_fixed_rng() -> Result<()> {
+        let private_key = load_file("pem/EC_P-256_private.pem")?;
+        let public_key = load_file("pem/EC_P-256_public.pem")?;
+        let signer = ES256.signer_from_pem(&private_key)?;
+        let verifier = ES256.verifier_from_pem(&public_key)?;
+
+        // The signature with a fixed nonce is checked against RFC 6979 A.2.5 in the
+        // ECDSA tests, so only the reproducibility by the context is checked here.
+        let nonce = openssl::bn::BigNum::from_hex_str(
+            "A6E3C57DD01ABE90086538398355DD4C3B17AA873382B0F24D6129493D8AAD60",
+        )?
+        .to_vec();
+        let mut context = JwsContext::new();
+        context.set_rng(Arc::new(FixedRandom(nonce)));
+
+        let mut header = JwsHeader::new();
+        header.set_token_type("JWT");
+        let jws = context.serialize_compact(b"test payload!", &header, &signer)?;
+        assert_eq!(
+            context.serialize_compact(b"test payload!", &header, &signer)?,
+            jws
+        );
+
+        let (payload, _) = context.deserialize_compact(&jws, &verifier)?;
+        assert_eq!(payload, b"test payload!");
+
+        context.set_rng(Arc::new(FixedRandom(vec![0x01; 32])));
+        let other = context.serialize_compact(b"test payload!", &header, &signer)?;
+        assert_ne!(other, jws);
+        context.deserialize_compact(&other, &verifier)?;
+
+        context.remove_rng();
+        assert!(context.rng().is_none());
+        let random = context.serialize_compact(b"test payload!", &header, &signer)?;
+        assert_ne!(random, jws);
+
+        // RSASSA-PSS draws the salt from the generator. The salt is 0x00..0x1F, and
+        // the signature is the same as one by an independent EMSA-PSS encoder with
+        // the raw RSA operation, which OpenSSL verifies with the salt length of 32.
+        let private_key = load_file("pem/RSA-PSS_2048bit_SHA-256_private.pem")?;
+        let public_key = load_file("pem/RSA-PSS_2048bit_SHA-256_public.pem")?;
+        let signer = PS256.signer_from_pem(&private_key)?;
+        let verifier = PS256.verifier_from_pem(&public_key)?;
+        let salt: Vec<u8> = (0..32).collect();
+        context.set_rng(Arc::new(FixedRandom(salt)));
+        let jws = context.serialize_compact(b"test payload!", &header, &signer)?;
+        assert_eq!(
+            jws,
+            concat!(
+                "eyJ0eXAiOiJKV1QiLCJhbGciOiJQUzI1NiJ9.dGVzdCBwYXlsb2FkIQ.",
+                "P3LLf1pMsdpRLb-sy1iUN1f2ANZ9MQTeDSdRBSoB0JnYJPQpjCVEpcariIIh3B63lhwPHp25",
+                "DSxLesCJwVLjqKpq5mCihT17LtUftNk550rahrnC6Jibz4doDOwDpaammf2qs8vRQgwgp0Km",
+                "UHX8zP2cxUVCL5_KiRD5GRPO4rd6FCf2hyyGsCJSyW0fExnFLEYeJ_JwaU5O_e_3ibcsnBze",
+                "_E25hnWJSTt9vT0Zv-jI4zto8X5t-ClglnXXqsaGJQA92tQcqiQ2PMINFXYO0Ms4IG8XBHyY",
+                "8cxDm4KDby_Jeic5vAkcJa0wHWVUqU10pExnrXZAQo6bKzsZnR4yiQ"
+            )
+        );
+        let (payload, _) = context.deserialize_compact(&jws, &verifier)?;
+        assert_eq!(payload, b"test payload!");
+
+        context.set_rng(Arc::new(FixedRandom(vec![0xFF; 32])));
+        let other = context.serialize_compact(b"test payload!", &header, &signer)?;
+        assert_ne!(other, jws);
+        context.deserialize_compact(&other, &verifier)?;
+
+        Ok(())
+    }
+
     fn load_file(path: &str) -> Result<Vec<u8>> {
         let mut pb = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
         pb.push("data");
@@ -1,7 +1,12 @@
 use std::fmt::Display;
 use std::ops::Deref;
+use std::str::FromStr;
 
 use anyhow::bail;
+use openssl::bn::{BigNum, BigNumContext};
+use openssl::ec::{EcKey, EcPoint};
+use openssl::ecdsa::EcdsaSig;
+use openssl::hash::{hash, MessageDigest};
 use openssl::pkey::{PKey, Private, Public};
 use openssl::sign::{Signer, Verifier};
 
@@ -12,7 +17,7 @@ use crate::jwk::{
 use crate::jws::{JwsAlgorithm, JwsSigner, JwsVerifier};
 use crate::util::der::{DerBuilder, DerReader, DerType};
 use crate::util::{self, HashAlgorithm};
-use crate::{JoseError, Value};
+use crate::{JoseError, JoseRandom, Value};
 
 #[derive(Debug, Eq, PartialEq, Copy, Clone)]
 pub enum EcdsaJwsAlgorithm {
@@ -70,6 +75,7 @@ impl EcdsaJwsAlgorithm {
             algorithm: self.clone(),
             private_key: key_pair.into_private_key(),
             key_id: None,
+            deterministic: false,
         })
     }
 
@@ -89,6 +95,7 @@ impl EcdsaJwsAlgorithm {
             algorithm: self.clone(),
             private_key: key_pair.into_private_key(),
             key_id: None,
+            deterministic: false,
         })
     }
 
@@ -113,7 +120,11 @@ impl EcdsaJwsAlgorithm {
             }
             match jwk.curve() {
                 Some(val) if val == self.curve().name() => {}
-                Some(val) => bail!("A parameter crv must be {} but {}", self.name(), val),
+                Some(val) => bail!(
+                    "A parameter crv must be {} but {}",
+                    self.curve().name(),
+                    val
+                ),
                 None => bail!("A parameter crv is required."),
             }
 
@@ -125,6 +136,7 @@ impl EcdsaJwsAlgorithm {
                 algorithm: self.clone(),
                 private_key,
                 key_id,
+                deterministic: false,
             })
         })()
         .map_err(|err| JoseError::InvalidKeyFormat(err))
@@ -237,13 +249,7 @@ impl EcdsaJwsAlgorithm {
                 None => bail!("A parameter y is required."),
             };
 
-            let mut vec = Vec::with_capacity(1 + x.len() + y.len());
-            vec.push(0x04);
-            vec.extend_from_slice(&x);
-            vec.extend_from_slice(&y);
-
-            let pkcs8 = EcKeyPair::to_pkcs8(&vec, true, self.curve());
-            let public_key = PKey::public_key_from_der(&pkcs8)?;
+            let public_key = EcKeyPair::public_key_from_coordinates(&x, &y, curve)?;
             let key_id = jwk.key_id().map(|val| val.to_string());
 
             Ok(EcdsaJwsVerifier {
@@ -303,6 +309,23 @@ impl Display for EcdsaJwsAlgorithm {
     }
 }
 
+impl FromStr for EcdsaJwsAlgorithm {
+    type Err = JoseError;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "ES256" => Ok(Self::Es256),
+            "ES384" => Ok(Self::Es384),
+            "ES512" => Ok(Self::Es512),
+            "ES256K" => Ok(Self::Es256k),
+            _ => Err(JoseError::UnsupportedSignatureAlgorithm(anyhow::anyhow!(
+                "Unknown algorithm: {}",
+                name
+            ))),
+        }
+    }
+}
+
 impl Deref for EcdsaJwsAlgorithm {
     type Target = dyn JwsAlgorithm;
 
@@ -316,6 +339,7 @@ pub struct EcdsaJwsSigner {
     algorithm: EcdsaJwsAlgorithm,
     private_key: PKey<Private>,
     key_id: Option<String>,
+    deterministic: bool,
 }
 
 impl EcdsaJwsSigner {
@@ -326,6 +350,168 @@ impl EcdsaJwsSigner {
     pub fn remove_key_id(&mut self) {
         self.key_id = None;
     }
+
+    /// Set whether the nonce k is generated deterministically (RFC 6979) instead of randomly.
+    ///
+    /// A deterministic signature is always the same for the same key and message,
+    /// and it can be verified by any ECDSA verifier. The arithmetic is done by
+    /// OpenSSL big numbers, so it may be less resistant to side channel attacks than
+    /// the default signing.
+    ///
+    /// # Arguments
+    /// * `deterministic` - true if the signature is deterministic
+    pub fn set_deterministic(&mut self, deterministic: bool) {
+        self.deterministic = deterministic;
+    }
+
+    /// Return whether the nonce k is generated deterministically (RFC 6979).
+    pub fn is_deterministic(&self) -> bool {
+        self.deterministic
+    }
+
+    fn sign_deterministic(&self, h1: &[u8]) -> anyhow::Result<Vec<u8>> {
+        let md = self.algorithm.hash_algorithm().message_digest();
+        let ec_key = self.private_key.ec_key()?;
+        let group = ec_key.group();
+        let mut ctx = BigNumContext::new()?;
+
+        let mut order = BigNum::new()?;
+        group.order(&mut order, &mut ctx)?;
+        let qlen = order.num_bits() as usize;
+        let rlen = self.signature_len() / 2;
+
+        let mut d = ec_key.private_key().to_owned()?;
+        d.set_const_time();
+
+        let z = bits2int(h1, qlen)?;
+        let mut h1_mod_q = BigNum::new()?;
+        h1_mod_q.nnmod(&z, &order, &mut ctx)?;
+
+        let x = d.to_vec_padded(rlen as i32)?;
+        let h1 = h1_mod_q.to_vec_padded(rlen as i32)?;
+
+        let mut v = vec![0x01; md.size()];
+        let mut k = vec![0x00; md.size()];
+        k = hmac(md, &k, &[&v, &[0x00], &x, &h1])?;
+        v = hmac(md, &k, &[&v])?;
+        k = hmac(md, &k, &[&v, &[0x01], &x, &h1])?;
+        v = hmac(md, &k, &[&v])?;
+
+        loop {
+            let mut t = Vec::with_capacity(rlen + md.size());
+            while t.len() < rlen {
+                v = hmac(md, &k, &[&v])?;
+                t.extend_from_slice(&v);
+            }
+
+            let nonce = bits2int(&t, qlen)?;
+            if let Some(signature) = sign_with_nonce(&ec_key, &order, &d, &z, nonce, rlen)? {
+                return Ok(signature);
+            }
+
+            k = hmac(md, &k, &[&v, &[0x00]])?;
+            v = hmac(md, &k, &[&v])?;
+        }
+    }
+
+    fn sign_random(&self, message: &[u8], rng: &dyn JoseRandom) -> anyhow::Result<Vec<u8>> {
+        let md = self.algorithm.hash_algorithm().message_digest();
+        let ec_key = self.private_key.ec_key()?;
+        let mut ctx = BigNumContext::new()?;
+
+        let mut order = BigNum::new()?;
+        ec_key.group().order(&mut order, &mut ctx)?;
+        let qlen = order.num_bits() as usize;
+        let rlen = self.signature_len() / 2;
+
+        let mut d = ec_key.private_key().to_owned()?;
+        d.set_const_time();
+
+        let z = bits2int(&hash(md, message)?, qlen)?;
+
+        for _ in 0..MAX_NONCE_ATTEMPTS {
+            let t = rng.random_bytes(util::ceiling(qlen, 8));
+            let nonce = bits2int(&t, qlen)?;
+            if let Some(signature) = sign_with_nonce(&ec_key, &order, &d, &z, nonce, rlen)? {
+                return Ok(signature);
+            }
+        }
+        bail!(
+            "The random generator didn't generate a valid nonce in {} attempts.",
+            MAX_NONCE_ATTEMPTS
+        );
+    }
+}
+
+/// The number of nonces drawn from a random generator before signing fails.
+///
+/// A nonce is out of range with a negligible probability for a uniform generator,
+/// so only a broken generator, e.g. one returning fixed bytes, reaches the limit.
+const MAX_NONCE_ATTEMPTS: usize = 16;
+
+/// Return the signature (r, s) for the nonce, or None when the nonce must be
+/// regenerated because it or the signature is out of range.
+fn sign_with_nonce(
+    ec_key: &EcKey<Private>,
+    order: &BigNum,
+    d: &BigNum,
+    z: &BigNum,
+    mut nonce: BigNum,
+    rlen: usize,
+) -> anyhow::Result<Option<Vec<u8>>> {
+    let group = ec_key.group();
+    let mut ctx = BigNumContext::new()?;
+
+    nonce.set_const_time();
+    if nonce.num_bits() == 0 || &nonce >= order {
+        return Ok(None);
+    }
+
+    let mut point = EcPoint::new(group)?;
+    point.mul_generator2(group, &nonce, &mut ctx)?;
+    let mut px = BigNum::new()?;
+    let mut py = BigNum::new()?;
+    point.affine_coordinates_gfp(group, &mut px, &mut py, &mut ctx)?;
+
+    let mut r = BigNum::new()?;
+    r.nnmod(&px, order, &mut ctx)?;
+
+    let mut nonce_inv = BigNum::new()?;
+    nonce_inv.mod_inverse(&nonce, order, &mut ctx)?;
+    let mut rd = BigNum::new()?;
+    rd.mod_mul(&r, d, order, &mut ctx)?;
+    let mut zrd = BigNum::new()?;
+    zrd.mod_add(z, &rd, order, &mut ctx)?;
+    let mut s = BigNum::new()?;
+    s.mod_mul(&nonce_inv, &zrd, order, &mut ctx)?;
+
+    if r.num_bits() == 0 || s.num_bits() == 0 {
+        return Ok(None);
+    }
+
+    let mut signature = r.to_vec_padded(rlen as i32)?;
+    signature.extend_from_slice(&s.to_vec_padded(rlen as i32)?);
+    Ok(Some(signature))
+}
+
+fn bits2int(input: &[u8], qlen: usize) -> anyhow::Result<BigNum> {
+    let mut val = BigNum::from_slice(input)?;
+    let blen = input.len() * 8;
+    if blen > qlen {
+        let mut shifted = BigNum::new()?;
+        shifted.rshift(&val, (blen - qlen) as i32)?;
+        val = shifted;
+    }
+    Ok(val)
+}
+
+fn hmac(md: MessageDigest, key: &[u8], input: &[&[u8]]) -> anyhow::Result<Vec<u8>> {
+    let key = PKey::hmac(key)?;
+    let mut signer = Signer::new(md, &key)?;
+    for val in input {
+        signer.update(val)?;
+    }
+    Ok(signer.sign_to_vec()?)
 }
 
 impl JwsSigner for EcdsaJwsSigner {
@@ -346,6 +532,11 @@ impl JwsSigner for EcdsaJwsSigner {
 
     fn sign(&self, message: &[u8]) -> Result<Vec<u8>, JoseError> {
         (|| -> anyhow::Result<Vec<u8>> {
+            if self.deterministic {
+                let md = self.algorithm.hash_algorithm().message_digest();
+                return self.sign_deterministic(&hash(md, message)?);
+            }
+
             let md = self.algorithm.hash_algorithm().message_digest();
 
             let mut signer = Signer::new(md, &self.private_key)?;
@@ -379,6 +570,41 @@ impl JwsSigner for EcdsaJwsSigner {
         .map_err(|err| JoseError::InvalidSignature(err))
     }
 
+    fn sign_prehashed(&self, digest: &[u8]) -> Result<Vec<u8>, JoseError> {
+        (|| -> anyhow::Result<Vec<u8>> {
+            let hash_len = self.algorithm.hash_algorithm().output_len();
+            if digest.len() != hash_len {
+                bail!(
+                    "The digest length must be {} bytes: {}",
+                    hash_len,
+                    digest.len()
+                );
+            }
+
+            if self.deterministic {
+                return self.sign_deterministic(digest);
+            }
+
+            let ec_key = self.private_key.ec_key()?;
+            let ecdsa_sig = EcdsaSig::sign(digest, &ec_key)?;
+
+            let sep = self.signature_len() / 2;
+            let mut signature = ecdsa_sig.r().to_vec_padded(sep as i32)?;
+            signature.extend_from_slice(&ecdsa_sig.s().to_vec_padded(sep as i32)?);
+            Ok(signature)
+        })()
+        .map_err(JoseError::InvalidSignature)
+    }
+
+    fn sign_with_rng(&self, message: &[u8], rng: &dyn JoseRandom) -> Result<Vec<u8>, JoseError> {
+        if self.deterministic {
+            return self.sign(message);
+        }
+
+        self.sign_random(message, rng)
+            .map_err(JoseError::InvalidSignature)
+    }
+
     fn box_clone(&self) -> Box<dyn JwsSigner> {
         Box::new(self.clone())
     }
@@ -714,6 +940,197 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn sign_and_verify_es256k_generated_jwk() -> Result<()> {
+        let input = b"abcde12345";
+        let alg = EcdsaJwsAlgorithm::Es256k;
+
+        let private_key = Jwk::generate_ec_key(EcCurve::Secp256k1)?;
+        assert_eq!(private_key.curve(), Some("secp256k1"));
+        let private_key = Jwk::from_bytes(private_key.to_string())?;
+        let public_key = private_key.to_public_key()?;
+        let public_key = Jwk::from_bytes(public_key.to_string())?;
+
+        let signer = alg.signer_from_jwk(&private_key)?;
+        let signature = signer.sign(input)?;
+
+        let verifier = alg.verifier_from_jwk(&public_key)?;
+        verifier.verify(input, &signature)?;
+
+        let verifier = alg.verifier_from_der(&public_key.to_der_public_key()?)?;
+        verifier.verify(input, &signature)?;
+
+        let p256_key = Jwk::from_bytes(load_file("jwk/EC_P-256_private.jwk")?)?;
+        match alg.signer_from_jwk(&p256_key) {
+            Err(JoseError::InvalidKeyFormat(err)) => {
+                assert_eq!(
+                    err.to_string(),
+                    "A parameter crv must be secp256k1 but P-256"
+                )
+            }
+            _ => unreachable!(),
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn sign_and_verify_ecdsa_deterministic() -> Result<()> {
+        let input = b"abcde12345";
+
+        for alg in &[
+            EcdsaJwsAlgorithm::Es256,
+            EcdsaJwsAlgorithm::Es384,
+            EcdsaJwsAlgorithm::Es512,
+            EcdsaJwsAlgorithm::Es256k,
+        ] {
+            let key_pair = alg.generate_key_pair()?;
+
+            let mut signer = alg.signer_from_der(key_pair.to_der_private_key())?;
+            signer.set_deterministic(true);
+            let signature1 = signer.sign(input)?;
+            let signature2 = signer.sign(input)?;
+            assert_eq!(signature1.len(), alg.signature_len());
+            assert_eq!(signature1, signature2);
+
+            let verifier = alg.verifier_from_der(key_pair.to_der_public_key())?;
+            verifier.verify(input, &signature1)?;
+
+            signer.set_deterministic(false);
+            assert_ne!(signer.sign(input)?, signature1);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn sign_ecdsa_deterministic_rfc6979() -> Result<()> {
+        // RFC 6979 A.2.5. ECDSA, 256 Bits (Prime Field), With SHA-256, "sample"
+        let alg = EcdsaJwsAlgorithm::Es256;
+        let x = BigNum::from_hex_str(
+            "C9AFA9D845BA75166B5C215767B1D6934E50C3DB36E89B127B8A622B120F6721",
+        )?;
+
+        let group = openssl::ec::EcGroup::from_curve_name(openssl::nid::Nid::X9_62_PRIME256V1)?;
+        let mut ctx = BigNumContext::new()?;
+        let mut public_key = EcPoint::new(&group)?;
+        public_key.mul_generator2(&group, &x, &mut ctx)?;
+        let ec_key = openssl::ec::EcKey::from_private_components(&group, &x, &public_key)?;
+
+        let signer = EcdsaJwsSigner {
+            algorithm: alg,
+            private_key: PKey::from_ec_key(ec_key)?,
+            key_id: None,
+            deterministic: true,
+        };
+        let signature = signer.sign(b"sample")?;
+
+        let r = BigNum::from_hex_str(
+            "EFD48B2AACB6A8FD1140DD9CD45E81D69D2C877B56AAF991C34D0EA84EAF3716",
+        )?;
+        let s = BigNum::from_hex_str(
+            "F7CB1C942D657C41D436C7A1B6E29F65F3E900DBB9AFF4064DC4AB2F843ACDA8",
+        )?;
+        let mut expected = r.to_vec_padded(32)?;
+        expected.extend_from_slice(&s.to_vec_padded(32)?);
+        assert_eq!(signature, expected);
+
+        Ok(())
+    }
+
+    #[test]
+    fn sign_ecdsa_with_rng() -> Result<()> {
+        // RFC 6979 A.2.5. ECDSA, 256 Bits (Prime Field), With SHA-256, "sample"
+        #[derive(Debug)]
+        struct FixedRandom(Vec<u8>);
+
+        impl JoseRandom for FixedRandom {
+            fn fill_bytes(&self, buf: &mut [u8]) {
+                buf.copy_from_slice(&self.0);
+            }
+        }
+
+        let alg = EcdsaJwsAlgorithm::Es256;
+        let x = BigNum::from_hex_str(
+            "C9AFA9D845BA75166B5C215767B1D6934E50C3DB36E89B127B8A622B120F6721",
+        )?;
+        let k = BigNum::from_hex_str(
+            "A6E3C57DD01ABE90086538398355DD4C3B17AA873382B0F24D6129493D8AAD60",
+        )?;
+
+        let group = openssl::ec::EcGroup::from_curve_name(openssl::nid::Nid::X9_62_PRIME256V1)?;
+        let mut ctx = BigNumContext::new()?;
+        let mut public_key = EcPoint::new(&group)?;
+        public_key.mul_generator2(&group, &x, &mut ctx)?;
+        let ec_key = openssl::ec::EcKey::from_private_components(&group, &x, &public_key)?;
+
+        let signer = EcdsaJwsSigner {
+            algorithm: alg,
+            private_key: PKey::from_ec_key(ec_key)?,
+            key_id: None,
+            deterministic: false,
+        };
+        let signature = signer.sign_with_rng(b"sample", &FixedRandom(k.to_vec()))?;
+
+        let r = BigNum::from_hex_str(
+            "EFD48B2AACB6A8FD1140DD9CD45E81D69D2C877B56AAF991C34D0EA84EAF3716",
+        )?;
+        let s = BigNum::from_hex_str(
+            "F7CB1C942D657C41D436C7A1B6E29F65F3E900DBB9AFF4064DC4AB2F843ACDA8",
+        )?;
+        let mut expected = r.to_vec_padded(32)?;
+        expected.extend_from_slice(&s.to_vec_padded(32)?);
+        assert_eq!(signature, expected);
+
+        assert!(matches!(
+            signer.sign_with_rng(b"sample", &FixedRandom(vec![0; 32])),
+            Err(JoseError::InvalidSignature(_))
+        ));
+        assert!(matches!(
+            signer.sign_with_rng(b"sample", &FixedRandom(vec![0xFF; 32])),
+            Err(JoseError::InvalidSignature(_))
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn sign_ecdsa_prehashed() -> Result<()> {
+        let input = b"abcde12345";
+
+        for alg in &[
+            EcdsaJwsAlgorithm::Es256,
+            EcdsaJwsAlgorithm::Es384,
+            EcdsaJwsAlgorithm::Es512,
+            EcdsaJwsAlgorithm::Es256k,
+        ] {
+            let key_pair = alg.generate_key_pair()?;
+            let digest = hash(alg.hash_algorithm().message_digest(), input)?;
+
+            let mut signer = alg.signer_from_der(key_pair.to_der_private_key())?;
+            let verifier = alg.verifier_from_der(key_pair.to_der_public_key())?;
+
+            let signature = signer.sign_prehashed(&digest)?;
+            assert_eq!(signature.len(), alg.signature_len());
+            verifier.verify(input, &signature)?;
+            verifier.verify(input, &signer.sign(input)?)?;
+
+            signer.set_deterministic(true);
+            assert_eq!(signer.sign_prehashed(&digest)?, signer.sign(input)?);
+
+            assert!(matches!(
+                signer.sign_prehashed(&digest[1..]),
+                Err(JoseError::InvalidSignature(_))
+            ));
+            assert!(matches!(
+                signer.sign_prehashed(input),
+                Err(JoseError::InvalidSignature(_))
+            ));
+        }
+
+        Ok(())
+    }
+
     fn load_file(path: &str) -> Result<Vec<u8>> {
         let mut pb = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
         pb.push("data");
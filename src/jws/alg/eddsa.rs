@@ -1,5 +1,6 @@
 use std::fmt::Display;
 use std::ops::Deref;
+use std::str::FromStr;
 
 use anyhow::bail;
 use openssl::pkey::{PKey, Private, Public};
@@ -220,8 +221,7 @@ impl EddsaJwsAlgorithm {
                 None => bail!("A parameter x is required."),
             };
 
-            let pkcs8 = EdKeyPair::to_pkcs8(&x, true, curve);
-            let public_key = PKey::public_key_from_der(&pkcs8)?;
+            let public_key = EdKeyPair::public_key_from_bytes(&x, curve)?;
             let key_id = jwk.key_id().map(|val| val.to_string());
 
             Ok(EddsaJwsVerifier {
@@ -250,6 +250,20 @@ impl Display for EddsaJwsAlgorithm {
     }
 }
 
+impl FromStr for EddsaJwsAlgorithm {
+    type Err = JoseError;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "EdDSA" => Ok(Self::Eddsa),
+            _ => Err(JoseError::UnsupportedSignatureAlgorithm(anyhow::anyhow!(
+                "Unknown algorithm: {}",
+                name
+            ))),
+        }
+    }
+}
+
 impl Deref for EddsaJwsAlgorithm {
     type Target = dyn JwsAlgorithm;
 
@@ -530,6 +544,42 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn sign_and_verify_eddsa_ed448_jwk() -> Result<()> {
+        let input = b"abcde12345";
+
+        let alg = EddsaJwsAlgorithm::Eddsa;
+        let private_key = Jwk::from_bytes(load_file("jwk/OKP_Ed448_private.jwk")?)?;
+        let public_key = Jwk::from_bytes(load_file("jwk/OKP_Ed448_public.jwk")?)?;
+
+        let signer = alg.signer_from_jwk(&private_key)?;
+        let signature = signer.sign(input)?;
+        assert_eq!(signature.len(), 114);
+
+        let verifier = alg.verifier_from_jwk(&public_key)?;
+        verifier.verify(input, &signature)?;
+
+        let ed25519_public_key = Jwk::from_bytes(load_file("jwk/OKP_Ed25519_public.jwk")?)?;
+        let mut invalid_public_key = public_key.clone();
+        invalid_public_key.set_parameter("x", ed25519_public_key.parameter("x").cloned())?;
+        match alg.verifier_from_jwk(&invalid_public_key) {
+            Err(JoseError::InvalidKeyFormat(err)) => {
+                assert_eq!(
+                    err.to_string(),
+                    "The length of the Ed448 public key must be 57: 32"
+                )
+            }
+            result => panic!("unexpected result: {:?}", result),
+        }
+
+        let ed25519_private_key = Jwk::from_bytes(load_file("jwk/OKP_Ed25519_private.jwk")?)?;
+        let mut invalid_private_key = private_key.clone();
+        invalid_private_key.set_parameter("d", ed25519_private_key.parameter("d").cloned())?;
+        assert!(alg.signer_from_jwk(&invalid_private_key).is_err());
+
+        Ok(())
+    }
+
     fn load_file(path: &str) -> Result<Vec<u8>> {
         let mut pb = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
         pb.push("data");
@@ -1,5 +1,6 @@
 use std::fmt::Display;
 use std::ops::Deref;
+use std::str::FromStr;
 
 use anyhow::bail;
 use openssl::pkey::{PKey, Private};
@@ -7,7 +8,7 @@ use openssl::sign::Signer;
 
 use crate::jwk::Jwk;
 use crate::jws::{JwsAlgorithm, JwsSigner, JwsVerifier};
-use crate::util::HashAlgorithm;
+use crate::util::{self, HashAlgorithm};
 use crate::{JoseError, Value};
 
 #[derive(Debug, Eq, PartialEq, Copy, Clone)]
@@ -65,6 +66,34 @@ impl HmacJwsAlgorithm {
         .map_err(|err| JoseError::InvalidKeyFormat(err))
     }
 
+    /// Return a signer from a secret key that may be shorter than the hash output length.
+    ///
+    /// RFC 7518 requires the key to be at least as long as the hash output, so this
+    /// should be used only for compatibility with existing short keys.
+    ///
+    /// # Arguments
+    /// * `data` - A secret key.
+    pub fn signer_from_bytes_allow_weak_key(
+        &self,
+        input: impl AsRef<[u8]>,
+    ) -> Result<HmacJwsSigner, JoseError> {
+        (|| -> anyhow::Result<HmacJwsSigner> {
+            let input = input.as_ref();
+            if input.is_empty() {
+                bail!("Secret key must not be empty.");
+            }
+
+            let private_key = PKey::hmac(input)?;
+
+            Ok(HmacJwsSigner {
+                algorithm: *self,
+                private_key,
+                key_id: None,
+            })
+        })()
+        .map_err(JoseError::InvalidKeyFormat)
+    }
+
     /// Return a signer from a secret key that is formatted by a JWK of oct type.
     ///
     /// # Arguments
@@ -146,6 +175,34 @@ impl HmacJwsAlgorithm {
         .map_err(|err| JoseError::InvalidKeyFormat(err))
     }
 
+    /// Return a verifier from a secret key that may be shorter than the hash output length.
+    ///
+    /// RFC 7518 requires the key to be at least as long as the hash output, so this
+    /// should be used only for compatibility with existing short keys.
+    ///
+    /// # Arguments
+    /// * `input` - A secret key.
+    pub fn verifier_from_bytes_allow_weak_key(
+        &self,
+        input: impl AsRef<[u8]>,
+    ) -> Result<HmacJwsVerifier, JoseError> {
+        (|| -> anyhow::Result<HmacJwsVerifier> {
+            let input = input.as_ref();
+            if input.is_empty() {
+                bail!("Secret key must not be empty.");
+            }
+
+            let private_key = PKey::hmac(input)?;
+
+            Ok(HmacJwsVerifier {
+                algorithm: *self,
+                private_key,
+                key_id: None,
+            })
+        })()
+        .map_err(JoseError::InvalidKeyFormat)
+    }
+
     /// Return a verifier from a secret key that is formatted by a JWK of oct type.
     ///
     /// # Arguments
@@ -226,6 +283,22 @@ impl Display for HmacJwsAlgorithm {
     }
 }
 
+impl FromStr for HmacJwsAlgorithm {
+    type Err = JoseError;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "HS256" => Ok(Self::Hs256),
+            "HS384" => Ok(Self::Hs384),
+            "HS512" => Ok(Self::Hs512),
+            _ => Err(JoseError::UnsupportedSignatureAlgorithm(anyhow::anyhow!(
+                "Unknown algorithm: {}",
+                name
+            ))),
+        }
+    }
+}
+
 impl Deref for HmacJwsAlgorithm {
     type Target = dyn JwsAlgorithm;
 
@@ -328,7 +401,7 @@ impl JwsVerifier for HmacJwsVerifier {
             let mut signer = Signer::new(md, &self.private_key)?;
             signer.update(message)?;
             let new_signature = signer.sign_to_vec()?;
-            if new_signature.as_slice() != signature {
+            if !util::constant_time_eq(&new_signature, signature) {
                 bail!("Failed to verify.");
             }
             Ok(())
@@ -423,6 +496,48 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn sign_and_verify_hmac_key_length() -> Result<()> {
+        let input = b"abcde12345";
+
+        for alg in &[
+            HmacJwsAlgorithm::Hs256,
+            HmacJwsAlgorithm::Hs384,
+            HmacJwsAlgorithm::Hs512,
+        ] {
+            let key_len = alg.hash_algorithm().output_len();
+
+            let private_key = util::random_bytes(key_len);
+            let signer = alg.signer_from_bytes(&private_key)?;
+            let signature = signer.sign(input)?;
+            let verifier = alg.verifier_from_bytes(&private_key)?;
+            verifier.verify(input, &signature)?;
+
+            let private_key = util::random_bytes(key_len - 1);
+            match alg.signer_from_bytes(&private_key) {
+                Err(JoseError::InvalidKeyFormat(_)) => {}
+                _ => unreachable!(),
+            }
+            match alg.verifier_from_bytes(&private_key) {
+                Err(JoseError::InvalidKeyFormat(_)) => {}
+                _ => unreachable!(),
+            }
+            match alg.signer_from_jwk(&alg.to_jwk(&private_key)) {
+                Err(JoseError::InvalidKeyFormat(_)) => {}
+                _ => unreachable!(),
+            }
+
+            let signer = alg.signer_from_bytes_allow_weak_key(&private_key)?;
+            let signature = signer.sign(input)?;
+            let verifier = alg.verifier_from_bytes_allow_weak_key(&private_key)?;
+            verifier.verify(input, &signature)?;
+
+            assert!(alg.signer_from_bytes_allow_weak_key(b"").is_err());
+        }
+
+        Ok(())
+    }
+
     fn load_file(path: &str) -> Result<Vec<u8>> {
         let mut pb = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
         pb.push("data");
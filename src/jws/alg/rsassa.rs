@@ -1,8 +1,12 @@
 use std::fmt::Display;
 use std::ops::Deref;
+use std::str::FromStr;
 
 use anyhow::bail;
+use openssl::md::Md;
 use openssl::pkey::{PKey, Private, Public};
+use openssl::pkey_ctx::PkeyCtx;
+use openssl::rsa::Padding;
 use openssl::sign::{Signer, Verifier};
 
 use crate::jwk::{alg::rsa::RsaKeyPair, Jwk};
@@ -331,6 +335,22 @@ impl Display for RsassaJwsAlgorithm {
     }
 }
 
+impl FromStr for RsassaJwsAlgorithm {
+    type Err = JoseError;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "RS256" => Ok(Self::Rs256),
+            "RS384" => Ok(Self::Rs384),
+            "RS512" => Ok(Self::Rs512),
+            _ => Err(JoseError::UnsupportedSignatureAlgorithm(anyhow::anyhow!(
+                "Unknown algorithm: {}",
+                name
+            ))),
+        }
+    }
+}
+
 impl Deref for RsassaJwsAlgorithm {
     type Target = dyn JwsAlgorithm;
 
@@ -384,6 +404,33 @@ impl JwsSigner for RsassaJwsSigner {
         .map_err(|err| JoseError::InvalidSignature(err))
     }
 
+    fn sign_prehashed(&self, digest: &[u8]) -> Result<Vec<u8>, JoseError> {
+        (|| -> anyhow::Result<Vec<u8>> {
+            let hash_algorithm = self.algorithm.hash_algorithm();
+            if digest.len() != hash_algorithm.output_len() {
+                bail!(
+                    "The digest length must be {} bytes: {}",
+                    hash_algorithm.output_len(),
+                    digest.len()
+                );
+            }
+
+            let md = match Md::from_nid(hash_algorithm.message_digest().type_()) {
+                Some(val) => val,
+                None => bail!("Unsupported hash algorithm"),
+            };
+
+            let mut ctx = PkeyCtx::new(&self.private_key)?;
+            ctx.sign_init()?;
+            ctx.set_rsa_padding(Padding::PKCS1)?;
+            ctx.set_signature_md(md)?;
+            let mut signature = Vec::new();
+            ctx.sign_to_vec(digest, &mut signature)?;
+            Ok(signature)
+        })()
+        .map_err(JoseError::InvalidSignature)
+    }
+
     fn box_clone(&self) -> Box<dyn JwsSigner> {
         Box::new(self.clone())
     }
@@ -676,6 +723,38 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn sign_and_verify_rsassa_jwt_with_algorithm() -> Result<()> {
+        let input = b"abcde12345";
+
+        let mut private_key = Jwk::from_bytes(load_file("jwk/RSA_private.jwk")?)?;
+        let mut public_key = Jwk::from_bytes(load_file("jwk/RSA_public.jwk")?)?;
+        private_key.set_algorithm("RS384");
+        public_key.set_algorithm("RS384");
+
+        let alg = RsassaJwsAlgorithm::Rs384;
+        let signer = alg.signer_from_jwk(&private_key)?;
+        let signature = signer.sign(input)?;
+        let verifier = alg.verifier_from_jwk(&public_key)?;
+        verifier.verify(input, &signature)?;
+
+        let alg = RsassaJwsAlgorithm::Rs256;
+        match alg.signer_from_jwk(&private_key) {
+            Err(JoseError::InvalidKeyFormat(err)) => {
+                assert_eq!(err.to_string(), "A parameter alg must be RS256 but RS384")
+            }
+            result => panic!("unexpected result: {:?}", result.map(|_| ())),
+        }
+        match alg.verifier_from_jwk(&public_key) {
+            Err(JoseError::InvalidKeyFormat(err)) => {
+                assert_eq!(err.to_string(), "A parameter alg must be RS256 but RS384")
+            }
+            result => panic!("unexpected result: {:?}", result.map(|_| ())),
+        }
+
+        Ok(())
+    }
+
     #[test]
     fn sign_and_verify_rsassa_mismatch() -> Result<()> {
         let input = b"abcde12345";
@@ -700,6 +779,31 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn sign_rsassa_prehashed() -> Result<()> {
+        let input = b"abcde12345";
+
+        for alg in &[
+            RsassaJwsAlgorithm::Rs256,
+            RsassaJwsAlgorithm::Rs384,
+            RsassaJwsAlgorithm::Rs512,
+        ] {
+            let private_key = load_file("jwk/RSA_private.jwk")?;
+            let digest = openssl::hash::hash(alg.hash_algorithm().message_digest(), input)?;
+
+            let signer = alg.signer_from_jwk(&Jwk::from_bytes(&private_key)?)?;
+            let signature = signer.sign_prehashed(&digest)?;
+            assert_eq!(signature, signer.sign(input)?);
+
+            assert!(matches!(
+                signer.sign_prehashed(&digest[1..]),
+                Err(JoseError::InvalidSignature(_))
+            ));
+        }
+
+        Ok(())
+    }
+
     fn load_file(path: &str) -> Result<Vec<u8>> {
         let mut pb = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
         pb.push("data");
@@ -1,16 +1,21 @@
 use std::fmt::Display;
 use std::ops::Deref;
+use std::str::FromStr;
 
 use anyhow::bail;
+use openssl::hash::{hash, Hasher, MessageDigest};
+use openssl::md::Md;
 use openssl::pkey::{PKey, Private, Public};
-use openssl::rsa::Rsa;
+use openssl::pkey_ctx::PkeyCtx;
+use openssl::rsa::{Padding, Rsa};
+use openssl::sign::RsaPssSaltlen;
 use openssl::sign::{Signer, Verifier};
 
 use crate::jwk::{alg::rsa::RsaKeyPair, alg::rsapss::RsaPssKeyPair, Jwk};
 use crate::jws::{JwsAlgorithm, JwsSigner, JwsVerifier};
 use crate::util::der::{DerBuilder, DerType};
 use crate::util::{self, HashAlgorithm};
-use crate::{JoseError, Value};
+use crate::{JoseError, JoseRandom, Value};
 
 #[derive(Debug, Eq, PartialEq, Copy, Clone)]
 pub enum RsassaPssJwsAlgorithm {
@@ -119,6 +124,7 @@ impl RsassaPssJwsAlgorithm {
             algorithm: self.clone(),
             private_key: key_pair.into_private_key(),
             key_id: None,
+            salt_len: None,
         })
     }
 
@@ -141,6 +147,7 @@ impl RsassaPssJwsAlgorithm {
             algorithm: self.clone(),
             private_key: key_pair.into_private_key(),
             key_id: None,
+            salt_len: None,
         })
     }
 
@@ -181,6 +188,7 @@ impl RsassaPssJwsAlgorithm {
                 algorithm: self.clone(),
                 private_key,
                 key_id,
+                salt_len: None,
             })
         })()
         .map_err(|err| JoseError::InvalidKeyFormat(err))
@@ -245,6 +253,7 @@ impl RsassaPssJwsAlgorithm {
                 algorithm: self.clone(),
                 public_key,
                 key_id: None,
+                salt_len: None,
             })
         })()
         .map_err(|err| JoseError::InvalidKeyFormat(err))
@@ -306,6 +315,7 @@ impl RsassaPssJwsAlgorithm {
                 algorithm: self.clone(),
                 public_key,
                 key_id: None,
+                salt_len: None,
             })
         })()
         .map_err(|err| JoseError::InvalidKeyFormat(err))
@@ -372,6 +382,7 @@ impl RsassaPssJwsAlgorithm {
                 algorithm: self.clone(),
                 public_key,
                 key_id,
+                salt_len: None,
             })
         })()
         .map_err(|err| JoseError::InvalidKeyFormat(err))
@@ -414,6 +425,22 @@ impl Display for RsassaPssJwsAlgorithm {
     }
 }
 
+impl FromStr for RsassaPssJwsAlgorithm {
+    type Err = JoseError;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "PS256" => Ok(Self::Ps256),
+            "PS384" => Ok(Self::Ps384),
+            "PS512" => Ok(Self::Ps512),
+            _ => Err(JoseError::UnsupportedSignatureAlgorithm(anyhow::anyhow!(
+                "Unknown algorithm: {}",
+                name
+            ))),
+        }
+    }
+}
+
 impl Deref for RsassaPssJwsAlgorithm {
     type Target = dyn JwsAlgorithm;
 
@@ -427,6 +454,7 @@ pub struct RsassaPssJwsSigner {
     algorithm: RsassaPssJwsAlgorithm,
     private_key: PKey<Private>,
     key_id: Option<String>,
+    salt_len: Option<usize>,
 }
 
 impl RsassaPssJwsSigner {
@@ -437,6 +465,29 @@ impl RsassaPssJwsSigner {
     pub fn remove_key_id(&mut self) {
         self.key_id = None;
     }
+
+    /// Set a salt length that is used instead of the default one (the hash length).
+    ///
+    /// RFC 7518 requires the salt length to be equal to the hash length, so a JWS
+    /// signed with other salt length may not be verified by other implementations.
+    /// And the salt length cannot be less than the default one, because the key is
+    /// restricted to the default salt length as the minimum.
+    ///
+    /// An error is returned when the salt length is less than the default one, or the
+    /// salt doesn't fit in the encoded message of the key (RFC 8017 Section 9.1.1).
+    ///
+    /// # Arguments
+    /// * `salt_len` - A salt length in bytes
+    pub fn set_salt_len(&mut self, salt_len: usize) -> Result<(), JoseError> {
+        check_salt_len(&self.algorithm, self.private_key.bits(), salt_len)?;
+        self.salt_len = Some(salt_len);
+        Ok(())
+    }
+
+    /// Return the salt length if it is set.
+    pub fn salt_len(&self) -> Option<usize> {
+        self.salt_len
+    }
 }
 
 impl JwsSigner for RsassaPssJwsSigner {
@@ -460,6 +511,9 @@ impl JwsSigner for RsassaPssJwsSigner {
             let md = self.algorithm.hash_algorithm().message_digest();
 
             let mut signer = Signer::new(md, &self.private_key)?;
+            if let Some(salt_len) = self.salt_len {
+                signer.set_rsa_pss_saltlen(RsaPssSaltlen::custom(salt_len as i32))?;
+            }
             signer.update(message)?;
             let signature = signer.sign_to_vec()?;
             Ok(signature)
@@ -467,11 +521,115 @@ impl JwsSigner for RsassaPssJwsSigner {
         .map_err(|err| JoseError::InvalidSignature(err))
     }
 
+    fn sign_with_rng(&self, message: &[u8], rng: &dyn JoseRandom) -> Result<Vec<u8>, JoseError> {
+        (|| -> anyhow::Result<Vec<u8>> {
+            let md = self.algorithm.hash_algorithm().message_digest();
+            let salt_len = match self.salt_len {
+                Some(val) => val,
+                None => self.algorithm.salt_len() as usize,
+            };
+            let salt = rng.random_bytes(salt_len);
+
+            let rsa = self.private_key.rsa()?;
+            let em = emsa_pss_encode(md, message, &salt, rsa.n().num_bits() as usize - 1)?;
+
+            let size = rsa.size() as usize;
+            let mut input = vec![0; size - em.len()];
+            input.extend_from_slice(&em);
+            let mut signature = vec![0; size];
+            let len = rsa.private_encrypt(&input, &mut signature, Padding::NONE)?;
+            signature.truncate(len);
+            Ok(signature)
+        })()
+        .map_err(JoseError::InvalidSignature)
+    }
+
+    fn sign_prehashed(&self, digest: &[u8]) -> Result<Vec<u8>, JoseError> {
+        (|| -> anyhow::Result<Vec<u8>> {
+            let hash_len = self.algorithm.hash_algorithm().output_len();
+            if digest.len() != hash_len {
+                bail!(
+                    "The digest length must be {} bytes: {}",
+                    hash_len,
+                    digest.len()
+                );
+            }
+
+            let md = match Md::from_nid(self.algorithm.hash_algorithm().message_digest().type_()) {
+                Some(val) => val,
+                None => bail!("Unsupported hash algorithm"),
+            };
+            let salt_len = match self.salt_len {
+                Some(val) => RsaPssSaltlen::custom(val as i32),
+                None => RsaPssSaltlen::DIGEST_LENGTH,
+            };
+
+            let mut ctx = PkeyCtx::new(&self.private_key)?;
+            ctx.sign_init()?;
+            ctx.set_rsa_padding(Padding::PKCS1_PSS)?;
+            ctx.set_signature_md(md)?;
+            ctx.set_rsa_mgf1_md(md)?;
+            ctx.set_rsa_pss_saltlen(salt_len)?;
+            let mut signature = Vec::new();
+            ctx.sign_to_vec(digest, &mut signature)?;
+            Ok(signature)
+        })()
+        .map_err(JoseError::InvalidSignature)
+    }
+
     fn box_clone(&self) -> Box<dyn JwsSigner> {
         Box::new(self.clone())
     }
 }
 
+/// Encode the message by EMSA-PSS with MGF1 of the same hash (RFC 8017 9.1.1).
+fn emsa_pss_encode(
+    md: MessageDigest,
+    message: &[u8],
+    salt: &[u8],
+    em_bits: usize,
+) -> anyhow::Result<Vec<u8>> {
+    let h_len = md.size();
+    let em_len = util::ceiling(em_bits, 8);
+    if em_len < h_len + salt.len() + 2 {
+        bail!("The salt length is too long for the key: {}", salt.len());
+    }
+
+    let m_hash = hash(md, message)?;
+    let mut hasher = Hasher::new(md)?;
+    hasher.update(&[0; 8])?;
+    hasher.update(&m_hash)?;
+    hasher.update(salt)?;
+    let h = hasher.finish()?;
+
+    let db_len = em_len - h_len - 1;
+    let mut db = vec![0; db_len - salt.len() - 1];
+    db.push(0x01);
+    db.extend_from_slice(salt);
+
+    let mut counter: u32 = 0;
+    let mut offset = 0;
+    while offset < db_len {
+        let mut hasher = Hasher::new(md)?;
+        hasher.update(&h)?;
+        hasher.update(&counter.to_be_bytes())?;
+        for val in hasher.finish()?.iter() {
+            if offset == db_len {
+                break;
+            }
+            db[offset] ^= val;
+            offset += 1;
+        }
+        counter += 1;
+    }
+    db[0] &= 0xFF >> (8 * em_len - em_bits);
+
+    let mut em = db;
+    em.extend_from_slice(&h);
+    em.push(0xBC);
+    Ok(em)
+}
+
 impl Deref for RsassaPssJwsSigner {
     type Target = dyn JwsSigner;
 
@@ -485,6 +643,7 @@ pub struct RsassaPssJwsVerifier {
     algorithm: RsassaPssJwsAlgorithm,
     public_key: PKey<Public>,
     key_id: Option<String>,
+    salt_len: Option<usize>,
 }
 
 impl RsassaPssJwsVerifier {
@@ -495,6 +654,28 @@ impl RsassaPssJwsVerifier {
     pub fn remove_key_id(&mut self) {
         self.key_id = None;
     }
+
+    /// Set a salt length that the signature must be generated with instead of the
+    /// default one (the hash length).
+    ///
+    /// By default, only a signature with the default salt length is accepted.
+    /// The salt length cannot be less than the default one, as for the signer.
+    ///
+    /// An error is returned when the salt length is less than the default one, or the
+    /// salt doesn't fit in the encoded message of the key (RFC 8017 Section 9.1.1).
+    ///
+    /// # Arguments
+    /// * `salt_len` - A salt length in bytes
+    pub fn set_salt_len(&mut self, salt_len: usize) -> Result<(), JoseError> {
+        check_salt_len(&self.algorithm, self.public_key.bits(), salt_len)?;
+        self.salt_len = Some(salt_len);
+        Ok(())
+    }
+
+    /// Return the salt length if it is set.
+    pub fn salt_len(&self) -> Option<usize> {
+        self.salt_len
+    }
 }
 
 impl JwsVerifier for RsassaPssJwsVerifier {
@@ -514,6 +695,9 @@ impl JwsVerifier for RsassaPssJwsVerifier {
             let md = self.algorithm.hash_algorithm().message_digest();
 
             let mut verifier = Verifier::new(md, &self.public_key)?;
+            if let Some(salt_len) = self.salt_len {
+                verifier.set_rsa_pss_saltlen(RsaPssSaltlen::custom(salt_len as i32))?;
+            }
             verifier.update(message)?;
             if !verifier.verify(signature)? {
                 bail!("The signature does not match.");
@@ -536,6 +720,35 @@ impl Deref for RsassaPssJwsVerifier {
     }
 }
 
+/// Check that the salt is not shorter than the default one and fits in the encoded
+/// message of a key.
+///
+/// The encoded message length is ceil((modBits - 1) / 8), and it must be at least
+/// the hash length + the salt length + 2 (RFC 8017 Section 9.1.1).
+fn check_salt_len(
+    algorithm: &RsassaPssJwsAlgorithm,
+    bits: u32,
+    salt_len: usize,
+) -> Result<(), JoseError> {
+    if salt_len < algorithm.salt_len() as usize {
+        return Err(JoseError::InvalidKeyFormat(anyhow::anyhow!(
+            "The salt length must be {} or more: {}",
+            algorithm.salt_len(),
+            salt_len
+        )));
+    }
+
+    let em_len = (bits as usize + 6) / 8;
+    let hash_len = algorithm.hash_algorithm().output_len();
+    match em_len.checked_sub(hash_len + 2) {
+        Some(max_salt_len) if salt_len <= max_salt_len => Ok(()),
+        _ => Err(JoseError::InvalidKeyFormat(anyhow::anyhow!(
+            "The salt length is too large for the key: {}",
+            salt_len
+        ))),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -544,6 +757,8 @@ mod tests {
     use std::fs;
     use std::path::PathBuf;
 
+    use crate::OsRandom;
+
     #[test]
     fn sign_and_verify_rsassa_pss_generated_der() -> Result<()> {
         let input = b"abcde12345";
@@ -775,6 +990,91 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn sign_and_verify_rsassa_pss_with_salt_len() -> Result<()> {
+        let input = b"abcde12345";
+
+        for alg in &[
+            RsassaPssJwsAlgorithm::Ps256,
+            RsassaPssJwsAlgorithm::Ps384,
+            RsassaPssJwsAlgorithm::Ps512,
+        ] {
+            let key_pair = alg.generate_key_pair(2048)?;
+
+            let mut signer = alg.signer_from_der(key_pair.to_der_private_key())?;
+            signer.set_salt_len(alg.salt_len() as usize + 8)?;
+            let signature = signer.sign(input)?;
+
+            let mut verifier = alg.verifier_from_der(key_pair.to_der_public_key())?;
+            verifier
+                .verify(input, &signature)
+                .expect_err("Unmatched salt length did not fail");
+
+            verifier.set_salt_len(alg.salt_len() as usize + 8)?;
+            verifier.verify(input, &signature)?;
+
+            // 2048-bit key: emLen (256) - hLen - 2
+            let max_salt_len = 256 - alg.hash_algorithm().output_len() - 2;
+            signer.set_salt_len(max_salt_len)?;
+            verifier.set_salt_len(max_salt_len)?;
+            verifier.verify(input, &signer.sign(input)?)?;
+            verifier.verify(input, &signer.sign_with_rng(input, &OsRandom)?)?;
+
+            for salt_len in [
+                0,
+                alg.salt_len() as usize - 1,
+                max_salt_len + 1,
+                i32::MAX as usize + 1,
+                usize::MAX,
+            ] {
+                assert!(matches!(
+                    signer.set_salt_len(salt_len),
+                    Err(JoseError::InvalidKeyFormat(_))
+                ));
+                assert!(matches!(
+                    verifier.set_salt_len(salt_len),
+                    Err(JoseError::InvalidKeyFormat(_))
+                ));
+            }
+            assert_eq!(signer.salt_len(), Some(max_salt_len));
+            assert_eq!(verifier.salt_len(), Some(max_salt_len));
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn sign_rsassa_pss_prehashed() -> Result<()> {
+        let input = b"abcde12345";
+
+        for alg in &[
+            RsassaPssJwsAlgorithm::Ps256,
+            RsassaPssJwsAlgorithm::Ps384,
+            RsassaPssJwsAlgorithm::Ps512,
+        ] {
+            let key_pair = alg.generate_key_pair(2048)?;
+            let digest = openssl::hash::hash(alg.hash_algorithm().message_digest(), input)?;
+
+            let mut signer = alg.signer_from_der(key_pair.to_der_private_key())?;
+            let signature = signer.sign_prehashed(&digest)?;
+
+            let mut verifier = alg.verifier_from_der(key_pair.to_der_public_key())?;
+            verifier.verify(input, &signature)?;
+
+            let salt_len = alg.hash_algorithm().output_len() + 8;
+            signer.set_salt_len(salt_len)?;
+            verifier.set_salt_len(salt_len)?;
+            verifier.verify(input, &signer.sign_prehashed(&digest)?)?;
+
+            assert!(matches!(
+                signer.sign_prehashed(&digest[1..]),
+                Err(JoseError::InvalidSignature(_))
+            ));
+        }
+
+        Ok(())
+    }
+
     fn load_file(path: &str) -> Result<Vec<u8>> {
         let mut pb = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
         pb.push("data");
@@ -1,6 +1,6 @@
 use std::fmt::Debug;
 
-use crate::JoseError;
+use crate::{JoseError, JoseRandom};
 
 pub trait JwsAlgorithm: Debug + Send + Sync {
     /// Return the "alg" (algorithm) header parameter value of JWS.
@@ -23,6 +23,18 @@ impl Clone for Box<dyn JwsAlgorithm> {
     }
 }
 
+/// A signer of JWS.
+///
+/// This trait can be implemented outside of this crate, for example to sign
+/// with a key that is stored in a HSM. The implementation must satisfy the
+/// following contract:
+///
+/// * `algorithm` returns the algorithm whose name is used as the "alg" header claim.
+/// * `key_id` returns the key ID that is used as the "kid" header claim, if any.
+/// * `signature_len` returns the (maximum) length of the signature, which is used
+///   only for estimating the output size.
+/// * `sign` returns the signature of the message in the form that is defined by the
+///   algorithm (e.g. the R || S form for ECDSA) without any encoding.
 pub trait JwsSigner: Debug + Send + Sync {
     /// Return the source algorithm instance.
     fn algorithm(&self) -> &dyn JwsAlgorithm;
@@ -41,6 +53,37 @@ pub trait JwsSigner: Debug + Send + Sync {
     /// * `message` - The message data to sign.
     fn sign(&self, message: &[u8]) -> Result<Vec<u8>, JoseError>;
 
+    /// Return a signature of the data, drawing the random values of the algorithm from the generator.
+    ///
+    /// The default implementation ignores the generator and calls `sign`. The
+    /// RSASSA-PSS signer uses it for the salt, and the ECDSA signer uses it for
+    /// the nonce unless it is deterministic.
+    ///
+    /// # Arguments
+    ///
+    /// * `message` - The message data to sign.
+    /// * `rng` - The random generator.
+    fn sign_with_rng(&self, message: &[u8], rng: &dyn JoseRandom) -> Result<Vec<u8>, JoseError> {
+        let _ = rng;
+        self.sign(message)
+    }
+
+    /// Return a signature of the digest that is already hashed by the hash algorithm of the algorithm.
+    ///
+    /// The default implementation returns an error. The ECDSA, RSASSA-PKCS1-v1_5 and
+    /// RSASSA-PSS signers support it, and they return an error when the digest length
+    /// is not the output length of the hash algorithm.
+    ///
+    /// # Arguments
+    ///
+    /// * `digest` - The digest of the message data to sign.
+    fn sign_prehashed(&self, _digest: &[u8]) -> Result<Vec<u8>, JoseError> {
+        Err(JoseError::UnsupportedSignatureAlgorithm(anyhow::anyhow!(
+            "A prehashed digest cannot be signed by the algorithm: {}",
+            self.algorithm().name()
+        )))
+    }
+
     fn box_clone(&self) -> Box<dyn JwsSigner>;
 }
 
@@ -50,6 +93,10 @@ impl Clone for Box<dyn JwsSigner> {
     }
 }
 
+/// A verifier of JWS.
+///
+/// Like [`JwsSigner`], this trait can be implemented outside of this crate.
+/// `verify` must return a error when the signature does not match the message.
 pub trait JwsVerifier: Debug + Send + Sync {
     /// Return the source algrithm instance.
     fn algorithm(&self) -> &dyn JwsAlgorithm;
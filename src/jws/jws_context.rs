@@ -1,21 +1,63 @@
+use std::borrow::Cow;
 use std::collections::BTreeSet;
 use std::fmt::Debug;
+use std::sync::Arc;
 
 use anyhow::bail;
 
 use crate::jws::{JwsHeader, JwsHeaderSet, JwsSigner, JwsVerifier};
 use crate::util;
-use crate::{JoseError, Map, Value};
+use crate::{JoseError, JoseRandom, Value};
 
-#[derive(Debug, Eq, PartialEq, Clone)]
+/// The payload and the verification result of each signature.
+type SignatureResults = (Vec<u8>, Vec<(JwsHeader, bool)>);
+
+/// The payload and the merged header, signing input and signature of each signature.
+type ParsedSignatures = (Vec<u8>, Vec<(JwsHeader, String, Vec<u8>)>);
+
+#[derive(Debug, Clone)]
 pub struct JwsContext {
     acceptable_criticals: BTreeSet<String>,
+    accepted_algorithms: Option<BTreeSet<String>>,
+    rng: Option<Arc<dyn JoseRandom>>,
 }
 
 impl JwsContext {
     pub fn new() -> Self {
         Self {
             acceptable_criticals: BTreeSet::new(),
+            accepted_algorithms: None,
+            rng: None,
+        }
+    }
+
+    /// Return the random generator for the signature algorithms.
+    pub fn rng(&self) -> Option<&dyn JoseRandom> {
+        self.rng.as_deref()
+    }
+
+    /// Set a random generator for the signature algorithms.
+    ///
+    /// When a generator is set, the signers are called by `sign_with_rng`, so the
+    /// salt of RSASSA-PSS and the nonce of ECDSA are drawn from it. By default,
+    /// the signers use the random generator of OpenSSL.
+    ///
+    /// # Arguments
+    ///
+    /// * `rng` - a random generator
+    pub fn set_rng(&mut self, rng: Arc<dyn JoseRandom>) {
+        self.rng = Some(rng);
+    }
+
+    /// Remove the random generator for the signature algorithms.
+    pub fn remove_rng(&mut self) {
+        self.rng = None;
+    }
+
+    fn sign_message(&self, message: &[u8], signer: &dyn JwsSigner) -> Result<Vec<u8>, JoseError> {
+        match &self.rng {
+            Some(rng) => signer.sign_with_rng(message, rng.as_ref()),
+            None => signer.sign(message),
         }
     }
 
@@ -46,6 +88,37 @@ impl JwsContext {
         self.acceptable_criticals.remove(name);
     }
 
+    /// Test a alg header claim value is accepted by the deserialization.
+    ///
+    /// Any algorithm is accepted unless set_accepted_algorithms is called.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - a alg header claim value
+    pub fn is_accepted_algorithm(&self, name: &str) -> bool {
+        match &self.accepted_algorithms {
+            Some(val) => val.contains(name),
+            None => true,
+        }
+    }
+
+    /// Set the alg header claim values that are accepted by the deserialization.
+    ///
+    /// A JWS that has another algorithm is rejected before the selector is called,
+    /// e.g. set only "ES256" to reject a "HS256" token that is signed with the public key.
+    ///
+    /// # Arguments
+    ///
+    /// * `names` - the accepted alg header claim values
+    pub fn set_accepted_algorithms(&mut self, names: &[&str]) {
+        self.accepted_algorithms = Some(names.iter().map(|val| val.to_string()).collect());
+    }
+
+    /// Remove the restriction of alg header claim values.
+    pub fn remove_accepted_algorithms(&mut self) {
+        self.accepted_algorithms = None;
+    }
+
     /// Return a representation of the data that is formatted by compact serialization.
     ///
     /// # Arguments
@@ -75,6 +148,57 @@ impl JwsContext {
         header: &JwsHeader,
         selector: F,
     ) -> Result<String, JoseError>
+    where
+        F: Fn(&JwsHeader) -> Option<&'a dyn JwsSigner>,
+    {
+        self.serialize_compact_with_detached(payload, header, None, selector, false)
+    }
+
+    /// Return a representation of the data that is formatted by compact serialization
+    /// with the protected header that is given as a JSON string.
+    ///
+    /// The header is encoded as it is without re-serialization, so the member order
+    /// and the whitespace are kept byte-for-byte. Therefore the header must already have
+    /// the "alg" header claim of the signer, and the "kid" header claim if the signer has
+    /// a key ID.
+    ///
+    /// # Arguments
+    ///
+    /// * `payload` - The payload data.
+    /// * `header` - The JWS header claims as a JSON string.
+    /// * `signer` - The JWS signer.
+    pub fn serialize_compact_with_raw_header(
+        &self,
+        payload: &[u8],
+        header: &str,
+        signer: &dyn JwsSigner,
+    ) -> Result<String, JoseError> {
+        let parsed = (|| -> anyhow::Result<JwsHeader> {
+            let map = util::parse_json_object(header.as_bytes())?;
+            Ok(JwsHeader::from_map(map)?)
+        })()
+        .map_err(|err| match err.downcast::<JoseError>() {
+            Ok(err) => err,
+            Err(err) => JoseError::InvalidJwsFormat(err),
+        })?;
+
+        self.serialize_compact_with_detached(
+            payload,
+            &parsed,
+            Some(header.as_bytes()),
+            |_header| Some(signer),
+            false,
+        )
+    }
+
+    fn serialize_compact_with_detached<'a, F>(
+        &self,
+        payload: &[u8],
+        header: &JwsHeader,
+        raw_header: Option<&[u8]>,
+        selector: F,
+        detached: bool,
+    ) -> Result<String, JoseError>
     where
         F: Fn(&JwsHeader) -> Option<&'a dyn JwsSigner>,
     {
@@ -93,15 +217,34 @@ impl JwsContext {
                 None => bail!("A signer is not found."),
             };
 
-            let mut header = header.claims_set().clone();
-            header.insert(
-                "alg".to_string(),
-                Value::String(signer.algorithm().name().to_string()),
-            );
-            if let Some(key_id) = signer.key_id() {
-                header.insert("kid".to_string(), Value::String(key_id.to_string()));
-            }
-            let header_bytes = serde_json::to_vec(&header)?;
+            let header_bytes = match raw_header {
+                Some(val) => {
+                    match header.algorithm() {
+                        Some(val) if val == signer.algorithm().name() => {}
+                        Some(_) => bail!("A signer is unmatched."),
+                        None => bail!("The JWS alg header claim is required."),
+                    }
+                    if let Some(key_id) = signer.key_id() {
+                        match header.key_id() {
+                            Some(val) if val == key_id => {}
+                            Some(val) => bail!("The JWS kid header claim is mismatched: {}", val),
+                            None => bail!("The JWS kid header claim is required."),
+                        }
+                    }
+                    Cow::Borrowed(val)
+                }
+                None => {
+                    let mut header = header.claims_set().clone();
+                    header.insert(
+                        "alg".to_string(),
+                        Value::String(signer.algorithm().name().to_string()),
+                    );
+                    if let Some(key_id) = signer.key_id() {
+                        header.insert("kid".to_string(), Value::String(key_id.to_string()));
+                    }
+                    Cow::Owned(serde_json::to_vec(&header)?)
+                }
+            };
 
             let mut capacity = 2;
             capacity += util::ceiling(header_bytes.len() * 4, 3);
@@ -115,18 +258,22 @@ impl JwsContext {
             let mut message = String::with_capacity(capacity);
             base64::encode_config_buf(header_bytes, base64::URL_SAFE_NO_PAD, &mut message);
             message.push_str(".");
+            let header_len = message.len();
             if b64 {
                 base64::encode_config_buf(payload, base64::URL_SAFE_NO_PAD, &mut message);
             } else {
                 let payload = std::str::from_utf8(payload)?;
-                if payload.contains(".") {
+                if !detached && payload.contains('.') {
                     bail!("A JWS payload cannot contain dot.");
                 }
                 message.push_str(payload);
             }
 
-            let signature = signer.sign(message.as_bytes())?;
+            let signature = self.sign_message(message.as_bytes(), signer)?;
 
+            if detached {
+                message.truncate(header_len);
+            }
             message.push_str(".");
             base64::encode_config_buf(signature, base64::URL_SAFE_NO_PAD, &mut message);
 
@@ -138,6 +285,68 @@ impl JwsContext {
         })
     }
 
+    /// Return the JWS signing input (the ASCII string of BASE64URL(header) + "." + payload)
+    /// that is computed in the same way as the compact serialization.
+    ///
+    /// The header is used as it is, so it should have the "alg" and "kid" header claims
+    /// that a signer would add. The payload is not encoded when the "b64" header claim
+    /// is false and is marked as critical.
+    ///
+    /// # Arguments
+    ///
+    /// * `payload` - The payload data.
+    /// * `header` - The JWS heaser claims.
+    pub fn signing_input(&self, payload: &[u8], header: &JwsHeader) -> Result<String, JoseError> {
+        (|| -> anyhow::Result<String> {
+            let mut b64 = true;
+            if let Some(vals) = header.critical() {
+                if vals.contains(&"b64") {
+                    if let Some(val) = header.base64url_encode_payload() {
+                        b64 = val;
+                    }
+                }
+            }
+
+            let header_bytes = serde_json::to_vec(header.claims_set())?;
+
+            let mut message = base64::encode_config(header_bytes, base64::URL_SAFE_NO_PAD);
+            message.push('.');
+            if b64 {
+                base64::encode_config_buf(payload, base64::URL_SAFE_NO_PAD, &mut message);
+            } else {
+                message.push_str(std::str::from_utf8(payload)?);
+            }
+
+            Ok(message)
+        })()
+        .map_err(|err| match err.downcast::<JoseError>() {
+            Ok(err) => err,
+            Err(err) => JoseError::InvalidJwsFormat(err),
+        })
+    }
+
+    /// Return a representation of the data that is formatted by compact serialization
+    /// with detached content (RFC 7515 Appendix F).
+    ///
+    /// The signature is computed in the same way as [`JwsContext::serialize_compact`],
+    /// but the payload part of the output is empty. The payload must be given
+    /// separately to [`JwsContext::deserialize_compact_detached`]. An unencoded payload
+    /// (b64=false) may contain dots in this form.
+    ///
+    /// # Arguments
+    ///
+    /// * `payload` - The payload data.
+    /// * `header` - The JWS heaser claims.
+    /// * `signer` - The JWS signer.
+    pub fn serialize_compact_detached(
+        &self,
+        payload: &[u8],
+        header: &JwsHeader,
+        signer: &dyn JwsSigner,
+    ) -> Result<String, JoseError> {
+        self.serialize_compact_with_detached(payload, header, None, |_header| Some(signer), true)
+    }
+
     /// Return a representation of the data that is formatted by flattened json serialization.
     ///
     /// # Arguments
@@ -222,7 +431,7 @@ impl JwsContext {
                 let unprotected_map = header.claims_set(false);
 
                 let message = format!("{}.{}", &protected_b64, &payload_b64);
-                let signature = signer.sign(message.as_bytes())?;
+                let signature = self.sign_message(message.as_bytes(), signer)?;
 
                 result.push_str("{\"protected\":\"");
                 result.push_str(&protected_b64);
@@ -251,6 +460,63 @@ impl JwsContext {
         })
     }
 
+    /// Return a representation of the data that is formatted by general json serialization
+    /// with a signature for each pair of the protected and unprotected header claims.
+    ///
+    /// The selector is called with the index of the pair and the merged header claims,
+    /// so the signer of each signature can be chosen by e.g. the "alg" header claim.
+    /// A header claim must not be both in the protected and unprotected headers.
+    ///
+    /// # Arguments
+    ///
+    /// * `payload` - The payload data.
+    /// * `headers` - The JWS protected and unprotected header claims pairs.
+    /// * `selector` - a function for selecting the signing algorithm.
+    pub fn serialize_general_json_with_selectors<'a, F>(
+        &self,
+        payload: &[u8],
+        headers: &[(Option<&JwsHeader>, Option<&JwsHeader>)],
+        selector: F,
+    ) -> Result<String, JoseError>
+    where
+        F: Fn(usize, &JwsHeader) -> Option<&'a dyn JwsSigner>,
+    {
+        let header_sets = (|| -> anyhow::Result<Vec<JwsHeaderSet>> {
+            let mut header_sets = Vec::with_capacity(headers.len());
+            for (protected, unprotected) in headers {
+                let mut header_set = JwsHeaderSet::new();
+                if let Some(protected) = protected {
+                    for (key, value) in protected.claims_set() {
+                        header_set.set_claim(key, Some(value.clone()), true)?;
+                    }
+                }
+                if let Some(unprotected) = unprotected {
+                    for (key, value) in unprotected.claims_set() {
+                        if header_set.claims_set(true).contains_key(key) {
+                            bail!("A duplicate key exists: {}", key);
+                        }
+                        header_set.set_claim(key, Some(value.clone()), false)?;
+                    }
+                }
+                header_sets.push(header_set);
+            }
+            Ok(header_sets)
+        })()
+        .map_err(|err| match err.downcast::<JoseError>() {
+            Ok(err) => err,
+            Err(err) => JoseError::InvalidJwsFormat(err),
+        })?;
+
+        self.serialize_general_json_with_selecter(
+            payload,
+            header_sets
+                .iter()
+                .collect::<Vec<&JwsHeaderSet>>()
+                .as_slice(),
+            selector,
+        )
+    }
+
     /// Return a representation of the data that is formatted by flattened json serialization.
     ///
     /// # Arguments
@@ -339,7 +605,7 @@ impl JwsContext {
             };
 
             let message = format!("{}.{}", &protected_b64, payload);
-            let signature = signer.sign(message.as_bytes())?;
+            let signature = self.sign_message(message.as_bytes(), signer)?;
 
             let mut json = String::new();
             json.push_str("{\"protected\":\"");
@@ -396,11 +662,65 @@ impl JwsContext {
         input: impl AsRef<[u8]>,
         selector: F,
     ) -> Result<(Vec<u8>, JwsHeader), JoseError>
+    where
+        F: Fn(&JwsHeader) -> Result<Option<&'a dyn JwsVerifier>, JoseError>,
+    {
+        self.deserialize_compact_with_payload(input.as_ref(), None, selector)
+    }
+
+    /// Deserialize the inputs that are formatted by compact serialization with the same
+    /// verifier, and return the result for each input in the same order.
+    ///
+    /// The verifier holds the parsed key, so it is shared by all inputs.
+    ///
+    /// # Arguments
+    ///
+    /// * `inputs` - The input data.
+    /// * `verifier` - The JWS verifier.
+    pub fn verify_batch(
+        &self,
+        inputs: &[impl AsRef<[u8]>],
+        verifier: &dyn JwsVerifier,
+    ) -> Vec<Result<(Vec<u8>, JwsHeader), JoseError>> {
+        inputs
+            .iter()
+            .map(|input| self.deserialize_compact(input, verifier))
+            .collect()
+    }
+
+    /// Deserialize the input that is formatted by compact serialization with detached content.
+    ///
+    /// The payload part of the input must be empty, and the signature is verified
+    /// against the payload that is given separately.
+    ///
+    /// # Arguments
+    ///
+    /// * `input` - The input data.
+    /// * `payload` - The detached payload data.
+    /// * `verifier` - The JWS verifier.
+    pub fn deserialize_compact_detached(
+        &self,
+        input: impl AsRef<[u8]>,
+        payload: &[u8],
+        verifier: &dyn JwsVerifier,
+    ) -> Result<JwsHeader, JoseError> {
+        let (_, header) =
+            self.deserialize_compact_with_payload(input.as_ref(), Some(payload), |_header| {
+                Ok(Some(verifier))
+            })?;
+        Ok(header)
+    }
+
+    fn deserialize_compact_with_payload<'a, F>(
+        &self,
+        input: &[u8],
+        detached_payload: Option<&[u8]>,
+        selector: F,
+    ) -> Result<(Vec<u8>, JwsHeader), JoseError>
     where
         F: Fn(&JwsHeader) -> Result<Option<&'a dyn JwsVerifier>, JoseError>,
     {
         (|| -> anyhow::Result<(Vec<u8>, JwsHeader)> {
-            let input = input.as_ref();
             let indexies: Vec<usize> = input
                 .iter()
                 .enumerate()
@@ -416,10 +736,14 @@ impl JwsContext {
             let header = &input[0..indexies[0]];
             let payload = &input[(indexies[0] + 1)..(indexies[1])];
             let signature = &input[(indexies[1] + 1)..];
+            if header.is_empty() {
+                bail!("The JWS protected header must not be empty.");
+            }
 
             let header = base64::decode_config(header, base64::URL_SAFE_NO_PAD)?;
-            let header: Map<String, Value> = serde_json::from_slice(&header)?;
+            let header = util::parse_json_object(&header)?;
             let header = JwsHeader::from_map(header)?;
+            self.check_accepted(&header)?;
 
             let verifier = match selector(&header)? {
                 Some(val) => val,
@@ -463,14 +787,36 @@ impl JwsContext {
                 }
             }
 
-            let message = &input[..(indexies[1])];
             let signature = base64::decode_config(signature, base64::URL_SAFE_NO_PAD)?;
-            verifier.verify(message, &signature)?;
 
-            let payload = if b64 {
-                base64::decode_config(payload, base64::URL_SAFE_NO_PAD)?
-            } else {
-                payload.to_vec()
+            let payload = match detached_payload {
+                Some(detached_payload) => {
+                    if !payload.is_empty() {
+                        bail!("The payload part of detached content must be empty.");
+                    }
+
+                    let mut message = input[..(indexies[1])].to_vec();
+                    if b64 {
+                        let payload_b64 =
+                            base64::encode_config(detached_payload, base64::URL_SAFE_NO_PAD);
+                        message.extend_from_slice(payload_b64.as_bytes());
+                    } else {
+                        message.extend_from_slice(detached_payload);
+                    }
+                    verifier.verify(&message, &signature)?;
+
+                    detached_payload.to_vec()
+                }
+                None => {
+                    let message = &input[..(indexies[1])];
+                    verifier.verify(message, &signature)?;
+
+                    if b64 {
+                        base64::decode_config(payload, base64::URL_SAFE_NO_PAD)?
+                    } else {
+                        payload.to_vec()
+                    }
+                }
             };
 
             Ok((payload, header))
@@ -516,6 +862,28 @@ impl JwsContext {
         })
     }
 
+    /// Deserialize the input that is formatted by compact or json serialization.
+    ///
+    /// The input is deserialized as json serialization if it starts with "{"
+    /// after any leading whitespace, or as compact serialization otherwise.
+    ///
+    /// # Arguments
+    ///
+    /// * `input` - The input data.
+    /// * `verifier` - The JWS verifier.
+    pub fn deserialize_auto(
+        &self,
+        input: impl AsRef<[u8]>,
+        verifier: &dyn JwsVerifier,
+    ) -> Result<(Vec<u8>, JwsHeader), JoseError> {
+        let input = input.as_ref();
+        if util::is_json_object(input) {
+            self.deserialize_json(input, verifier)
+        } else {
+            self.deserialize_compact(input, verifier)
+        }
+    }
+
     /// Deserialize the input that is formatted by json serialization.
     ///
     /// # Arguments
@@ -532,141 +900,250 @@ impl JwsContext {
         F: Fn(&JwsHeader) -> Result<Option<&'a dyn JwsVerifier>, JoseError>,
     {
         (|| -> anyhow::Result<(Vec<u8>, JwsHeader)> {
-            let input = input.as_ref();
-            let mut map: Map<String, Value> = serde_json::from_slice(input)?;
+            let (payload, signatures) = self.parse_json_signatures(input.as_ref())?;
 
-            let payload_b64 = match map.remove("payload") {
-                Some(Value::String(val)) => val,
-                Some(_) => bail!("The payload field must be string."),
-                None => bail!("The payload field is required."),
-            };
+            let mut rejected = None;
+            for (merged, message, signature) in signatures {
+                if let Err(err) = self.check_accepted(&merged) {
+                    rejected = Some(err);
+                    continue;
+                }
 
-            let signatures = match map.remove("signatures") {
-                Some(Value::Array(vals)) => {
-                    let mut vec = Vec::with_capacity(vals.len());
-                    for val in vals {
-                        if let Value::Object(val) = val {
-                            vec.push(val);
-                        } else {
-                            bail!("The signatures field must be a array of object.");
-                        }
-                    }
-                    vec
+                let verifier = match selector(&merged)? {
+                    Some(val) => val,
+                    None => continue,
+                };
+
+                Self::check_verifier(&merged, verifier)?;
+                verifier.verify(message.as_bytes(), &signature)?;
+
+                return Ok((payload, merged));
+            }
+
+            if let Some(err) = rejected {
+                return Err(err);
+            }
+            bail!("A signature that matched the header claims is not found.");
+        })()
+        .map_err(|err| match err.downcast::<JoseError>() {
+            Ok(err) => err,
+            Err(err) => JoseError::InvalidJwsFormat(err),
+        })
+    }
+
+    /// Deserialize the input that is formatted by flattened or general json serialization,
+    /// and return the verification result of each signature.
+    ///
+    /// The selector is called with the merged header of each signature. A signature is
+    /// reported as valid only when the selector returns a verifier, the header matches
+    /// the verifier and the signature is verified by it. This is useful to implement
+    /// a policy that requires a number of valid signatures.
+    ///
+    /// # Arguments
+    ///
+    /// * `input` - The input data.
+    /// * `selector` - a function for selecting the verifying algorithm.
+    pub fn deserialize_general_json_with_selector<'a, F>(
+        &self,
+        input: impl AsRef<[u8]>,
+        selector: F,
+    ) -> Result<SignatureResults, JoseError>
+    where
+        F: Fn(&JwsHeader) -> Result<Option<&'a dyn JwsVerifier>, JoseError>,
+    {
+        (|| -> anyhow::Result<SignatureResults> {
+            let (payload, signatures) = self.parse_json_signatures(input.as_ref())?;
+
+            let mut results = Vec::with_capacity(signatures.len());
+            for (merged, message, signature) in signatures {
+                if self.check_accepted(&merged).is_err() {
+                    results.push((merged, false));
+                    continue;
                 }
-                Some(_) => bail!("The signatures field must be a array."),
-                None => {
-                    let mut vec = Vec::with_capacity(1);
-                    vec.push(map);
-                    vec
+
+                let verified = match selector(&merged)? {
+                    Some(verifier) => {
+                        Self::check_verifier(&merged, verifier).is_ok()
+                            && verifier.verify(message.as_bytes(), &signature).is_ok()
+                    }
+                    None => false,
+                };
+                results.push((merged, verified));
+            }
+
+            Ok((payload, results))
+        })()
+        .map_err(|err| match err.downcast::<JoseError>() {
+            Ok(err) => err,
+            Err(err) => JoseError::InvalidJwsFormat(err),
+        })
+    }
+
+    /// Parse the input that is formatted by flattened or general json serialization,
+    /// and return the payload and the merged header, signing input and signature
+    /// of each signature.
+    fn parse_json_signatures(&self, input: &[u8]) -> anyhow::Result<ParsedSignatures> {
+        let mut map = util::parse_json_object(input)?;
+
+        let payload_b64 = match map.remove("payload") {
+            Some(Value::String(val)) => val,
+            Some(_) => bail!("The payload field must be string."),
+            None => bail!("The payload field is required."),
+        };
+
+        let signatures = match map.remove("signatures") {
+            Some(Value::Array(vals)) => {
+                let mut vec = Vec::with_capacity(vals.len());
+                for val in vals {
+                    if let Value::Object(val) = val {
+                        vec.push(val);
+                    } else {
+                        bail!("The signatures field must be a array of object.");
+                    }
                 }
-            };
+                vec
+            }
+            Some(_) => bail!("The signatures field must be a array."),
+            None => {
+                let mut vec = Vec::with_capacity(1);
+                vec.push(map);
+                vec
+            }
+        };
 
-            for mut sig in signatures {
-                let header = sig.remove("header");
+        let mut payload_b64_flag = None;
+        let mut results = Vec::with_capacity(signatures.len());
+        for mut sig in signatures {
+            let header = sig.remove("header");
 
-                let protected_b64 = match sig.get("protected") {
-                    Some(Value::String(val)) => val,
-                    Some(_) => bail!("The protected field must be a string."),
-                    None => bail!("The JWS alg header claim must be in protected."),
-                };
+            let protected_b64 = match sig.get("protected") {
+                Some(Value::String(val)) => val,
+                Some(_) => bail!("The protected field must be a string."),
+                None => bail!("The JWS alg header claim must be in protected."),
+            };
 
-                let protected_vec = base64::decode_config(&protected_b64, base64::URL_SAFE_NO_PAD)?;
-                let protected_map: Map<String, Value> = serde_json::from_slice(&protected_vec)?;
+            let protected_vec = base64::decode_config(&protected_b64, base64::URL_SAFE_NO_PAD)?;
+            let protected_map = util::parse_json_object(&protected_vec)?;
 
-                let mut b64 = true;
-                if let Some(Value::Array(vals)) = protected_map.get("critical") {
-                    for val in vals {
-                        match val {
-                            Value::String(name) => {
-                                if !self.is_acceptable_critical(name) {
-                                    bail!("The critical name '{}' is not supported.", name);
-                                }
+            let mut b64 = true;
+            if let Some(Value::Array(vals)) = protected_map.get("crit") {
+                for val in vals {
+                    match val {
+                        Value::String(name) => {
+                            if !self.is_acceptable_critical(name) {
+                                bail!("The critical name '{}' is not supported.", name);
+                            }
 
-                                if name == "b64" {
-                                    match protected_map.get("b64") {
-                                        Some(Value::Bool(b64_val)) => {
-                                            b64 = *b64_val;
-                                        }
-                                        Some(_) => bail!("The JWS b64 header claim must be bool."),
-                                        None => {}
+                            if name == "b64" {
+                                match protected_map.get("b64") {
+                                    Some(Value::Bool(b64_val)) => {
+                                        b64 = *b64_val;
                                     }
+                                    Some(_) => bail!("The JWS b64 header claim must be bool."),
+                                    None => {}
                                 }
                             }
-                            _ => bail!("The JWS critical header claim must be a array of string."),
                         }
+                        _ => bail!("The JWS critical header claim must be a array of string."),
                     }
                 }
+            }
 
-                let merged_map = match header {
-                    Some(Value::Object(mut val)) => {
-                        for (key, value) in protected_map {
-                            if val.contains_key(&key) {
-                                bail!("A duplicate key exists: {}", key);
-                            } else {
-                                val.insert(key.clone(), value.clone());
-                            }
+            match payload_b64_flag {
+                Some(val) if val != b64 => {
+                    bail!("The JWS b64 header claim must be the same in all signatures.")
+                }
+                _ => payload_b64_flag = Some(b64),
+            }
+
+            let merged_map = match header {
+                Some(Value::Object(mut val)) => {
+                    for (key, value) in protected_map {
+                        if val.contains_key(&key) {
+                            bail!("A duplicate key exists: {}", key);
+                        } else {
+                            val.insert(key.clone(), value.clone());
                         }
-                        val
                     }
-                    Some(_) => bail!("The protected field must be a object."),
-                    None => protected_map.clone(),
-                };
-
-                if let None = merged_map.get("alg") {
-                    bail!("The JWS alg header claim must be in protected.");
+                    val
                 }
+                Some(_) => bail!("The protected field must be a object."),
+                None => protected_map.clone(),
+            };
 
-                let signature = match sig.get("signature") {
-                    Some(Value::String(val)) => {
-                        base64::decode_config(val, base64::URL_SAFE_NO_PAD)?
-                    }
-                    Some(_) => bail!("The signature field must be string."),
-                    None => bail!("The signature field is required."),
-                };
+            if let None = merged_map.get("alg") {
+                bail!("The JWS alg header claim must be in protected.");
+            }
 
-                let merged = JwsHeader::from_map(merged_map)?;
-                let verifier = match selector(&merged)? {
-                    Some(val) => val,
-                    None => continue,
-                };
+            let signature = match sig.get("signature") {
+                Some(Value::String(val)) => base64::decode_config(val, base64::URL_SAFE_NO_PAD)?,
+                Some(_) => bail!("The signature field must be string."),
+                None => bail!("The signature field is required."),
+            };
 
-                match merged.claim("alg") {
-                    Some(Value::String(val)) => {
-                        let expected_alg = verifier.algorithm().name();
-                        if val != expected_alg {
-                            bail!("The JWS alg header claim is not {}: {}", expected_alg, val);
-                        }
-                    }
-                    Some(_) => bail!("The JWS alg header claim must be a string."),
-                    None => bail!("The JWS alg header claim is required."),
+            let merged = JwsHeader::from_map(merged_map)?;
+            let message = format!("{}.{}", &protected_b64, &payload_b64);
+            results.push((merged, message, signature));
+        }
+
+        let payload = if payload_b64_flag.unwrap_or(true) {
+            base64::decode_config(&payload_b64, base64::URL_SAFE_NO_PAD)?
+        } else {
+            payload_b64.into_bytes()
+        };
+
+        Ok((payload, results))
+    }
+
+    fn check_accepted(&self, merged: &JwsHeader) -> anyhow::Result<()> {
+        if self.accepted_algorithms.is_some() {
+            match merged.claim("alg") {
+                Some(Value::String(val)) if self.is_accepted_algorithm(val) => {}
+                Some(Value::String(val)) => {
+                    bail!("The JWS alg header claim is not accepted: {}", val)
                 }
+                Some(_) => bail!("The JWS alg header claim must be a string."),
+                None => bail!("The JWS alg header claim is required."),
+            }
+        }
+        Ok(())
+    }
 
-                match verifier.key_id() {
-                    Some(expected) => match merged.key_id() {
-                        Some(actual) if expected == actual => {}
-                        Some(actual) => bail!("The JWS kid header claim is mismatched: {}", actual),
-                        None => bail!("The JWS kid header claim is required."),
-                    },
-                    None => {}
+    fn check_verifier(merged: &JwsHeader, verifier: &dyn JwsVerifier) -> anyhow::Result<()> {
+        match merged.claim("alg") {
+            Some(Value::String(val)) => {
+                let expected_alg = verifier.algorithm().name();
+                if val != expected_alg {
+                    bail!("The JWS alg header claim is not {}: {}", expected_alg, val);
                 }
+            }
+            Some(_) => bail!("The JWS alg header claim must be a string."),
+            None => bail!("The JWS alg header claim is required."),
+        }
 
-                let message = format!("{}.{}", &protected_b64, &payload_b64);
-                verifier.verify(message.as_bytes(), &signature)?;
+        match verifier.key_id() {
+            Some(expected) => match merged.key_id() {
+                Some(actual) if expected == actual => {}
+                Some(actual) => bail!("The JWS kid header claim is mismatched: {}", actual),
+                None => bail!("The JWS kid header claim is required."),
+            },
+            None => {}
+        }
 
-                let payload = if b64 {
-                    base64::decode_config(&payload_b64, base64::URL_SAFE_NO_PAD)?
-                } else {
-                    payload_b64.into_bytes()
-                };
+        Ok(())
+    }
+}
 
-                return Ok((payload, merged));
+impl PartialEq for JwsContext {
+    fn eq(&self, other: &Self) -> bool {
+        self.acceptable_criticals == other.acceptable_criticals
+            && self.accepted_algorithms == other.accepted_algorithms
+            && match (&self.rng, &other.rng) {
+                (Some(val1), Some(val2)) => Arc::ptr_eq(val1, val2),
+                (None, None) => true,
+                _ => false,
             }
-
-            bail!("A signature that matched the header claims is not found.");
-        })()
-        .map_err(|err| match err.downcast::<JoseError>() {
-            Ok(err) => err,
-            Err(err) => JoseError::InvalidJwsFormat(err),
-        })
     }
 }
+
+impl Eq for JwsContext {}
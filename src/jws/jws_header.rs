@@ -8,6 +8,11 @@ use crate::util;
 use crate::{JoseError, JoseHeader, Map, Value};
 
 /// Represent JWS header claims
+///
+/// Two headers are equal when they have the same claims, regardless of the order
+/// of the members and the whitespace of the JSON they are parsed from. The members
+/// of a nested object are also compared regardless of the order, while the elements
+/// of an array are compared in order.
 #[derive(Debug, Eq, PartialEq, Clone)]
 pub struct JwsHeader {
     claims: Map<String, Value>,
@@ -26,7 +31,7 @@ impl JwsHeader {
     /// * `value` - The json style header claims
     pub fn from_bytes(value: &[u8]) -> Result<Self, JoseError> {
         let claims = (|| -> anyhow::Result<Map<String, Value>> {
-            let claims: Map<String, Value> = serde_json::from_slice(value)?;
+            let claims = util::parse_json_object(value)?;
             Ok(claims)
         })()
         .map_err(|err| JoseError::InvalidJson(err))?;
@@ -99,16 +104,23 @@ impl JwsHeader {
 
     /// Set a value for JWK header claim (jwk).
     ///
+    /// The private parameters of the JWK (e.g. "d") are removed, because the header
+    /// is not encrypted.
+    ///
     /// # Arguments
     ///
     /// * `value` - a JWK
     pub fn set_jwk(&mut self, value: Jwk) {
         let key = "jwk";
-        let value: Map<String, Value> = value.into();
+        let value: Map<String, Value> = value.without_private_parameters().into();
         self.claims.insert(key.to_string(), Value::Object(value));
     }
 
     /// Return the value for JWK header claim (jwk).
+    ///
+    /// The key is given by the sender of the JWS. A verifier that is created from it
+    /// proves only that the JWS is signed by the key, so the key must be checked
+    /// against a trusted key or certificate before the payload is trusted.
     pub fn jwk(&self) -> Option<Jwk> {
         match self.claims.get("jwk") {
             Some(Value::Object(vals)) => match Jwk::from_map(vals.clone()) {
@@ -148,7 +160,7 @@ impl JwsHeader {
         for val in values {
             vec.push(Value::String(base64::encode_config(
                 val.as_ref(),
-                base64::URL_SAFE_NO_PAD,
+                base64::STANDARD,
             )));
         }
         self.claims.insert(key.to_string(), Value::Array(vec));
@@ -162,7 +174,7 @@ impl JwsHeader {
                 for val in vals {
                     match val {
                         Value::String(val2) => {
-                            match base64::decode_config(val2, base64::URL_SAFE_NO_PAD) {
+                            match base64::decode_config(val2, base64::STANDARD) {
                                 Ok(val3) => vec.push(val3.clone()),
                                 Err(_) => return None,
                             }
@@ -220,6 +232,55 @@ impl JwsHeader {
         }
     }
 
+    /// Set the X.509 certificate chain header claim (x5c) and the thumbprint header
+    /// claims (x5t and x5t#S256) of the leaf certificate.
+    ///
+    /// # Arguments
+    ///
+    /// * `values` - DER encoded X.509 certificates, the leaf certificate first
+    pub fn set_x509_certificate_chain_with_thumbprints(&mut self, values: &Vec<impl AsRef<[u8]>>) {
+        self.set_x509_certificate_chain(values);
+        if let Some(leaf) = values.first() {
+            let leaf = leaf.as_ref();
+            self.set_x509_certificate_sha1_thumbprint(util::x509_thumbprint(util::SHA_1, leaf));
+            self.set_x509_certificate_sha256_thumbprint(util::x509_thumbprint(util::SHA_256, leaf));
+        }
+    }
+
+    /// Verify that the x5t and x5t#S256 header claims are the thumbprints of the leaf
+    /// certificate of the x5c header claim.
+    ///
+    /// The thumbprint header claims that are absent are not checked, but
+    /// the x5c header claim is required when any of them is present.
+    pub fn verify_x509_certificate_thumbprints(&self) -> Result<(), JoseError> {
+        (|| -> anyhow::Result<()> {
+            let thumbprints = [
+                ("x5t", util::SHA_1, self.x509_certificate_sha1_thumbprint()),
+                (
+                    "x5t#S256",
+                    util::SHA_256,
+                    self.x509_certificate_sha256_thumbprint(),
+                ),
+            ];
+            for (key, hash, expected) in thumbprints {
+                if let Some(expected) = expected {
+                    let leaf = match self.x509_certificate_chain() {
+                        Some(vals) if !vals.is_empty() => vals.into_iter().next().unwrap(),
+                        _ => bail!("The JWS x5c header claim is required to verify {}.", key),
+                    };
+                    if util::x509_thumbprint(hash, &leaf) != expected {
+                        bail!(
+                            "The JWS {} header claim does not match the leaf certificate.",
+                            key
+                        );
+                    }
+                }
+            }
+            Ok(())
+        })()
+        .map_err(JoseError::InvalidJwsFormat)
+    }
+
     /// Set a value for key ID header claim (kid).
     ///
     /// # Arguments
@@ -382,6 +443,22 @@ impl JwsHeader {
         Ok(())
     }
 
+    /// Remove the header claim of a specified key and return its value.
+    ///
+    /// The order of the remaining header claims is kept.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - a key name of header claim
+    pub fn remove_claim(&mut self, key: &str) -> Option<Value> {
+        self.claims.shift_remove(key)
+    }
+
+    /// Return an iterator over the key names and the values of header claims.
+    pub fn claims(&self) -> impl Iterator<Item = (&str, &Value)> {
+        self.claims.iter().map(|(key, value)| (key.as_str(), value))
+    }
+
     /// Return values for header claims set
     pub fn claims_set(&self) -> &Map<String, Value> {
         &self.claims
@@ -498,11 +575,18 @@ impl Deref for JwsHeader {
 
 #[cfg(test)]
 mod tests {
+    use std::fs;
+    use std::path::PathBuf;
+
     use anyhow::Result;
+    use openssl::x509::X509;
     use serde_json::json;
 
     use crate::jwk::Jwk;
-    use crate::jws::JwsHeader;
+    use crate::jws::{self, JwsHeader, ES256};
+    use crate::jwt;
+    use crate::util;
+    use crate::Value;
 
     #[test]
     fn test_new_jws_header() -> Result<()> {
@@ -547,4 +631,120 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_jws_header_set_jwk_with_private_key() -> Result<()> {
+        let private_key = Jwk::from_bytes(&load_file("jwk/EC_P-256_private.jwk")?)?;
+        let signer = ES256.signer_from_jwk(&private_key)?;
+
+        let mut header = JwsHeader::new();
+        header.set_jwk(private_key.clone());
+        let jws = jws::serialize_compact(b"test payload!", &header, &signer)?;
+
+        let header = jwt::decode_jws_header(&jws)?;
+        let jwk = header.jwk().unwrap();
+        assert_eq!(jwk, private_key.to_public_key()?);
+        assert_eq!(jwk.parameter("d"), None);
+
+        let verifier = ES256.verifier_from_jwk(&jwk)?;
+        let (payload, _) = jws::deserialize_compact(&jws, &verifier)?;
+        assert_eq!(payload, b"test payload!");
+
+        let mut header = JwsHeader::new();
+        header.set_jwk(Jwk::generate_oct_key(16)?);
+        assert_eq!(header.jwk().unwrap().parameter("k"), None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_jws_header_remove_claim() -> Result<()> {
+        let mut header = JwsHeader::from_bytes(br#"{"alg":"A","kid":"key-1","typ":"JWT","a":1}"#)?;
+
+        assert_eq!(header.remove_claim("kid"), Some(json!("key-1")));
+        assert_eq!(header.key_id(), None);
+        assert_eq!(header.remove_claim("kid"), None);
+
+        let claims: Vec<(&str, &Value)> = header.claims().collect();
+        assert_eq!(
+            claims,
+            vec![
+                ("alg", &json!("A")),
+                ("typ", &json!("JWT")),
+                ("a", &json!(1)),
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_jws_header_equality() -> Result<()> {
+        let header1 = JwsHeader::from_bytes(
+            br#"{"alg":"A","jwk":{"kty":"EC","crv":"P-256"},"crit":["a","b"],"a":1}"#,
+        )?;
+        let header2 = JwsHeader::from_bytes(
+            br#"{ "a": 1, "crit": ["a", "b"], "jwk": {"crv": "P-256", "kty": "EC"}, "alg": "A" }"#,
+        )?;
+        assert_eq!(header1, header2);
+
+        let header3 = JwsHeader::from_bytes(
+            br#"{"alg":"A","jwk":{"kty":"EC","crv":"P-256"},"crit":["b","a"],"a":1}"#,
+        )?;
+        assert_ne!(header1, header3);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_jws_header_x509_certificate_chain() -> Result<()> {
+        let mut chain = Vec::new();
+        for path in &["pem/RSA_2048bit_certificate.pem", "pem/CA_certificate.pem"] {
+            let cert = X509::from_pem(&load_file(path)?)?;
+            chain.push(cert.to_der()?);
+        }
+
+        let mut header = JwsHeader::new();
+        header.set_x509_certificate_chain_with_thumbprints(&chain);
+
+        let encoded = match header.claim("x5c") {
+            Some(Value::Array(vals)) => vals.clone(),
+            _ => unreachable!(),
+        };
+        for (val, der) in encoded.iter().zip(&chain) {
+            assert_eq!(val, &Value::String(base64::encode(der)));
+        }
+        assert_eq!(header.x509_certificate_chain(), Some(chain.clone()));
+        assert_eq!(
+            header.x509_certificate_sha1_thumbprint(),
+            Some(util::x509_thumbprint(util::SHA_1, &chain[0]))
+        );
+        header.verify_x509_certificate_thumbprints()?;
+
+        let header = JwsHeader::from_bytes(header.to_string().as_bytes())?;
+        assert_eq!(header.x509_certificate_chain(), Some(chain.clone()));
+        header.verify_x509_certificate_thumbprints()?;
+
+        let mut header2 = header.clone();
+        header2.set_x509_certificate_sha256_thumbprint(util::x509_thumbprint(
+            util::SHA_256,
+            &chain[1],
+        ));
+        assert!(header2.verify_x509_certificate_thumbprints().is_err());
+
+        let mut header3 = JwsHeader::new();
+        header3.set_x509_certificate_sha1_thumbprint(util::x509_thumbprint(util::SHA_1, &chain[0]));
+        assert!(header3.verify_x509_certificate_thumbprints().is_err());
+
+        Ok(())
+    }
+
+    fn load_file(path: &str) -> Result<Vec<u8>> {
+        let mut pb = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        pb.push("data");
+        pb.push(path);
+
+        let data = fs::read(&pb)?;
+        Ok(data)
+    }
 }
@@ -76,12 +76,15 @@ impl JwsHeaderSet {
 
     /// Set a value for JWK header claim (jwk).
     ///
+    /// The private parameters of the JWK (e.g. "d") are removed, because the header
+    /// is not encrypted.
+    ///
     /// # Arguments
     ///
     /// * `value` - a JWK
     pub fn set_jwk(&mut self, value: Jwk, protection: bool) {
         let key = "jwk";
-        let value: Map<String, Value> = value.into();
+        let value: Map<String, Value> = value.without_private_parameters().into();
         if protection {
             self.unprotected.remove(key);
             self.protected.insert(key.to_string(), Value::Object(value));
@@ -138,7 +141,7 @@ impl JwsHeaderSet {
         let key = "x5c";
         let vec = values
             .iter()
-            .map(|v| Value::String(base64::encode_config(v.as_ref(), base64::URL_SAFE_NO_PAD)))
+            .map(|v| Value::String(base64::encode_config(v.as_ref(), base64::STANDARD)))
             .collect();
         if protection {
             self.unprotected.remove(key);
@@ -157,7 +160,7 @@ impl JwsHeaderSet {
                 for val in vals {
                     match val {
                         Value::String(val2) => {
-                            match base64::decode_config(val2, base64::URL_SAFE_NO_PAD) {
+                            match base64::decode_config(val2, base64::STANDARD) {
                                 Ok(val3) => vec.push(val3.clone()),
                                 Err(_) => return None,
                             }
@@ -324,7 +327,7 @@ impl JwsHeaderSet {
         let key = "crit";
         let vec = values
             .iter()
-            .map(|v| Value::String(base64::encode_config(v.as_ref(), base64::URL_SAFE_NO_PAD)))
+            .map(|v| Value::String(v.as_ref().to_string()))
             .collect();
         self.unprotected.remove(key);
         self.protected.insert(key.to_string(), Value::Array(vec));
@@ -0,0 +1,239 @@
+use anyhow::bail;
+use openssl::pkey::{Id, PKey, Public};
+
+use crate::jwk::Jwk;
+use crate::jws::alg::ecdsa::EcdsaJwsAlgorithm;
+use crate::jws::alg::eddsa::EddsaJwsAlgorithm;
+use crate::jws::alg::hmac::HmacJwsAlgorithm;
+use crate::jws::alg::rsassa::RsassaJwsAlgorithm;
+use crate::jws::alg::rsassa_pss::RsassaPssJwsAlgorithm;
+use crate::jws::{JwsHeader, JwsVerifier, DEFAULT_CONTEXT};
+use crate::JoseError;
+
+/// A set of JWS verifiers that dispatches to the verifier of a token
+/// by the "alg" and "kid" header claims.
+///
+/// This is useful when the keys of a JWK set have different algorithms.
+#[derive(Debug, Clone, Default)]
+pub struct MultiVerifier {
+    verifiers: Vec<Box<dyn JwsVerifier>>,
+}
+
+impl MultiVerifier {
+    /// Return a new empty MultiVerifier instance.
+    pub fn new() -> Self {
+        Self {
+            verifiers: Vec::new(),
+        }
+    }
+
+    /// Add a verifier.
+    ///
+    /// # Arguments
+    ///
+    /// * `verifier` - a JWS verifier
+    pub fn push(&mut self, verifier: Box<dyn JwsVerifier>) {
+        self.verifiers.push(verifier);
+    }
+
+    /// Add a verifier of the algorithm that is created from the JWK.
+    ///
+    /// # Arguments
+    ///
+    /// * `alg` - a JWS algorithm name, e.g. "RS256"
+    /// * `jwk` - a JWK for the algorithm
+    pub fn push_jwk(&mut self, alg: &str, jwk: &Jwk) -> Result<(), JoseError> {
+        let verifier = verifier_from_jwk(alg, jwk)?;
+        self.verifiers.push(verifier);
+        Ok(())
+    }
+
+    /// Return the verifiers in the order they were added.
+    pub fn verifiers(&self) -> Vec<&dyn JwsVerifier> {
+        self.verifiers.iter().map(|val| val.as_ref()).collect()
+    }
+
+    /// Return the first verifier that matches the header.
+    ///
+    /// A verifier matches when its algorithm is the "alg" header claim, and its
+    /// key ID is the "kid" header claim if the verifier has a key ID.
+    ///
+    /// # Arguments
+    ///
+    /// * `header` - The JWS header claims.
+    pub fn select(&self, header: &JwsHeader) -> Option<&dyn JwsVerifier> {
+        let alg = header.algorithm()?;
+        self.verifiers
+            .iter()
+            .map(|val| val.as_ref())
+            .filter(|val| val.algorithm().name() == alg)
+            .find(|val| match val.key_id() {
+                Some(key_id) => header.key_id() == Some(key_id),
+                None => true,
+            })
+    }
+
+    /// Deserialize the input that is formatted by compact serialization
+    /// with the verifier that matches the header.
+    ///
+    /// # Arguments
+    ///
+    /// * `input` - The input data.
+    pub fn deserialize_compact(
+        &self,
+        input: impl AsRef<[u8]>,
+    ) -> Result<(Vec<u8>, JwsHeader), JoseError> {
+        DEFAULT_CONTEXT.deserialize_compact_with_selector(input, |header| Ok(self.select(header)))
+    }
+
+    /// Deserialize the input that is formatted by json serialization
+    /// with the verifier that matches the header of a signature.
+    ///
+    /// # Arguments
+    ///
+    /// * `input` - The input data.
+    pub fn deserialize_json(
+        &self,
+        input: impl AsRef<[u8]>,
+    ) -> Result<(Vec<u8>, JwsHeader), JoseError> {
+        DEFAULT_CONTEXT.deserialize_json_with_selector(input, |header| Ok(self.select(header)))
+    }
+}
+
+/// Create a verifier of the algorithm from the JWK.
+///
+/// # Arguments
+///
+/// * `alg` - a JWS algorithm name
+/// * `jwk` - a JWK for the algorithm
+pub(crate) fn verifier_from_jwk(alg: &str, jwk: &Jwk) -> Result<Box<dyn JwsVerifier>, JoseError> {
+    if let Ok(val) = alg.parse::<HmacJwsAlgorithm>() {
+        Ok(Box::new(val.verifier_from_jwk(jwk)?))
+    } else if let Ok(val) = alg.parse::<RsassaJwsAlgorithm>() {
+        Ok(Box::new(val.verifier_from_jwk(jwk)?))
+    } else if let Ok(val) = alg.parse::<RsassaPssJwsAlgorithm>() {
+        Ok(Box::new(val.verifier_from_jwk(jwk)?))
+    } else if let Ok(val) = alg.parse::<EcdsaJwsAlgorithm>() {
+        Ok(Box::new(val.verifier_from_jwk(jwk)?))
+    } else if let Ok(val) = alg.parse::<EddsaJwsAlgorithm>() {
+        Ok(Box::new(val.verifier_from_jwk(jwk)?))
+    } else {
+        Err(JoseError::UnsupportedSignatureAlgorithm(anyhow::anyhow!(
+            "Unknown algorithm: {}",
+            alg
+        )))
+    }
+}
+
+/// Create a verifier of the algorithm from the public key.
+///
+/// The key type of the public key must be the one of the algorithm.
+///
+/// # Arguments
+///
+/// * `alg` - a JWS algorithm name
+/// * `public_key` - a public key for the algorithm
+pub(crate) fn verifier_from_public_key(
+    alg: &str,
+    public_key: &PKey<Public>,
+) -> Result<Box<dyn JwsVerifier>, JoseError> {
+    (|| -> anyhow::Result<Box<dyn JwsVerifier>> {
+        let id = public_key.id();
+        let verifier: Box<dyn JwsVerifier> = if alg.parse::<HmacJwsAlgorithm>().is_ok() {
+            bail!("A public key can't be used for the algorithm: {}", alg);
+        } else if let Ok(val) = alg.parse::<RsassaJwsAlgorithm>() {
+            if id != Id::RSA {
+                bail!("The key type is mismatched for the algorithm: {}", alg);
+            }
+            Box::new(val.verifier_from_der(public_key.public_key_to_der()?)?)
+        } else if let Ok(val) = alg.parse::<RsassaPssJwsAlgorithm>() {
+            if id != Id::RSA && id != Id::RSA_PSS {
+                bail!("The key type is mismatched for the algorithm: {}", alg);
+            }
+            Box::new(val.verifier_from_der(public_key.public_key_to_der()?)?)
+        } else if let Ok(val) = alg.parse::<EcdsaJwsAlgorithm>() {
+            if id != Id::EC {
+                bail!("The key type is mismatched for the algorithm: {}", alg);
+            }
+            Box::new(val.verifier_from_der(public_key.public_key_to_der()?)?)
+        } else if let Ok(val) = alg.parse::<EddsaJwsAlgorithm>() {
+            if id != Id::ED25519 && id != Id::ED448 {
+                bail!("The key type is mismatched for the algorithm: {}", alg);
+            }
+            Box::new(val.verifier_from_der(public_key.public_key_to_der()?)?)
+        } else {
+            return Err(JoseError::UnsupportedSignatureAlgorithm(anyhow::anyhow!(
+                "Unknown algorithm: {}",
+                alg
+            ))
+            .into());
+        };
+        Ok(verifier)
+    })()
+    .map_err(|err| match err.downcast::<JoseError>() {
+        Ok(err) => err,
+        Err(err) => JoseError::InvalidKeyFormat(err),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::PathBuf;
+
+    use anyhow::Result;
+
+    use super::MultiVerifier;
+    use crate::jwk::Jwk;
+    use crate::jws::{self, JwsHeader, ES256, RS256};
+    use crate::JoseError;
+
+    #[test]
+    fn test_multi_verifier() -> Result<()> {
+        let rsa_private_key = Jwk::from_bytes(&load_file("jwk/RSA_private.jwk")?)?;
+        let rsa_public_key = Jwk::from_bytes(&load_file("jwk/RSA_public.jwk")?)?;
+        let ec_private_key = Jwk::from_bytes(&load_file("jwk/EC_P-256_private.jwk")?)?;
+        let ec_public_key = Jwk::from_bytes(&load_file("jwk/EC_P-256_public.jwk")?)?;
+
+        let mut verifier = MultiVerifier::new();
+        verifier.push_jwk("RS256", &rsa_public_key)?;
+        verifier.push(Box::new(ES256.verifier_from_jwk(&ec_public_key)?));
+        assert_eq!(verifier.verifiers().len(), 2);
+        assert!(matches!(
+            verifier.push_jwk("UNKNOWN", &rsa_public_key),
+            Err(JoseError::UnsupportedSignatureAlgorithm(_))
+        ));
+
+        let header = JwsHeader::new();
+        let rs256 =
+            jws::serialize_compact(b"rs256", &header, &RS256.signer_from_jwk(&rsa_private_key)?)?;
+        let es256 =
+            jws::serialize_compact(b"es256", &header, &ES256.signer_from_jwk(&ec_private_key)?)?;
+
+        let (payload, header) = verifier.deserialize_compact(&rs256)?;
+        assert_eq!(payload, b"rs256");
+        assert_eq!(header.algorithm(), Some("RS256"));
+
+        let (payload, header) = verifier.deserialize_compact(&es256)?;
+        assert_eq!(payload, b"es256");
+        assert_eq!(header.algorithm(), Some("ES256"));
+
+        let hs256 = jws::serialize_compact(
+            b"hs256",
+            &JwsHeader::new(),
+            &jws::HS256.signer_from_bytes(b"0123456789ABCDEF0123456789ABCDEF")?,
+        )?;
+        assert!(verifier.deserialize_compact(&hs256).is_err());
+
+        Ok(())
+    }
+
+    fn load_file(path: &str) -> Result<Vec<u8>> {
+        let mut pb = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        pb.push("data");
+        pb.push(path);
+
+        let data = fs::read(&pb)?;
+        Ok(data)
+    }
+}
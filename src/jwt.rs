@@ -32,6 +32,9 @@ pub fn encode_unsecured(payload: &JwtPayload, header: &JwsHeader) -> Result<Stri
 
 /// Return the string repsentation of the JWT with the siginig algorithm.
 ///
+/// The "typ" header claim is not set automatically, so it is only encoded when the
+/// header has it.
+///
 /// # Arguments
 ///
 /// * `payload` - The payload data.
@@ -47,6 +50,9 @@ pub fn encode_with_signer(
 
 /// Return the string repsentation of the JWT with the encrypting algorithm.
 ///
+/// The "typ" header claim is not set automatically, so it is only encoded when the
+/// header has it.
+///
 /// # Arguments
 ///
 /// * `payload` - The payload data.
@@ -60,6 +66,29 @@ pub fn encode_with_encrypter(
     DEFAULT_CONTEXT.encode_with_encrypter(payload, header, encrypter)
 }
 
+/// Return the string repsentation of the nested JWT that is signed and then encrypted.
+///
+/// The "cty" header claim of the JWE header is set to "JWT", and the "typ" header
+/// claims are left as they are in the headers.
+///
+/// # Arguments
+///
+/// * `payload` - The payload data.
+/// * `jws_header` - The JWS heaser claims.
+/// * `signer` - a signer object.
+/// * `jwe_header` - The JWE heaser claims.
+/// * `encrypter` - a encrypter object.
+pub fn encode_with_signer_and_encrypter(
+    payload: &JwtPayload,
+    jws_header: &JwsHeader,
+    signer: &dyn JwsSigner,
+    jwe_header: &JweHeader,
+    encrypter: &dyn JweEncrypter,
+) -> Result<String, JoseError> {
+    DEFAULT_CONTEXT
+        .encode_with_signer_and_encrypter(payload, jws_header, signer, jwe_header, encrypter)
+}
+
 /// Return the Jose header decoded from JWT.
 ///
 /// # Arguments
@@ -69,6 +98,24 @@ pub fn decode_header(input: impl AsRef<[u8]>) -> Result<Box<dyn JoseHeader>, Jos
     DEFAULT_CONTEXT.decode_header(input)
 }
 
+/// Return the JWS header decoded from a signed JWT without verifying the signature.
+///
+/// # Arguments
+///
+/// * `input` - a JWT string representation.
+pub fn decode_jws_header(input: impl AsRef<[u8]>) -> Result<JwsHeader, JoseError> {
+    DEFAULT_CONTEXT.decode_jws_header(input)
+}
+
+/// Return the JWE header decoded from a encrypted JWT without decrypting the content.
+///
+/// # Arguments
+///
+/// * `input` - a JWT string representation.
+pub fn decode_jwe_header(input: impl AsRef<[u8]>) -> Result<JweHeader, JoseError> {
+    DEFAULT_CONTEXT.decode_jwe_header(input)
+}
+
 /// Return the JWT object decoded with the "none" algorithm.
 ///
 /// # Arguments
@@ -125,6 +172,23 @@ where
     DEFAULT_CONTEXT.decode_with_verifier_in_jwk_set(input, jwk_set, selector)
 }
 
+/// Return the nested JWT object that is decrypted by the decrypter and then verified by the verifier.
+///
+/// The "cty" header claim of the JWE header must be "JWT".
+///
+/// # Arguments
+///
+/// * `input` - a JWT string representation.
+/// * `decrypter` - a decrypter of the decrypting algorithm.
+/// * `verifier` - a verifier of the signing algorithm.
+pub fn decode_with_decrypter_and_verifier(
+    input: impl AsRef<[u8]>,
+    decrypter: &dyn JweDecrypter,
+    verifier: &dyn JwsVerifier,
+) -> Result<(JwtPayload, JwsHeader, JweHeader), JoseError> {
+    DEFAULT_CONTEXT.decode_with_decrypter_and_verifier(input, decrypter, verifier)
+}
+
 /// Return the JWT object decoded by the selected decrypter.
 ///
 /// # Arguments
@@ -172,6 +236,21 @@ where
     DEFAULT_CONTEXT.decode_with_decrypter_in_jwk_set(input, jwk_set, selector)
 }
 
+/// Return the JWT object decrypted by a key in the JWK set.
+///
+/// See [`JwtContext::decode_with_jwk_set`] for the key selection.
+///
+/// # Arguments
+///
+/// * `input` - a JWT string representation.
+/// * `jwk_set` - a JWK set.
+pub fn decode_with_jwk_set(
+    input: impl AsRef<[u8]>,
+    jwk_set: &JwkSet,
+) -> Result<(JwtPayload, JweHeader), JoseError> {
+    DEFAULT_CONTEXT.decode_with_jwk_set(input, jwk_set)
+}
+
 #[cfg(test)]
 mod tests {
     use std::fs;
@@ -187,14 +266,15 @@ mod tests {
         ECDH_ES_A192KW, ECDH_ES_A256KW, PBES2_HS256_A128KW, PBES2_HS384_A192KW, PBES2_HS512_A256KW,
         RSA1_5, RSA_OAEP, RSA_OAEP_256,
     };
-    use crate::jwk::Jwk;
+    use crate::jwe::{JweEncrypter, JweHeader};
+    use crate::jwk::{Jwk, JwkSet};
     use crate::jws::{
         EdDSA, JwsHeader, ES256, ES256K, ES384, ES512, HS256, HS384, HS512, PS256, PS384, PS512,
         RS256, RS384, RS512,
     };
-    use crate::jwt::{self, JwtPayload};
+    use crate::jwt::{self, JwtContext, JwtPayload};
     use crate::util;
-    use crate::Value;
+    use crate::{JoseError, Value};
 
     #[test]
     fn test_decode_header() -> Result<()> {
@@ -209,6 +289,174 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_decode_jws_and_jwe_header() -> Result<()> {
+        let mut src_payload = JwtPayload::new();
+        src_payload.set_subject("test");
+
+        let key = util::random_bytes(32);
+
+        let mut jws_header = JwsHeader::new();
+        jws_header.set_key_id("jws-key");
+        let signer = HS256.signer_from_bytes(&key)?;
+        let jwt_string = jwt::encode_with_signer(&src_payload, &jws_header, &signer)?;
+
+        let header = jwt::decode_jws_header(&jwt_string)?;
+        assert_eq!(header.key_id(), Some("jws-key"));
+        assert_eq!(header.algorithm(), Some("HS256"));
+        assert!(jwt::decode_jwe_header(&jwt_string).is_err());
+
+        let mut jwe_header = JweHeader::new();
+        jwe_header.set_content_encryption("A128GCM");
+        jwe_header.set_key_id("jwe-key");
+        let encrypter = Dir.encrypter_from_bytes(util::random_bytes(16))?;
+        let jwt_string = jwt::encode_with_encrypter(&src_payload, &jwe_header, &encrypter)?;
+
+        let header = jwt::decode_jwe_header(&jwt_string)?;
+        assert_eq!(header.key_id(), Some("jwe-key"));
+        assert_eq!(header.algorithm(), Some("dir"));
+        assert!(jwt::decode_jws_header(&jwt_string).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_jwt_with_expected_token_type() -> Result<()> {
+        let key = util::random_bytes(32);
+        let signer = HS256.signer_from_bytes(&key)?;
+        let verifier = HS256.verifier_from_bytes(&key)?;
+        let payload = JwtPayload::new();
+
+        let mut context = JwtContext::new();
+        context.set_expected_token_type("at+jwt");
+        assert_eq!(context.expected_token_type(), Some("at+jwt"));
+
+        let mut header = JwsHeader::new();
+        header.set_token_type("at+JWT");
+        let jwt_string = jwt::encode_with_signer(&payload, &header, &signer)?;
+        context.decode_with_verifier(&jwt_string, &verifier)?;
+
+        header.set_token_type("application/at+jwt");
+        let jwt_string = jwt::encode_with_signer(&payload, &header, &signer)?;
+        context.decode_with_verifier(&jwt_string, &verifier)?;
+
+        header.set_token_type("JWT");
+        let jwt_string = jwt::encode_with_signer(&payload, &header, &signer)?;
+        assert!(matches!(
+            context.decode_with_verifier(&jwt_string, &verifier),
+            Err(JoseError::InvalidJwtFormat(_))
+        ));
+        jwt::decode_with_verifier(&jwt_string, &verifier)?;
+
+        let jwt_string = jwt::encode_with_signer(&payload, &JwsHeader::new(), &signer)?;
+        assert!(matches!(
+            context.decode_with_verifier(&jwt_string, &verifier),
+            Err(JoseError::InvalidJwtFormat(_))
+        ));
+
+        context.remove_expected_token_type();
+        context.decode_with_verifier(&jwt_string, &verifier)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_jwt_encode_with_token_type() -> Result<()> {
+        let key = util::random_bytes(32);
+        let signer = HS256.signer_from_bytes(&key)?;
+        let encrypter = Dir.encrypter_from_bytes(&key)?;
+        let payload = JwtPayload::new();
+
+        let mut jwe_header = JweHeader::new();
+        jwe_header.set_content_encryption("A256GCM");
+
+        let jwt_string = jwt::encode_with_signer(&payload, &JwsHeader::new(), &signer)?;
+        assert_eq!(jwt::decode_header(&jwt_string)?.claim("typ"), None);
+
+        let jwt_string = jwt::encode_with_encrypter(&payload, &jwe_header, &encrypter)?;
+        assert_eq!(jwt::decode_header(&jwt_string)?.claim("typ"), None);
+
+        let mut jws_header = JwsHeader::new();
+        jws_header.set_token_type("at+jwt");
+        let jwt_string = jwt::encode_with_signer(&payload, &jws_header, &signer)?;
+        assert_eq!(
+            jwt::decode_jws_header(&jwt_string)?.token_type(),
+            Some("at+jwt")
+        );
+
+        jwe_header.set_token_type("at+jwt");
+        let jwt_string = jwt::encode_with_encrypter(&payload, &jwe_header, &encrypter)?;
+        assert_eq!(
+            jwt::decode_jwe_header(&jwt_string)?.token_type(),
+            Some("at+jwt")
+        );
+
+        let jwt_string = jwt::encode_with_signer_and_encrypter(
+            &payload,
+            &JwsHeader::new(),
+            &signer,
+            &jwe_header,
+            &encrypter,
+        )?;
+        let header = jwt::decode_jwe_header(&jwt_string)?;
+        assert_eq!(header.token_type(), Some("at+jwt"));
+        assert_eq!(header.content_type(), Some("JWT"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_jwt_with_auto_issued_at() -> Result<()> {
+        let key = util::random_bytes(32);
+        let signer = HS256.signer_from_bytes(&key)?;
+        let verifier = HS256.verifier_from_bytes(&key)?;
+        let header = JwsHeader::new();
+
+        let mut context = JwtContext::new();
+        context.set_auto_issued_at(true);
+        context.set_expires_in(Duration::from_secs(60));
+        assert!(context.is_auto_issued_at());
+        assert_eq!(context.expires_in(), Some(Duration::from_secs(60)));
+
+        let before = SystemTime::now() - Duration::from_secs(1);
+        let jwt_string = context.encode_with_signer(&JwtPayload::new(), &header, &signer)?;
+        let (payload, _) = jwt::decode_with_verifier(&jwt_string, &verifier)?;
+        let issued_at = payload.issued_at().unwrap();
+        assert!(issued_at >= before && issued_at <= SystemTime::now());
+        assert_eq!(
+            payload.expires_at(),
+            Some(issued_at + Duration::from_secs(60))
+        );
+
+        let iat = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000_000);
+        let mut src_payload = JwtPayload::new();
+        src_payload.set_issued_at(&iat);
+        let jwt_string = context.encode_with_signer(&src_payload, &header, &signer)?;
+        let (payload, _) = jwt::decode_with_verifier(&jwt_string, &verifier)?;
+        assert_eq!(payload.issued_at(), Some(iat));
+        assert_eq!(payload.expires_at(), Some(iat + Duration::from_secs(60)));
+
+        let exp = iat + Duration::from_secs(10);
+        src_payload.set_expires_at(&exp);
+        let encrypter = Dir.encrypter_from_bytes(&key)?;
+        let decrypter = Dir.decrypter_from_bytes(&key)?;
+        let mut jwe_header = JweHeader::new();
+        jwe_header.set_content_encryption("A128CBC-HS256");
+        let jwt_string = context.encode_with_encrypter(&src_payload, &jwe_header, &encrypter)?;
+        let (payload, _) = jwt::decode_with_decrypter(&jwt_string, &decrypter)?;
+        assert_eq!(payload.issued_at(), Some(iat));
+        assert_eq!(payload.expires_at(), Some(exp));
+
+        context.set_auto_issued_at(false);
+        context.remove_expires_in();
+        let jwt_string = context.encode_with_signer(&JwtPayload::new(), &header, &signer)?;
+        let (payload, _) = jwt::decode_with_verifier(&jwt_string, &verifier)?;
+        assert_eq!(payload.issued_at(), None);
+        assert_eq!(payload.expires_at(), None);
+
+        Ok(())
+    }
+
     #[test]
     fn test_jwt_unsecured() -> Result<()> {
         let mut src_header = JwsHeader::new();
@@ -234,7 +482,12 @@ mod tests {
         let jwt_string = jwt::encode_with_signer(&src_payload, &src_header, &signer)?;
 
         let verifier = alg.verifier();
-        let (dst_payload, dst_header) = jwt::decode_with_verifier(&jwt_string, &verifier)?;
+        match jwt::decode_with_verifier(&jwt_string, &verifier) {
+            Err(JoseError::InvalidJwsFormat(_)) => {}
+            result => panic!("unexpected result: {:?}", result),
+        }
+
+        let (dst_payload, dst_header) = jwt::decode_unsecured(&jwt_string)?;
 
         src_header.set_claim("alg", Some(json!(alg.name())))?;
         assert_eq!(src_header, dst_header);
@@ -243,6 +496,32 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_jwt_reject_none_with_verifier() -> Result<()> {
+        let private_key = util::random_bytes(64);
+
+        let mut src_header = JwsHeader::new();
+        src_header.set_token_type("JWT");
+        let src_payload = JwtPayload::new();
+        let signer = HS256.signer_from_bytes(&private_key)?;
+        let jwt_string = jwt::encode_with_signer(&src_payload, &src_header, &signer)?;
+
+        // Replace the header with alg "none" and strip the signature.
+        let parts: Vec<&str> = jwt_string.split('.').collect();
+        let header =
+            base64::encode_config(r#"{"typ":"JWT","alg":"none"}"#, base64::URL_SAFE_NO_PAD);
+        let unsecured_jwt = format!("{}.{}.", header, parts[1]);
+
+        let verifier = HS256.verifier_from_bytes(&private_key)?;
+        match jwt::decode_with_verifier(&unsecured_jwt, &verifier) {
+            Err(JoseError::InvalidJwsFormat(_)) => {}
+            result => panic!("unexpected result: {:?}", result),
+        }
+        jwt::decode_unsecured(&unsecured_jwt)?;
+
+        Ok(())
+    }
+
     #[test]
     fn test_jwt_with_hmac() -> Result<()> {
         for alg in &[HS256, HS384, HS512] {
@@ -410,6 +689,87 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_nested_jwt() -> Result<()> {
+        let mut src_payload = JwtPayload::new();
+        src_payload.set_subject("nested");
+
+        let mut jws_header = JwsHeader::new();
+        jws_header.set_token_type("JWT");
+        let mut jwe_header = JweHeader::new();
+        jwe_header.set_content_encryption("A256GCM");
+
+        let signer = ES256.signer_from_pem(&load_file("pem/EC_P-256_private.pem")?)?;
+        let encrypter =
+            RSA_OAEP_256.encrypter_from_pem(&load_file("pem/RSA_2048bit_public.pem")?)?;
+        let jwt_string = jwt::encode_with_signer_and_encrypter(
+            &src_payload,
+            &jws_header,
+            &signer,
+            &jwe_header,
+            &encrypter,
+        )?;
+
+        let verifier = ES256.verifier_from_pem(&load_file("pem/EC_P-256_public.pem")?)?;
+        let decrypter =
+            RSA_OAEP_256.decrypter_from_pem(&load_file("pem/RSA_2048bit_private.pem")?)?;
+        let (dst_payload, dst_jws_header, dst_jwe_header) =
+            jwt::decode_with_decrypter_and_verifier(&jwt_string, &decrypter, &verifier)?;
+        assert_eq!(src_payload, dst_payload);
+        assert_eq!(dst_jws_header.algorithm(), Some("ES256"));
+        assert_eq!(dst_jwe_header.algorithm(), Some("RSA-OAEP-256"));
+        assert_eq!(dst_jwe_header.content_type(), Some("JWT"));
+
+        let jwe_string = jwt::encode_with_encrypter(&src_payload, &jwe_header, &encrypter)?;
+        assert!(
+            jwt::decode_with_decrypter_and_verifier(&jwe_string, &decrypter, &verifier).is_err()
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_jwt_decode_with_jwk_set() -> Result<()> {
+        let mut src_payload = JwtPayload::new();
+        src_payload.set_subject("jwk set");
+
+        let mut jwk_set = JwkSet::new();
+        for (kid, file) in [
+            ("rsa", "jwk/RSA_private.jwk"),
+            ("ec-1", "jwk/EC_P-256_private.jwk"),
+            ("ec-2", "jwk/EC_P-384_private.jwk"),
+        ] {
+            let mut jwk = Jwk::from_bytes(&load_file(file)?)?;
+            jwk.set_key_id(kid);
+            jwk_set.push_key(jwk);
+        }
+
+        let mut header = JweHeader::new();
+        header.set_content_encryption("A128GCM");
+
+        let public_key = |kid: &str| jwk_set.get_by_kid(kid).unwrap().to_public_key();
+        let encrypters: Vec<Box<dyn JweEncrypter>> = vec![
+            Box::new(RSA_OAEP.encrypter_from_jwk(&public_key("rsa")?)?),
+            Box::new(ECDH_ES_A128KW.encrypter_from_jwk(&public_key("ec-1")?)?),
+            Box::new(ECDH_ES.encrypter_from_jwk(&public_key("ec-2")?)?),
+        ];
+        for encrypter in &encrypters {
+            let jwt_string = jwt::encode_with_encrypter(&src_payload, &header, &**encrypter)?;
+
+            let (dst_payload, dst_header) = jwt::decode_with_jwk_set(&jwt_string, &jwk_set)?;
+            assert_eq!(src_payload, dst_payload);
+            assert_eq!(dst_header.algorithm(), Some(encrypter.algorithm().name()));
+            assert_eq!(dst_header.key_id(), encrypter.key_id());
+        }
+
+        // The "kid" header claim names another key in the set.
+        header.set_key_id("ec-1");
+        let jwt_string = jwt::encode_with_encrypter(&src_payload, &header, &*encrypters[2])?;
+        assert!(jwt::decode_with_jwk_set(&jwt_string, &jwk_set).is_err());
+
+        Ok(())
+    }
+
     #[test]
     fn test_external_jwt_verify_with_hmac() -> Result<()> {
         let jwk = Jwk::from_bytes(&load_file("jwk/oct_512bit_private.jwk")?)?;
@@ -774,7 +1134,7 @@ mod tests {
                     )?;
 
                     let jwk = Jwk::from_bytes(&jwk)?;
-                    let decrypter = alg.decrypter_from_jwk(&jwk)?;
+                    let decrypter = alg.decrypter_from_jwk_insecure(&jwk)?;
                     let jwt_string = String::from_utf8(external_jwt)?;
                     let (payload, header) = jwt::decode_with_decrypter(&jwt_string, &decrypter)?;
 
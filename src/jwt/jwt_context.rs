@@ -1,15 +1,22 @@
-use anyhow::bail;
+use std::borrow::Cow;
+use std::time::{Duration, SystemTime};
+
+use anyhow::{anyhow, bail};
 
 use crate::jwe::{JweContext, JweDecrypter, JweEncrypter, JweHeader};
 use crate::jwk::{Jwk, JwkSet};
 use crate::jws::{JwsContext, JwsHeader, JwsSigner, JwsVerifier};
 use crate::jwt::{self, JwtPayload};
+use crate::util;
 use crate::{JoseError, JoseHeader, Map, Value};
 
 #[derive(Debug, Eq, PartialEq, Clone)]
 pub struct JwtContext {
     jws_context: JwsContext,
     jwe_context: JweContext,
+    expected_token_type: Option<String>,
+    auto_issued_at: bool,
+    expires_in: Option<Duration>,
 }
 
 impl JwtContext {
@@ -17,6 +24,9 @@ impl JwtContext {
         Self {
             jws_context: JwsContext::new(),
             jwe_context: JweContext::new(),
+            expected_token_type: None,
+            auto_issued_at: false,
+            expires_in: None,
         }
     }
 
@@ -49,6 +59,85 @@ impl JwtContext {
         self.jwe_context.remove_acceptable_critical(name);
     }
 
+    /// Set a token type that the "typ" header claim of a decoded JWT must be equal to.
+    ///
+    /// The comparison is case-insensitive and ignores the "application/" prefix.
+    ///
+    /// # Arguments
+    ///
+    /// * `value` - a expected token type (e.g. "at+jwt")
+    pub fn set_expected_token_type(&mut self, value: impl Into<String>) {
+        self.expected_token_type = Some(value.into());
+    }
+
+    /// Remove the expected token type.
+    pub fn remove_expected_token_type(&mut self) {
+        self.expected_token_type = None;
+    }
+
+    /// Return the token type that the "typ" header claim of a decoded JWT must be equal to.
+    pub fn expected_token_type(&self) -> Option<&str> {
+        self.expected_token_type.as_deref()
+    }
+
+    /// Set whether the issued at payload claim (iat) of an encoded JWT is set to
+    /// the current time when the payload doesn't have it.
+    ///
+    /// # Arguments
+    ///
+    /// * `value` - true to set the iat payload claim automatically
+    pub fn set_auto_issued_at(&mut self, value: bool) {
+        self.auto_issued_at = value;
+    }
+
+    /// Return whether the issued at payload claim (iat) is set automatically.
+    pub fn is_auto_issued_at(&self) -> bool {
+        self.auto_issued_at
+    }
+
+    /// Set a lifetime of an encoded JWT.
+    ///
+    /// When the payload doesn't have the expires at payload claim (exp), it is set to
+    /// the issued at payload claim (iat) plus the lifetime, or the current time plus
+    /// the lifetime if the payload doesn't have iat.
+    ///
+    /// # Arguments
+    ///
+    /// * `value` - a lifetime of a JWT
+    pub fn set_expires_in(&mut self, value: Duration) {
+        self.expires_in = Some(value);
+    }
+
+    /// Remove the lifetime of an encoded JWT.
+    pub fn remove_expires_in(&mut self) {
+        self.expires_in = None;
+    }
+
+    /// Return the lifetime of an encoded JWT.
+    pub fn expires_in(&self) -> Option<Duration> {
+        self.expires_in
+    }
+
+    /// Return the payload that the iat and exp payload claims are populated.
+    fn populate_payload<'a>(&self, payload: &'a JwtPayload) -> Cow<'a, JwtPayload> {
+        let populate_iat = self.auto_issued_at && payload.issued_at().is_none();
+        let populate_exp = self.expires_in.is_some() && payload.expires_at().is_none();
+        if !populate_iat && !populate_exp {
+            return Cow::Borrowed(payload);
+        }
+
+        let now = SystemTime::now();
+        let mut payload = payload.clone();
+        if populate_iat {
+            payload.set_issued_at(&now);
+        }
+        if let (true, Some(expires_in)) = (populate_exp, self.expires_in) {
+            let issued_at = payload.issued_at().unwrap_or(now);
+            payload.set_expires_at(&(issued_at + expires_in));
+        }
+        Cow::Owned(payload)
+    }
+
     /// Return the string repsentation of the JWT with a "none" algorithm.
     ///
     /// # Arguments
@@ -65,6 +154,9 @@ impl JwtContext {
 
     /// Return the string repsentation of the JWT with the siginig algorithm.
     ///
+    /// The "typ" header claim is not set automatically, so it is only encoded when the
+    /// header has it.
+    ///
     /// # Arguments
     ///
     /// * `payload` - The payload data.
@@ -83,6 +175,7 @@ impl JwtContext {
                 }
             }
 
+            let payload = self.populate_payload(payload);
             let payload_bytes = serde_json::to_vec(payload.claims_set()).unwrap();
             let jwt = self
                 .jws_context
@@ -97,6 +190,9 @@ impl JwtContext {
 
     /// Return the string repsentation of the JWT with the encrypting algorithm.
     ///
+    /// The "typ" header claim is not set automatically, so it is only encoded when the
+    /// header has it.
+    ///
     /// # Arguments
     ///
     /// * `payload` - The payload data.
@@ -108,6 +204,7 @@ impl JwtContext {
         header: &JweHeader,
         encrypter: &dyn JweEncrypter,
     ) -> Result<String, JoseError> {
+        let payload = self.populate_payload(payload);
         let payload_bytes = serde_json::to_vec(payload.claims_set()).unwrap();
         let jwt = self
             .jwe_context
@@ -115,6 +212,36 @@ impl JwtContext {
         Ok(jwt)
     }
 
+    /// Return the string repsentation of the nested JWT that is signed and then encrypted.
+    ///
+    /// The "cty" header claim of the JWE header is set to "JWT", and the "typ" header
+    /// claims are left as they are in the headers.
+    ///
+    /// # Arguments
+    ///
+    /// * `payload` - The payload data.
+    /// * `jws_header` - The JWS heaser claims.
+    /// * `signer` - a signer object.
+    /// * `jwe_header` - The JWE heaser claims.
+    /// * `encrypter` - a encrypter object.
+    pub fn encode_with_signer_and_encrypter(
+        &self,
+        payload: &JwtPayload,
+        jws_header: &JwsHeader,
+        signer: &dyn JwsSigner,
+        jwe_header: &JweHeader,
+        encrypter: &dyn JweEncrypter,
+    ) -> Result<String, JoseError> {
+        let jws = self.encode_with_signer(payload, jws_header, signer)?;
+
+        let mut jwe_header = jwe_header.clone();
+        jwe_header.set_content_type("JWT");
+        let jwt = self
+            .jwe_context
+            .serialize_compact(jws.as_bytes(), &jwe_header, encrypter)?;
+        Ok(jwt)
+    }
+
     /// Return the Jose header decoded from JWT.
     ///
     /// # Arguments
@@ -127,13 +254,13 @@ impl JwtContext {
             if parts.len() == 3 {
                 // JWS
                 let header = base64::decode_config(parts[0], base64::URL_SAFE_NO_PAD)?;
-                let header: Map<String, Value> = serde_json::from_slice(&header)?;
+                let header = util::parse_json_object(&header)?;
                 let header = JwsHeader::from_map(header)?;
                 Ok(Box::new(header))
             } else if parts.len() == 5 {
                 // JWE
                 let header = base64::decode_config(parts[0], base64::URL_SAFE_NO_PAD)?;
-                let header: Map<String, Value> = serde_json::from_slice(&header)?;
+                let header = util::parse_json_object(&header)?;
                 let header = JweHeader::from_map(header)?;
                 Ok(Box::new(header))
             } else {
@@ -146,8 +273,65 @@ impl JwtContext {
         })
     }
 
+    /// Return the JWS header decoded from a signed JWT without verifying the signature.
+    ///
+    /// This is useful for selecting a key by the "kid" or "alg" header claims
+    /// before verification.
+    ///
+    /// # Arguments
+    ///
+    /// * `input` - a JWT string representation.
+    pub fn decode_jws_header(&self, input: impl AsRef<[u8]>) -> Result<JwsHeader, JoseError> {
+        (|| -> anyhow::Result<JwsHeader> {
+            let input = input.as_ref();
+            let parts: Vec<&[u8]> = input.split(|b| *b == b'.').collect();
+            if parts.len() != 3 {
+                bail!("The input cannot be recognized as a signed JWT.");
+            }
+
+            let header = base64::decode_config(parts[0], base64::URL_SAFE_NO_PAD)?;
+            let header = util::parse_json_object(&header)?;
+            let header = JwsHeader::from_map(header)?;
+            Ok(header)
+        })()
+        .map_err(|err| match err.downcast::<JoseError>() {
+            Ok(err) => err,
+            Err(err) => JoseError::InvalidJwtFormat(err),
+        })
+    }
+
+    /// Return the JWE header decoded from a encrypted JWT without decrypting the content.
+    ///
+    /// This is useful for selecting a key by the "kid" or "alg" header claims
+    /// before decryption.
+    ///
+    /// # Arguments
+    ///
+    /// * `input` - a JWT string representation.
+    pub fn decode_jwe_header(&self, input: impl AsRef<[u8]>) -> Result<JweHeader, JoseError> {
+        (|| -> anyhow::Result<JweHeader> {
+            let input = input.as_ref();
+            let parts: Vec<&[u8]> = input.split(|b| *b == b'.').collect();
+            if parts.len() != 5 {
+                bail!("The input cannot be recognized as a encrypted JWT.");
+            }
+
+            let header = base64::decode_config(parts[0], base64::URL_SAFE_NO_PAD)?;
+            let header = util::parse_json_object(&header)?;
+            let header = JweHeader::from_map(header)?;
+            Ok(header)
+        })()
+        .map_err(|err| match err.downcast::<JoseError>() {
+            Ok(err) => err,
+            Err(err) => JoseError::InvalidJwtFormat(err),
+        })
+    }
+
     /// Return the JWT object decoded with the "none" algorithm.
     ///
+    /// This is the only way to accept a unsecured JWT. The other decode functions
+    /// reject the "none" algorithm.
+    ///
     /// # Arguments
     ///
     /// * `input` - a JWT string representation.
@@ -155,7 +339,22 @@ impl JwtContext {
         &self,
         input: impl AsRef<[u8]>,
     ) -> Result<(JwtPayload, JwsHeader), JoseError> {
-        self.decode_with_verifier(input, &jwt::None.verifier())
+        (|| -> anyhow::Result<(JwtPayload, JwsHeader)> {
+            let (payload, header) = self
+                .jws_context
+                .deserialize_compact(input, &jwt::None.verifier())?;
+
+            self.validate_token_type(&header)?;
+
+            let payload: Map<String, Value> = serde_json::from_slice(&payload)?;
+            let payload = JwtPayload::from_map(payload)?;
+
+            Ok((payload, header))
+        })()
+        .map_err(|err| match err.downcast::<JoseError>() {
+            Ok(err) => err,
+            Err(err) => JoseError::InvalidJwtFormat(err),
+        })
     }
 
     /// Return the JWT object decoded by the selected verifier.
@@ -191,6 +390,13 @@ impl JwtContext {
                 self.jws_context
                     .deserialize_compact_with_selector(input, |header| {
                         (|| -> anyhow::Result<Option<&'a dyn JwsVerifier>> {
+                            if header.algorithm() == Some(jwt::None.name()) {
+                                return Err(JoseError::InvalidJwsFormat(anyhow!(
+                                    "The none algorithm is only accepted by decode_unsecured."
+                                ))
+                                .into());
+                            }
+
                             let verifier = match selector(&header)? {
                                 Some(val) => val,
                                 None => return Ok(None),
@@ -210,6 +416,8 @@ impl JwtContext {
                         })
                     })?;
 
+            self.validate_token_type(&header)?;
+
             let payload: Map<String, Value> = serde_json::from_slice(&payload)?;
             let payload = JwtPayload::from_map(payload)?;
 
@@ -252,6 +460,39 @@ impl JwtContext {
         })
     }
 
+    /// Return the nested JWT object that is decrypted by the decrypter and then verified by the verifier.
+    ///
+    /// The "cty" header claim of the JWE header must be "JWT".
+    ///
+    /// # Arguments
+    ///
+    /// * `input` - a JWT string representation.
+    /// * `decrypter` - a decrypter of the decrypting algorithm.
+    /// * `verifier` - a verifier of the signing algorithm.
+    pub fn decode_with_decrypter_and_verifier(
+        &self,
+        input: impl AsRef<[u8]>,
+        decrypter: &dyn JweDecrypter,
+        verifier: &dyn JwsVerifier,
+    ) -> Result<(JwtPayload, JwsHeader, JweHeader), JoseError> {
+        (|| -> anyhow::Result<(JwtPayload, JwsHeader, JweHeader)> {
+            let (jws, jwe_header) = self.jwe_context.deserialize_compact(input, decrypter)?;
+
+            match jwe_header.content_type() {
+                Some(val) if val.eq_ignore_ascii_case("JWT") => {}
+                Some(val) => bail!("The JWE cty header claim must be JWT: {}", val),
+                None => bail!("The JWE cty header claim is required for a nested JWT."),
+            }
+
+            let (payload, jws_header) = self.decode_with_verifier(&jws, verifier)?;
+            Ok((payload, jws_header, jwe_header))
+        })()
+        .map_err(|err| match err.downcast::<JoseError>() {
+            Ok(err) => err,
+            Err(err) => JoseError::InvalidJwtFormat(err),
+        })
+    }
+
     /// Return the JWT object decoded by the selected decrypter.
     ///
     /// # Arguments
@@ -292,6 +533,8 @@ impl JwtContext {
                         Ok(Some(decrypter))
                     })?;
 
+            self.validate_token_type(&header)?;
+
             let payload: Map<String, Value> = serde_json::from_slice(&payload)?;
             let payload = JwtPayload::from_map(payload)?;
 
@@ -333,4 +576,59 @@ impl JwtContext {
             Ok(None)
         })
     }
+
+    /// Return the JWT object decrypted by a key in the JWK set.
+    ///
+    /// The decrypter is made from a JWK that has the same key ID as the "kid" header
+    /// claim, or from a JWK that has no key ID when the header claim is missing, for
+    /// the "alg" header claim. See [`JweContext::deserialize_compact_with_jwk_set`].
+    ///
+    /// # Arguments
+    ///
+    /// * `input` - a JWT string representation.
+    /// * `jwk_set` - a JWK set.
+    pub fn decode_with_jwk_set(
+        &self,
+        input: impl AsRef<[u8]>,
+        jwk_set: &JwkSet,
+    ) -> Result<(JwtPayload, JweHeader), JoseError> {
+        (|| -> anyhow::Result<(JwtPayload, JweHeader)> {
+            let (payload, header) = self
+                .jwe_context
+                .deserialize_compact_with_jwk_set(input, jwk_set)?;
+
+            self.validate_token_type(&header)?;
+
+            let payload: Map<String, Value> = serde_json::from_slice(&payload)?;
+            let payload = JwtPayload::from_map(payload)?;
+
+            Ok((payload, header))
+        })()
+        .map_err(|err| match err.downcast::<JoseError>() {
+            Ok(err) => err,
+            Err(err) => JoseError::InvalidJwtFormat(err),
+        })
+    }
+
+    fn validate_token_type(&self, header: &dyn JoseHeader) -> anyhow::Result<()> {
+        let expected = match &self.expected_token_type {
+            Some(val) => val,
+            None => return Ok(()),
+        };
+
+        let normalize = |val: &str| -> String {
+            let val = val.to_ascii_lowercase();
+            match val.strip_prefix("application/") {
+                Some(val) => val.to_string(),
+                None => val,
+            }
+        };
+
+        match header.claim("typ") {
+            Some(Value::String(val)) if normalize(val) == normalize(expected) => Ok(()),
+            Some(Value::String(val)) => bail!("The typ header claim must be {}: {}", expected, val),
+            Some(_) => bail!("The typ header claim must be a string."),
+            None => bail!("The typ header claim is required: {}", expected),
+        }
+    }
 }
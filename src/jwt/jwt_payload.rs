@@ -117,25 +117,12 @@ impl JwtPayload {
     ///
     /// * `value` - A expiration time on or after which the JWT must not be accepted for processing.
     pub fn set_expires_at(&mut self, value: &SystemTime) {
-        let key = "exp".to_string();
-        let val = Number::from(
-            value
-                .duration_since(SystemTime::UNIX_EPOCH)
-                .unwrap()
-                .as_secs(),
-        );
-        self.claims.insert(key.clone(), Value::Number(val));
+        self.set_numeric_date("exp", value);
     }
 
     /// Return the system time for expires at payload claim (exp).
     pub fn expires_at(&self) -> Option<SystemTime> {
-        match self.claims.get("exp") {
-            Some(Value::Number(val)) => match val.as_u64() {
-                Some(val) => Some(SystemTime::UNIX_EPOCH + Duration::from_secs(val)),
-                None => None,
-            },
-            _ => None,
-        }
+        self.numeric_date("exp")
     }
 
     /// Set a system time for not before payload claim (nbf).
@@ -144,25 +131,35 @@ impl JwtPayload {
     ///
     /// * `value` - A time before which the JWT must not be accepted for processing.
     pub fn set_not_before(&mut self, value: &SystemTime) {
-        let key = "nbf".to_string();
-        let val = Number::from(
-            value
-                .duration_since(SystemTime::UNIX_EPOCH)
-                .unwrap()
-                .as_secs(),
-        );
-        self.claims.insert(key.clone(), Value::Number(val));
+        self.set_numeric_date("nbf", value);
     }
 
     /// Return the system time for not before payload claim (nbf).
     pub fn not_before(&self) -> Option<SystemTime> {
-        match self.claims.get("nbf") {
-            Some(Value::Number(val)) => match val.as_u64() {
-                Some(val) => Some(SystemTime::UNIX_EPOCH + Duration::from_secs(val)),
-                None => None,
-            },
-            _ => None,
-        }
+        self.numeric_date("nbf")
+    }
+
+    /// Return whether the JWT is expired at the time, or None when the expires at
+    /// payload claim (exp) is missing.
+    ///
+    /// The JWT is expired at and after the time of the claim, as in JwtPayloadValidator
+    /// without a clock skew.
+    ///
+    /// # Arguments
+    ///
+    /// * `at` - a time at which the JWT is checked.
+    pub fn is_expired(&self, at: SystemTime) -> Option<bool> {
+        self.expires_at().map(|expires_at| expires_at <= at)
+    }
+
+    /// Return whether the JWT is not yet valid at the time, or None when the not before
+    /// payload claim (nbf) is missing.
+    ///
+    /// # Arguments
+    ///
+    /// * `at` - a time at which the JWT is checked.
+    pub fn not_yet_valid(&self, at: SystemTime) -> Option<bool> {
+        self.not_before().map(|not_before| not_before > at)
     }
 
     /// Set a time for issued at payload claim (iat).
@@ -171,25 +168,12 @@ impl JwtPayload {
     ///
     /// * `value` - a time at which the JWT was issued.
     pub fn set_issued_at(&mut self, value: &SystemTime) {
-        let key = "iat".to_string();
-        let val = Number::from(
-            value
-                .duration_since(SystemTime::UNIX_EPOCH)
-                .unwrap()
-                .as_secs(),
-        );
-        self.claims.insert(key.clone(), Value::Number(val));
+        self.set_numeric_date("iat", value);
     }
 
     /// Return the time for a issued at payload claim (iat).
     pub fn issued_at(&self) -> Option<SystemTime> {
-        match self.claims.get("iat") {
-            Some(Value::Number(val)) => match val.as_u64() {
-                Some(val) => Some(SystemTime::UNIX_EPOCH + Duration::from_secs(val)),
-                None => None,
-            },
-            _ => None,
-        }
+        self.numeric_date("iat")
     }
 
     /// Set a value for JWT ID payload claim (jti).
@@ -210,27 +194,72 @@ impl JwtPayload {
         }
     }
 
-    /// Set a value for payload claim of a specified key.
+    /// Set a system time for payload claim of a specified key as a NumericDate.
+    ///
+    /// The value is stored as the integer number of seconds from the UNIX epoch,
+    /// so the fractional seconds are truncated.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - a key name of payload claim
+    /// * `value` - a system time
+    pub fn set_numeric_date(&mut self, key: &str, value: &SystemTime) {
+        let val = Number::from(
+            value
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .unwrap()
+                .as_secs(),
+        );
+        self.claims.insert(key.to_string(), Value::Number(val));
+    }
+
+    /// Return the system time for payload claim of a specified key as a NumericDate.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - a key name of payload claim
+    pub fn numeric_date(&self, key: &str) -> Option<SystemTime> {
+        match self.claims.get(key) {
+            Some(Value::Number(val)) => match val.as_u64() {
+                Some(val) => Some(SystemTime::UNIX_EPOCH + Duration::from_secs(val)),
+                None => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// Set a value for payload claim of a specified key, and return the previous value.
+    ///
+    /// The claim is removed when the value is None. An error is returned when a
+    /// registered claim has a value of the wrong type, and the claim is not changed then.
     ///
     /// # Arguments
     ///
     /// * `key` - a key name of payload claim
     /// * `value` - a typed value of payload claim
-    pub fn set_claim(&mut self, key: &str, value: Option<Value>) -> Result<(), JoseError> {
-        (|| -> anyhow::Result<()> {
-            match value {
-                Some(val) => {
-                    Self::check_claim(key, &val)?;
-                    self.claims.insert(key.to_string(), val);
-                }
-                None => {
-                    self.claims.remove(key);
-                }
-            }
+    pub fn set_claim(
+        &mut self,
+        key: &str,
+        value: Option<Value>,
+    ) -> Result<Option<Value>, JoseError> {
+        match value {
+            Some(val) => self.try_set_claim(key, val),
+            None => Ok(self.claims.remove(key)),
+        }
+    }
 
-            Ok(())
-        })()
-        .map_err(|err| JoseError::InvalidJwtFormat(err))
+    /// Set a value for payload claim of a specified key, and return the previous value.
+    ///
+    /// An error is returned when a registered claim has a value of the wrong type,
+    /// e.g. a string for "exp", and the claim is not changed then.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - a key name of payload claim
+    /// * `value` - a typed value of payload claim
+    pub fn try_set_claim(&mut self, key: &str, value: Value) -> Result<Option<Value>, JoseError> {
+        Self::check_claim(key, &value)?;
+        Ok(self.claims.insert(key.to_string(), value))
     }
 
     /// Return a value for payload claim of a specified key.
@@ -247,6 +276,11 @@ impl JwtPayload {
         &self.claims
     }
 
+    /// Consume the JWT payload and return the payload claims as a map.
+    pub fn into_map(self) -> Map<String, Value> {
+        self.claims
+    }
+
     fn check_claim(key: &str, value: &Value) -> Result<(), JoseError> {
         (|| -> anyhow::Result<()> {
             match key {
@@ -277,7 +311,7 @@ impl JwtPayload {
                             key
                         ),
                     },
-                    _ => bail!("The JWT {} header claim must be a string.", key),
+                    _ => bail!("The JWT {} payload claim must be a number.", key),
                 },
                 _ => {}
             }
@@ -309,7 +343,7 @@ impl Display for JwtPayload {
 
 #[cfg(test)]
 mod tests {
-    use std::time::SystemTime;
+    use std::time::{Duration, SystemTime};
 
     use anyhow::Result;
     use serde_json::json;
@@ -343,4 +377,119 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_payload_time_predicates() -> Result<()> {
+        let time = SystemTime::UNIX_EPOCH + Duration::from_secs(1000);
+        let before = time - Duration::from_secs(1);
+        let after = time + Duration::from_secs(1);
+
+        let mut payload = JwtPayload::new();
+        assert_eq!(payload.is_expired(time), None);
+        assert_eq!(payload.not_yet_valid(time), None);
+
+        payload.set_expires_at(&time);
+        assert_eq!(payload.is_expired(before), Some(false));
+        assert_eq!(payload.is_expired(time), Some(true));
+        assert_eq!(payload.is_expired(after), Some(true));
+        assert_eq!(payload.not_yet_valid(time), None);
+
+        payload.set_not_before(&time);
+        assert_eq!(payload.not_yet_valid(before), Some(true));
+        assert_eq!(payload.not_yet_valid(time), Some(false));
+        assert_eq!(payload.not_yet_valid(after), Some(false));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_payload_set_claim_returns_previous() -> Result<()> {
+        let mut payload = JwtPayload::new();
+        assert_eq!(payload.try_set_claim("role", json!("user"))?, None);
+        assert_eq!(
+            payload.try_set_claim("role", json!("admin"))?,
+            Some(json!("user"))
+        );
+        assert_eq!(payload.set_claim("role", None)?, Some(json!("admin")));
+        assert_eq!(payload.set_claim("role", None)?, None);
+
+        payload.set_expires_at(&SystemTime::UNIX_EPOCH);
+        assert!(payload.try_set_claim("exp", json!("1300819380")).is_err());
+        assert!(payload.set_claim("exp", Some(json!("1300819380"))).is_err());
+        assert_eq!(payload.expires_at(), Some(SystemTime::UNIX_EPOCH));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_payload_map_round_trip() -> Result<()> {
+        let map = json!({
+            "iss": "joe",
+            "aud": ["aud0", "aud1"],
+            "exp": 1300819380,
+            "nbf": 1300819000,
+            "custom": {"nested": [1, 2, 3]},
+            "flag": true,
+        });
+        let map = match map {
+            serde_json::Value::Object(val) => val,
+            _ => unreachable!(),
+        };
+
+        let payload = JwtPayload::from_map(map.clone())?;
+        assert_eq!(payload.issuer(), Some("joe"));
+        assert_eq!(payload.audience(), Some(vec!["aud0", "aud1"]));
+        assert_eq!(
+            payload.expires_at(),
+            Some(SystemTime::UNIX_EPOCH + Duration::from_secs(1300819380))
+        );
+        assert_eq!(payload.claim("flag"), Some(&json!(true)));
+        assert_eq!(payload.into_map(), map);
+
+        for (key, value) in [
+            ("exp", json!(1300819380.5)),
+            ("iat", json!(-1)),
+            ("nbf", json!("1300819380")),
+            ("iss", json!(1)),
+        ] {
+            let mut invalid = map.clone();
+            invalid.insert(key.to_string(), value);
+            assert!(JwtPayload::from_map(invalid).is_err());
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_payload_registered_claims() -> Result<()> {
+        let time = SystemTime::UNIX_EPOCH + Duration::new(1300819380, 500_000_000);
+        let seconds = SystemTime::UNIX_EPOCH + Duration::from_secs(1300819380);
+
+        let mut payload = JwtPayload::new();
+        payload.set_issuer("joe");
+        payload.set_subject("sub");
+        payload.set_audience(vec!["aud"]);
+        payload.set_expires_at(&time);
+        payload.set_not_before(&time);
+        payload.set_issued_at(&time);
+        payload.set_jwt_id("id-1");
+        payload.set_numeric_date("auth_time", &time);
+
+        assert_eq!(payload.issuer(), Some("joe"));
+        assert_eq!(payload.subject(), Some("sub"));
+        assert_eq!(payload.audience(), Some(vec!["aud"]));
+        assert_eq!(payload.expires_at(), Some(seconds));
+        assert_eq!(payload.not_before(), Some(seconds));
+        assert_eq!(payload.issued_at(), Some(seconds));
+        assert_eq!(payload.jwt_id(), Some("id-1"));
+        assert_eq!(payload.numeric_date("auth_time"), Some(seconds));
+        assert_eq!(payload.claim("exp"), Some(&json!(1300819380)));
+
+        let payload = JwtPayload::from_map(payload.claims_set().clone())?;
+        assert_eq!(payload.expires_at(), Some(seconds));
+        assert_eq!(payload.jwt_id(), Some("id-1"));
+        assert_eq!(payload.numeric_date("iss"), None);
+
+        Ok(())
+    }
 }
@@ -1,5 +1,5 @@
 use std::convert::Into;
-use std::time::SystemTime;
+use std::time::{Duration, SystemTime};
 
 use anyhow::bail;
 
@@ -12,7 +12,8 @@ pub struct JwtPayloadValidator {
     base_time: Option<SystemTime>,
     min_issued_time: Option<SystemTime>,
     max_issued_time: Option<SystemTime>,
-    audience: Option<String>,
+    clock_skew: Duration,
+    audiences: Vec<String>,
     claims: Map<String, Value>,
 }
 
@@ -23,21 +24,25 @@ impl JwtPayloadValidator {
             base_time: None,
             min_issued_time: None,
             max_issued_time: None,
-            audience: None,
+            clock_skew: Duration::from_secs(0),
+            audiences: Vec::new(),
             claims: Map::new(),
         }
     }
 
-    /// Set a base time for time related claims (exp, nbf) validation.
+    /// Set a base time for time related claims (exp, nbf, iat) validation.
+    ///
+    /// The claims are validated as of the base time instead of the current time,
+    /// and it is also the default maximum time for issued at payload claim (iat).
     ///
     /// # Arguments
     ///
-    /// * `base_time` - a min time
+    /// * `base_time` - a time as of which the JWT is validated
     pub fn set_base_time(&mut self, base_time: SystemTime) {
         self.base_time = Some(base_time);
     }
 
-    /// Return the base time for time related claims (exp, nbf) validation.
+    /// Return the base time for time related claims (exp, nbf, iat) validation.
     pub fn base_time(&self) -> Option<&SystemTime> {
         self.base_time.as_ref()
     }
@@ -70,8 +75,29 @@ impl JwtPayloadValidator {
         self.max_issued_time.as_ref()
     }
 
+    /// Set a clock skew for time related claims (exp, nbf, iat) validation.
+    ///
+    /// The expiration time is treated as valid until exp + clock skew, the not before
+    /// time is treated as valid from nbf - clock skew, and the issued at time is
+    /// accepted until the maximum issued time + clock skew. The default is zero.
+    ///
+    /// # Arguments
+    ///
+    /// * `clock_skew` - a clock skew
+    pub fn set_clock_skew(&mut self, clock_skew: Duration) {
+        self.clock_skew = clock_skew;
+    }
+
+    /// Return the clock skew for time related claims (exp, nbf, iat) validation.
+    pub fn clock_skew(&self) -> Duration {
+        self.clock_skew
+    }
+
     /// Set a value for issuer payload claim (iss) validation.
     ///
+    /// The issuer claim is compared with the value as an exact UTF-8 string,
+    /// without case folding, trimming or Unicode normalization.
+    ///
     /// # Arguments
     ///
     /// * `value` - a issuer
@@ -80,6 +106,17 @@ impl JwtPayloadValidator {
         self.claims.insert("iss".to_string(), Value::String(value));
     }
 
+    /// Require the issuer payload claim (iss) to be present and exactly equal to the value.
+    ///
+    /// This is the same as set_issuer.
+    ///
+    /// # Arguments
+    ///
+    /// * `value` - a issuer
+    pub fn require_issuer(&mut self, value: &str) {
+        self.set_issuer(value);
+    }
+
     /// Return the value for issuer payload claim (iss) validation.
     pub fn issuer(&self) -> Option<&str> {
         match self.claims.get("iss") {
@@ -90,6 +127,9 @@ impl JwtPayloadValidator {
 
     /// Set a value for subject payload claim (sub) validation.
     ///
+    /// The subject claim is compared with the value as an exact UTF-8 string,
+    /// without case folding, trimming or Unicode normalization.
+    ///
     /// # Arguments
     ///
     /// * `value` - a subject
@@ -108,20 +148,44 @@ impl JwtPayloadValidator {
 
     /// Set a value for audience payload claim (aud) validation.
     ///
+    /// This replaces the audiences that are added by add_audience.
+    /// When an audience is set, a payload without the audience payload claim
+    /// is rejected.
+    ///
     /// # Arguments
     ///
     /// * `value` - a audience
     pub fn set_audience(&mut self, value: impl Into<String>) {
+        self.audiences.clear();
+        self.audiences.push(value.into());
+    }
+
+    /// Add a acceptable value for audience payload claim (aud) validation.
+    ///
+    /// The validation succeeds when the audience payload claim contains
+    /// at least one of the acceptable values. The values are compared as exact
+    /// UTF-8 strings, without case folding, trimming or Unicode normalization.
+    /// When an audience is added, a payload without the audience payload claim
+    /// is rejected.
+    ///
+    /// # Arguments
+    ///
+    /// * `value` - a audience
+    pub fn add_audience(&mut self, value: impl Into<String>) {
         let value: String = value.into();
-        self.audience = Some(value);
+        if !self.audiences.contains(&value) {
+            self.audiences.push(value);
+        }
     }
 
-    /// Return the value for audience payload claim (aud) validation.
+    /// Return the first value for audience payload claim (aud) validation.
     pub fn audience(&self) -> Option<&str> {
-        match self.audience {
-            Some(ref val) => Some(val),
-            _ => None,
-        }
+        self.audiences.first().map(|val| val.as_str())
+    }
+
+    /// Return the acceptable values for audience payload claim (aud) validation.
+    pub fn audiences(&self) -> Vec<&str> {
+        self.audiences.iter().map(|val| val.as_str()).collect()
     }
 
     /// Set a value for JWT ID payload claim (jti) validation.
@@ -171,10 +235,15 @@ impl JwtPayloadValidator {
             let now = SystemTime::now();
             let current_time = self.base_time().unwrap_or(&now);
             let min_issued_time = self.min_issued_time().unwrap_or(&SystemTime::UNIX_EPOCH);
-            let max_issued_time = self.max_issued_time().unwrap_or(&now);
+            let max_issued_time = self.max_issued_time().unwrap_or(current_time);
 
             if let Some(not_before) = payload.not_before() {
-                if &not_before > current_time {
+                // A skew that overflows the time is satisfied by any not before time.
+                let is_valid = match current_time.checked_add(self.clock_skew) {
+                    Some(val) => not_before <= val,
+                    None => true,
+                };
+                if !is_valid {
                     bail!(
                         "The token is not yet valid: {}",
                         time::OffsetDateTime::from(not_before),
@@ -183,7 +252,12 @@ impl JwtPayloadValidator {
             }
 
             if let Some(expires_at) = payload.expires_at() {
-                if &expires_at <= current_time {
+                // A skew that overflows the time never expires the token.
+                let is_expired = match expires_at.checked_add(self.clock_skew) {
+                    Some(val) => val <= *current_time,
+                    None => false,
+                };
+                if is_expired {
                     bail!(
                         "The token has expired: {}",
                         time::OffsetDateTime::from(expires_at),
@@ -199,7 +273,12 @@ impl JwtPayloadValidator {
                     );
                 }
 
-                if &issued_at > max_issued_time {
+                // A skew that overflows the time accepts any issued time.
+                let is_too_new = match max_issued_time.checked_add(self.clock_skew) {
+                    Some(val) => issued_at > val,
+                    None => false,
+                };
+                if is_too_new {
                     bail!(
                         "The issued time is too new: {}",
                         time::OffsetDateTime::from(issued_at),
@@ -207,11 +286,17 @@ impl JwtPayloadValidator {
                 }
             }
 
-            if let Some(audience) = &self.audience {
-                if let Some(audiences) = payload.audience() {
-                    if !audiences.contains(&audience.as_str()) {
-                        bail!("Key aud is invalid: {}", audiences.join(", "));
+            if !self.audiences.is_empty() {
+                match payload.audience() {
+                    Some(audiences) => {
+                        if !audiences
+                            .iter()
+                            .any(|val| self.audiences.iter().any(|val2| val == val2))
+                        {
+                            bail!("Key aud is invalid: {}", audiences.join(", "));
+                        }
                     }
+                    None => bail!("Key aud is missing."),
                 }
             }
 
@@ -242,6 +327,7 @@ mod tests {
     use serde_json::json;
 
     use crate::jwt::{JwtPayload, JwtPayloadValidator};
+    use crate::JoseError;
 
     #[test]
     fn test_jwt_payload_validate() -> Result<()> {
@@ -264,4 +350,168 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_jwt_payload_validate_with_base_time() -> Result<()> {
+        let issued_at = SystemTime::UNIX_EPOCH + Duration::from_secs(1000);
+
+        let mut payload = JwtPayload::new();
+        payload.set_issued_at(&issued_at);
+        payload.set_not_before(&issued_at);
+        payload.set_expires_at(&(issued_at + Duration::from_secs(60)));
+
+        let mut validator = JwtPayloadValidator::new();
+        validator.set_base_time(issued_at + Duration::from_secs(30));
+        assert_eq!(
+            validator.base_time(),
+            Some(&(issued_at + Duration::from_secs(30)))
+        );
+        validator.validate(&payload)?;
+
+        validator.set_base_time(issued_at + Duration::from_secs(60));
+        assert!(validator.validate(&payload).is_err());
+
+        validator.set_base_time(issued_at - Duration::from_secs(1));
+        assert!(validator.validate(&payload).is_err());
+
+        let mut payload = JwtPayload::new();
+        payload.set_issued_at(&issued_at);
+        assert!(validator.validate(&payload).is_err());
+
+        validator.set_base_time(issued_at);
+        validator.validate(&payload)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_jwt_payload_validate_audiences() -> Result<()> {
+        let mut validator = JwtPayloadValidator::new();
+        validator.add_audience("aud0");
+        validator.add_audience("aud1");
+        assert_eq!(validator.audiences(), vec!["aud0", "aud1"]);
+
+        let mut payload = JwtPayload::new();
+        payload.set_audience(vec!["aud1"]);
+        validator.validate(&payload)?;
+
+        let mut payload = JwtPayload::new();
+        payload.set_claim("aud", Some(json!("aud0")))?;
+        validator.validate(&payload)?;
+
+        let mut payload = JwtPayload::new();
+        payload.set_audience(vec!["aud2", "aud1"]);
+        validator.validate(&payload)?;
+
+        let mut payload = JwtPayload::new();
+        payload.set_audience(vec!["aud2", "aud3"]);
+        assert!(validator.validate(&payload).is_err());
+
+        let payload = JwtPayload::new();
+        assert!(validator.validate(&payload).is_err());
+
+        validator.set_audience("aud2");
+        assert_eq!(validator.audiences(), vec!["aud2"]);
+        let mut payload = JwtPayload::new();
+        payload.set_audience(vec!["aud0", "aud1"]);
+        assert!(validator.validate(&payload).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_jwt_payload_validate_missing_audience() -> Result<()> {
+        let payload = JwtPayload::new();
+
+        let validator = JwtPayloadValidator::new();
+        validator.validate(&payload)?;
+
+        let mut validator = JwtPayloadValidator::new();
+        validator.set_audience("aud0");
+        match validator.validate(&payload) {
+            Err(JoseError::InvalidClaim(err)) => {
+                assert_eq!(err.to_string(), "Key aud is missing.")
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+
+        let mut validator = JwtPayloadValidator::new();
+        validator.add_audience("aud0");
+        match validator.validate(&payload) {
+            Err(JoseError::InvalidClaim(err)) => {
+                assert_eq!(err.to_string(), "Key aud is missing.")
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_jwt_payload_validate_with_clock_skew() -> Result<()> {
+        let now = SystemTime::UNIX_EPOCH + Duration::from_secs(1000);
+
+        let mut payload = JwtPayload::new();
+        payload.set_expires_at(&(now - Duration::from_secs(30)));
+
+        let mut validator = JwtPayloadValidator::new();
+        validator.set_base_time(now);
+        assert_eq!(validator.clock_skew(), Duration::from_secs(0));
+        assert!(validator.validate(&payload).is_err());
+
+        validator.set_clock_skew(Duration::from_secs(60));
+        validator.validate(&payload)?;
+
+        let mut payload = JwtPayload::new();
+        payload.set_not_before(&(now + Duration::from_secs(30)));
+        payload.set_issued_at(&(now + Duration::from_secs(30)));
+        validator.set_max_issued_time(now);
+        validator.validate(&payload)?;
+
+        validator.set_clock_skew(Duration::from_secs(0));
+        assert!(validator.validate(&payload).is_err());
+
+        validator.set_clock_skew(Duration::MAX);
+        validator.validate(&payload)?;
+        let mut payload = JwtPayload::new();
+        payload.set_expires_at(&(now - Duration::from_secs(30)));
+        validator.validate(&payload)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_jwt_payload_validate_exact_match() -> Result<()> {
+        let mut validator = JwtPayloadValidator::new();
+        validator.require_issuer("https://issuer.example.com");
+        validator.set_subject("user");
+        validator.set_audience("Client");
+        assert_eq!(validator.issuer(), Some("https://issuer.example.com"));
+
+        let mut payload = JwtPayload::new();
+        payload.set_issuer("https://issuer.example.com");
+        payload.set_subject("user");
+        payload.set_audience(vec!["Client"]);
+        validator.validate(&payload)?;
+
+        for (key, value) in &[
+            ("iss", " https://issuer.example.com"),
+            ("iss", "https://ISSUER.example.com"),
+            ("sub", " user"),
+            ("sub", "User"),
+            ("aud", " Client"),
+            ("aud", "client"),
+        ] {
+            let mut invalid_payload = payload.clone();
+            invalid_payload.set_claim(key, Some(json!(value)))?;
+            assert!(validator.validate(&invalid_payload).is_err());
+        }
+
+        let mut payload = JwtPayload::new();
+        payload.set_subject("user");
+        payload.set_audience(vec!["Client"]);
+        assert!(validator.validate(&payload).is_err());
+
+        Ok(())
+    }
 }
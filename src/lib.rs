@@ -10,9 +10,11 @@ pub mod util;
 
 mod jose_error;
 mod jose_header;
+mod jose_random;
 
 pub use crate::jose_error::JoseError;
 pub use crate::jose_header::JoseHeader;
+pub use crate::jose_random::{JoseRandom, OsRandom};
 
 pub use serde_json::{Map, Number, Value};
 
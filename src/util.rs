@@ -1,13 +1,21 @@
+pub mod base64url_decoder;
 pub mod der;
 pub mod hash_algorithm;
 pub mod oid;
 
+use std::fmt;
+
 use anyhow::bail;
 use once_cell::sync::Lazy;
 use openssl::bn::BigNumRef;
+use openssl::memcmp;
 use openssl::rand;
+use serde::de::{self, Deserialize, Deserializer, MapAccess, SeqAccess, Visitor};
+
+use crate::{Map, Value};
 use regex::{self, bytes};
 
+pub use crate::util::base64url_decoder::Base64UrlDecoder;
 pub use crate::util::hash_algorithm::HashAlgorithm;
 
 pub use HashAlgorithm::Sha1 as SHA_1;
@@ -21,6 +29,190 @@ pub fn random_bytes(len: usize) -> Vec<u8> {
     vec
 }
 
+/// Compare two byte slices in constant time.
+///
+/// The time taken by the comparison depends only on the lengths of the slices,
+/// not on their contents. Use this instead of `==` when comparing secret-dependent
+/// data such as authentication tags, so that an attacker can't learn how many
+/// leading bytes of a forged value are correct by measuring the response time.
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && memcmp::eq(a, b)
+}
+
+/// Encode the input by base64url without padding (RFC 7515 Section 2).
+///
+/// # Arguments
+///
+/// * `input` - The input data.
+pub fn base64url_encode(input: impl AsRef<[u8]>) -> String {
+    base64::encode_config(input, base64::URL_SAFE_NO_PAD)
+}
+
+/// Decode the input that is encoded by base64url without padding (RFC 7515 Section 2).
+///
+/// A padding character, a character of the standard base64 alphabet ('+' and '/'),
+/// a whitespace and non-zero trailing bits are rejected.
+///
+/// # Arguments
+///
+/// * `input` - The base64url encoded data.
+pub fn base64url_decode(input: impl AsRef<[u8]>) -> Result<Vec<u8>, base64::DecodeError> {
+    let input = input.as_ref();
+    if let Some(pos) = input.iter().position(|b| *b == b'=') {
+        return Err(base64::DecodeError::InvalidByte(pos, b'='));
+    }
+    base64::decode_config(input, base64::URL_SAFE_NO_PAD)
+}
+
+/// Return the thumbprint of a DER encoded X.509 certificate (RFC 7515 Section 4.1.7).
+///
+/// SHA-1 is used for the x5t header claim and SHA-256 for the x5t#S256 header claim.
+///
+/// # Arguments
+///
+/// * `hash` - The hash algorithm.
+/// * `der` - A DER encoded X.509 certificate.
+pub fn x509_thumbprint(hash: HashAlgorithm, der: impl AsRef<[u8]>) -> Vec<u8> {
+    openssl::hash::hash(hash.message_digest(), der.as_ref())
+        .unwrap()
+        .to_vec()
+}
+
+/// Return the canonical JSON representation of the value.
+///
+/// The members of every object are sorted by the UTF-16 code units of their names
+/// and no whitespace is emitted, as in JSON Canonicalization Scheme (RFC 8785).
+/// Strings and numbers are written as serde_json writes them, which is the same as
+/// RFC 8785 except for some floating point numbers.
+///
+/// # Arguments
+///
+/// * `value` - The JSON value.
+pub fn to_canonical_json(value: &Value) -> String {
+    let mut json = String::new();
+    write_canonical_json(value, &mut json);
+    json
+}
+
+fn write_canonical_json(value: &Value, json: &mut String) {
+    match value {
+        Value::Array(vals) => {
+            json.push('[');
+            for (i, val) in vals.iter().enumerate() {
+                if i > 0 {
+                    json.push(',');
+                }
+                write_canonical_json(val, json);
+            }
+            json.push(']');
+        }
+        Value::Object(map) => {
+            let mut entries: Vec<(&String, &Value)> = map.iter().collect();
+            entries.sort_by(|a, b| a.0.encode_utf16().cmp(b.0.encode_utf16()));
+
+            json.push('{');
+            for (i, (key, val)) in entries.into_iter().enumerate() {
+                if i > 0 {
+                    json.push(',');
+                }
+                json.push_str(&Value::String(key.clone()).to_string());
+                json.push(':');
+                write_canonical_json(val, json);
+            }
+            json.push('}');
+        }
+        _ => json.push_str(&value.to_string()),
+    }
+}
+
+/// Parse the input as a JSON object, rejecting a duplicate member name in any object.
+///
+/// serde_json keeps the last member of duplicate names silently, so the claims that
+/// a parser of another implementation sees may differ from the claims seen here.
+pub(crate) fn parse_json_object(input: &[u8]) -> Result<Map<String, Value>, serde_json::Error> {
+    match serde_json::from_slice::<UniqueKeyValue>(input)?.0 {
+        Value::Object(map) => Ok(map),
+        val => Err(de::Error::custom(format!(
+            "invalid type: {}, expected a JSON object",
+            val
+        ))),
+    }
+}
+
+struct UniqueKeyValue(Value);
+
+impl<'de> Deserialize<'de> for UniqueKeyValue {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(UniqueKeyVisitor)
+    }
+}
+
+struct UniqueKeyVisitor;
+
+impl<'de> Visitor<'de> for UniqueKeyVisitor {
+    type Value = UniqueKeyValue;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("any valid JSON value")
+    }
+
+    fn visit_bool<E>(self, value: bool) -> Result<Self::Value, E> {
+        Ok(UniqueKeyValue(Value::Bool(value)))
+    }
+
+    fn visit_i64<E>(self, value: i64) -> Result<Self::Value, E> {
+        Ok(UniqueKeyValue(Value::from(value)))
+    }
+
+    fn visit_u64<E>(self, value: u64) -> Result<Self::Value, E> {
+        Ok(UniqueKeyValue(Value::from(value)))
+    }
+
+    fn visit_f64<E>(self, value: f64) -> Result<Self::Value, E> {
+        Ok(UniqueKeyValue(Value::from(value)))
+    }
+
+    fn visit_str<E>(self, value: &str) -> Result<Self::Value, E> {
+        Ok(UniqueKeyValue(Value::String(value.to_string())))
+    }
+
+    fn visit_string<E>(self, value: String) -> Result<Self::Value, E> {
+        Ok(UniqueKeyValue(Value::String(value)))
+    }
+
+    fn visit_unit<E>(self) -> Result<Self::Value, E> {
+        Ok(UniqueKeyValue(Value::Null))
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let mut vec = Vec::new();
+        while let Some(UniqueKeyValue(val)) = seq.next_element()? {
+            vec.push(val);
+        }
+        Ok(UniqueKeyValue(Value::Array(vec)))
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        let mut values = Map::new();
+        while let Some(key) = map.next_key::<String>()? {
+            if values.contains_key(&key) {
+                return Err(de::Error::custom(format!(
+                    "A duplicate key exists: {}",
+                    key
+                )));
+            }
+            let UniqueKeyValue(val) = map.next_value()?;
+            values.insert(key, val);
+        }
+        Ok(UniqueKeyValue(Value::Object(values)))
+    }
+}
+
+/// Return whether the input starts with "{" after any leading whitespace.
+pub(crate) fn is_json_object(input: &[u8]) -> bool {
+    input.iter().find(|b| !b.is_ascii_whitespace()) == Some(&b'{')
+}
+
 pub(crate) fn ceiling(len: usize, div: usize) -> usize {
     (len + (div - 1)) / div
 }
@@ -83,7 +275,12 @@ pub(crate) fn num_to_vec(num: &BigNumRef, len: usize) -> Vec<u8> {
 
 #[cfg(test)]
 mod tests {
-    use super::is_base64_url_safe_nopad;
+    use serde_json::json;
+
+    use super::{
+        base64url_decode, base64url_encode, constant_time_eq, is_base64_url_safe_nopad,
+        parse_json_object, to_canonical_json,
+    };
 
     #[test]
     fn test_is_base64_url_safe_nopad() {
@@ -94,4 +291,75 @@ mod tests {
         assert!(!is_base64_url_safe_nopad("MDEyMzQ1Njc4OQ="));
         assert!(!is_base64_url_safe_nopad("MDEyMzQ1Njc4O"));
     }
+
+    #[test]
+    fn test_base64url() {
+        for input in &[&b""[..], b"f", b"fo", b"foo", b"foob", &[0xFB, 0xFF, 0xBF]] {
+            let encoded = base64url_encode(input);
+            assert!(!encoded.contains('='));
+            assert_eq!(base64url_decode(&encoded).unwrap(), *input);
+        }
+        assert_eq!(base64url_encode([0xFB, 0xFF, 0xBF]), "-_-_");
+
+        assert!(base64url_decode("+/+/").is_err());
+        assert!(base64url_decode("Zg+").is_err());
+        assert!(base64url_decode("Zm/v").is_err());
+        assert!(base64url_decode("Zg==").is_err());
+        assert!(base64url_decode("Zg=").is_err());
+        assert!(base64url_decode("Zm9v=").is_err());
+        assert!(base64url_decode("Zm 9v").is_err());
+        assert!(base64url_decode("Zh").is_err());
+    }
+
+    #[test]
+    fn test_to_canonical_json() {
+        let value = json!({
+            "z": {"b": [3, {"y": null, "x": true}], "a": "\u{20ac}\n"},
+            "a": 1,
+            "\u{e9}": -0.5,
+            "B": "\"",
+            "\u{1f600}": [],
+            "\u{ff61}": {},
+        });
+        // U+1F600 is sorted before U+FF61 because it is a surrogate pair in UTF-16.
+        assert_eq!(
+            to_canonical_json(&value),
+            concat!(
+                "{\"B\":\"\\\"\",\"a\":1,\"z\":{\"a\":\"\u{20ac}\\n\",\"b\":[3,{\"x\":true,\"y\":null}]},",
+                "\"\u{e9}\":-0.5,\"\u{1f600}\":[],\"\u{ff61}\":{}}"
+            )
+        );
+
+        assert_eq!(to_canonical_json(&json!("text")), r#""text""#);
+        assert_eq!(to_canonical_json(&json!([])), "[]");
+    }
+
+    #[test]
+    fn test_parse_json_object() {
+        let map = parse_json_object(br#"{"a":[1,-2,0.5,"x",null],"b":{"c":true}}"#).unwrap();
+        assert_eq!(
+            serde_json::Value::Object(map),
+            json!({"a": [1, -2, 0.5, "x", null], "b": {"c": true}})
+        );
+
+        for input in [
+            &br#"{"alg":"HS256","alg":"none"}"#[..],
+            br#"{"a":{"b":1,"b":2}}"#,
+            br#"{"a":[{"b":1,"b":2}]}"#,
+        ] {
+            let err = parse_json_object(input).unwrap_err();
+            assert!(err.to_string().starts_with("A duplicate key exists: "));
+        }
+        assert!(parse_json_object(b"[]").is_err());
+        assert!(parse_json_object(b"{").is_err());
+    }
+
+    #[test]
+    fn test_constant_time_eq() {
+        assert!(constant_time_eq(b"", b""));
+        assert!(constant_time_eq(b"abcde", b"abcde"));
+        assert!(!constant_time_eq(b"abcde", b"abcdf"));
+        assert!(!constant_time_eq(b"abcde", b"abcd"));
+        assert!(!constant_time_eq(b"", b"a"));
+    }
 }
@@ -0,0 +1,134 @@
+use std::cmp;
+use std::io::{self, Read};
+
+/// A reader that decodes base64url without padding (RFC 7515 Section 2) from a string
+/// as it is read.
+///
+/// Only a group of four characters is decoded at a time, so a large segment, e.g. the
+/// ciphertext of a JWE, can be given to a stream decryption without decoding it all
+/// into memory. The same input as [`base64url_decode`](crate::util::base64url_decode)
+/// is rejected with an error of [`io::ErrorKind::InvalidData`].
+#[derive(Debug, Clone)]
+pub struct Base64UrlDecoder<'a> {
+    input: &'a [u8],
+    pos: usize,
+    pending: [u8; 3],
+    pending_pos: usize,
+    pending_len: usize,
+}
+
+impl<'a> Base64UrlDecoder<'a> {
+    /// Return a new decoder of the input.
+    ///
+    /// # Arguments
+    ///
+    /// * `input` - The base64url encoded data.
+    pub fn new(input: &'a str) -> Self {
+        Self {
+            input: input.as_bytes(),
+            pos: 0,
+            pending: [0; 3],
+            pending_pos: 0,
+            pending_len: 0,
+        }
+    }
+
+    fn decode(&self, input: &[u8], output: &mut [u8]) -> io::Result<usize> {
+        if let Some(pos) = input.iter().position(|b| *b == b'=') {
+            return Err(invalid_data(base64::DecodeError::InvalidByte(
+                self.pos + pos,
+                b'=',
+            )));
+        }
+        base64::decode_config_slice(input, base64::URL_SAFE_NO_PAD, output).map_err(invalid_data)
+    }
+}
+
+impl Read for Base64UrlDecoder<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut written = 0;
+
+        if self.pending_pos < self.pending_len {
+            let len = cmp::min(self.pending_len - self.pending_pos, buf.len());
+            buf[..len].copy_from_slice(&self.pending[self.pending_pos..(self.pending_pos + len)]);
+            self.pending_pos += len;
+            written += len;
+        }
+
+        // The full groups that fit in the buffer are decoded into it directly.
+        let remaining = &self.input[self.pos..];
+        let groups = cmp::min(remaining.len() / 4, (buf.len() - written) / 3);
+        if groups > 0 {
+            let len = self.decode(&remaining[..(groups * 4)], &mut buf[written..])?;
+            self.pos += groups * 4;
+            written += len;
+        }
+
+        // The next group is decoded into the pending bytes if the buffer has space left.
+        let remaining = &self.input[self.pos..];
+        if written < buf.len() && !remaining.is_empty() {
+            let group = &remaining[..cmp::min(remaining.len(), 4)];
+            let mut pending = [0; 3];
+            let len = self.decode(group, &mut pending)?;
+            self.pos += group.len();
+            self.pending = pending;
+            self.pending_len = len;
+
+            let copied = cmp::min(len, buf.len() - written);
+            buf[written..(written + copied)].copy_from_slice(&pending[..copied]);
+            self.pending_pos = copied;
+            written += copied;
+        }
+
+        Ok(written)
+    }
+}
+
+fn invalid_data(err: base64::DecodeError) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, err)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{ErrorKind, Read};
+
+    use anyhow::Result;
+
+    use super::Base64UrlDecoder;
+    use crate::util;
+
+    #[test]
+    fn test_base64url_decoder() -> Result<()> {
+        let data = util::random_bytes(100_000 + 2);
+        let encoded = util::base64url_encode(&data);
+
+        for chunk_size in [1, 2, 3, 4, 5, 7, 4096, 200_000] {
+            let mut decoder = Base64UrlDecoder::new(&encoded);
+            let mut decoded = Vec::new();
+            let mut chunk = vec![0; chunk_size];
+            loop {
+                let len = decoder.read(&mut chunk)?;
+                if len == 0 {
+                    break;
+                }
+                decoded.extend_from_slice(&chunk[..len]);
+            }
+            assert_eq!(decoded, util::base64url_decode(&encoded)?);
+        }
+
+        let mut decoded = Vec::new();
+        Base64UrlDecoder::new("").read_to_end(&mut decoded)?;
+        assert!(decoded.is_empty());
+
+        for input in ["Zg==", "Zg+", "Zh", "Zm9vY", "Zm 9v"] {
+            let mut decoded = Vec::new();
+            let err = Base64UrlDecoder::new(input)
+                .read_to_end(&mut decoded)
+                .unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::InvalidData);
+            assert!(util::base64url_decode(input).is_err());
+        }
+
+        Ok(())
+    }
+}
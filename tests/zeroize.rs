@@ -0,0 +1,76 @@
+//! Check that the key material is zeroed before it is deallocated.
+//!
+//! The check replaces the global allocator, so it runs in its own test binary.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use anyhow::Result;
+use josekit::jwe::Dir;
+
+/// The key to look for in the deallocated buffers.
+///
+/// It is a constant, so the original is never deallocated.
+const KEY: &[u8; 32] = b"josekit-zeroize-test-key-32bytes";
+
+/// A wrapper of the system allocator that checks whether a buffer that is
+/// deallocated while the check is enabled still contains the key.
+struct ZeroCheckAllocator;
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static FOUND: AtomicBool = AtomicBool::new(false);
+
+impl ZeroCheckAllocator {
+    /// Return true if the key was left in a buffer deallocated during the function.
+    fn leaks<F: FnOnce() -> Result<()>>(f: F) -> Result<bool> {
+        FOUND.store(false, Ordering::SeqCst);
+        ENABLED.store(true, Ordering::SeqCst);
+        let result = f();
+        ENABLED.store(false, Ordering::SeqCst);
+        result?;
+        Ok(FOUND.load(Ordering::SeqCst))
+    }
+}
+
+unsafe impl GlobalAlloc for ZeroCheckAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        if ENABLED.load(Ordering::SeqCst) && layout.size() >= KEY.len() {
+            let buf = std::slice::from_raw_parts(ptr, layout.size());
+            if buf.windows(KEY.len()).any(|val| val == KEY) {
+                FOUND.store(true, Ordering::SeqCst);
+            }
+        }
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: ZeroCheckAllocator = ZeroCheckAllocator;
+
+#[test]
+fn zeroize_direct_key_on_drop() -> Result<()> {
+    assert!(!ZeroCheckAllocator::leaks(|| {
+        let encrypter = Dir.encrypter_from_bytes(KEY)?;
+        drop(encrypter);
+        Ok(())
+    })?);
+
+    assert!(!ZeroCheckAllocator::leaks(|| {
+        let decrypter = Dir.decrypter_from_bytes(KEY)?;
+        drop(decrypter);
+        Ok(())
+    })?);
+
+    // A plain buffer is not zeroed, so the check itself is effective.
+    assert!(ZeroCheckAllocator::leaks(|| {
+        let key = KEY.to_vec();
+        drop(key);
+        Ok(())
+    })?);
+
+    Ok(())
+}